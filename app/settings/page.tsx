@@ -6,6 +6,8 @@ import {
     Palette,
     Bell,
     Shield,
+    Download,
+    KeyRound,
     Info,
     LogOut,
     ChevronLeft
@@ -19,10 +21,12 @@ import { AccountSection, SectionHeader } from '@/components/settings/AccountSect
 import { AppearanceSection } from '@/components/settings/AppearanceSection';
 import { NotificationsSection } from '@/components/settings/NotificationsSection';
 import { SecuritySection } from '@/components/settings/SecuritySection';
+import { DownloadsSection } from '@/components/settings/DownloadsSection';
+import { EncryptionSection } from '@/components/settings/EncryptionSection';
 
 // --- Types ---
 
-type SettingsTab = 'account' | 'appearance' | 'notifications' | 'security' | 'about';
+type SettingsTab = 'account' | 'appearance' | 'notifications' | 'downloads' | 'encryption' | 'security' | 'about';
 
 interface SettingsTabConfig {
     id: SettingsTab;
@@ -34,6 +38,8 @@ const SETTINGS_TABS: SettingsTabConfig[] = [
     { id: 'account', label: 'Account', icon: User },
     { id: 'appearance', label: 'Appearance', icon: Palette },
     { id: 'notifications', label: 'Notifications', icon: Bell },
+    { id: 'downloads', label: 'Downloads', icon: Download },
+    { id: 'encryption', label: 'Encryption', icon: KeyRound },
     { id: 'security', label: 'Privacy & Security', icon: Shield },
 ];
 
@@ -135,6 +141,26 @@ export default function SettingsPage() {
                             </motion.div>
                         )}
 
+                        {activeTab === 'downloads' && (
+                            <motion.div key="downloads" initial={{ opacity: 0 }} animate={{ opacity: 1 }} exit={{ opacity: 0 }}>
+                                <SectionHeader
+                                    title="Downloads"
+                                    description="Choose where attachments are saved."
+                                />
+                                <DownloadsSection />
+                            </motion.div>
+                        )}
+
+                        {activeTab === 'encryption' && (
+                            <motion.div key="encryption" initial={{ opacity: 0 }} animate={{ opacity: 1 }} exit={{ opacity: 0 }}>
+                                <SectionHeader
+                                    title="Encryption"
+                                    description="Manage the OpenPGP keys used to read, sign and encrypt mail."
+                                />
+                                <EncryptionSection />
+                            </motion.div>
+                        )}
+
                         {activeTab === 'security' && (
                             <motion.div key="security" initial={{ opacity: 0 }} animate={{ opacity: 1 }} exit={{ opacity: 0 }}>
                                 <SectionHeader
@@ -146,7 +172,7 @@ export default function SettingsPage() {
                         )}
 
                         {/* Placeholders for other tabs */}
-                        {(!['account', 'appearance', 'notifications', 'security'].includes(activeTab)) && (
+                        {(!['account', 'appearance', 'notifications', 'downloads', 'encryption', 'security'].includes(activeTab)) && (
                             <motion.div key="placeholder" initial={{ opacity: 0 }} animate={{ opacity: 1 }} exit={{ opacity: 0 }}>
                                 <SectionHeader
                                     title={SETTINGS_TABS.find(t => t.id === activeTab)?.label || 'Settings'}
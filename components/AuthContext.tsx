@@ -23,6 +23,7 @@ interface AuthContextType {
     needsRefresh: boolean;
     isAuthenticated: boolean;
     loginWithGoogle: () => Promise<void>;
+    cancelLogin: () => Promise<void>;
     logout: (accountId: string) => Promise<void>;
 }
 
@@ -112,6 +113,17 @@ export const AuthProvider = ({ children }: { children: ReactNode }) => {
         }
     };
 
+    /**
+     * Aborts a pending Google login that is waiting on the browser.
+     */
+    const cancelLogin = async () => {
+        try {
+            await invoke("cancel_login");
+        } catch (error) {
+            console.error("Auth: Cancel login failed", error);
+        }
+    };
+
     /**
      * Terminates the current account session.
      */
@@ -140,6 +152,7 @@ export const AuthProvider = ({ children }: { children: ReactNode }) => {
                 needsRefresh,
                 isAuthenticated: !!user,
                 loginWithGoogle,
+                cancelLogin,
                 logout,
             }}
         >
@@ -1,10 +1,16 @@
 "use client";
 
-import React, { useState } from 'react';
+import React, { useEffect, useState } from 'react';
 import { motion, AnimatePresence } from 'framer-motion';
 import { X, Minus, Square, Pencil, Paperclip, Image as ImageIcon, Smile, Bold, Italic, Underline, Trash2, Send, UserPlus } from 'lucide-react';
 import { cn } from '@/lib/utils';
-import { MOCK_EMAILS } from '@/lib/data';
+import { invoke } from '@tauri-apps/api/core';
+
+interface ContactSuggestion {
+    email: string;
+    name: string | null;
+    writtenTo: boolean;
+}
 
 interface ComposeModalProps {
     onClose: () => void;
@@ -15,9 +21,20 @@ export default function ComposeModal({ onClose }: ComposeModalProps) {
     const [inputValue, setInputValue] = useState("");
     const [isDropdownOpen, setIsDropdownOpen] = useState(false);
 
-    // Derive unique contacts from mock data
-    const contacts = Array.from(new Set(MOCK_EMAILS.map(e => ({ name: e.sender, email: e.senderEmail }))))
-        .filter((v, i, a) => a.findIndex(t => t.email === v.email) === i);
+    const [contacts, setContacts] = useState<ContactSuggestion[]>([]);
+
+    // Contacts harvested from synced mail, ranked for what has been typed so far
+    useEffect(() => {
+        let cancelled = false;
+        invoke<ContactSuggestion[]>('autocomplete_recipients', { query: inputValue, limit: 8 })
+            .then(results => {
+                if (!cancelled) setContacts(results);
+            })
+            .catch(console.error);
+        return () => {
+            cancelled = true;
+        };
+    }, [inputValue]);
 
     const handleAddRecipient = (email: string) => {
         const trimmedEmail = email.trim();
@@ -111,7 +128,10 @@ export default function ComposeModal({ onClose }: ComposeModalProps) {
                                 placeholder={recipients.length === 0 ? "Add recipients..." : ""}
                                 type="text"
                                 value={inputValue}
-                                onChange={(e) => setInputValue(e.target.value)}
+                                onChange={(e) => {
+                                    setInputValue(e.target.value);
+                                    setIsDropdownOpen(e.target.value.trim().length > 0);
+                                }}
                                 onKeyDown={handleKeyDown}
                                 autoFocus
                             />
@@ -148,7 +168,7 @@ export default function ComposeModal({ onClose }: ComposeModalProps) {
                                                     onClick={() => handleAddRecipient(contact.email)}
                                                     className="w-full flex flex-col items-start px-3 py-2 hover:bg-primary/10 transition-colors group border-b border-black/[0.02] dark:border-white/[0.02] last:border-0"
                                                 >
-                                                    <span className="text-sm font-semibold text-foreground dark:text-white/90 group-hover:text-primary transition-colors">{contact.name}</span>
+                                                    <span className="text-sm font-semibold text-foreground dark:text-white/90 group-hover:text-primary transition-colors">{contact.name ?? contact.email}</span>
                                                     <span className="text-xs text-muted-foreground dark:text-white/50 truncate w-full text-left">{contact.email}</span>
                                                 </button>
                                             ))}
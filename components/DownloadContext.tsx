@@ -1,8 +1,26 @@
 "use client";
 
-import React, { createContext, useContext, useState, useCallback, ReactNode } from 'react';
+import React, { createContext, useContext, useState, useCallback, useEffect, ReactNode } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
 
-export type DownloadStatus = 'downloading' | 'completed' | 'error';
+export type DownloadStatus = 'queued' | 'downloading' | 'paused' | 'completed' | 'error' | 'cancelled';
+
+/** An entry of the backend download history, sent by `list_downloads` and `download:updated`. */
+interface DownloadRecord {
+    id: string;
+    account: string;
+    uid: number;
+    partId: string;
+    fileName: string;
+    path: string;
+    status: 'queued' | 'downloading' | 'paused' | 'completed' | 'failed' | 'cancelled';
+    received: number;
+    total: number;
+    error?: string | null;
+    createdAt: number;
+    finishedAt?: number | null;
+}
 
 export interface DownloadItem {
     id: string;
@@ -10,26 +28,70 @@ export interface DownloadItem {
     status: DownloadStatus;
     progress: number;
     path?: string;
+    error?: string;
+    /** Tracked only in the UI (e.g. "Save all"), not by the backend download manager. */
+    local?: boolean;
 }
 
 interface DownloadContextType {
     downloads: DownloadItem[];
+    startDownload: (uid: number, partId: string, fileName: string, savePath?: string) => Promise<DownloadItem>;
     addDownload: (filename: string) => string;
     updateDownloadProgress: (id: string, progress: number) => void;
     updateDownloadStatus: (id: string, status: DownloadStatus, path?: string) => void;
     removeDownload: (id: string) => void;
     clearDownloads: () => void;
+    pauseDownload: (id: string) => void;
+    resumeDownload: (id: string) => void;
+    cancelDownload: (id: string) => void;
+    openDownload: (id: string) => void;
     activeCount: number;
 }
 
 const DownloadContext = createContext<DownloadContextType | undefined>(undefined);
 
+function toItem(record: DownloadRecord): DownloadItem {
+    return {
+        id: record.id,
+        filename: record.fileName,
+        status: record.status === 'failed' ? 'error' : record.status,
+        progress: record.status === 'completed' ? 100 : record.total > 0 ? Math.round(record.received / record.total * 100) : 0,
+        path: record.path,
+        error: record.error ?? undefined,
+    };
+}
+
 export function DownloadProvider({ children }: { children: ReactNode }) {
     const [downloads, setDownloads] = useState<DownloadItem[]>([]);
 
+    const upsert = useCallback((item: DownloadItem) => {
+        setDownloads(prev => prev.some(d => d.id === item.id)
+            ? prev.map(d => d.id === item.id ? item : d)
+            : [item, ...prev]);
+    }, []);
+
+    // The history lives in the backend; load it once, then follow its updates
+    useEffect(() => {
+        invoke<DownloadRecord[]>('list_downloads')
+            .then(records => setDownloads(prev => [...prev.filter(d => d.local), ...records.map(toItem)]))
+            .catch(err => console.error("Failed to load downloads:", err));
+
+        let unlisten: (() => void) | undefined;
+        listen<DownloadRecord>('download:updated', (event) => upsert(toItem(event.payload)))
+            .then(fn => { unlisten = fn; });
+        return () => unlisten?.();
+    }, [upsert]);
+
+    const startDownload = useCallback(async (uid: number, partId: string, fileName: string, savePath?: string) => {
+        const record = await invoke<DownloadRecord>('download_attachment', { uid, partId, fileName, savePath });
+        const item = toItem(record);
+        upsert(item);
+        return item;
+    }, [upsert]);
+
     const addDownload = useCallback((filename: string) => {
         const id = Math.random().toString(36).substring(2, 9);
-        setDownloads(prev => [{ id, filename, status: 'downloading', progress: 0 }, ...prev]);
+        setDownloads(prev => [{ id, filename, status: 'downloading', progress: 0, local: true }, ...prev]);
         return id;
     }, []);
 
@@ -45,20 +107,45 @@ export function DownloadProvider({ children }: { children: ReactNode }) {
         setDownloads(prev => prev.filter(d => d.id !== id));
     }, []);
 
+    const pauseDownload = useCallback((id: string) => {
+        invoke('cancel_download', { downloadId: id }).catch(err => console.error("Pause failed:", err));
+    }, []);
+
+    // Also retries failed and cancelled downloads; the backend continues from the partial file if there is one
+    const resumeDownload = useCallback((id: string) => {
+        invoke<DownloadRecord>('retry_download', { downloadId: id })
+            .then(record => upsert(toItem(record)))
+            .catch(err => console.error("Resume failed:", err));
+    }, [upsert]);
+
+    const cancelDownload = useCallback((id: string) => {
+        invoke('cancel_download', { downloadId: id, discard: true }).catch(err => console.error("Cancel failed:", err));
+    }, []);
+
+    const openDownload = useCallback((id: string) => {
+        invoke('open_download', { downloadId: id }).catch(err => alert(`Could not open file: ${err}`));
+    }, []);
+
     const clearDownloads = useCallback(() => {
-        setDownloads([]);
+        setDownloads(prev => prev.filter(d => ['queued', 'downloading', 'paused'].includes(d.status)));
+        invoke('clear_download_history').catch(err => console.error("Failed to clear downloads:", err));
     }, []);
 
-    const activeCount = downloads.filter(d => d.status === 'downloading').length;
+    const activeCount = downloads.filter(d => d.status === 'downloading' || d.status === 'queued').length;
 
     return (
         <DownloadContext.Provider value={{
             downloads,
+            startDownload,
             addDownload,
             updateDownloadProgress,
             updateDownloadStatus,
             removeDownload,
             clearDownloads,
+            pauseDownload,
+            resumeDownload,
+            cancelDownload,
+            openDownload,
             activeCount
         }}>
             {children}
@@ -9,18 +9,18 @@ import {
     AlertOctagon,
     File,
     Download,
-    Star
+    Star,
+    ImageOff
 } from 'lucide-react';
-import { cn } from '@/lib/utils';
+import { cn, messageDocumentUrl } from '@/lib/utils';
 import { Email } from '@/lib/types';
 import { motion, AnimatePresence } from 'framer-motion';
-import DOMPurify from 'isomorphic-dompurify';
-import sanitizeHtml from 'sanitize-html';
 import { useTheme } from 'next-themes';
 import { DetailToolbar } from './inbox/DetailToolbar';
-import { MessageHeader, AttachmentCard } from './inbox/MessageHeader';
+import { MessageHeader, AttachmentCard, SaveAllAttachments } from './inbox/MessageHeader';
 import { useEmailBody } from '@/hooks/useEmailBody';
 import OrbitLoader from './inbox/OrbitLoader';
+import { PgpBanner } from './inbox/PgpBanner';
 
 // --- Types ---
 
@@ -38,7 +38,18 @@ const EmailDetail: React.FC<EmailDetailProps> = ({ className, email, onToggleSta
     const { resolvedTheme } = useTheme();
     const isDark = resolvedTheme === 'dark';
 
-    const { bodyContent, attachments, isLoadingBody, iframeHeight } = useEmailBody(
+    const {
+        attachments,
+        isLoadingBody,
+        iframeHeight,
+        remoteContent,
+        pgp,
+        reloadCount,
+        loadError,
+        loadRemoteContent,
+        alwaysLoadFromSender,
+        unlockKey
+    } = useEmailBody(
         email?.id,
         email?.unread,
         onMarkAsRead
@@ -88,6 +99,30 @@ const EmailDetail: React.FC<EmailDetailProps> = ({ className, email, onToggleSta
                     >
                         <MessageHeader email={email} />
 
+                        {!isLoadingBody && pgp && <PgpBanner status={pgp} onUnlock={unlockKey} />}
+
+                        {!isLoadingBody && remoteContent && !remoteContent.loaded && remoteContent.blocked > 0 && (
+                            <div className="flex items-center justify-between gap-4 mb-6 px-4 py-3 rounded-xl bg-black/5 dark:bg-white/5 text-sm">
+                                <div className="flex items-center gap-2 text-muted-foreground">
+                                    <ImageOff className="w-4 h-4" />
+                                    <span>
+                                        Remote images blocked ({remoteContent.blocked})
+                                        {remoteContent.trackersRemoved > 0 && `, ${remoteContent.trackersRemoved} tracker${remoteContent.trackersRemoved === 1 ? '' : 's'} removed`}
+                                    </span>
+                                </div>
+                                <div className="flex items-center gap-3 whitespace-nowrap">
+                                    <button onClick={loadRemoteContent} className="font-medium text-primary hover:underline">
+                                        Load images
+                                    </button>
+                                    {remoteContent.sender && (
+                                        <button onClick={alwaysLoadFromSender} className="font-medium text-primary hover:underline">
+                                            Always load from {remoteContent.sender}
+                                        </button>
+                                    )}
+                                </div>
+                            </div>
+                        )}
+
                         {isLoadingBody ? (
                             <div className="flex flex-col h-[60vh] items-center justify-center">
                                 <OrbitLoader
@@ -95,136 +130,19 @@ const EmailDetail: React.FC<EmailDetailProps> = ({ className, email, onToggleSta
                                     size="md"
                                 />
                             </div>
+                        ) : loadError ? (
+                            <p className="text-sm text-red-500">Error loading message body: {loadError}</p>
                         ) : (
+                            // Served by the orbitmail-msg:// protocol under its own strict CSP;
+                            // no allow-same-origin, so the message can't reach the app or its IPC
                             <iframe
+                                key={`${email.id}-${remoteContent?.loaded ? 'remote' : 'blocked'}-${reloadCount}`}
                                 title="Email Content"
                                 className="w-full border-0 email-content-iframe bg-white dark:bg-[#111111]"
-                                sandbox="allow-popups allow-popups-to-escape-sandbox allow-same-origin allow-scripts"
+                                sandbox="allow-popups allow-popups-to-escape-sandbox allow-scripts"
                                 scrolling="no"
                                 style={{ height: `${iframeHeight}px`, overflow: 'hidden' }}
-                                srcDoc={`
-                                <!DOCTYPE html>
-                                <html>
-                                <head>
-                                    <meta charset="utf-8">
-                                    <meta name="viewport" content="width=device-width, initial-scale=1">
-                                    <style>
-                                        html { opacity: 0; }
-                                        html.ready { opacity: 1; }
-                                        html, body { font-family: system-ui, -apple-system, sans-serif; margin: 0; padding: 0; overflow: hidden; width: 100%; }
-                                        img { max-width: 100%; height: auto; }
-                                        a { color: #2563eb; }
-                                        #email-content-wrapper { display: block; overflow: hidden; }
-                                        
-                                        /* Smart Invert for Dark Mode */
-                                        html.dark-mode {
-                                            filter: invert(1) hue-rotate(180deg) brightness(1.05);
-                                            background-color: #EEEEEE; /* Inverts to ~ #111111 matching the outer pane */
-                                        }
-                                        html.dark-mode img, 
-                                        html.dark-mode video, 
-                                        html.dark-mode picture,
-                                        html.dark-mode svg,
-                                        html.dark-mode [style*="background-image"] {
-                                            filter: invert(1) hue-rotate(180deg);
-                                        }
-                                        html.dark-mode a {
-                                            color: #3b82f6; /* Adjust link color for dark mode */
-                                        }
-                                    </style>
-                                </head>
-                                <body>
-                                    <div id="email-content-wrapper">
-                                        ${DOMPurify.sanitize(
-                                    sanitizeHtml(bodyContent, {
-                                        allowedTags: sanitizeHtml.defaults.allowedTags.concat([
-                                            "img", "table", "tbody", "tr", "td", "th", "style", "head", "meta", "html", "body"
-                                        ]),
-                                        allowedAttributes: {
-                                            a: ["href", "name", "target"],
-                                            img: ["src", "alt", "width", "height"],
-                                            td: ["colspan", "rowspan", "align"],
-                                            "*": ["style"]
-                                        }
-                                    }),
-                                    { FORBID_TAGS: ['script', 'iframe', 'object', 'embed'] }
-                                )}
-                                    </div>
-                                    <script>
-                                        const wrapper = document.getElementById('email-content-wrapper');
-                                        let lastHeight = 0;
-                                        
-                                        // --- Smart Dark Mode Heuristics ---
-                                        const applySmartInvert = () => {
-                                            if (!${isDark}) return;
-                                            
-                                            // Function to parse rgb/rgba to check brightness
-                                            const getBrightness = (colorStr) => {
-                                                const match = colorStr.match(/rgba?\\((\\d+),\\s*(\\d+),\\s*(\\d+)/);
-                                                if (!match) return 255; // default to light if parsing fails
-                                                const r = parseInt(match[1]);
-                                                const g = parseInt(match[2]);
-                                                const b = parseInt(match[3]);
-                                                // Standard luminance formula
-                                                return (r * 299 + g * 587 + b * 114) / 1000;
-                                            };
-
-                                            // Check body and wrapper computed backgrounds
-                                            let bodyBg = window.getComputedStyle(document.body).backgroundColor;
-                                            let wrapperBg = window.getComputedStyle(wrapper).backgroundColor;
-                                            
-                                            // If both are transparent/rgba(0,0,0,0), assume it's "white" by default HTML standards
-                                            let isTransparent = (bg) => bg === 'rgba(0, 0, 0, 0)' || bg === 'transparent';
-                                            
-                                            let effectiveBg = 'rgb(255, 255, 255)'; // Default HTML bg
-                                            if (!isTransparent(wrapperBg)) effectiveBg = wrapperBg;
-                                            else if (!isTransparent(bodyBg)) effectiveBg = bodyBg;
-
-                                            const brightness = getBrightness(effectiveBg);
-                                            
-                                            // If the background is light (brightness > 128), invert it!
-                                            // If it's already dark (brightness <= 128), leave it alone because it's a native dark email.
-                                            if (brightness > 128) {
-                                                document.documentElement.classList.add('dark-mode');
-                                            }
-                                        };
-                                        
-                                        // Run IMMEDIATELY as the DOM parses, before images load!
-                                        applySmartInvert();
-                                        document.documentElement.classList.add('ready');
-
-                                        const sendHeight = () => {
-                                            if (!wrapper) return;
-                                            // Only measure the strict wrapper height, ignoring the stretched iframe
-                                            const height = wrapper.scrollHeight;
-                                            
-                                            if (height !== lastHeight) {
-                                                lastHeight = height;
-                                                window.parent.postMessage({ type: 'resize', height: height, id: '${email.id}' }, '*');
-                                            }
-                                        };
-                                        
-                                        window.addEventListener('load', sendHeight);
-                                        
-                                        // 1. Observe size changes strictly on wrapper, NOT document.body
-                                        new ResizeObserver(sendHeight).observe(wrapper);
-                                        
-                                        // 2. Observe DOM mutations strictly inside wrapper
-                                        new MutationObserver(sendHeight).observe(wrapper, { 
-                                            childList: true, subtree: true, attributes: true 
-                                        });
-                                        
-                                        // 3. Aggressive polling for 5 seconds
-                                        let pollCount = 0;
-                                        const pollInterval = setInterval(() => {
-                                            sendHeight();
-                                            pollCount++;
-                                            if (pollCount > 50) clearInterval(pollInterval);
-                                        }, 100);
-                                    </script>
-                                </body>
-                                </html>
-                            `}
+                                src={messageDocumentUrl(email.id, { remote: remoteContent?.loaded, dark: isDark })}
                             />
                         )}
 
@@ -235,6 +153,15 @@ const EmailDetail: React.FC<EmailDetailProps> = ({ className, email, onToggleSta
                                     <h3 className="text-sm font-bold uppercase tracking-[0.2em] text-muted-foreground/50 dark:text-white/30">
                                         Attachments ({attachments.length})
                                     </h3>
+                                    {attachments.length > 1 && (
+                                        <div className="ml-auto">
+                                            <SaveAllAttachments
+                                                uid={Number(email.id)}
+                                                subject={email.subject}
+                                                attachments={attachments}
+                                            />
+                                        </div>
+                                    )}
                                 </div>
                                 <div className="grid grid-cols-[repeat(auto-fill,minmax(260px,1fr))] gap-4">
                                     {attachments.map((att, i) => (
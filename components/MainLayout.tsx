@@ -131,22 +131,26 @@ export default function MainLayout() {
         }
     };
 
-    const formatEmailFromMessage = (msg: any): Email => ({
-        id: msg.uid.toString(),
-        sender: msg.from.split('<')[0].trim() || msg.from,
-        senderEmail: msg.from,
-        subject: msg.subject || '(No Subject)',
-        preview: msg.snippet?.trim() || msg.subject?.substring(0, 100) || 'No preview available',
-        avatar: `https://ui-avatars.com/api/?name=${encodeURIComponent(msg.from.split('<')[0].trim() || msg.from)}&background=random`,
-        time: formatEmailTime(msg.date * 1000),
-        date: new Date(msg.date * 1000).toLocaleString(),
-        unread: !msg.seen,
-        folder: "inbox",
-        tags: [],
-        starred: msg.flagged,
-        body: msg.snippet || '<p>Message body not fetched in this milestone.</p>',
-        attachments: [],
-    });
+    const formatEmailFromMessage = (msg: any): Email => {
+        const from = msg.from?.[0];
+        const senderName = from?.name || from?.email || 'Unknown sender';
+        return {
+            id: msg.uid.toString(),
+            sender: senderName,
+            senderEmail: from?.email || '',
+            subject: msg.subject || '(No Subject)',
+            preview: msg.snippet?.trim() || msg.subject?.substring(0, 100) || 'No preview available',
+            avatar: `https://ui-avatars.com/api/?name=${encodeURIComponent(senderName)}&background=random`,
+            time: formatEmailTime(msg.date * 1000),
+            date: new Date(msg.date * 1000).toLocaleString(),
+            unread: !msg.seen,
+            folder: "inbox",
+            tags: [],
+            starred: msg.flagged,
+            body: msg.snippet || '<p>Message body not fetched in this milestone.</p>',
+            attachments: [],
+        };
+    };
 
     const fetchCache = async () => {
         try {
@@ -2,7 +2,7 @@
 
 
 import { Window as TauriWindow } from "@tauri-apps/api/window";
-import { Minus, Square, X, RefreshCw, CheckCircle2, Download, AlertCircle, File, FolderOpen } from "lucide-react";
+import { Minus, Square, X, RefreshCw, CheckCircle2, Download, AlertCircle, File, FolderOpen, Pause, Play, RotateCw, Clock, Ban } from "lucide-react";
 import { useEffect, useState, memo, useRef } from "react";
 import { invoke } from "@tauri-apps/api/core";
 import { cn } from "@/lib/utils";
@@ -151,7 +151,7 @@ function SyncIndicator() {
  * Download Manager Popover
  */
 function DownloadManagerPopover() {
-    const { downloads, activeCount, clearDownloads } = useDownloads();
+    const { downloads, activeCount, clearDownloads, pauseDownload, resumeDownload, cancelDownload, openDownload } = useDownloads();
     const [isOpen, setIsOpen] = useState(false);
     const popoverRef = useRef<HTMLDivElement>(null);
 
@@ -213,7 +213,15 @@ function DownloadManagerPopover() {
                                 </div>
                             ) : (
                                 downloads.map(item => (
-                                    <div key={item.id} className="flex items-start gap-3 p-2 rounded-lg hover:bg-black/5 dark:hover:bg-white/5 transition-colors group">
+                                    <div
+                                        key={item.id}
+                                        onClick={() => { if (item.status === 'completed' && !item.local) openDownload(item.id); }}
+                                        title={item.status === 'completed' && !item.local ? "Open" : undefined}
+                                        className={cn(
+                                            "flex items-start gap-3 p-2 rounded-lg hover:bg-black/5 dark:hover:bg-white/5 transition-colors group",
+                                            item.status === 'completed' && !item.local && "cursor-pointer"
+                                        )}
+                                    >
                                         <div className="mt-0.5 shrink-0 bg-primary/10 text-primary p-1.5 rounded-md">
                                             <File size={14} />
                                         </div>
@@ -221,18 +229,66 @@ function DownloadManagerPopover() {
                                             <p className="text-xs font-semibold text-foreground/90 dark:text-white/90 truncate">{item.filename}</p>
                                             <div className="flex items-center gap-1.5 mt-1">
                                                 {item.status === 'downloading' ? (
-                                                    <span className="text-[10px] text-primary animate-pulse font-medium">Downloading...</span>
+                                                    <span className="text-[10px] text-primary animate-pulse font-medium">
+                                                        Downloading... {item.progress > 0 && `${item.progress}%`}
+                                                    </span>
+                                                ) : item.status === 'queued' ? (
+                                                    <span className="text-[10px] text-foreground/50 dark:text-white/40 font-medium flex items-center gap-1">
+                                                        <Clock size={10} /> Waiting...
+                                                    </span>
+                                                ) : item.status === 'cancelled' ? (
+                                                    <span className="text-[10px] text-foreground/50 dark:text-white/40 font-medium flex items-center gap-1">
+                                                        <Ban size={10} /> Cancelled
+                                                    </span>
+                                                ) : item.status === 'paused' ? (
+                                                    <span className="text-[10px] text-foreground/50 dark:text-white/40 font-medium">
+                                                        Paused at {item.progress}%
+                                                    </span>
                                                 ) : item.status === 'completed' ? (
                                                     <span className="text-[10px] text-emerald-500 font-medium flex items-center gap-1">
                                                         <CheckCircle2 size={10} /> Saved
                                                     </span>
                                                 ) : (
-                                                    <span className="text-[10px] text-red-500 font-medium flex items-center gap-1">
+                                                    <span className="text-[10px] text-red-500 font-medium flex items-center gap-1" title={item.error}>
                                                         <AlertCircle size={10} /> Failed
                                                     </span>
                                                 )}
                                             </div>
+                                            {(item.status === 'downloading' || item.status === 'paused') && (
+                                                <div className="mt-1.5 h-1 rounded-full bg-black/5 dark:bg-white/10 overflow-hidden">
+                                                    <div className="h-full bg-primary transition-all duration-300" style={{ width: `${item.progress}%` }} />
+                                                </div>
+                                            )}
                                         </div>
+                                        {!item.local && item.status !== 'completed' && (
+                                            <div className="flex items-center self-center opacity-0 group-hover:opacity-100 transition-all">
+                                                <button
+                                                    onClick={(e) => {
+                                                        e.stopPropagation();
+                                                        if (item.status === 'downloading' || item.status === 'queued') pauseDownload(item.id);
+                                                        else resumeDownload(item.id);
+                                                    }}
+                                                    className="p-1.5 text-foreground/50 dark:text-white/50 hover:bg-black/5 dark:hover:bg-white/10 hover:text-primary dark:hover:text-primary rounded-md"
+                                                    title={item.status === 'downloading' || item.status === 'queued' ? "Pause" : item.status === 'paused' ? "Resume" : "Retry"}
+                                                >
+                                                    {item.status === 'downloading' || item.status === 'queued'
+                                                        ? <Pause size={14} />
+                                                        : item.status === 'paused' ? <Play size={14} /> : <RotateCw size={14} />}
+                                                </button>
+                                                {item.status !== 'cancelled' && (
+                                                    <button
+                                                        onClick={(e) => {
+                                                            e.stopPropagation();
+                                                            cancelDownload(item.id);
+                                                        }}
+                                                        className="p-1.5 text-foreground/50 dark:text-white/50 hover:bg-black/5 dark:hover:bg-white/10 hover:text-red-500 rounded-md"
+                                                        title="Cancel"
+                                                    >
+                                                        <X size={14} />
+                                                    </button>
+                                                )}
+                                            </div>
+                                        )}
                                         {item.status === 'completed' && item.path && (
                                             <button
                                                 onClick={(e) => {
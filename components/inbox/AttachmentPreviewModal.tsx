@@ -0,0 +1,100 @@
+"use client";
+
+import React, { useEffect } from 'react';
+import { motion } from 'framer-motion';
+import { X, Download, FileQuestion } from 'lucide-react';
+import { AttachmentPreview } from '@/lib/types';
+
+interface AttachmentPreviewModalProps {
+    preview: AttachmentPreview;
+    onClose: () => void;
+    onDownload: () => void;
+}
+
+export function AttachmentPreviewModal({ preview, onClose, onDownload }: AttachmentPreviewModalProps) {
+    useEffect(() => {
+        const handleKey = (e: KeyboardEvent) => {
+            if (e.key === 'Escape') onClose();
+        };
+        window.addEventListener('keydown', handleKey);
+        return () => window.removeEventListener('keydown', handleKey);
+    }, [onClose]);
+
+    const renderBody = () => {
+        if (preview.kind === 'image' && preview.url) {
+            return <img src={preview.url} alt={preview.name} className="max-w-full max-h-full object-contain mx-auto" />;
+        }
+        if (preview.kind === 'pdf' && preview.url) {
+            // Not sandboxed: sandboxed frames can't host the built-in PDF viewer. The document
+            // comes from the message protocol's origin, not the app's, with its own CSP
+            return <iframe src={preview.url} title={preview.name} className="w-full h-full border-none bg-white" />;
+        }
+        if (preview.text) {
+            return (
+                <pre className="text-xs leading-relaxed whitespace-pre-wrap break-words font-mono text-foreground/80 dark:text-white/80 p-4">
+                    {preview.text}
+                    {preview.truncated && <span className="block mt-4 text-muted-foreground">…</span>}
+                </pre>
+            );
+        }
+        return (
+            <div className="h-full flex flex-col items-center justify-center gap-3 text-muted-foreground">
+                <FileQuestion className="w-10 h-10" />
+                <p className="text-sm">No preview available for this file.</p>
+            </div>
+        );
+    };
+
+    return (
+        <div className="fixed inset-0 z-50 flex items-center justify-center">
+            {/* Backdrop */}
+            <motion.div
+                initial={{ opacity: 0 }}
+                animate={{ opacity: 1 }}
+                exit={{ opacity: 0 }}
+                onClick={onClose}
+                className="absolute inset-0 bg-slate-900/10 backdrop-blur-sm"
+            />
+
+            <motion.div
+                initial={{ opacity: 0, scale: 0.95, y: 20 }}
+                animate={{ opacity: 1, scale: 1, y: 0 }}
+                exit={{ opacity: 0, scale: 0.95, y: 20 }}
+                transition={{ duration: 0.2, ease: "easeOut" }}
+                className="relative w-full max-w-4xl bg-white/70 dark:bg-[#1C1C21]/70 backdrop-blur-2xl rounded-xl shadow-2xl border border-white/40 dark:border-white/5 overflow-hidden flex flex-col h-[80vh] m-4"
+            >
+                <header className="flex items-center justify-between gap-4 px-4 py-2.5 border-b border-black/5 dark:border-white/5 select-none">
+                    <div className="min-w-0">
+                        <h1 className="text-xs font-semibold text-foreground/70 dark:text-white/70 tracking-tight truncate" title={preview.name}>
+                            {preview.name}
+                        </h1>
+                        {preview.pageCount != null && (
+                            <p className="text-[10px] text-muted-foreground">
+                                {preview.pageCount} {preview.pageCount === 1 ? 'page' : 'pages'}
+                            </p>
+                        )}
+                    </div>
+                    <div className="flex items-center gap-1">
+                        <button
+                            onClick={onDownload}
+                            className="p-1.5 rounded-md hover:bg-black/5 dark:hover:bg-white/10 text-foreground/60"
+                            title="Download"
+                        >
+                            <Download className="w-4 h-4" />
+                        </button>
+                        <button
+                            onClick={onClose}
+                            className="p-1.5 rounded-md hover:bg-black/5 dark:hover:bg-white/10 text-foreground/60"
+                            title="Close"
+                        >
+                            <X className="w-4 h-4" />
+                        </button>
+                    </div>
+                </header>
+                <div className="flex-1 min-h-0 overflow-auto">
+                    {renderBody()}
+                </div>
+            </motion.div>
+        </div>
+    );
+}
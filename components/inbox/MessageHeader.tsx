@@ -1,13 +1,15 @@
 "use client";
 
 import React, { memo } from 'react';
-import { motion } from 'framer-motion';
-import { File, Download, FileText, Image as ImageIcon, FileArchive, FileCode, Video, Music, Presentation, Table, FileSpreadsheet, FileAudio, FileVideo, FileType } from 'lucide-react';
+import { motion, AnimatePresence } from 'framer-motion';
+import { File, Download, FolderDown, FileText, Image as ImageIcon, FileArchive, FileCode, Video, Music, Presentation, Table, FileSpreadsheet, FileAudio, FileVideo, FileType } from 'lucide-react';
 import { invoke } from '@tauri-apps/api/core';
-import { save } from '@tauri-apps/plugin-dialog';
+import { listen } from '@tauri-apps/api/event';
+import { save, open } from '@tauri-apps/plugin-dialog';
 import { cn } from '@/lib/utils';
-import { Email, Attachment } from '@/lib/types';
+import { Email, Attachment, AttachmentPreview, AttachmentExportProgress } from '@/lib/types';
 import { useDownloads } from '@/components/DownloadContext';
+import { AttachmentPreviewModal } from '@/components/inbox/AttachmentPreviewModal';
 
 export const MessageHeader = memo(({ email }: { email: Email }) => (
     <header className="mb-8">
@@ -114,40 +116,58 @@ const getTypeColor = (mime: string | undefined): string => {
 
 export const AttachmentCard = memo(({ uid, attachment }: { uid: number, attachment: Attachment }) => {
     const [isDownloading, setIsDownloading] = React.useState(false);
-    const { addDownload, updateDownloadStatus } = useDownloads();
+    const [isPreviewing, setIsPreviewing] = React.useState(false);
+    const [preview, setPreview] = React.useState<AttachmentPreview | null>(null);
+    const [showPreview, setShowPreview] = React.useState(false);
+    const { startDownload } = useDownloads();
 
-    const handleDownload = async () => {
-        setIsDownloading(true);
+    const handlePreview = async () => {
+        // Attachments of encrypted messages are never written to the preview cache
+        if (attachment.partId.startsWith('pgp.')) {
+            handleDownload();
+            return;
+        }
+        if (preview) {
+            setShowPreview(true);
+            return;
+        }
+        setIsPreviewing(true);
         try {
-            // Ask user for save location
-            const savePath = await save({
-                defaultPath: attachment.name,
-                title: "Save Attachment",
+            const result = await invoke<AttachmentPreview>('preview_attachment', {
+                uid,
+                partId: attachment.partId,
             });
+            setPreview(result);
+            setShowPreview(true);
+        } catch (err) {
+            console.error("Preview failed:", err);
+            alert(`Preview failed: ${err}`);
+        } finally {
+            setIsPreviewing(false);
+        }
+    };
 
-            if (!savePath) {
+    const handleDownload = async () => {
+        setIsDownloading(true);
+        try {
+            // Saved to the download folder unless the user wants to pick a place each time
+            const settings = await invoke<{ askWhereToSave: boolean }>('get_download_settings');
+            let savePath: string | undefined;
+            if (settings.askWhereToSave) {
+                const chosen = await save({
+                    defaultPath: attachment.name,
+                    title: "Save Attachment",
+                });
                 // User cancelled the dialog
-                setIsDownloading(false);
-                return;
+                if (!chosen) return;
+                savePath = chosen;
             }
 
-            // Register in the global download manager
-            const downloadId = addDownload(attachment.name);
-
-            // Execute backend download
-            // Since imap fetch blocks, we don't have true byte-level progress in NextJS easily.
-            // The context will show "downloading" spinner until the IPC call returns.
-            const resultPath = await invoke<string>('download_attachment', {
-                uid,
-                partId: attachment.partId,
-                savePath: savePath // Note: Rust expects `save_path`, Tauri backend automatically camelCases mapping. Wait, let's use `save_path` explicitly or rely on Tauri mapping. Usually tauri maps `savePath` from JS to `save_path` in Rust if `#[tauri::command(rename_all = "camelCase")]` is used globally, or default is camelCase. If it fails, we will check.
-            });
-            console.log(`Downloaded to ${resultPath}`);
-            updateDownloadStatus(downloadId, 'completed', resultPath);
+            // Queued in the backend download manager; progress shows up in the titlebar
+            await startDownload(uid, attachment.partId, attachment.name, savePath);
         } catch (err) {
             console.error("Download failed:", err);
             alert(`Download failed: ${err}`);
-            // If we had the downloadId here, we could mark it as error it, but addDownload might not have fired if dialog failed.
         } finally {
             setIsDownloading(false);
         }
@@ -158,6 +178,7 @@ export const AttachmentCard = memo(({ uid, attachment }: { uid: number, attachme
     const shortType = getShortType(attachment.type);
 
     return (
+        <>
         <motion.div
             initial={{ opacity: 0, y: 10 }}
             animate={{ opacity: 1, y: 0 }}
@@ -169,16 +190,26 @@ export const AttachmentCard = memo(({ uid, attachment }: { uid: number, attachme
                 "hover:border-primary/40"
             )}
         >
-            <div className={cn(
-                "w-14 h-14 shrink-0 rounded-2xl flex items-center justify-center transition-all duration-500",
-                "bg-primary/5 text-primary group-hover:bg-primary/10",
-                typeColor !== "primary" && `text-${typeColor} bg-${typeColor}/5 group-hover:bg-${typeColor}/10`
-            )}>
-                <div className="group-hover:scale-110 transition-transform duration-500">
-                    {getFileIcon(attachment.type)}
-                </div>
-            </div>
-            <div className="flex-1 min-w-0 flex flex-col gap-0.5">
+            <button
+                onClick={handlePreview}
+                disabled={isPreviewing}
+                title="Preview"
+                className={cn(
+                    "w-14 h-14 shrink-0 rounded-2xl flex items-center justify-center overflow-hidden transition-all duration-500",
+                    "bg-primary/5 text-primary group-hover:bg-primary/10",
+                    typeColor !== "primary" && `text-${typeColor} bg-${typeColor}/5 group-hover:bg-${typeColor}/10`,
+                    isPreviewing && "animate-pulse"
+                )}
+            >
+                {preview?.thumbnailUrl ? (
+                    <img src={preview.thumbnailUrl} alt="" className="w-full h-full object-cover" />
+                ) : (
+                    <div className="group-hover:scale-110 transition-transform duration-500">
+                        {getFileIcon(attachment.type)}
+                    </div>
+                )}
+            </button>
+            <div className="flex-1 min-w-0 flex flex-col gap-0.5 cursor-pointer" onClick={handlePreview}>
                 <p
                     className="text-sm font-bold text-foreground/90 dark:text-white/90 line-clamp-2 leading-tight group-hover:text-primary transition-colors duration-300"
                     title={attachment.name}
@@ -216,6 +247,76 @@ export const AttachmentCard = memo(({ uid, attachment }: { uid: number, attachme
                 )}
             </button>
         </motion.div>
+        <AnimatePresence>
+            {showPreview && preview && (
+                <AttachmentPreviewModal
+                    preview={preview}
+                    onClose={() => setShowPreview(false)}
+                    onDownload={handleDownload}
+                />
+            )}
+        </AnimatePresence>
+        </>
     );
 });
 AttachmentCard.displayName = "AttachmentCard";
+
+export const SaveAllAttachments = memo(({ uid, subject, attachments }: { uid: number, subject: string, attachments: Attachment[] }) => {
+    const [isSaving, setIsSaving] = React.useState(false);
+    const { addDownload, updateDownloadProgress, updateDownloadStatus } = useDownloads();
+
+    const saveAll = async (asZip: boolean) => {
+        const destination = asZip
+            ? await save({
+                defaultPath: `${(subject || "Attachments").replace(/[<>:"/\\|?*]/g, "_")}.zip`,
+                title: "Save Attachments as ZIP",
+                filters: [{ name: "ZIP archive", extensions: ["zip"] }],
+            })
+            : await open({ directory: true, title: "Save Attachments To" });
+        if (!destination || Array.isArray(destination)) return;
+
+        setIsSaving(true);
+        const downloadId = addDownload(asZip ? destination.split(/[\\/]/).pop() || "Attachments.zip" : `${attachments.length} attachments`);
+        const unlisten = await listen<AttachmentExportProgress>('mail:attachment-export-progress', (event) => {
+            if (event.payload.uid === uid) {
+                updateDownloadProgress(downloadId, Math.round(event.payload.completed / event.payload.total * 100));
+            }
+        });
+        try {
+            const result = await invoke<{ path: string, files: string[] }>('download_all_attachments', {
+                uid,
+                attachments,
+                destination,
+                asZip,
+            });
+            updateDownloadStatus(downloadId, 'completed', result.path);
+        } catch (err) {
+            console.error("Saving attachments failed:", err);
+            updateDownloadStatus(downloadId, 'error');
+            alert(`Saving attachments failed: ${err}`);
+        } finally {
+            unlisten();
+            setIsSaving(false);
+        }
+    };
+
+    const buttonClass = cn(
+        "flex items-center gap-1.5 px-3 py-1.5 rounded-xl text-xs font-semibold transition-all duration-300",
+        "bg-primary/5 text-primary hover:bg-primary hover:text-white",
+        isSaving && "animate-pulse pointer-events-none"
+    );
+
+    return (
+        <div className="flex items-center gap-2">
+            <button onClick={() => saveAll(false)} disabled={isSaving} className={buttonClass} title="Save all to a folder">
+                <FolderDown className="w-4 h-4" />
+                Save all
+            </button>
+            <button onClick={() => saveAll(true)} disabled={isSaving} className={buttonClass} title="Save all as a ZIP archive">
+                <FileArchive className="w-4 h-4" />
+                ZIP
+            </button>
+        </div>
+    );
+});
+SaveAllAttachments.displayName = "SaveAllAttachments";
@@ -0,0 +1,127 @@
+"use client";
+
+import React, { memo, useState } from 'react';
+import { Lock, LockOpen, ShieldCheck, ShieldAlert, ShieldQuestion, KeyRound } from 'lucide-react';
+import { cn } from '@/lib/utils';
+import { PgpStatus, SignatureStatus } from '@/lib/types';
+
+/** Key ids and fingerprints, grouped in fours the way OpenPGP tools print them. */
+const formatKeyId = (id: string) => id.toUpperCase().replace(/(.{4})(?=.)/g, '$1 ');
+
+function describeSignature(signature: SignatureStatus): { text: string; tone: 'good' | 'bad' | 'neutral' } {
+    switch (signature.state) {
+        case 'valid': {
+            const signer = signature.signer ?? 'a known key';
+            if (!signature.senderMatches) {
+                return { text: `Signed by ${signer}, which isn't the sender's key`, tone: 'bad' };
+            }
+            // Autocrypt keys come from headers anyone can write, so they don't prove who sent it
+            if (signature.source !== 'imported') {
+                return { text: `Signed by ${signer} with a key taken from their mail, not verified`, tone: 'neutral' };
+            }
+            return { text: `Signed by ${signer}`, tone: 'good' };
+        }
+        case 'invalid':
+            return { text: `Bad signature: ${signature.detail ?? 'the message doesn\'t match it'}`, tone: 'bad' };
+        case 'unknownKey':
+            return {
+                text: signature.keyId
+                    ? `Signed with key ${formatKeyId(signature.keyId)}, which isn't in your keyring`
+                    : 'Signed with a key that isn\'t in your keyring',
+                tone: 'neutral',
+            };
+        default:
+            return { text: `Signature could not be checked: ${signature.detail ?? 'unknown error'}`, tone: 'bad' };
+    }
+}
+
+interface PgpBannerProps {
+    status: PgpStatus;
+    onUnlock: (fingerprint: string, passphrase: string) => Promise<void>;
+}
+
+export const PgpBanner = memo(({ status, onUnlock }: PgpBannerProps) => {
+    const [passphrase, setPassphrase] = useState('');
+    const [unlockError, setUnlockError] = useState<string | null>(null);
+    const [isUnlocking, setIsUnlocking] = useState(false);
+
+    const signature = status.signature ? describeSignature(status.signature) : null;
+    const SignatureIcon = status.signature?.state === 'valid' && signature?.tone === 'good'
+        ? ShieldCheck
+        : signature?.tone === 'bad' ? ShieldAlert : ShieldQuestion;
+
+    const handleUnlock = async (e: React.FormEvent) => {
+        e.preventDefault();
+        if (!status.lockedKey || !passphrase) return;
+        setIsUnlocking(true);
+        setUnlockError(null);
+        try {
+            await onUnlock(status.lockedKey, passphrase);
+            setPassphrase('');
+        } catch (err) {
+            setUnlockError(String(err));
+        } finally {
+            setIsUnlocking(false);
+        }
+    };
+
+    return (
+        <div className="flex flex-col gap-2 mb-6 px-4 py-3 rounded-xl bg-black/5 dark:bg-white/5 text-sm">
+            {status.encrypted && (
+                <div className="flex items-center gap-2 text-muted-foreground">
+                    {status.decrypted ? <LockOpen className="w-4 h-4 text-emerald-500" /> : <Lock className="w-4 h-4" />}
+                    <span>
+                        {status.decrypted
+                            ? 'Encrypted message, decrypted for this session'
+                            : status.error ?? 'Encrypted message'}
+                    </span>
+                </div>
+            )}
+
+            {!status.encrypted && status.error && (
+                <div className="flex items-center gap-2 text-red-500">
+                    <ShieldAlert className="w-4 h-4" />
+                    <span>{status.error}</span>
+                </div>
+            )}
+
+            {signature && (
+                <div
+                    className={cn(
+                        "flex items-center gap-2",
+                        signature.tone === 'good' && "text-emerald-600 dark:text-emerald-400",
+                        signature.tone === 'bad' && "text-red-500",
+                        signature.tone === 'neutral' && "text-muted-foreground"
+                    )}
+                    title={status.signature?.fingerprint ? formatKeyId(status.signature.fingerprint) : undefined}
+                >
+                    <SignatureIcon className="w-4 h-4" />
+                    <span>{signature.text}</span>
+                </div>
+            )}
+
+            {status.lockedKey && !status.decrypted && (
+                <form onSubmit={handleUnlock} className="flex items-center gap-3 mt-1">
+                    <KeyRound className="w-4 h-4 text-muted-foreground shrink-0" />
+                    <input
+                        type="password"
+                        value={passphrase}
+                        onChange={e => setPassphrase(e.target.value)}
+                        placeholder={`Passphrase for ${formatKeyId(status.lockedKey.slice(-16))}`}
+                        className="flex-1 min-w-0 px-3 py-1.5 rounded-lg bg-white dark:bg-black/40 border border-black/10 dark:border-white/10 outline-none focus:border-primary/50"
+                        autoFocus
+                    />
+                    <button
+                        type="submit"
+                        disabled={isUnlocking || !passphrase}
+                        className="font-medium text-primary hover:underline disabled:opacity-50 whitespace-nowrap"
+                    >
+                        {isUnlocking ? 'Unlocking...' : 'Unlock and decrypt'}
+                    </button>
+                </form>
+            )}
+            {unlockError && <p className="text-xs text-red-500">{unlockError}</p>}
+        </div>
+    );
+});
+PgpBanner.displayName = "PgpBanner";
@@ -0,0 +1,97 @@
+"use client";
+
+import React, { memo, useEffect, useState } from 'react';
+import { motion } from 'framer-motion';
+import { invoke } from '@tauri-apps/api/core';
+import { open } from '@tauri-apps/plugin-dialog';
+
+interface DownloadSettings {
+    directory: string | null;
+    askWhereToSave: boolean;
+}
+
+export const DownloadsSection = memo(() => {
+    const [settings, setSettings] = useState<DownloadSettings>({ directory: null, askWhereToSave: false });
+
+    useEffect(() => {
+        invoke<DownloadSettings>('get_download_settings')
+            .then(setSettings)
+            .catch(err => console.error("Failed to load download settings:", err));
+    }, []);
+
+    const update = async (next: DownloadSettings) => {
+        const previous = settings;
+        setSettings(next);
+        try {
+            await invoke('update_download_settings', { settings: next });
+        } catch (err) {
+            console.error("Failed to save download settings:", err);
+            alert(`Failed to save download settings: ${err}`);
+            setSettings(previous);
+        }
+    };
+
+    const chooseFolder = async () => {
+        const directory = await open({ directory: true, title: "Download Folder" });
+        if (!directory || Array.isArray(directory)) return;
+        update({ ...settings, directory });
+    };
+
+    return (
+    <motion.div
+        initial={{ opacity: 0, y: 10 }}
+        animate={{ opacity: 1, y: 0 }}
+        className="space-y-8"
+    >
+        <div className="p-8 rounded-3xl bg-secondary/30 dark:bg-black/20 border border-white/20 dark:border-white/10 backdrop-blur-md">
+            <h3 className="text-xs font-bold text-muted-foreground uppercase tracking-wider mb-6">Attachments</h3>
+
+            <div className="space-y-6">
+                <div className="flex items-center justify-between">
+                    <div className="flex-1 min-w-0 pr-6">
+                        <h4 className="font-semibold text-foreground">Download Folder</h4>
+                        <p className="text-sm text-muted-foreground mt-1 truncate" title={settings.directory ?? undefined}>
+                            {settings.directory ?? "System Downloads folder"}
+                        </p>
+                    </div>
+                    <div className="flex items-center gap-2">
+                        {settings.directory && (
+                            <button
+                                onClick={() => update({ ...settings, directory: null })}
+                                className="px-4 py-2 whitespace-nowrap text-muted-foreground font-medium rounded-xl hover:bg-black/5 dark:hover:bg-white/10 transition-colors text-sm"
+                            >
+                                Reset
+                            </button>
+                        )}
+                        <button
+                            onClick={chooseFolder}
+                            className="px-5 py-2 whitespace-nowrap bg-white text-black font-medium rounded-xl shadow-sm hover:bg-white/90 transition-colors text-sm dark:bg-black/40 dark:text-white dark:border dark:border-white/10 dark:hover:bg-black/60"
+                        >
+                            Change...
+                        </button>
+                    </div>
+                </div>
+
+                <div className="h-px bg-white/10 dark:bg-white/5 w-full" />
+
+                <div className="flex items-center justify-between">
+                    <div>
+                        <h4 className="font-semibold text-foreground">Ask Where to Save Each File</h4>
+                        <p className="text-sm text-muted-foreground mt-1">Otherwise attachments go straight to the download folder, renamed if the name is taken.</p>
+                    </div>
+                    <label className="relative inline-flex items-center cursor-pointer">
+                        <input
+                            type="checkbox"
+                            className="sr-only peer"
+                            checked={settings.askWhereToSave}
+                            onChange={e => update({ ...settings, askWhereToSave: e.target.checked })}
+                        />
+                        <div className="w-11 h-6 bg-black/10 dark:bg-white/10 peer-focus:outline-none rounded-full peer peer-checked:after:translate-x-full peer-checked:after:border-white after:content-[''] after:absolute after:top-[2px] after:left-[2px] after:bg-white after:border-gray-300 dark:after:border-gray-600 after:border after:rounded-full after:h-5 after:w-5 after:transition-all peer-checked:bg-primary"></div>
+                    </label>
+                </div>
+            </div>
+        </div>
+    </motion.div>
+    );
+});
+DownloadsSection.displayName = "DownloadsSection";
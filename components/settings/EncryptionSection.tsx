@@ -0,0 +1,212 @@
+"use client";
+
+import React, { memo, useCallback, useEffect, useState } from 'react';
+import { motion } from 'framer-motion';
+import { invoke } from '@tauri-apps/api/core';
+import { open, save } from '@tauri-apps/plugin-dialog';
+import { KeyRound, Lock, LockOpen, Trash2, Upload, Download } from 'lucide-react';
+import { PgpKey } from '@/lib/types';
+
+const formatFingerprint = (fingerprint: string) => fingerprint.toUpperCase().replace(/(.{4})(?=.)/g, '$1 ');
+
+export const EncryptionSection = memo(() => {
+    const [keys, setKeys] = useState<PgpKey[]>([]);
+    const [pasted, setPasted] = useState('');
+    const [unlocking, setUnlocking] = useState<string | null>(null);
+    const [passphrase, setPassphrase] = useState('');
+
+    const refresh = useCallback(() => {
+        invoke<PgpKey[]>('list_pgp_keys')
+            .then(setKeys)
+            .catch(err => console.error("Failed to load keys:", err));
+    }, []);
+
+    useEffect(() => { refresh(); }, [refresh]);
+
+    const importFile = async () => {
+        const path = await open({
+            title: "Import OpenPGP Keys",
+            filters: [{ name: "OpenPGP keys", extensions: ["asc", "gpg", "pgp", "key"] }],
+        });
+        if (!path || Array.isArray(path)) return;
+        try {
+            await invoke('import_pgp_key_file', { path });
+            refresh();
+        } catch (err) {
+            alert(`Import failed: ${err}`);
+        }
+    };
+
+    const importPasted = async () => {
+        if (!pasted.trim()) return;
+        try {
+            await invoke('import_pgp_keys', { armored: pasted });
+            setPasted('');
+            refresh();
+        } catch (err) {
+            alert(`Import failed: ${err}`);
+        }
+    };
+
+    const exportKey = async (key: PgpKey, includeSecret: boolean) => {
+        const path = await save({
+            defaultPath: `${key.fingerprint.slice(-16)}${includeSecret ? '-secret' : ''}.asc`,
+            title: includeSecret ? "Export Secret Key" : "Export Public Key",
+        });
+        if (!path) return;
+        try {
+            await invoke('export_pgp_key', { fingerprint: key.fingerprint, path, includeSecret });
+        } catch (err) {
+            alert(`Export failed: ${err}`);
+        }
+    };
+
+    const deleteKey = async (key: PgpKey) => {
+        const what = key.hasSecret ? 'this key pair, including its secret key' : 'this key';
+        if (!confirm(`Delete ${what}? Mail encrypted to it can no longer be read here.`)) return;
+        try {
+            await invoke('delete_pgp_key', { fingerprint: key.fingerprint });
+            refresh();
+        } catch (err) {
+            alert(`Delete failed: ${err}`);
+        }
+    };
+
+    const unlockKey = async (e: React.FormEvent) => {
+        e.preventDefault();
+        if (!unlocking) return;
+        try {
+            await invoke('unlock_pgp_key', { fingerprint: unlocking, passphrase });
+            setUnlocking(null);
+            setPassphrase('');
+            refresh();
+        } catch (err) {
+            alert(`${err}`);
+        }
+    };
+
+    return (
+    <motion.div
+        initial={{ opacity: 0, y: 10 }}
+        animate={{ opacity: 1, y: 0 }}
+        className="space-y-8"
+    >
+        <div className="p-8 rounded-3xl bg-secondary/30 dark:bg-black/20 border border-white/20 dark:border-white/10 backdrop-blur-md">
+            <div className="flex items-center justify-between mb-6">
+                <h3 className="text-xs font-bold text-muted-foreground uppercase tracking-wider">OpenPGP Keyring</h3>
+                <button
+                    onClick={importFile}
+                    className="flex items-center gap-2 px-4 py-2 whitespace-nowrap bg-white text-black font-medium rounded-xl shadow-sm hover:bg-white/90 transition-colors text-sm dark:bg-black/40 dark:text-white dark:border dark:border-white/10 dark:hover:bg-black/60"
+                >
+                    <Upload className="w-4 h-4" />
+                    Import File...
+                </button>
+            </div>
+
+            {keys.length === 0 ? (
+                <p className="text-sm text-muted-foreground">
+                    No keys yet. Import your own key pair to read encrypted mail and sign messages; keys of people who write to you are picked up from their Autocrypt headers.
+                </p>
+            ) : (
+                <div className="space-y-4">
+                    {keys.map((key, i) => (
+                        <React.Fragment key={key.fingerprint}>
+                            {i > 0 && <div className="h-px bg-white/10 dark:bg-white/5 w-full" />}
+                            <div className="flex items-start justify-between gap-4">
+                                <div className="flex-1 min-w-0">
+                                    <div className="flex items-center gap-2">
+                                        <KeyRound className="w-4 h-4 text-muted-foreground shrink-0" />
+                                        <h4 className="font-semibold text-foreground truncate">{key.userIds[0] ?? 'No user id'}</h4>
+                                        {key.hasSecret && (
+                                            <span className="px-1.5 py-0.5 rounded-md text-[10px] font-bold uppercase bg-primary/10 text-primary">Secret</span>
+                                        )}
+                                        {key.source === 'autocrypt' && (
+                                            <span className="px-1.5 py-0.5 rounded-md text-[10px] font-bold uppercase bg-black/5 dark:bg-white/10 text-muted-foreground">Autocrypt</span>
+                                        )}
+                                    </div>
+                                    <p className="text-xs font-mono text-muted-foreground mt-1">{formatFingerprint(key.fingerprint)}</p>
+                                    {key.userIds.length > 1 && (
+                                        <p className="text-xs text-muted-foreground mt-1 truncate">Also {key.userIds.slice(1).join(', ')}</p>
+                                    )}
+                                    {!key.canEncrypt && (
+                                        <p className="text-xs text-muted-foreground mt-1">Can't be encrypted to</p>
+                                    )}
+                                    {unlocking === key.fingerprint && (
+                                        <form onSubmit={unlockKey} className="flex items-center gap-2 mt-3">
+                                            <input
+                                                type="password"
+                                                value={passphrase}
+                                                onChange={e => setPassphrase(e.target.value)}
+                                                placeholder="Passphrase"
+                                                className="flex-1 px-3 py-1.5 rounded-lg bg-white dark:bg-black/40 border border-black/10 dark:border-white/10 text-sm outline-none focus:border-primary/50"
+                                                autoFocus
+                                            />
+                                            <button type="submit" className="text-sm font-medium text-primary hover:underline">Unlock</button>
+                                            <button type="button" onClick={() => setUnlocking(null)} className="text-sm text-muted-foreground hover:underline">Cancel</button>
+                                        </form>
+                                    )}
+                                </div>
+                                <div className="flex items-center gap-1 shrink-0">
+                                    {key.hasSecret && (
+                                        <button
+                                            onClick={() => key.locked && setUnlocking(key.fingerprint)}
+                                            title={key.locked ? "Unlock for this session" : "Unlocked for this session"}
+                                            className="p-2 rounded-lg text-muted-foreground hover:bg-black/5 dark:hover:bg-white/10 transition-colors"
+                                        >
+                                            {key.locked ? <Lock className="w-4 h-4" /> : <LockOpen className="w-4 h-4 text-emerald-500" />}
+                                        </button>
+                                    )}
+                                    <button
+                                        onClick={() => exportKey(key, false)}
+                                        title="Export public key"
+                                        className="p-2 rounded-lg text-muted-foreground hover:bg-black/5 dark:hover:bg-white/10 transition-colors"
+                                    >
+                                        <Download className="w-4 h-4" />
+                                    </button>
+                                    {key.hasSecret && (
+                                        <button
+                                            onClick={() => exportKey(key, true)}
+                                            title="Export secret key"
+                                            className="px-2 py-1 rounded-lg text-xs font-medium text-muted-foreground hover:bg-black/5 dark:hover:bg-white/10 transition-colors"
+                                        >
+                                            Secret
+                                        </button>
+                                    )}
+                                    <button
+                                        onClick={() => deleteKey(key)}
+                                        title="Delete"
+                                        className="p-2 rounded-lg text-red-500 hover:bg-red-500/10 transition-colors"
+                                    >
+                                        <Trash2 className="w-4 h-4" />
+                                    </button>
+                                </div>
+                            </div>
+                        </React.Fragment>
+                    ))}
+                </div>
+            )}
+        </div>
+
+        <div className="p-8 rounded-3xl bg-secondary/30 dark:bg-black/20 border border-white/20 dark:border-white/10 backdrop-blur-md">
+            <h3 className="text-xs font-bold text-muted-foreground uppercase tracking-wider mb-6">Paste a Key</h3>
+            <textarea
+                value={pasted}
+                onChange={e => setPasted(e.target.value)}
+                placeholder="-----BEGIN PGP PUBLIC KEY BLOCK-----"
+                rows={5}
+                className="w-full px-4 py-3 rounded-xl bg-white dark:bg-black/40 border border-black/10 dark:border-white/10 text-xs font-mono outline-none focus:border-primary/50 resize-none"
+            />
+            <div className="flex justify-end mt-4">
+                <button
+                    onClick={importPasted}
+                    disabled={!pasted.trim()}
+                    className="px-5 py-2 bg-primary text-white font-semibold rounded-xl shadow-lg shadow-primary/30 hover:bg-primary/90 transition-all active:scale-95 text-sm disabled:opacity-50"
+                >
+                    Import
+                </button>
+            </div>
+        </div>
+    </motion.div>
+    );
+});
+EncryptionSection.displayName = "EncryptionSection";
@@ -1,9 +1,35 @@
 "use client";
 
-import React, { memo } from 'react';
+import React, { memo, useEffect, useState } from 'react';
 import { motion } from 'framer-motion';
+import { invoke } from '@tauri-apps/api/core';
 
-export const SecuritySection = memo(() => (
+interface RemoteContentSettings {
+    blockRemoteContent: boolean;
+    allowedSenders: string[];
+}
+
+export const SecuritySection = memo(() => {
+    const [remoteSettings, setRemoteSettings] = useState<RemoteContentSettings>({ blockRemoteContent: true, allowedSenders: [] });
+
+    useEffect(() => {
+        invoke<RemoteContentSettings>('get_remote_content_settings')
+            .then(setRemoteSettings)
+            .catch(err => console.error("Failed to load remote content settings:", err));
+    }, []);
+
+    const toggleBlockRemote = async (checked: boolean) => {
+        const next = { ...remoteSettings, blockRemoteContent: checked };
+        setRemoteSettings(next);
+        try {
+            await invoke('update_remote_content_settings', { settings: next });
+        } catch (err) {
+            console.error("Failed to save remote content settings:", err);
+            setRemoteSettings(remoteSettings);
+        }
+    };
+
+    return (
     <motion.div
         initial={{ opacity: 0, y: 10 }}
         animate={{ opacity: 1, y: 0 }}
@@ -52,7 +78,12 @@ export const SecuritySection = memo(() => (
                         <p className="text-sm text-muted-foreground mt-1">Prevent senders from tracking you via spy pixels.</p>
                     </div>
                     <label className="relative inline-flex items-center cursor-pointer">
-                        <input type="checkbox" className="sr-only peer" defaultChecked />
+                        <input
+                            type="checkbox"
+                            className="sr-only peer"
+                            checked={remoteSettings.blockRemoteContent}
+                            onChange={e => toggleBlockRemote(e.target.checked)}
+                        />
                         <div className="w-11 h-6 bg-black/10 dark:bg-white/10 peer-focus:outline-none rounded-full peer peer-checked:after:translate-x-full peer-checked:after:border-white after:content-[''] after:absolute after:top-[2px] after:left-[2px] after:bg-white after:border-gray-300 dark:after:border-gray-600 after:border after:rounded-full after:h-5 after:w-5 after:transition-all peer-checked:bg-primary"></div>
                     </label>
                 </div>
@@ -78,5 +109,6 @@ export const SecuritySection = memo(() => (
             </div>
         </div>
     </motion.div>
-));
+    );
+});
 SecuritySection.displayName = "SecuritySection";
@@ -1,16 +1,30 @@
-import { useState, useEffect } from "react";
+import { useState, useEffect, useCallback } from "react";
 import { invoke } from "@tauri-apps/api/core";
 
-import { Attachment } from "@/lib/types";
+import { Attachment, PgpStatus } from "@/lib/types";
+
+export interface RemoteContentReport {
+    blocked: number;
+    trackersRemoved: number;
+    loaded: boolean;
+    sender?: string | null;
+}
 
 interface MessageDetail {
     body: string;
     attachments: Attachment[];
+    remoteContent?: RemoteContentReport;
+    pgp?: PgpStatus | null;
 }
 
 export function useEmailBody(emailId: string | undefined, emailUnread: boolean | undefined, onMarkAsRead?: (id: string) => void) {
     const [bodyContent, setBodyContent] = useState<string>("");
     const [attachments, setAttachments] = useState<Attachment[]>([]);
+    const [remoteContent, setRemoteContent] = useState<RemoteContentReport | null>(null);
+    const [pgp, setPgp] = useState<PgpStatus | null>(null);
+    // Bumped to fetch the body again, e.g. after unlocking the key that decrypts it
+    const [reloadCount, setReloadCount] = useState<number>(0);
+    const [loadError, setLoadError] = useState<string | null>(null);
     const [isLoadingBody, setIsLoadingBody] = useState<boolean>(false);
     const [iframeHeight, setIframeHeight] = useState<number>(400);
 
@@ -20,17 +34,22 @@ export function useEmailBody(emailId: string | undefined, emailUnread: boolean |
         if (!emailId) {
             setBodyContent("");
             setAttachments([]);
+            setRemoteContent(null);
+            setPgp(null);
             return;
         }
 
         const fetchBody = async () => {
             setIsLoadingBody(true);
             setIframeHeight(400); // Reset height on new email
+            setLoadError(null);
             try {
                 const detail: MessageDetail = await invoke('get_message_body', { uid: Number(emailId) });
                 if (isMounted) {
                     setBodyContent(detail.body || "<p>Message has no content.</p>");
                     setAttachments(detail.attachments || []);
+                    setRemoteContent(detail.remoteContent ?? null);
+                    setPgp(detail.pgp ?? null);
                 }
             } catch (err) {
                 console.error("Failed to load message body:", err);
@@ -42,6 +61,9 @@ export function useEmailBody(emailId: string | undefined, emailUnread: boolean |
                 if (isMounted) {
                     setBodyContent(`<p class="text-red-500">Error loading message body: ${err}</p>`);
                     setAttachments([]);
+                    setRemoteContent(null);
+                    setPgp(null);
+                    setLoadError(String(err));
                 }
             } finally {
                 if (isMounted) {
@@ -60,7 +82,7 @@ export function useEmailBody(emailId: string | undefined, emailUnread: boolean |
         return () => {
             isMounted = false;
         };
-    }, [emailId, emailUnread, onMarkAsRead]);
+    }, [emailId, emailUnread, onMarkAsRead, reloadCount]);
 
     // Handle iframe resize messages sent from the injected srcDoc script
     useEffect(() => {
@@ -74,5 +96,34 @@ export function useEmailBody(emailId: string | undefined, emailUnread: boolean |
         return () => window.removeEventListener('message', handleMessage);
     }, [emailId]);
 
-    return { bodyContent, attachments, isLoadingBody, iframeHeight };
+    // Re-render the body with remote images loaded, just for this view
+    const loadRemoteContent = useCallback(async () => {
+        if (!emailId) return;
+        try {
+            const detail: MessageDetail = await invoke('load_remote_content', { uid: Number(emailId) });
+            setBodyContent(detail.body || "<p>Message has no content.</p>");
+            setRemoteContent(detail.remoteContent ?? null);
+        } catch (err) {
+            console.error("Failed to load remote content:", err);
+        }
+    }, [emailId]);
+
+    // Trust the sender permanently, then re-render the current message
+    const alwaysLoadFromSender = useCallback(async () => {
+        if (!remoteContent?.sender) return;
+        try {
+            await invoke('allow_remote_content_from', { sender: remoteContent.sender });
+            await loadRemoteContent();
+        } catch (err) {
+            console.error("Failed to allow sender:", err);
+        }
+    }, [remoteContent, loadRemoteContent]);
+
+    // Unlock the secret key for this session, then decrypt the message again
+    const unlockKey = useCallback(async (fingerprint: string, passphrase: string) => {
+        await invoke('unlock_pgp_key', { fingerprint, passphrase });
+        setReloadCount(count => count + 1);
+    }, []);
+
+    return { bodyContent, attachments, isLoadingBody, iframeHeight, remoteContent, pgp, reloadCount, loadError, loadRemoteContent, alwaysLoadFromSender, unlockKey };
 }
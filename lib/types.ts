@@ -40,3 +40,55 @@ export interface SettingsTabConfig {
     label: string;
     icon: React.ElementType;
 }
+
+export interface AttachmentPreview {
+    kind: "image" | "pdf" | "text" | "other";
+    name: string;
+    mime: string;
+    size: number;
+    url?: string | null;
+    thumbnailUrl?: string | null;
+    text?: string | null;
+    truncated: boolean;
+    pageCount?: number | null;
+}
+
+export interface AttachmentExportProgress {
+    uid: number;
+    partId: string;
+    fileName: string;
+    completed: number;
+    total: number;
+    bytes: number;
+}
+
+export interface SignatureStatus {
+    state: "valid" | "invalid" | "unknownKey" | "error";
+    keyId?: string | null;
+    fingerprint?: string | null;
+    signer?: string | null;
+    senderMatches: boolean;
+    /** Where the verifying key came from; Autocrypt keys are unauthenticated. */
+    source?: "imported" | "autocrypt" | null;
+    detail?: string | null;
+}
+
+/** What OpenPGP did with a PGP/MIME message. */
+export interface PgpStatus {
+    encrypted: boolean;
+    decrypted: boolean;
+    signature?: SignatureStatus | null;
+    /** Fingerprint of a secret key that can decrypt the message once unlocked. */
+    lockedKey?: string | null;
+    error?: string | null;
+}
+
+export interface PgpKey {
+    fingerprint: string;
+    userIds: string[];
+    hasSecret: boolean;
+    locked: boolean;
+    canEncrypt: boolean;
+    source: "imported" | "autocrypt";
+    addedAt: number;
+}
@@ -1,5 +1,6 @@
 import { clsx, type ClassValue } from "clsx";
 import { twMerge } from "tailwind-merge";
+import { convertFileSrc } from "@tauri-apps/api/core";
 
 export function cn(...inputs: ClassValue[]) {
   return twMerge(clsx(inputs));
@@ -31,3 +32,12 @@ export function formatEmailTime(dateStringOrTimestamp: number | string | Date):
     return date.toLocaleDateString(undefined, options);
   }
 }
+
+/** URL of a message body on the `orbitmail-msg://` protocol, for the reading pane iframe. */
+export function messageDocumentUrl(uid: string, options: { remote?: boolean; dark?: boolean } = {}): string {
+  const params = new URLSearchParams();
+  if (options.remote) params.set("remote", "1");
+  if (options.dark) params.set("theme", "dark");
+  const query = params.toString();
+  return `${convertFileSrc("", "orbitmail-msg")}${uid}${query ? `?${query}` : ""}`;
+}
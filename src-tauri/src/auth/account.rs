@@ -1,3 +1,4 @@
+use crate::mail::tls::ImapServerConfig;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -11,6 +12,8 @@ pub struct Account {
     pub last_sync: Option<i64>,
     pub profile_name: String,
     pub profile_picture: String,
+    #[serde(default)]
+    pub imap: ImapServerConfig,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -2,7 +2,6 @@ use crate::auth::session;
 use crate::auth::account::UserProfile;
 use tauri::AppHandle;
 use chrono::Utc;
-use reqwest::Client;
 use serde_json::Value;
 
 #[derive(Debug, serde::Serialize)]
@@ -27,7 +26,6 @@ pub async fn bootstrap_accounts(app_handle: &AppHandle) -> BootstrapResult {
             let client_id = env!("GOOGLE_CLIENT_ID");
             let client_secret = env!("GOOGLE_CLIENT_SECRET");
             {
-                let http_client = Client::new();
                 let payload = format!(
                     "client_id={}&client_secret={}&refresh_token={}&grant_type=refresh_token",
                     url::form_urlencoded::byte_serialize(client_id.as_bytes()).collect::<String>(),
@@ -35,11 +33,15 @@ pub async fn bootstrap_accounts(app_handle: &AppHandle) -> BootstrapResult {
                     url::form_urlencoded::byte_serialize(account.refresh_token.as_bytes()).collect::<String>()
                 );
 
-                let res = http_client.post("https://oauth2.googleapis.com/token")
-                    .header("Content-Type", "application/x-www-form-urlencoded")
-                    .body(payload)
-                    .send()
-                    .await;
+                let res = match crate::net::proxy::http_client() {
+                    Ok(http_client) => http_client.post("https://oauth2.googleapis.com/token")
+                        .header("Content-Type", "application/x-www-form-urlencoded")
+                        .body(payload)
+                        .send()
+                        .await
+                        .map_err(|e| e.to_string()),
+                    Err(e) => Err(e),
+                };
 
                 if let Ok(response) = res {
                     if let Ok(json) = response.json::<Value>().await {
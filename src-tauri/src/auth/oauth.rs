@@ -1,24 +1,67 @@
 use crate::auth::account::Account;
+use crate::mail::tls::ImapServerConfig;
 use oauth2::basic::BasicClient;
 use oauth2::{
     AuthUrl, AuthorizationCode, ClientId, ClientSecret, CsrfToken, PkceCodeChallenge,
     RedirectUrl, Scope, TokenResponse, TokenUrl,
 };
-use reqwest::Client as HttpClient;
 use serde_json::Value;
 use std::io::{BufRead, BufReader, Write};
-use std::net::TcpListener;
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 use url::Url;
 
+/// How long the loopback server waits for the browser redirect when no timeout is supplied.
+pub const DEFAULT_LOGIN_TIMEOUT_SECS: u64 = 300;
+
+/// Login attempts are numbered so a cancel applies to one of them only.
+static NEXT_LOGIN: AtomicU64 = AtomicU64::new(1);
+/// The attempt running now, or 0 when none is.
+static RUNNING_LOGIN: AtomicU64 = AtomicU64::new(0);
+/// The attempt `cancel_login` was last called during.
+static CANCELLED_LOGIN: AtomicU64 = AtomicU64::new(0);
+
+/// Aborts an in-progress `start_google_login`. Does nothing when no login is running.
+pub fn cancel_login() {
+    CANCELLED_LOGIN.store(RUNNING_LOGIN.load(Ordering::SeqCst), Ordering::SeqCst);
+}
+
+fn is_cancelled(attempt: u64) -> bool {
+    CANCELLED_LOGIN.load(Ordering::SeqCst) == attempt
+}
+
+/// One run of `start_google_login`, from the moment it starts, so a cancel sent before the
+/// listener is up still counts. It stops being cancellable once it ends, however it ended.
+struct LoginAttempt {
+    id: u64,
+}
+
+impl LoginAttempt {
+    fn begin() -> Self {
+        let id = NEXT_LOGIN.fetch_add(1, Ordering::SeqCst);
+        RUNNING_LOGIN.store(id, Ordering::SeqCst);
+        LoginAttempt { id }
+    }
+}
+
+impl Drop for LoginAttempt {
+    fn drop(&mut self) {
+        let _ = RUNNING_LOGIN.compare_exchange(self.id, 0, Ordering::SeqCst, Ordering::SeqCst);
+    }
+}
+
 /// Orchestrates the Google OAuth 2.0 Authorization Code flow for desktop applications.
 /// 
 /// This function:
 /// 1. Loads credentials from environment variables.
 /// 2. Spins up a temporary loopback server to catch the authorization code.
 /// 3. Opens the system browser for user authentication.
-/// 4. Exchanges the received code for access/refresh tokens.
-/// 5. Fetches the user profile from Google's UserInfo API.
-pub async fn start_google_login() -> Result<Account, String> {
+/// 4. Waits (up to `timeout_secs`) for the redirect carrying a matching `state`.
+/// 5. Exchanges the received code for access/refresh tokens.
+/// 6. Fetches the user profile from Google's UserInfo API.
+pub async fn start_google_login(timeout_secs: Option<u64>) -> Result<Account, String> {
+    let attempt = LoginAttempt::begin();
     dotenvy::dotenv().ok();
 
     let google_client_id = ClientId::new(
@@ -47,7 +90,7 @@ pub async fn start_google_login() -> Result<Account, String> {
 
     let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
 
-    let (authorize_url, _csrf_state) = client
+    let (authorize_url, csrf_state) = client
         .authorize_url(CsrfToken::new_random)
         .add_scope(Scope::new("openid".to_string()))
         .add_scope(Scope::new("https://www.googleapis.com/auth/userinfo.email".to_string()))
@@ -61,28 +104,33 @@ pub async fn start_google_login() -> Result<Account, String> {
 
 
 
+    if is_cancelled(attempt.id) {
+        return Err("Login cancelled".to_string());
+    }
     open::that(authorize_url.as_str()).map_err(|e| e.to_string())?;
 
-    let mut stream = listener.incoming().next().ok_or("Listener died")?.map_err(|e| e.to_string())?;
-    let mut reader = BufReader::new(&stream);
-    let mut request_line = String::new();
-    reader.read_line(&mut request_line).map_err(|e| e.to_string())?;
-
-    let redirect_url_path = request_line.split_whitespace().nth(1).ok_or("Malformed request")?;
-    let url = Url::parse(&format!("http://localhost{}", redirect_url_path)).map_err(|e| e.to_string())?;
-    
-    let code = url
-        .query_pairs()
-        .find(|(key, _)| key == "code")
-        .map(|(_, value)| AuthorizationCode::new(value.into_owned()))
-        .ok_or("No code received from Google")?;
+    let timeout = Duration::from_secs(timeout_secs.unwrap_or(DEFAULT_LOGIN_TIMEOUT_SECS));
+    let expected_state = csrf_state.secret().clone();
+    let attempt_id = attempt.id;
+    let (mut stream, code) = tokio::task::spawn_blocking(move || {
+        wait_for_callback(listener, &expected_state, timeout, attempt_id)
+    })
+    .await
+    .map_err(|e| format!("Task failed: {}", e))??;
 
     let token_result = client
         .exchange_code(code)
         .set_pkce_verifier(pkce_verifier)
-        .request_async(oauth2::reqwest::async_http_client)
-        .await
-        .map_err(|e| format!("Token exchange failed: {}", e))?;
+        .request_async(crate::net::proxy::oauth_http_client)
+        .await;
+
+    let token_result = match token_result {
+        Ok(t) => t,
+        Err(e) => {
+            write_html_response(&mut stream, "500 Internal Server Error", "Sign-in Failed", "OrbitMail could not complete sign-in. Please return to the app and try again.");
+            return Err(format!("Token exchange failed: {}", e));
+        }
+    };
 
     let access_token = token_result.access_token().secret();
     let refresh_token = token_result
@@ -93,7 +141,7 @@ pub async fn start_google_login() -> Result<Account, String> {
     let expires_in = token_result.expires_in().map(|d| d.as_secs()).unwrap_or(3600);
     let expires_at = chrono::Utc::now().timestamp() + expires_in as i64;
 
-    let http_client = HttpClient::new();
+    let http_client = crate::net::proxy::http_client()?;
     let user_info: Value = http_client
         .get("https://www.googleapis.com/oauth2/v2/userinfo")
         .bearer_auth(access_token)
@@ -110,8 +158,7 @@ pub async fn start_google_login() -> Result<Account, String> {
         .ok_or("Failed to identify user (missing id/sub)")?
         .to_string();
 
-    let success_response = "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n\r\n<html><body><script>window.close()</script><h1>Authentication Successful</h1><p>You can close this window now.</p></body></html>";
-    stream.write_all(success_response.as_bytes()).ok();
+    write_html_response(&mut stream, "200 OK", "Authentication Successful", "You can close this window now.");
 
     Ok(Account {
         id,
@@ -123,5 +170,163 @@ pub async fn start_google_login() -> Result<Account, String> {
         last_sync: None,
         profile_name: user_info["name"].as_str().unwrap_or_default().to_string(),
         profile_picture: user_info["picture"].as_str().unwrap_or_default().to_string(),
+        imap: ImapServerConfig::default(),
     })
 }
+
+/// Accepts loopback connections until the real OAuth redirect arrives.
+///
+/// Unrelated requests (favicon fetches, browser preconnects) are answered with a 404 and ignored.
+/// A redirect with a mismatched `state` is rejected as a possible CSRF attempt.
+/// Returns the open stream so the caller can render the final result page.
+fn wait_for_callback(
+    listener: TcpListener,
+    expected_state: &str,
+    timeout: Duration,
+    attempt: u64,
+) -> Result<(TcpStream, AuthorizationCode), String> {
+    listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        if is_cancelled(attempt) {
+            return Err("Login cancelled".to_string());
+        }
+        if Instant::now() >= deadline {
+            return Err("Login timed out waiting for the browser".to_string());
+        }
+
+        let mut stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                std::thread::sleep(Duration::from_millis(100));
+                continue;
+            }
+            Err(e) => return Err(e.to_string()),
+        };
+
+        // The accepted socket inherits non-blocking mode on some platforms
+        stream.set_nonblocking(false).ok();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).ok();
+
+        let mut request_line = String::new();
+        if BufReader::new(&stream).read_line(&mut request_line).is_err() {
+            continue;
+        }
+
+        let Some(path) = request_line.split_whitespace().nth(1) else { continue };
+        let Ok(url) = Url::parse(&format!("http://localhost{}", path)) else { continue };
+        let param = |name: &str| url.query_pairs().find(|(k, _)| k == name).map(|(_, v)| v.into_owned());
+
+        let state = param("state");
+        let code = param("code");
+        let error = param("error");
+
+        if state.is_none() && code.is_none() && error.is_none() {
+            stream.write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n").ok();
+            continue;
+        }
+
+        if state.as_deref() != Some(expected_state) {
+            log::warn!("OAuth callback rejected: state mismatch.");
+            write_html_response(&mut stream, "400 Bad Request", "Invalid Sign-in Request", "This sign-in link is stale or was not started by OrbitMail. Please start again from the app.");
+            continue;
+        }
+
+        if let Some(error) = error {
+            let (title, message) = if error == "access_denied" {
+                ("Sign-in Cancelled", "Access was not granted. You can close this window and try again from OrbitMail.")
+            } else {
+                ("Sign-in Failed", "Google reported an error. Please return to OrbitMail and try again.")
+            };
+            write_html_response(&mut stream, "200 OK", title, message);
+            return Err(if error == "access_denied" {
+                "Access denied by user".to_string()
+            } else {
+                format!("OAuth error: {}", error)
+            });
+        }
+
+        let Some(code) = code else {
+            write_html_response(&mut stream, "400 Bad Request", "Sign-in Failed", "No authorization code was received.");
+            return Err("No code received from Google".to_string());
+        };
+
+        return Ok((stream, AuthorizationCode::new(code)));
+    }
+}
+
+/// Writes a minimal self-closing HTML page back to the browser tab.
+fn write_html_response(stream: &mut TcpStream, status: &str, title: &str, message: &str) {
+    let body = format!(
+        "<html><head><title>{0}</title></head><body><script>window.close()</script><h1>{0}</h1><p>{1}</p></body></html>",
+        title, message
+    );
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).ok();
+}
+
+/// Revokes a Google OAuth token so it can no longer be used to access the account.
+/// Revoking the refresh token also invalidates every access token issued from it.
+pub async fn revoke_token(token: &str) -> Result<(), String> {
+    if token.is_empty() {
+        return Ok(());
+    }
+
+    let payload = format!(
+        "token={}",
+        url::form_urlencoded::byte_serialize(token.as_bytes()).collect::<String>()
+    );
+
+    let response = crate::net::proxy::http_client()?
+        .post("https://oauth2.googleapis.com/revoke")
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(payload)
+        .send()
+        .await
+        .map_err(|e| format!("Token revocation request failed: {}", e))?;
+
+    let status = response.status();
+    if status.is_success() {
+        return Ok(());
+    }
+
+    // Google answers 400 `invalid_token` for tokens that are already revoked or expired,
+    // which is the state we want anyway.
+    let body = response.text().await.unwrap_or_default();
+    if body.contains("invalid_token") {
+        log::info!("Token was already revoked at provider.");
+        return Ok(());
+    }
+
+    Err(format!("Token revocation failed ({}): {}", status, body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_cancel_only_applies_to_the_running_login() {
+        // Sent while no login is running
+        cancel_login();
+        let first = LoginAttempt::begin();
+        assert!(!is_cancelled(first.id));
+
+        cancel_login();
+        assert!(is_cancelled(first.id));
+        drop(first);
+
+        // Sent after that login ended
+        cancel_login();
+        let second = LoginAttempt::begin();
+        assert!(!is_cancelled(second.id));
+        cancel_login();
+        assert!(is_cancelled(second.id));
+    }
+}
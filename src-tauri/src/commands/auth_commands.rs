@@ -3,10 +3,48 @@ use crate::auth::oauth;
 use crate::auth::session;
 use tauri::{AppHandle, command};
 
+/// Drops the in-memory and on-disk body caches after the database cache was cleared.
+async fn clear_body_caches(app_handle: &AppHandle) -> Result<(), String> {
+    crate::mail::prefetch::clear_prefetch_queue().await;
+    crate::mail::body_cache::clear_cached_bodies();
+    let app = app_handle.clone();
+    tokio::task::spawn_blocking(move || crate::mail::message_body::purge_inline_cache(&app))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// The mail cache holds one account at a time: whatever another account left in it goes
+/// before `email`'s first sync.
+async fn claim_mail_cache(app_handle: &AppHandle, email: &str) -> Result<(), String> {
+    // Hold the sync lock so an in-flight sync can't write into the cache as it changes hands
+    let _sync_guard = crate::mail::sync::SYNC_LOCK.lock().await;
+    let app = app_handle.clone();
+    let email = email.to_string();
+    let cleared = tokio::task::spawn_blocking(move || crate::mail::database::claim_mail_cache(&app, &email))
+        .await
+        .map_err(|e| e.to_string())??;
+    if cleared {
+        clear_body_caches(app_handle).await?;
+    }
+    Ok(())
+}
+
 #[command]
-pub async fn login_google(app_handle: AppHandle) -> Result<UserProfile, String> {
-    let account = oauth::start_google_login().await?;
+pub async fn login_google(app_handle: AppHandle, timeout_secs: Option<u64>) -> Result<UserProfile, String> {
+    let mut account = oauth::start_google_login(timeout_secs).await?;
+    // Signing in again keeps the server settings, CA bundle and pin made for this account
+    if let Some(existing) = session::load_accounts(&app_handle).into_iter().find(|a| a.id == account.id) {
+        account.imap = existing.imap;
+    }
+
+    // Background tasks belong to the active account, which is about to change
+    let switching = session::get_active_account(&app_handle).is_some_and(|a| a.id != account.id);
+    if switching {
+        crate::mail::idle::stop_idle_listener();
+        crate::mail::poll::stop_polling();
+    }
     session::save_account(&app_handle, account.clone(), true)?;
+    claim_mail_cache(&app_handle, &account.email).await?;
     
     // Initial sync
     if let Ok(_guard) = crate::mail::sync::SYNC_LOCK.try_lock() {
@@ -22,6 +60,11 @@ pub async fn login_google(app_handle: AppHandle) -> Result<UserProfile, String>
     Ok(UserProfile::from(account))
 }
 
+#[command]
+pub fn cancel_login() {
+    oauth::cancel_login();
+}
+
 #[command]
 pub fn get_current_user(app_handle: AppHandle) -> Option<UserProfile> {
     session::get_active_account(&app_handle).map(UserProfile::from)
@@ -36,8 +79,73 @@ pub fn list_accounts(app_handle: AppHandle) -> Vec<UserProfile> {
 }
 
 #[command]
-pub fn logout_user(app_handle: AppHandle, account_id: String) -> Result<(), String> {
-    session::remove_account(&app_handle, account_id)
+pub async fn logout_user(app_handle: AppHandle, account_id: String) -> Result<(), String> {
+    let account = session::load_accounts(&app_handle)
+        .into_iter()
+        .find(|a| a.id == account_id)
+        .ok_or_else(|| "Account not found".to_string())?;
+    let was_active = session::get_active_account(&app_handle)
+        .map(|a| a.id == account.id)
+        .unwrap_or(false);
+
+    // Background tasks always belong to the active account
+    if was_active {
+        crate::mail::idle::stop_idle_listener();
+        crate::mail::poll::stop_polling();
+        crate::mail::prefetch::clear_prefetch_queue().await;
+    }
+
+    // Downloads are kept per account, active or not; stop them before their sessions close
+    let app = app_handle.clone();
+    let email = account.email.clone();
+    tokio::task::spawn_blocking(move || crate::mail::download_manager::purge_account(&app, &email))
+        .await
+        .map_err(|e| e.to_string())??;
+
+    // Revoking the refresh token also kills any access token minted from it
+    let token = if account.refresh_token.is_empty() { &account.access_token } else { &account.refresh_token };
+    if let Err(e) = oauth::revoke_token(token).await {
+        log::warn!("Logout: {}", e);
+    }
+
+    crate::mail::imap_session::close_sessions_for(&account.email).await;
+
+    {
+        // Hold the sync lock so an in-flight sync can't repopulate the cache we're wiping
+        let _sync_guard = crate::mail::sync::SYNC_LOCK.lock().await;
+        let app = app_handle.clone();
+        let email = account.email.clone();
+        let purged = tokio::task::spawn_blocking(move || crate::mail::database::purge_account_data(&app, &email))
+            .await
+            .map_err(|e| e.to_string())??;
+        if purged {
+            clear_body_caches(&app_handle).await?;
+        }
+    }
+
+    // Previewed attachments are kept per account, active or not
+    let app = app_handle.clone();
+    let email = account.email.clone();
+    tokio::task::spawn_blocking(move || crate::mail::attachment_preview::purge_account(&app, &email))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    // Decrypted mail and unlocked keys don't outlive the session that opened them
+    crate::mail::openpgp::forget_account(&account.email);
+    crate::mail::pgp_keyring::lock_all();
+
+    session::remove_account(&app_handle, account_id)?;
+
+    // Hand the background tasks over to whichever account became active
+    if was_active {
+        if let Some(next) = session::get_active_account(&app_handle) {
+            claim_mail_cache(&app_handle, &next.email).await?;
+            crate::mail::idle::start_idle_listener(app_handle.clone(), next.clone());
+            crate::mail::poll::start_polling(app_handle.clone(), next);
+        }
+    }
+
+    Ok(())
 }
 
 #[command]
@@ -45,6 +153,7 @@ pub async fn bootstrap_accounts(app_handle: AppHandle) -> Result<crate::auth::bo
     let res = crate::auth::bootstrap::bootstrap_accounts(&app_handle).await;
     if res.user.is_some() {
         if let Some(account) = session::get_active_account(&app_handle) {
+            claim_mail_cache(&app_handle, &account.email).await?;
             crate::mail::idle::start_idle_listener(app_handle.clone(), account.clone());
             crate::mail::poll::start_polling(app_handle.clone(), account);
         }
@@ -89,7 +198,16 @@ pub async fn get_message_body(app_handle: AppHandle, uid: u32) -> Result<crate::
     let account = session::get_active_account(&app_handle)
         .ok_or_else(|| "No active account".to_string())?;
 
-    crate::mail::message_body::get_message_body(&app_handle, account, uid).await
+    crate::mail::message_body::get_message_body(&app_handle, account, uid, false).await
+}
+
+/// Same as `get_message_body` but with remote images and styles loaded, for this view only.
+#[command]
+pub async fn load_remote_content(app_handle: AppHandle, uid: u32) -> Result<crate::mail::message_body::MessageDetail, String> {
+    let account = session::get_active_account(&app_handle)
+        .ok_or_else(|| "No active account".to_string())?;
+
+    crate::mail::message_body::get_message_body(&app_handle, account, uid, true).await
 }
 
 #[command]
@@ -1,17 +1,40 @@
 use crate::auth::session::get_active_account;
 use crate::mail::database;
+use crate::mail::download_manager;
 use crate::mail::imap_session::{execute_with_session, SessionKind};
+use crate::mail::paging::{self, MessageFilter, MessagePage, SortDirection, SortKey};
+use crate::mail::saved_searches;
+use std::io::{Read, Write};
 use tauri::AppHandle;
 
+/// Runs `f` with `folder` selected, then selects INBOX again, which pooled sessions are
+/// expected to have selected.
+fn in_folder<T: Read + Write, R>(
+    session: &mut imap::Session<T>,
+    folder: &str,
+    f: impl FnOnce(&mut imap::Session<T>) -> Result<R, String>,
+) -> Result<R, String> {
+    if folder == "INBOX" {
+        return f(session);
+    }
+    session.select(folder).map_err(|e| format!("IMAP Select Error: {}", e))?;
+    let result = f(session);
+    session.select("INBOX").map_err(|e| format!("IMAP Select Error: {}", e))?;
+    result
+}
+
+/// Message actions take the folder from `MessageHeader::folder`; threads mix INBOX and Sent.
 #[tauri::command]
-pub async fn mark_as_read(app_handle: AppHandle, uid: u32) -> Result<(), String> {
+pub async fn mark_as_read(app_handle: AppHandle, uid: u32, folder: Option<String>) -> Result<(), String> {
     let account = get_active_account(&app_handle).ok_or("No active account")?;
+    let folder = folder.unwrap_or_else(|| "INBOX".to_string());
 
     // Idempotency Check: Don't hit IMAP if already updated locally
     let is_already_seen = tokio::task::spawn_blocking({
         let app = app_handle.clone();
+        let folder = folder.clone();
         move || {
-            database::is_message_seen(&app, "INBOX", uid)
+            database::is_message_seen(&app, &folder, uid)
         }
     }).await.map_err(|e| e.to_string())??;
 
@@ -20,23 +43,29 @@ pub async fn mark_as_read(app_handle: AppHandle, uid: u32) -> Result<(), String>
     }
 
     // Update IMAP (Silent Flag to avoid untagged responses)
+    let imap_folder = folder.clone();
     execute_with_session(&account, SessionKind::Primary, move |session| {
-        session.uid_store(uid.to_string(), "+FLAGS.SILENT (\\Seen)")
-            .map_err(|e| format!("IMAP Error marking read: {}", e))?;
-        Ok::<(), String>(())
+        in_folder(session, &imap_folder, |session| {
+            session.uid_store(uid.to_string(), "+FLAGS.SILENT (\\Seen)")
+                .map_err(|e| format!("IMAP Error marking read: {}", e))?;
+            Ok::<(), String>(())
+        })
     }).await?;
 
     // Update SQLite
     let _ = tokio::task::spawn_blocking(move || {
-        database::set_message_seen(&app_handle, "INBOX", uid, true)
+        database::set_message_seen(&app_handle, &folder, uid, true)?;
+        saved_searches::schedule_refresh(&app_handle);
+        Ok::<(), String>(())
     }).await;
 
     Ok(())
 }
 
 #[tauri::command]
-pub async fn toggle_star(app_handle: AppHandle, uid: u32, should_star: bool) -> Result<(), String> {
+pub async fn toggle_star(app_handle: AppHandle, uid: u32, should_star: bool, folder: Option<String>) -> Result<(), String> {
     let account = get_active_account(&app_handle).ok_or("No active account")?;
+    let folder = folder.unwrap_or_else(|| "INBOX".to_string());
 
     // Update IMAP
     let flag_cmd = if should_star {
@@ -45,42 +74,53 @@ pub async fn toggle_star(app_handle: AppHandle, uid: u32, should_star: bool) ->
         "-FLAGS.SILENT (\\Flagged)"
     };
 
+    let imap_folder = folder.clone();
     execute_with_session(&account, SessionKind::Primary, move |session| {
-        session.uid_store(uid.to_string(), flag_cmd)
-            .map_err(|e| format!("IMAP Error toggling star: {}", e))?;
-        Ok::<(), String>(())
+        in_folder(session, &imap_folder, |session| {
+            session.uid_store(uid.to_string(), flag_cmd)
+                .map_err(|e| format!("IMAP Error toggling star: {}", e))?;
+            Ok::<(), String>(())
+        })
     }).await?;
 
     // Update SQLite
     let _ = tokio::task::spawn_blocking(move || {
-        database::set_message_flagged(&app_handle, "INBOX", uid, should_star)
+        database::set_message_flagged(&app_handle, &folder, uid, should_star)?;
+        saved_searches::schedule_refresh(&app_handle);
+        Ok::<(), String>(())
     }).await;
 
     Ok(())
 }
 
 #[tauri::command]
-pub async fn delete_message(app_handle: AppHandle, uid: u32) -> Result<(), String> {
+pub async fn delete_message(app_handle: AppHandle, uid: u32, folder: Option<String>) -> Result<(), String> {
     let account = get_active_account(&app_handle).ok_or("No active account")?;
+    let folder = folder.unwrap_or_else(|| "INBOX".to_string());
 
     // IMAP Action: Try MOVE, fallback to Label + Deleted Flag
+    let imap_folder = folder.clone();
     execute_with_session(&account, SessionKind::Primary, move |session| {
-        // Attempt standard IMAP MOVE to Gmail trash
-        let move_result = session.uid_mv(uid.to_string(), "[Gmail]/Trash");
-        
-        if let Err(e) = move_result {
-            log::warn!("MOVE to Trash failed, attempting fallback: {}", e);
-            // Fallback: Gmail Labels extension + \Deleted
-            let _ = session.uid_store(uid.to_string(), "+X-GM-LABELS (\\Trash)");
-            let _ = session.uid_store(uid.to_string(), "+FLAGS.SILENT (\\Deleted)");
-        }
-        
-        Ok::<(), String>(())
+        in_folder(session, &imap_folder, |session| {
+            // Attempt standard IMAP MOVE to Gmail trash
+            let move_result = session.uid_mv(uid.to_string(), "[Gmail]/Trash");
+
+            if let Err(e) = move_result {
+                log::warn!("MOVE to Trash failed, attempting fallback: {}", e);
+                // Fallback: Gmail Labels extension + \Deleted
+                let _ = session.uid_store(uid.to_string(), "+X-GM-LABELS (\\Trash)");
+                let _ = session.uid_store(uid.to_string(), "+FLAGS.SILENT (\\Deleted)");
+            }
+
+            Ok::<(), String>(())
+        })
     }).await?;
 
     // Delete locally
     let _ = tokio::task::spawn_blocking(move || {
-        database::delete_message_local(&app_handle, "INBOX", uid)
+        database::delete_message_local(&app_handle, &folder, uid)?;
+        saved_searches::schedule_refresh(&app_handle);
+        Ok::<(), String>(())
     }).await;
 
     Ok(())
@@ -119,21 +159,265 @@ pub async fn get_messages_page(
     Ok(pages)
 }
 
+/// Sorted, filtered message list. Pass back `nextCursor` from the previous page as `cursor`
+/// with the same sort and direction to continue.
+#[tauri::command]
+pub async fn get_message_list(
+    app_handle: AppHandle,
+    folder: Option<String>,
+    sort: Option<SortKey>,
+    direction: Option<SortDirection>,
+    filter: Option<MessageFilter>,
+    cursor: Option<String>,
+    limit: u32,
+) -> Result<MessagePage, String> {
+    let safe_limit = limit.clamp(1, 100);
+    let folder = folder.unwrap_or_else(|| "INBOX".to_string());
+
+    tokio::task::spawn_blocking(move || {
+        paging::load_page(
+            &app_handle,
+            &folder,
+            sort.unwrap_or_default(),
+            direction.unwrap_or_default(),
+            &filter.unwrap_or_default(),
+            cursor.as_deref(),
+            safe_limit,
+        )
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Conversation list for the inbox. Pass the `latestDate` and `threadId` of the last
+/// summary received to get the next page.
+#[tauri::command]
+pub async fn get_threads_page(
+    app_handle: AppHandle,
+    before_date: Option<i64>,
+    before_thread_id: Option<String>,
+    limit: u32,
+) -> Result<Vec<crate::mail::threading::ThreadSummary>, String> {
+    let safe_limit = limit.min(100);
+    let before = before_date.map(|date| (date, before_thread_id.unwrap_or_default()));
+
+    tokio::task::spawn_blocking(move || {
+        crate::mail::threading::load_threads_page(&app_handle, before, safe_limit)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn get_thread(app_handle: AppHandle, thread_id: String) -> Result<crate::mail::threading::Thread, String> {
+    tokio::task::spawn_blocking(move || {
+        crate::mail::threading::load_thread(&app_handle, &thread_id)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn search_messages(
+    app_handle: AppHandle,
+    query: String,
+    limit: Option<u32>,
+    include_server: Option<bool>,
+) -> Result<Vec<crate::mail::search::SearchHit>, String> {
+    let safe_limit = limit.unwrap_or(50).min(200);
+
+    if include_server.unwrap_or(false) {
+        let account = get_active_account(&app_handle).ok_or("No active account")?;
+        return crate::mail::search::search_with_server(&app_handle, &account, &query, safe_limit).await;
+    }
+
+    tokio::task::spawn_blocking(move || {
+        crate::mail::search::search_messages(&app_handle, &query, safe_limit)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Parses a search query without running it, so the UI can point at syntax errors as the user types.
+#[tauri::command]
+pub fn parse_search_query(query: String) -> Result<crate::mail::query::Query, crate::mail::query::QueryError> {
+    crate::mail::query::parse(&query)
+}
+
+/// Recipient suggestions for the compose window, best match first.
+#[tauri::command]
+pub async fn autocomplete_recipients(
+    app_handle: AppHandle,
+    query: String,
+    limit: Option<u32>,
+) -> Result<Vec<crate::mail::contacts::ContactSuggestion>, String> {
+    let safe_limit = limit.unwrap_or(8).min(50);
+
+    tokio::task::spawn_blocking(move || {
+        crate::mail::contacts::autocomplete(&app_handle, &query, safe_limit)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Saved searches with their current total and unread counts, in sidebar order.
+#[tauri::command]
+pub async fn get_smart_folders(app_handle: AppHandle) -> Result<Vec<saved_searches::SmartFolder>, String> {
+    tokio::task::spawn_blocking(move || saved_searches::list_smart_folders(&app_handle))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn create_saved_search(
+    app_handle: AppHandle,
+    name: String,
+    icon: Option<String>,
+    query: String,
+) -> Result<saved_searches::SmartFolder, String> {
+    tokio::task::spawn_blocking(move || saved_searches::create_saved_search(&app_handle, &name, icon, &query))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn update_saved_search(
+    app_handle: AppHandle,
+    id: i64,
+    name: String,
+    icon: Option<String>,
+    query: String,
+) -> Result<saved_searches::SmartFolder, String> {
+    tokio::task::spawn_blocking(move || saved_searches::update_saved_search(&app_handle, id, &name, icon, &query))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn delete_saved_search(app_handle: AppHandle, id: i64) -> Result<(), String> {
+    tokio::task::spawn_blocking(move || saved_searches::delete_saved_search(&app_handle, id))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn reorder_saved_searches(app_handle: AppHandle, ids: Vec<i64>) -> Result<(), String> {
+    tokio::task::spawn_blocking(move || saved_searches::reorder_saved_searches(&app_handle, &ids))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Messages in a smart folder. Pass the `date`, `folder` and `uid` of the last message
+/// received to get the next page.
+#[tauri::command]
+pub async fn get_smart_folder_page(
+    app_handle: AppHandle,
+    id: i64,
+    before_date: Option<i64>,
+    before_folder: Option<String>,
+    before_uid: Option<u32>,
+    limit: u32,
+) -> Result<Vec<crate::mail::message_list::MessageHeader>, String> {
+    let safe_limit = limit.min(100);
+    let before = match (before_date, before_folder, before_uid) {
+        (Some(date), Some(folder), Some(uid)) => Some((date, folder, uid)),
+        (None, None, None) => None,
+        _ => return Err("Smart folder cursor needs beforeDate, beforeFolder and beforeUid".to_string()),
+    };
+
+    tokio::task::spawn_blocking(move || {
+        saved_searches::load_saved_search_page(&app_handle, id, before, safe_limit)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Queues an attachment download and returns its entry in the download history. Without
+/// `save_path`, it goes to the download folder under a name that doesn't clash.
 #[tauri::command]
 pub async fn download_attachment(
     app_handle: tauri::AppHandle,
     uid: u32,
     part_id: String,
-    save_path: String,
-) -> Result<String, String> {
+    file_name: String,
+    save_path: Option<String>,
+) -> Result<download_manager::DownloadRecord, String> {
     let account = get_active_account(&app_handle).ok_or("No active account")?;
-    
-    let bytes = crate::mail::message_body::fetch_attachment_part(&account, uid, &part_id).await?;
-    
-    std::fs::write(&save_path, bytes)
-        .map_err(|e| format!("Failed to write file to {}: {}", save_path, e))?;
-    
-    Ok(save_path)
+
+    tokio::task::spawn_blocking(move || {
+        download_manager::enqueue(&app_handle, &account, uid, part_id, file_name, save_path)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Queued, running and finished downloads, newest first.
+#[tauri::command]
+pub async fn list_downloads(app_handle: AppHandle) -> Result<Vec<download_manager::DownloadRecord>, String> {
+    tokio::task::spawn_blocking(move || download_manager::list(&app_handle))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Pauses a download, or with `discard` stops it and deletes what was downloaded.
+#[tauri::command]
+pub async fn cancel_download(app_handle: AppHandle, download_id: String, discard: Option<bool>) -> Result<(), String> {
+    tokio::task::spawn_blocking(move || {
+        if discard.unwrap_or(false) {
+            download_manager::cancel(&app_handle, &download_id)
+        } else {
+            download_manager::pause(&app_handle, &download_id)
+        }
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Queues a paused, failed or cancelled download again, continuing where it stopped if possible.
+#[tauri::command]
+pub async fn retry_download(app_handle: AppHandle, download_id: String) -> Result<download_manager::DownloadRecord, String> {
+    tokio::task::spawn_blocking(move || download_manager::retry(&app_handle, &download_id))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn open_download(app_handle: AppHandle, download_id: String) -> Result<(), String> {
+    tokio::task::spawn_blocking(move || download_manager::open(&app_handle, &download_id))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Removes finished downloads from the history; the saved files are left alone.
+#[tauri::command]
+pub async fn clear_download_history(app_handle: AppHandle) -> Result<(), String> {
+    tokio::task::spawn_blocking(move || download_manager::clear_history(&app_handle))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Saves several attachments of a message at once, to a directory or a ZIP archive.
+#[tauri::command]
+pub async fn download_all_attachments(
+    app_handle: AppHandle,
+    uid: u32,
+    attachments: Vec<crate::mail::message_body::MessageAttachment>,
+    destination: String,
+    as_zip: bool,
+) -> Result<crate::mail::attachment_export::AttachmentExport, String> {
+    let account = get_active_account(&app_handle).ok_or("No active account")?;
+    crate::mail::attachment_export::download_all(&app_handle, &account, uid, attachments, destination, as_zip).await
+}
+
+/// Caches an attachment for in-app viewing and returns where the webview can load it from.
+#[tauri::command]
+pub async fn preview_attachment(
+    app_handle: AppHandle,
+    uid: u32,
+    part_id: String,
+) -> Result<crate::mail::attachment_preview::AttachmentPreview, String> {
+    let account = get_active_account(&app_handle).ok_or("No active account")?;
+    crate::mail::attachment_preview::preview_attachment(&app_handle, &account, uid, &part_id).await
 }
 
 #[tauri::command]
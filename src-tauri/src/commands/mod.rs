@@ -1,2 +1,4 @@
 pub mod auth_commands;
 pub mod message_commands;
+pub mod settings_commands;
+pub mod pgp_commands;
@@ -0,0 +1,108 @@
+use crate::auth::session::get_active_account;
+use crate::mail::openpgp::{self, ProtectedMessage};
+use crate::mail::pgp_keyring::{self, KeySummary};
+use serde::Serialize;
+use tauri::AppHandle;
+
+/// Which key, if any, mail to an address would be encrypted to.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecipientKey {
+    pub address: String,
+    pub fingerprint: Option<String>,
+    pub source: Option<String>,
+}
+
+#[tauri::command]
+pub async fn list_pgp_keys(app_handle: AppHandle) -> Result<Vec<KeySummary>, String> {
+    tokio::task::spawn_blocking(move || pgp_keyring::list(&app_handle))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Imports pasted keys, armored. Returns the fingerprints stored.
+#[tauri::command]
+pub async fn import_pgp_keys(app_handle: AppHandle, armored: String) -> Result<Vec<String>, String> {
+    tokio::task::spawn_blocking(move || pgp_keyring::import(&app_handle, armored.as_bytes(), "imported"))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Imports the keys in a file, armored or binary.
+#[tauri::command]
+pub async fn import_pgp_key_file(app_handle: AppHandle, path: String) -> Result<Vec<String>, String> {
+    tokio::task::spawn_blocking(move || {
+        let data = std::fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        pgp_keyring::import(&app_handle, &data, "imported")
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Writes a key, armored, to `path`.
+#[tauri::command]
+pub async fn export_pgp_key(app_handle: AppHandle, fingerprint: String, path: String, include_secret: bool) -> Result<(), String> {
+    tokio::task::spawn_blocking(move || {
+        let armored = pgp_keyring::export(&app_handle, &fingerprint, include_secret)?;
+        std::fs::write(&path, armored).map_err(|e| format!("Failed to write file to {}: {}", path, e))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn delete_pgp_key(app_handle: AppHandle, fingerprint: String) -> Result<(), String> {
+    tokio::task::spawn_blocking(move || pgp_keyring::delete(&app_handle, &fingerprint))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Unlocks a secret key for this session. The passphrase is kept in memory only.
+#[tauri::command]
+pub async fn unlock_pgp_key(app_handle: AppHandle, fingerprint: String, passphrase: String) -> Result<(), String> {
+    tokio::task::spawn_blocking(move || pgp_keyring::unlock(&app_handle, &fingerprint, passphrase))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn pgp_recipient_keys(app_handle: AppHandle, addresses: Vec<String>) -> Result<Vec<RecipientKey>, String> {
+    tokio::task::spawn_blocking(move || {
+        let entries = pgp_keyring::all(&app_handle)?;
+        addresses
+            .into_iter()
+            .map(|address| {
+                let entry = pgp_keyring::key_for_address(&app_handle, &entries, &address)?.map(|index| &entries[index]);
+                Ok(RecipientKey {
+                    fingerprint: entry.map(|e| e.fingerprint.clone()),
+                    source: entry.map(|e| e.source.clone()),
+                    address,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Signs and/or encrypts the content of an outgoing message from the active account.
+///
+/// Not delivered to users: OrbitMail can't send mail yet (Send in the composer only closes
+/// it), so nothing calls this and no outgoing mail is signed or encrypted. A send path has
+/// to call it on the finished content entity before handing the message to SMTP.
+#[tauri::command]
+pub async fn protect_outgoing_message(
+    app_handle: AppHandle,
+    entity: String,
+    recipients: Vec<String>,
+    sign: bool,
+    encrypt: bool,
+) -> Result<ProtectedMessage, String> {
+    let account = get_active_account(&app_handle).ok_or("No active account")?;
+
+    tokio::task::spawn_blocking(move || {
+        openpgp::protect_outgoing(&app_handle, &account.email, &recipients, &entity, sign, encrypt)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
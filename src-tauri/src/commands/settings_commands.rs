@@ -0,0 +1,99 @@
+use crate::auth::session;
+use crate::mail::download_manager::{self, DownloadSettings};
+use crate::mail::imap_session;
+use crate::mail::remote_content::{self, RemoteContentSettings};
+use crate::mail::tls::{self, ImapServerConfig};
+use crate::net::proxy::{self, ProxySettings};
+use tauri::{AppHandle, command};
+
+#[command]
+pub fn get_imap_settings(app_handle: AppHandle) -> Result<ImapServerConfig, String> {
+    let account = session::get_active_account(&app_handle)
+        .ok_or_else(|| "No active account".to_string())?;
+    Ok(account.imap)
+}
+
+#[command]
+pub async fn update_imap_settings(app_handle: AppHandle, settings: ImapServerConfig) -> Result<(), String> {
+    let mut account = session::get_active_account(&app_handle)
+        .ok_or_else(|| "No active account".to_string())?;
+
+    account.imap = settings;
+    session::save_account(&app_handle, account.clone(), true)?;
+
+    // Pooled sessions were negotiated under the old settings
+    imap_session::close_sessions_for(&account.email).await;
+    Ok(())
+}
+
+/// Connects once without verification and returns the server's certificate fingerprint,
+/// so the user can compare it out-of-band before trusting it.
+#[command]
+pub async fn probe_server_certificate(app_handle: AppHandle) -> Result<String, String> {
+    let account = session::get_active_account(&app_handle)
+        .ok_or_else(|| "No active account".to_string())?;
+
+    tokio::task::spawn_blocking(move || tls::probe_fingerprint(&account.imap))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Pins the given certificate fingerprint for the active account after an explicit user decision.
+#[command]
+pub async fn trust_server_certificate(app_handle: AppHandle, fingerprint: String) -> Result<(), String> {
+    let mut account = session::get_active_account(&app_handle)
+        .ok_or_else(|| "No active account".to_string())?;
+
+    account.imap.pinned_sha256 = Some(tls::parse_pinned_fingerprint(&fingerprint)?);
+    session::save_account(&app_handle, account.clone(), true)?;
+
+    imap_session::close_sessions_for(&account.email).await;
+    Ok(())
+}
+
+#[command]
+pub fn get_proxy_settings() -> ProxySettings {
+    proxy::current_settings()
+}
+
+#[command]
+pub async fn update_proxy_settings(app_handle: AppHandle, settings: ProxySettings) -> Result<(), String> {
+    proxy::save_settings(&app_handle, settings)?;
+
+    // Drop pooled IMAP connections so the next operation reconnects through the new route
+    if let Some(account) = session::get_active_account(&app_handle) {
+        imap_session::close_sessions_for(&account.email).await;
+    }
+    Ok(())
+}
+
+#[command]
+pub fn get_remote_content_settings() -> RemoteContentSettings {
+    remote_content::current_settings()
+}
+
+#[command]
+pub fn update_remote_content_settings(app_handle: AppHandle, settings: RemoteContentSettings) -> Result<(), String> {
+    remote_content::save_settings(&app_handle, settings)
+}
+
+/// Always load remote content in messages from `sender`.
+#[command]
+pub fn allow_remote_content_from(app_handle: AppHandle, sender: String) -> Result<RemoteContentSettings, String> {
+    remote_content::allow_sender(&app_handle, &sender)
+}
+
+#[command]
+pub fn disallow_remote_content_from(app_handle: AppHandle, sender: String) -> Result<RemoteContentSettings, String> {
+    remote_content::disallow_sender(&app_handle, &sender)
+}
+
+#[command]
+pub fn get_download_settings() -> DownloadSettings {
+    download_manager::current_settings()
+}
+
+#[command]
+pub fn update_download_settings(app_handle: AppHandle, settings: DownloadSettings) -> Result<(), String> {
+    download_manager::save_settings(&app_handle, settings)
+}
@@ -1,11 +1,16 @@
 mod auth;
 mod commands;
 mod mail;
+mod net;
+mod settings_store;
 
 use crate::commands::auth_commands::*;
 use crate::commands::message_commands::*;
-use tauri::Manager;
+use crate::commands::settings_commands::*;
+use crate::commands::pgp_commands::*;
 
+#[cfg(target_os = "windows")]
+use tauri::Manager;
 #[cfg(target_os = "windows")]
 use window_vibrancy::apply_mica;
 
@@ -50,17 +55,23 @@ pub fn run() {
       app.handle().plugin(tauri_plugin_dialog::init())?;
       app.handle().plugin(tauri_plugin_notification::init())?;
 
-      if let Ok(cache_dir) = app.handle().path().app_cache_dir() {
-        let inline_dir = cache_dir.join("orbitmail_inline");
-        let _ = std::fs::remove_dir_all(&inline_dir);
-      }
-
+      crate::net::proxy::init(app.handle());
+      crate::mail::remote_content::init(app.handle());
       crate::mail::database::init_db(app.handle())?;
+      crate::mail::download_manager::init(app.handle());
+      crate::mail::indexer::spawn_body_index_backfill(app.handle().clone());
 
       Ok(())
     })
+    .register_asynchronous_uri_scheme_protocol(crate::mail::message_protocol::SCHEME, |ctx, request, responder| {
+      let app_handle = ctx.app_handle().clone();
+      tauri::async_runtime::spawn(async move {
+        responder.respond(crate::mail::message_protocol::handle(app_handle, request).await);
+      });
+    })
     .invoke_handler(tauri::generate_handler![
       login_google,
+      cancel_login,
       get_current_user,
       list_accounts,
       logout_user,
@@ -70,13 +81,53 @@ pub fn run() {
       get_cached_messages,
       sync_inbox,
       get_message_body,
+      load_remote_content,
       get_messages_page,
+      get_message_list,
+      get_threads_page,
+      get_thread,
+      search_messages,
+      parse_search_query,
+      autocomplete_recipients,
+      get_smart_folders,
+      create_saved_search,
+      update_saved_search,
+      delete_saved_search,
+      reorder_saved_searches,
+      get_smart_folder_page,
       mark_as_read,
       toggle_star,
       delete_message,
       download_attachment,
+      download_all_attachments,
+      list_downloads,
+      cancel_download,
+      retry_download,
+      open_download,
+      clear_download_history,
+      preview_attachment,
       show_in_folder,
-      show_main_window
+      show_main_window,
+      get_imap_settings,
+      update_imap_settings,
+      probe_server_certificate,
+      trust_server_certificate,
+      get_proxy_settings,
+      update_proxy_settings,
+      get_remote_content_settings,
+      update_remote_content_settings,
+      allow_remote_content_from,
+      disallow_remote_content_from,
+      get_download_settings,
+      update_download_settings,
+      list_pgp_keys,
+      import_pgp_keys,
+      import_pgp_key_file,
+      export_pgp_key,
+      delete_pgp_key,
+      unlock_pgp_key,
+      pgp_recipient_keys,
+      protect_outgoing_message
     ])
     .run(tauri::generate_context!())
     .expect("error while running tauri application");
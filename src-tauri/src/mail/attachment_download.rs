@@ -0,0 +1,551 @@
+use crate::auth::account::Account;
+use crate::mail::message_body::{self, CONCURRENT_FETCH_LIMIT};
+use crate::mail::{download_manager, imap_session, openpgp, snippet};
+use dashmap::DashMap;
+use imap_proto::types::{BodyStructure, SectionPath};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter, Manager};
+
+/// Emitted with a `DownloadProgress` after every chunk and when a download stops.
+pub const DOWNLOAD_PROGRESS_EVENT: &str = "download:progress";
+
+/// Error a paused or discarded download ends with, so callers can tell it from a failure.
+pub const DOWNLOAD_CANCELLED: &str = "Download cancelled";
+
+/// Encoded bytes requested per FETCH. Memory use stays at a few times this, whatever the
+/// attachment size.
+const CHUNK_BYTES: u32 = 512 * 1024;
+
+const RUNNING: u8 = 0;
+const PAUSE: u8 = 1;
+const DISCARD: u8 = 2;
+
+/// Control flags of the downloads in progress, by download id.
+static ACTIVE: Lazy<DashMap<String, Arc<AtomicU8>>> = Lazy::new(DashMap::new);
+
+/// Everything needed to pick an interrupted download up where it stopped. Saved after
+/// each chunk, once that chunk's bytes are in the partial file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DownloadState {
+    id: String,
+    account: String,
+    uid: u32,
+    /// UIDVALIDITY of the account's INBOX when the download started.
+    uid_validity: Option<u32>,
+    part_id: String,
+    destination: String,
+    encoding: String,
+    /// Encoded size of the part, from the BODYSTRUCTURE.
+    total: u64,
+    /// Encoded bytes fetched and consumed so far.
+    fetched: u64,
+    /// Decoded bytes in the partial file.
+    written: u64,
+    /// Fetched bytes that can't be decoded until the next chunk arrives.
+    pending: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DownloadStatus {
+    /// Waiting for a slot in the download manager.
+    Queued,
+    Downloading,
+    Paused,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DownloadProgress {
+    pub id: String,
+    pub uid: u32,
+    pub part_id: String,
+    pub status: DownloadStatus,
+    /// Encoded bytes fetched; with `total`, this gives the fraction done.
+    pub received: u64,
+    pub total: u64,
+    pub path: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Ids end up in file names, so they're restricted to a safe alphabet.
+fn valid_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= 64 && id.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+fn state_dir(app_handle: &AppHandle) -> Result<PathBuf, String> {
+    let dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to resolve App Data Dir: {}", e))?
+        .join("downloads");
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    Ok(dir)
+}
+
+fn state_path(app_handle: &AppHandle, id: &str) -> Result<PathBuf, String> {
+    Ok(state_dir(app_handle)?.join(format!("{}.json", id)))
+}
+
+/// The file being written, next to the destination so the final rename stays on one file system.
+pub fn partial_path(destination: &str) -> PathBuf {
+    PathBuf::from(format!("{}.part", destination))
+}
+
+/// Whether an interrupted download left enough behind for `resume`.
+pub fn has_state(app_handle: &AppHandle, id: &str) -> bool {
+    valid_id(id) && load_state(app_handle, id).is_some()
+}
+
+fn load_state(app_handle: &AppHandle, id: &str) -> Option<DownloadState> {
+    let content = fs::read_to_string(state_path(app_handle, id).ok()?).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/// Written to a temporary file and renamed, so a crash never leaves half a state behind.
+fn save_state(app_handle: &AppHandle, state: &DownloadState) -> Result<(), String> {
+    let path = state_path(app_handle, &state.id)?;
+    let temp = path.with_extension("json.tmp");
+    let json = serde_json::to_vec(state).map_err(|e| e.to_string())?;
+    fs::write(&temp, json).and_then(|_| fs::rename(&temp, &path)).map_err(|e| e.to_string())
+}
+
+fn remove_files(app_handle: &AppHandle, id: &str, destination: Option<&str>) {
+    if let Ok(path) = state_path(app_handle, id) {
+        let _ = fs::remove_file(path);
+    }
+    if let Some(destination) = destination {
+        let _ = fs::remove_file(partial_path(destination));
+    }
+}
+
+fn emit(app_handle: &AppHandle, state: &DownloadState, status: DownloadStatus, error: Option<String>) {
+    let progress = DownloadProgress {
+        id: state.id.clone(),
+        uid: state.uid,
+        part_id: state.part_id.clone(),
+        status,
+        received: state.fetched,
+        total: state.total,
+        path: (status == DownloadStatus::Completed).then(|| state.destination.clone()),
+        error,
+    };
+    if let Err(e) = app_handle.emit(DOWNLOAD_PROGRESS_EVENT, &progress) {
+        log::warn!("Failed to emit {}: {}", DOWNLOAD_PROGRESS_EVENT, e);
+    }
+    download_manager::record_progress(app_handle, &progress);
+}
+
+/// The BODYSTRUCTURE node at `path`. A single-part message's body is section 1.
+fn find_part<'a>(bs: &'a BodyStructure<'a>, path: &[u32]) -> Option<&'a BodyStructure<'a>> {
+    match (bs, path) {
+        (BodyStructure::Multipart { bodies, .. }, [first, rest @ ..]) => {
+            find_part(bodies.get(first.checked_sub(1)? as usize)?, rest)
+        }
+        (BodyStructure::Multipart { .. }, []) => None,
+        (_, []) | (_, [1]) => Some(bs),
+        _ => None,
+    }
+}
+
+fn section(part_id: &str) -> Result<Vec<u32>, String> {
+    part_id
+        .split('.')
+        .map(|n| n.parse::<u32>().ok().filter(|n| *n > 0))
+        .collect::<Option<Vec<u32>>>()
+        .ok_or_else(|| format!("Invalid part id: {}", part_id))
+}
+
+/// Encoded size and Content-Transfer-Encoding of a part.
+pub(crate) async fn part_outline(account: &Account, uid: u32, part_id: &str) -> Result<(u64, String), String> {
+    let path = section(part_id)?;
+    imap_session::execute_with_session(account, imap_session::SessionKind::Primary, move |session| {
+        let fetch = session.uid_fetch(uid.to_string(), "(BODYSTRUCTURE)")
+            .map_err(|e| format!("IMAP fetch_bs error: {}", e))?;
+        let bs = fetch.iter().next().and_then(|msg| msg.bodystructure()).ok_or("Message not found")?;
+        match find_part(bs, &path) {
+            Some(BodyStructure::Basic { other, .. } | BodyStructure::Text { other, .. } | BodyStructure::Message { other, .. }) => {
+                Ok((u64::from(other.octets), snippet::encoding_name(&other.transfer_encoding)))
+            }
+            _ => Err("Could not retrieve attachment part.".to_string()),
+        }
+    }).await
+}
+
+/// UIDVALIDITY of `account`'s INBOX, asked of the server: the local cache only ever holds
+/// the active account's mailbox, and a download may belong to another one.
+async fn inbox_validity(account: &Account) -> Result<Option<u32>, String> {
+    imap_session::execute_with_session(account, imap_session::SessionKind::Primary, |session| {
+        let mailbox = session.select("INBOX").map_err(|e| format!("IMAP Select Error: {}", e))?;
+        Ok(mailbox.uid_validity)
+    }).await
+}
+
+/// Fetches `len` encoded bytes of a part from `offset`. Takes a fetch permit per chunk, so
+/// a long download shares the connection budget with body fetches instead of hogging it.
+async fn fetch_range(account: &Account, uid: u32, part_id: &str, offset: u64, len: u32) -> Result<Vec<u8>, String> {
+    let path = section(part_id)?;
+    let query = format!("(BODY.PEEK[{}]<{}.{}>)", part_id, offset, len);
+
+    let _permit = CONCURRENT_FETCH_LIMIT.clone().acquire_owned().await.map_err(|e| e.to_string())?;
+    imap_session::execute_with_session(account, imap_session::SessionKind::Primary, move |session| {
+        let fetch = session.uid_fetch(uid.to_string(), &query)
+            .map_err(|e| format!("IMAP fetch attachment error: {}", e))?;
+        let msg = fetch.iter().next().ok_or("Could not retrieve attachment part.")?;
+        Ok(msg.section(&SectionPath::Part(path.clone(), None)).map(<[u8]>::to_vec).unwrap_or_default())
+    }).await
+}
+
+pub(crate) fn is_base64(encoding: &str) -> bool {
+    encoding.eq_ignore_ascii_case("base64")
+}
+
+fn is_quoted_printable(encoding: &str) -> bool {
+    encoding.eq_ignore_ascii_case("quoted-printable")
+}
+
+/// Appends a fetched chunk to `pending` and takes off the front what can be decoded on its
+/// own: whole 4-character quanta of base64, whole lines of quoted-printable. Whatever is
+/// left waits for the next chunk; `last` takes everything.
+fn take_decodable(encoding: &str, pending: &mut Vec<u8>, chunk: &[u8], last: bool) -> Vec<u8> {
+    if is_base64(encoding) {
+        pending.extend(chunk.iter().filter(|b| !b.is_ascii_whitespace()));
+    } else {
+        pending.extend_from_slice(chunk);
+    }
+
+    let split = if last {
+        pending.len()
+    } else if is_base64(encoding) {
+        pending.len() - pending.len() % 4
+    } else if is_quoted_printable(encoding) {
+        pending.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1)
+    } else {
+        pending.len()
+    };
+    let rest = pending.split_off(split);
+    std::mem::replace(pending, rest)
+}
+
+/// Decodes a self-contained run of a part's body, the same way `snippet` decodes previews.
+fn decode(encoding: &str, data: &[u8]) -> Result<Vec<u8>, String> {
+    if data.is_empty() || !(is_base64(encoding) || is_quoted_printable(encoding)) {
+        return Ok(data.to_vec());
+    }
+    let mut raw = format!("Content-Transfer-Encoding: {}\r\n\r\n", encoding).into_bytes();
+    raw.extend_from_slice(data);
+    mailparse::parse_mail(&raw)
+        .and_then(|parsed| parsed.get_body_raw())
+        .map_err(|e| format!("Decoding error: {}", e))
+}
+
+/// Removes a download from `ACTIVE` however it ends.
+struct ActiveGuard(String);
+
+impl Drop for ActiveGuard {
+    fn drop(&mut self) {
+        ACTIVE.remove(&self.0);
+    }
+}
+
+/// Fetches the rest of the part chunk by chunk, decoding into the partial file, and
+/// moves it to the destination once complete.
+async fn run(app_handle: &AppHandle, account: &Account, mut state: DownloadState) -> Result<String, String> {
+    let control = Arc::new(AtomicU8::new(RUNNING));
+    match ACTIVE.entry(state.id.clone()) {
+        dashmap::mapref::entry::Entry::Occupied(_) => return Err("Download already running".to_string()),
+        dashmap::mapref::entry::Entry::Vacant(slot) => {
+            slot.insert(control.clone());
+        }
+    }
+    let _guard = ActiveGuard(state.id.clone());
+
+    let partial = partial_path(&state.destination);
+    let result = async {
+        let mut file = File::options()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&partial)
+            .map_err(|e| format!("Failed to write file to {}: {}", partial.display(), e))?;
+        // Bytes past the last saved state belong to a chunk that will be fetched again
+        file.set_len(state.written).map_err(|e| e.to_string())?;
+        file.seek(SeekFrom::End(0)).map_err(|e| e.to_string())?;
+
+        loop {
+            match control.load(Ordering::SeqCst) {
+                PAUSE | DISCARD => return Err(DOWNLOAD_CANCELLED.to_string()),
+                _ => {}
+            }
+
+            let remaining = state.total.saturating_sub(state.fetched);
+            let chunk = if remaining == 0 {
+                Vec::new()
+            } else {
+                let len = remaining.min(u64::from(CHUNK_BYTES)) as u32;
+                fetch_range(account, state.uid, &state.part_id, state.fetched, len).await?
+            };
+            // An empty read means the server has no more, whatever the BODYSTRUCTURE said
+            let last = chunk.len() as u64 >= remaining || chunk.is_empty();
+            state.fetched += chunk.len() as u64;
+
+            let decodable = take_decodable(&state.encoding, &mut state.pending, &chunk, last);
+            let bytes = decode(&state.encoding, &decodable)?;
+            file.write_all(&bytes)
+                .and_then(|_| file.flush())
+                .map_err(|e| format!("Failed to write file to {}: {}", partial.display(), e))?;
+            state.written += bytes.len() as u64;
+
+            if last {
+                state.total = state.fetched;
+                break;
+            }
+            save_state(app_handle, &state)?;
+            emit(app_handle, &state, DownloadStatus::Downloading, None);
+        }
+
+        file.sync_all().map_err(|e| e.to_string())?;
+        drop(file);
+        fs::rename(&partial, &state.destination)
+            .map_err(|e| format!("Failed to write file to {}: {}", state.destination, e))?;
+        Ok::<_, String>(state.destination.clone())
+    }
+    .await;
+
+    match &result {
+        Ok(_) => {
+            remove_files(app_handle, &state.id, None);
+            emit(app_handle, &state, DownloadStatus::Completed, None);
+        }
+        Err(e) if control.load(Ordering::SeqCst) == DISCARD => {
+            remove_files(app_handle, &state.id, Some(&state.destination));
+            emit(app_handle, &state, DownloadStatus::Cancelled, Some(e.clone()));
+        }
+        Err(e) => {
+            // Kept for `resume`; a failed save just means restarting from the last good one
+            let _ = save_state(app_handle, &state);
+            let status = if e == DOWNLOAD_CANCELLED { DownloadStatus::Paused } else { DownloadStatus::Failed };
+            emit(app_handle, &state, status, Some(e.clone()));
+        }
+    }
+    result
+}
+
+/// Streams attachment `part_id` of `uid` to `destination`, reporting progress under `id`.
+pub async fn start(app_handle: &AppHandle, account: &Account, id: String, uid: u32, part_id: String, destination: String) -> Result<String, String> {
+    if !valid_id(&id) {
+        return Err(format!("Invalid download id: {}", id));
+    }
+    if ACTIVE.contains_key(&id) {
+        return Err("Download already running".to_string());
+    }
+
+    // Attachments of encrypted messages are already decrypted in memory; there is nothing
+    // to stream or resume
+    if openpgp::is_decrypted_part(&part_id) {
+        let bytes = message_body::fetch_attachment_part(account, uid, &part_id).await?;
+        let partial = partial_path(&destination);
+        fs::write(&partial, &bytes)
+            .and_then(|_| fs::rename(&partial, &destination))
+            .map_err(|e| format!("Failed to write file to {}: {}", destination, e))?;
+
+        let size = bytes.len() as u64;
+        let state = DownloadState {
+            id,
+            account: account.email.clone(),
+            uid,
+            uid_validity: None,
+            part_id,
+            destination,
+            encoding: String::new(),
+            total: size,
+            fetched: size,
+            written: size,
+            pending: Vec::new(),
+        };
+        emit(app_handle, &state, DownloadStatus::Completed, None);
+        return Ok(state.destination);
+    }
+
+    let (total, encoding) = part_outline(account, uid, &part_id).await?;
+    let state = DownloadState {
+        id,
+        account: account.email.clone(),
+        uid,
+        uid_validity: inbox_validity(account).await?,
+        part_id,
+        destination,
+        encoding,
+        total,
+        fetched: 0,
+        written: 0,
+        pending: Vec::new(),
+    };
+    save_state(app_handle, &state)?;
+    run(app_handle, account, state).await
+}
+
+/// Continues a paused or failed download from its last completed chunk.
+pub async fn resume(app_handle: &AppHandle, account: &Account, id: &str) -> Result<String, String> {
+    if !valid_id(id) {
+        return Err(format!("Invalid download id: {}", id));
+    }
+    let mut state = load_state(app_handle, id).ok_or("Nothing to resume for this download")?;
+    if !state.account.eq_ignore_ascii_case(&account.email) {
+        return Err("This download belongs to another account".to_string());
+    }
+    // A new UIDVALIDITY means the UID may now name a different message
+    if state.uid_validity != inbox_validity(account).await? {
+        remove_files(app_handle, id, Some(&state.destination));
+        return Err("The mailbox changed since this download started; download it again".to_string());
+    }
+
+    // The partial file may have been removed or cut short; start over rather than corrupt it
+    let on_disk = fs::metadata(partial_path(&state.destination)).map(|m| m.len()).unwrap_or(0);
+    if on_disk < state.written {
+        state.fetched = 0;
+        state.written = 0;
+        state.pending.clear();
+    }
+    run(app_handle, account, state).await
+}
+
+/// Deletes the saved state and partial file of every download of `email` that isn't running.
+/// Running ones clean up after themselves once cancelled with `discard`.
+pub fn purge_account(app_handle: &AppHandle, email: &str) {
+    let Ok(entries) = state_dir(app_handle).and_then(|dir| fs::read_dir(dir).map_err(|e| e.to_string())) else { return };
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+        let Some(state) = path.file_stem().and_then(|id| load_state(app_handle, &id.to_string_lossy())) else { continue };
+        if state.account.eq_ignore_ascii_case(email) && !ACTIVE.contains_key(&state.id) {
+            remove_files(app_handle, &state.id, Some(&state.destination));
+        }
+    }
+}
+
+/// Stops a running download. Paused downloads keep their partial file for `resume`;
+/// with `discard`, it and the saved state are deleted, also when the download isn't running.
+pub fn cancel(app_handle: &AppHandle, id: &str, discard: bool) -> Result<(), String> {
+    if !valid_id(id) {
+        return Err(format!("Invalid download id: {}", id));
+    }
+    if let Some(control) = ACTIVE.get(id) {
+        control.store(if discard { DISCARD } else { PAUSE }, Ordering::SeqCst);
+    } else if discard {
+        let destination = load_state(app_handle, id).map(|s| s.destination);
+        remove_files(app_handle, id, destination.as_deref());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::Engine;
+
+    /// Feeds `encoded` through in `chunk_len` pieces the way `run` does and returns
+    /// the decoded bytes.
+    fn stream(encoding: &str, encoded: &[u8], chunk_len: usize) -> Vec<u8> {
+        let chunks: Vec<&[u8]> = encoded.chunks(chunk_len).collect();
+        let mut pending = Vec::new();
+        let mut out = Vec::new();
+        for (i, chunk) in chunks.iter().enumerate() {
+            let last = i + 1 == chunks.len();
+            let run = take_decodable(encoding, &mut pending, chunk, last);
+            if last {
+                assert!(pending.is_empty());
+            } else if is_base64(encoding) {
+                assert_eq!(run.len() % 4, 0);
+                assert!(pending.len() < 4);
+            } else if is_quoted_printable(encoding) {
+                assert!(run.is_empty() || run.ends_with(b"\n"));
+                assert!(!pending.contains(&b'\n'));
+            }
+            out.extend(decode(encoding, &run).unwrap());
+        }
+        out
+    }
+
+    fn sample_bytes() -> Vec<u8> {
+        (0..=255u8).cycle().take(1000).collect()
+    }
+
+    fn mime_base64(data: &[u8]) -> Vec<u8> {
+        let encoded = base64::engine::general_purpose::STANDARD.encode(data);
+        encoded.as_bytes().chunks(76).collect::<Vec<_>>().join(&b"\r\n"[..])
+    }
+
+    #[test]
+    fn base64_decodes_across_every_chunk_boundary() {
+        let data = sample_bytes();
+        let encoded = mime_base64(&data);
+        for chunk_len in 1..=80 {
+            assert_eq!(stream("BASE64", &encoded, chunk_len), data, "chunks of {}", chunk_len);
+        }
+        assert_eq!(stream("base64", &encoded, encoded.len()), data);
+    }
+
+    #[test]
+    fn base64_padding_is_kept_for_the_last_chunk() {
+        for len in [1, 2, 3, 4, 5] {
+            let data = &sample_bytes()[..len];
+            let encoded = mime_base64(data);
+            for chunk_len in 1..=encoded.len() {
+                assert_eq!(stream("base64", &encoded, chunk_len), data, "{} bytes in chunks of {}", len, chunk_len);
+            }
+        }
+    }
+
+    #[test]
+    fn quoted_printable_decodes_across_every_chunk_boundary() {
+        let encoded = concat!(
+            "Caf=C3=A9 cr=C3=A8me br=C3=BBl=C3=A9e, a line long enough to need a soft =\r\n",
+            "break, then an equals sign =3D and a trailing space=20\r\n",
+            "\r\n",
+            "Bare LF line endings=\n",
+            " join too\n",
+            "=E2=82=AC last line without a break"
+        ).as_bytes();
+        let expected = decode("quoted-printable", encoded).unwrap();
+        assert_eq!(
+            String::from_utf8(expected.clone()).unwrap(),
+            "Café crème brûlée, a line long enough to need a soft break, then an equals sign = and a trailing space \r\n\r\nBare LF line endings join too\r\n€ last line without a break"
+        );
+        for chunk_len in 1..=encoded.len() {
+            assert_eq!(stream("Quoted-Printable", encoded, chunk_len), expected, "chunks of {}", chunk_len);
+        }
+    }
+
+    #[test]
+    fn quoted_printable_waits_for_a_whole_line() {
+        let mut pending = Vec::new();
+        assert!(take_decodable("quoted-printable", &mut pending, b"caf=C", false).is_empty());
+        assert_eq!(take_decodable("quoted-printable", &mut pending, b"3=A9\r\nnext=", false), b"caf=C3=A9\r\n");
+        assert_eq!(pending, b"next=");
+        assert_eq!(take_decodable("quoted-printable", &mut pending, b"\r", true), b"next=\r");
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn other_encodings_pass_through() {
+        let data = sample_bytes();
+        for encoding in ["binary", "8bit", "7bit", ""] {
+            let mut pending = Vec::new();
+            assert_eq!(take_decodable(encoding, &mut pending, &data[..10], false), &data[..10]);
+            assert!(pending.is_empty());
+            assert_eq!(stream(encoding, &data, 7), data);
+        }
+    }
+}
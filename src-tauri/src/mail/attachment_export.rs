@@ -0,0 +1,476 @@
+use crate::auth::account::Account;
+use crate::mail::message_body::{self, MessageAttachment};
+use chrono::{Datelike, Local, Timelike};
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Emitter};
+
+/// Emitted with an `AttachmentExportProgress` after each attachment is written.
+pub const EXPORT_PROGRESS_EVENT: &str = "mail:attachment-export-progress";
+
+/// Names Windows refuses for files, whatever the extension.
+const RESERVED_NAMES: &[&str] = &[
+    "con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8", "com9",
+    "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
+
+/// Longest file name written, in bytes; most file systems stop at 255.
+const MAX_NAME_BYTES: usize = 200;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AttachmentExportProgress {
+    pub uid: u32,
+    pub part_id: String,
+    /// The name the file was written under, after de-duplication.
+    pub file_name: String,
+    /// Files written so far, this one included.
+    pub completed: usize,
+    pub total: usize,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AttachmentExport {
+    /// The ZIP archive, or the directory the files were saved to.
+    pub path: String,
+    pub files: Vec<String>,
+}
+
+fn split_extension(name: &str) -> (&str, &str) {
+    match name.rfind('.') {
+        Some(index) if index > 0 => name.split_at(index),
+        _ => (name, ""),
+    }
+}
+
+/// Makes an attachment name safe to use as a file name: no path separators, control or
+/// reserved characters, no Windows device names, and a bounded length.
+pub fn safe_file_name(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| if c.is_control() || r#"<>:"/\|?*"#.contains(c) { '_' } else { c })
+        .collect();
+    let cleaned = cleaned.trim().trim_end_matches(['.', ' ']).trim_start_matches('.');
+    if cleaned.is_empty() {
+        return "attachment".to_string();
+    }
+
+    let (stem, extension) = split_extension(cleaned);
+    let mut stem = stem.to_string();
+    if RESERVED_NAMES.iter().any(|r| stem.eq_ignore_ascii_case(r)) {
+        stem.insert(0, '_');
+    }
+    let extension = if extension.len() > 16 { "" } else { extension };
+    while stem.len() + extension.len() > MAX_NAME_BYTES {
+        stem.pop();
+    }
+    format!("{}{}", stem, extension)
+}
+
+/// Picks `name`, or `name (1)`, `name (2)` ... before the extension, whichever is neither
+/// taken in this export nor rejected by `exists`. Case is ignored, as on Windows and macOS.
+pub fn unique_name(name: &str, taken: &mut HashSet<String>, exists: impl Fn(&str) -> bool) -> String {
+    let (stem, extension) = split_extension(name);
+    let mut candidate = name.to_string();
+    let mut counter = 1;
+    while taken.contains(&candidate.to_lowercase()) || exists(&candidate) {
+        candidate = format!("{} ({}){}", stem, counter, extension);
+        counter += 1;
+    }
+    taken.insert(candidate.to_lowercase());
+    candidate
+}
+
+struct CentralEntry {
+    name: String,
+    method: u16,
+    crc: u32,
+    compressed_size: u32,
+    size: u32,
+    offset: u32,
+}
+
+/// Writes a ZIP archive entry by entry, so only one attachment is compressed at a time.
+/// Entries are deflated unless that doesn't help, names are flagged as UTF-8, and there is
+/// no ZIP64: entries and the archive must stay under 4 GiB, far above any message size.
+struct ZipWriter<W: Write> {
+    out: W,
+    offset: u64,
+    entries: Vec<CentralEntry>,
+    dos_time: u16,
+    dos_date: u16,
+}
+
+const ZIP_VERSION: u16 = 20;
+/// Version made by: Unix. Info-ZIP ignores the UTF-8 flag for MS-DOS archives and
+/// reads their names as code page 437.
+const ZIP_MADE_BY: u16 = (3 << 8) | ZIP_VERSION;
+/// External attributes for a Unix host: a regular file, `rw-r--r--`.
+const ZIP_FILE_MODE: u32 = 0o100644 << 16;
+/// General purpose flag bit 11: the name is UTF-8.
+const ZIP_UTF8_FLAG: u16 = 0x0800;
+
+fn too_large() -> io::Error {
+    io::Error::other("Attachments are too large for a ZIP archive")
+}
+
+impl<W: Write> ZipWriter<W> {
+    fn new(out: W) -> Self {
+        let now = Local::now();
+        let dos_time = ((now.hour() << 11) | (now.minute() << 5) | (now.second() / 2)) as u16;
+        let dos_date = (((now.year() - 1980).max(0) as u32) << 9 | (now.month() << 5) | now.day()) as u16;
+        Self { out, offset: 0, entries: Vec::new(), dos_time, dos_date }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.out.write_all(bytes)?;
+        self.offset += bytes.len() as u64;
+        Ok(())
+    }
+
+    fn add(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        let crc = crc32fast::hash(data);
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data)?;
+        let deflated = encoder.finish()?;
+        // Scans and archives are compressed already; deflating them again only adds bytes
+        let (method, body) = if deflated.len() < data.len() { (8u16, deflated.as_slice()) } else { (0u16, data) };
+
+        let entry = CentralEntry {
+            name: name.to_string(),
+            method,
+            crc,
+            compressed_size: u32::try_from(body.len()).map_err(|_| too_large())?,
+            size: u32::try_from(data.len()).map_err(|_| too_large())?,
+            offset: u32::try_from(self.offset).map_err(|_| too_large())?,
+        };
+        let name_len = u16::try_from(name.len()).map_err(|_| too_large())?;
+
+        let mut header = Vec::with_capacity(30 + name.len());
+        header.extend_from_slice(&0x04034b50u32.to_le_bytes());
+        header.extend_from_slice(&ZIP_VERSION.to_le_bytes());
+        header.extend_from_slice(&ZIP_UTF8_FLAG.to_le_bytes());
+        header.extend_from_slice(&entry.method.to_le_bytes());
+        header.extend_from_slice(&self.dos_time.to_le_bytes());
+        header.extend_from_slice(&self.dos_date.to_le_bytes());
+        header.extend_from_slice(&entry.crc.to_le_bytes());
+        header.extend_from_slice(&entry.compressed_size.to_le_bytes());
+        header.extend_from_slice(&entry.size.to_le_bytes());
+        header.extend_from_slice(&name_len.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(name.as_bytes());
+
+        self.write(&header)?;
+        self.write(body)?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<W> {
+        let directory_offset = u32::try_from(self.offset).map_err(|_| too_large())?;
+        let count = u16::try_from(self.entries.len()).map_err(|_| too_large())?;
+
+        let mut directory = Vec::new();
+        for entry in &self.entries {
+            directory.extend_from_slice(&0x02014b50u32.to_le_bytes());
+            directory.extend_from_slice(&ZIP_MADE_BY.to_le_bytes());
+            directory.extend_from_slice(&ZIP_VERSION.to_le_bytes());
+            directory.extend_from_slice(&ZIP_UTF8_FLAG.to_le_bytes());
+            directory.extend_from_slice(&entry.method.to_le_bytes());
+            directory.extend_from_slice(&self.dos_time.to_le_bytes());
+            directory.extend_from_slice(&self.dos_date.to_le_bytes());
+            directory.extend_from_slice(&entry.crc.to_le_bytes());
+            directory.extend_from_slice(&entry.compressed_size.to_le_bytes());
+            directory.extend_from_slice(&entry.size.to_le_bytes());
+            directory.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+            // Extra field, comment, disk number and internal attributes
+            directory.extend_from_slice(&[0u8; 8]);
+            directory.extend_from_slice(&ZIP_FILE_MODE.to_le_bytes());
+            directory.extend_from_slice(&entry.offset.to_le_bytes());
+            directory.extend_from_slice(entry.name.as_bytes());
+        }
+        let directory_size = u32::try_from(directory.len()).map_err(|_| too_large())?;
+        self.write(&directory)?;
+
+        let mut end = Vec::with_capacity(22);
+        end.extend_from_slice(&0x06054b50u32.to_le_bytes());
+        end.extend_from_slice(&[0u8; 4]);
+        end.extend_from_slice(&count.to_le_bytes());
+        end.extend_from_slice(&count.to_le_bytes());
+        end.extend_from_slice(&directory_size.to_le_bytes());
+        end.extend_from_slice(&directory_offset.to_le_bytes());
+        end.extend_from_slice(&0u16.to_le_bytes());
+        self.write(&end)?;
+
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+fn report(app_handle: &AppHandle, progress: AttachmentExportProgress) {
+    if let Err(e) = app_handle.emit(EXPORT_PROGRESS_EVENT, &progress) {
+        log::warn!("Failed to emit {}: {}", EXPORT_PROGRESS_EVENT, e);
+    }
+}
+
+fn write_directory(app_handle: &AppHandle, uid: u32, dir: &Path, files: Vec<(MessageAttachment, Vec<u8>)>) -> Result<AttachmentExport, String> {
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+
+    let total = files.len();
+    let mut taken = HashSet::new();
+    let mut written = Vec::with_capacity(total);
+    for (attachment, bytes) in files {
+        let file_name = unique_name(&safe_file_name(&attachment.name), &mut taken, |n| dir.join(n).exists());
+        let path = dir.join(&file_name);
+        // create_new: never overwrite something that appeared since the check
+        File::options()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .and_then(|mut file| file.write_all(&bytes))
+            .map_err(|e| format!("Failed to write file to {}: {}", path.display(), e))?;
+
+        written.push(file_name.clone());
+        report(app_handle, AttachmentExportProgress {
+            uid,
+            part_id: attachment.part_id,
+            file_name,
+            completed: written.len(),
+            total,
+            bytes: bytes.len() as u64,
+        });
+    }
+
+    Ok(AttachmentExport { path: dir.to_string_lossy().into_owned(), files: written })
+}
+
+fn write_zip(app_handle: &AppHandle, uid: u32, path: &Path, files: Vec<(MessageAttachment, Vec<u8>)>) -> Result<AttachmentExport, String> {
+    // Built next to the destination and moved in place at the end, so a failed export
+    // leaves no truncated archive behind
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".part");
+    let partial = PathBuf::from(partial);
+
+    let result = (|| {
+        let file = File::create(&partial).map_err(|e| format!("Failed to create {}: {}", partial.display(), e))?;
+        let mut zip = ZipWriter::new(BufWriter::new(file));
+
+        let total = files.len();
+        let mut taken = HashSet::new();
+        let mut written = Vec::with_capacity(total);
+        for (attachment, bytes) in files {
+            let file_name = unique_name(&safe_file_name(&attachment.name), &mut taken, |_| false);
+            zip.add(&file_name, &bytes).map_err(|e| format!("Failed to write {}: {}", file_name, e))?;
+
+            written.push(file_name.clone());
+            report(app_handle, AttachmentExportProgress {
+                uid,
+                part_id: attachment.part_id,
+                file_name,
+                completed: written.len(),
+                total,
+                bytes: bytes.len() as u64,
+            });
+        }
+
+        zip.finish().map_err(|e| format!("Failed to write {}: {}", partial.display(), e))?;
+        fs::rename(&partial, path).map_err(|e| format!("Failed to write file to {}: {}", path.display(), e))?;
+        Ok(written)
+    })();
+
+    match result {
+        Ok(files) => Ok(AttachmentExport { path: path.to_string_lossy().into_owned(), files }),
+        Err(e) => {
+            let _ = fs::remove_file(&partial);
+            Err(e)
+        }
+    }
+}
+
+/// Fetches `attachments` of `uid` in one round trip and saves them, either as files in
+/// the directory `destination` or as the ZIP archive `destination`. Emits
+/// `EXPORT_PROGRESS_EVENT` as each file is written.
+pub async fn download_all(
+    app_handle: &AppHandle,
+    account: &Account,
+    uid: u32,
+    attachments: Vec<MessageAttachment>,
+    destination: String,
+    as_zip: bool,
+) -> Result<AttachmentExport, String> {
+    if attachments.is_empty() {
+        return Err("No attachments to save".to_string());
+    }
+
+    let part_ids: Vec<String> = attachments.iter().map(|a| a.part_id.clone()).collect();
+    let parts = message_body::fetch_attachment_parts(account, uid, &part_ids).await?;
+
+    let app = app_handle.clone();
+    tokio::task::spawn_blocking(move || {
+        let files: Vec<(MessageAttachment, Vec<u8>)> = attachments.into_iter().zip(parts).collect();
+        let destination = Path::new(&destination);
+        if as_zip {
+            write_zip(&app, uid, destination, files)
+        } else {
+            write_directory(&app, uid, destination, files)
+        }
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::DeflateDecoder;
+    use std::io::Read;
+    use std::process::Command;
+
+    fn u16_at(bytes: &[u8], at: usize) -> usize {
+        u16::from_le_bytes([bytes[at], bytes[at + 1]]) as usize
+    }
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    /// Reads an archive back through its central directory, checking each entry's CRC.
+    fn read_zip(archive: &[u8]) -> Vec<(String, u16, Vec<u8>)> {
+        let end = archive.len() - 22;
+        assert_eq!(u32_at(archive, end), 0x06054b50);
+        let count = u16_at(archive, end + 10);
+        let mut at = u32_at(archive, end + 16) as usize;
+
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            assert_eq!(u32_at(archive, at), 0x02014b50);
+            assert_eq!(u16_at(archive, at + 4), ZIP_MADE_BY as usize);
+            assert_eq!(u16_at(archive, at + 8), ZIP_UTF8_FLAG as usize);
+            assert_eq!(u32_at(archive, at + 38), ZIP_FILE_MODE);
+            let method = u16_at(archive, at + 10) as u16;
+            let crc = u32_at(archive, at + 16);
+            let compressed_size = u32_at(archive, at + 20) as usize;
+            let name_len = u16_at(archive, at + 28);
+            let offset = u32_at(archive, at + 42) as usize;
+            let name = String::from_utf8(archive[at + 46..at + 46 + name_len].to_vec()).unwrap();
+
+            assert_eq!(u32_at(archive, offset), 0x04034b50);
+            let start = offset + 30 + u16_at(archive, offset + 26) + u16_at(archive, offset + 28);
+            let body = &archive[start..start + compressed_size];
+            let data = if method == 8 {
+                let mut data = Vec::new();
+                DeflateDecoder::new(body).read_to_end(&mut data).unwrap();
+                data
+            } else {
+                body.to_vec()
+            };
+            assert_eq!(crc32fast::hash(&data), crc, "{}", name);
+
+            entries.push((name, method, data));
+            at += 46 + name_len;
+        }
+        entries
+    }
+
+    fn sample_archive() -> (Vec<u8>, Vec<(&'static str, Vec<u8>)>) {
+        let text = "Quarterly report\n".repeat(200).into_bytes();
+        // Deterministic noise that doesn't deflate
+        let mut state = 0x2545_f491_u32;
+        let noise: Vec<u8> = (0..4096).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        }).collect();
+        let files = vec![("report.txt", text), ("Résumé 日本.bin", noise), ("empty.txt", Vec::new())];
+
+        let mut zip = ZipWriter::new(Vec::new());
+        for (name, data) in &files {
+            zip.add(name, data).unwrap();
+        }
+        (zip.finish().unwrap(), files)
+    }
+
+    #[test]
+    fn file_names_are_made_safe() {
+        assert_eq!(safe_file_name("../../etc/passwd"), "_.._etc_passwd");
+        assert_eq!(safe_file_name("a<b>c:d\"e|f?g*h\\i.txt"), "a_b_c_d_e_f_g_h_i.txt");
+        assert_eq!(safe_file_name("tab\there\u{7}.pdf"), "tab_here_.pdf");
+        assert_eq!(safe_file_name("  .hidden. . "), "hidden");
+        assert_eq!(safe_file_name("..."), "attachment");
+        assert_eq!(safe_file_name(""), "attachment");
+        assert_eq!(safe_file_name("CON.txt"), "_CON.txt");
+        assert_eq!(safe_file_name("lpt1"), "_lpt1");
+        assert_eq!(safe_file_name("console.txt"), "console.txt");
+        assert_eq!(safe_file_name("Résumé 日本.pdf"), "Résumé 日本.pdf");
+    }
+
+    #[test]
+    fn long_names_keep_their_extension() {
+        let name = safe_file_name(&format!("{}.pdf", "x".repeat(300)));
+        assert_eq!(name.len(), MAX_NAME_BYTES);
+        assert!(name.ends_with(".pdf"));
+
+        let name = safe_file_name(&format!("{}.pdf", "日".repeat(100)));
+        assert!(name.len() <= MAX_NAME_BYTES && name.ends_with("日.pdf"));
+
+        assert_eq!(safe_file_name("archive.averyveryverylongextension"), "archive");
+    }
+
+    #[test]
+    fn duplicate_names_are_numbered() {
+        let mut taken = HashSet::new();
+        let names: Vec<String> = ["a.pdf", "A.PDF", "a.pdf", "notes", "notes", ".profile"]
+            .iter()
+            .map(|n| unique_name(n, &mut taken, |_| false))
+            .collect();
+        assert_eq!(names, ["a.pdf", "A (1).PDF", "a (2).pdf", "notes", "notes (1)", ".profile"]);
+
+        let mut taken = HashSet::new();
+        let on_disk = ["scan.jpg", "scan (1).jpg"];
+        assert_eq!(unique_name("scan.jpg", &mut taken, |n| on_disk.contains(&n)), "scan (2).jpg");
+    }
+
+    #[test]
+    fn zip_entries_read_back() {
+        let (archive, files) = sample_archive();
+        let entries = read_zip(&archive);
+
+        assert_eq!(entries.len(), files.len());
+        for ((name, method, data), (expected_name, expected)) in entries.iter().zip(&files) {
+            assert_eq!(name, expected_name);
+            assert_eq!(data, expected);
+            // Text is deflated, noise and empty files are stored
+            assert_eq!(*method, if *expected_name == "report.txt" { 8 } else { 0 });
+        }
+    }
+
+    #[test]
+    #[ignore = "needs the unzip command; run with --ignored"]
+    fn zip_opens_with_unzip() {
+        let (archive, files) = sample_archive();
+        let dir = std::env::temp_dir().join(format!("attachment-export-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("attachments.zip");
+        fs::write(&path, &archive).unwrap();
+
+        let test = Command::new("unzip").arg("-t").arg(&path).output().expect("unzip is not installed");
+        assert!(test.status.success(), "{}", String::from_utf8_lossy(&test.stdout));
+
+        let out = dir.join("out");
+        let extract = Command::new("unzip").arg("-q").arg(&path).arg("-d").arg(&out).output().unwrap();
+        assert!(extract.status.success(), "{}", String::from_utf8_lossy(&extract.stderr));
+        for (name, data) in &files {
+            assert_eq!(&fs::read(out.join(name)).unwrap(), data, "{}", name);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,550 @@
+use crate::auth::account::Account;
+use crate::mail::message_body::{self, MessageAttachment};
+use crate::mail::{attachment_download, database, message_protocol, mime_decode, openpgp};
+use image::{DynamicImage, GrayImage, ImageFormat, ImageReader, Limits, RgbImage};
+use lopdf::{Document, Object, ObjectId, Stream};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tauri::{AppHandle, Manager};
+
+const CACHE_DIR_NAME: &str = "orbitmail_attachments";
+
+/// Attachments are only previewed from INBOX, like the cached attachment lists they come from.
+const FOLDER: &str = "INBOX";
+
+/// Disk budget per account. The least recently previewed files go first once it's exceeded.
+const MAX_ACCOUNT_CACHE_BYTES: u64 = 256 * 1024 * 1024;
+
+/// Attachments above this are not cached or previewed; they can still be downloaded.
+const MAX_PREVIEW_BYTES: usize = 50 * 1024 * 1024;
+
+/// Thumbnails fit in a square of this many pixels.
+const THUMBNAIL_SIZE: u32 = 320;
+
+/// Text previews are cut after this many characters.
+const MAX_TEXT_CHARS: usize = 64 * 1024;
+
+/// A PDF page image smaller than this on either side is a logo or icon, not a scanned page.
+const MIN_PAGE_IMAGE_PIXELS: i64 = 300;
+
+/// Largest decoded image accepted, which keeps decompression bombs out.
+const MAX_DECODED_IMAGE_BYTES: u64 = 128 * 1024 * 1024;
+
+const TEXT_EXTENSIONS: &[&str] = &["txt", "text", "log", "csv", "tsv", "md", "markdown"];
+const TEXT_MIMES: &[&str] = &["text/plain", "text/csv", "text/tab-separated-values", "text/markdown", "text/x-markdown"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PreviewKind {
+    Image,
+    Pdf,
+    Text,
+    Other,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AttachmentPreview {
+    pub kind: PreviewKind,
+    pub name: String,
+    pub mime: String,
+    pub size: u64,
+    /// Message protocol URL of the attachment itself; `None` for types the webview can't show.
+    pub url: Option<String>,
+    pub thumbnail_url: Option<String>,
+    /// Decoded text of text attachments, or of the first page of a PDF.
+    pub text: Option<String>,
+    pub truncated: bool,
+    pub page_count: Option<u32>,
+}
+
+/// The cache directory of one account. Accounts are kept apart because UIDs are only
+/// unique within a mailbox of a single account.
+fn account_dir(app_handle: &AppHandle, email: &str) -> Option<PathBuf> {
+    let cache_dir = app_handle.path().app_cache_dir().ok()?;
+    Some(cache_dir.join(CACHE_DIR_NAME).join(short_hash(&email.trim().to_lowercase(), 8)))
+}
+
+/// Hex of the first `len` bytes of `value`'s SHA-256, for names that must be path-safe.
+fn short_hash(value: &str, len: usize) -> String {
+    Sha256::digest(value.as_bytes()).iter().take(len).map(|b| format!("{:02x}", b)).collect()
+}
+
+/// IMAP section numbers are dot-separated integers; anything else could escape the cache dir.
+pub(crate) fn valid_part_id(part_id: &str) -> bool {
+    !part_id.is_empty() && part_id.split('.').all(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Name cached files of a part start with. A UID only names the same message within one
+/// folder and only until that folder's UIDVALIDITY changes, so both are part of the name.
+fn part_stem(folder: &str, uid_validity: u32, uid: u32, part_id: &str) -> String {
+    format!("{}_{}_{}_{}", short_hash(folder, 4), uid_validity, uid, part_id)
+}
+
+/// Stem of a part of `uid` as last synced, keyed by the UIDVALIDITY the local cache holds.
+fn synced_stem(app_handle: &AppHandle, uid: u32, part_id: &str) -> String {
+    let uid_validity = database::get_mailbox_validity(app_handle, FOLDER).ok().flatten().unwrap_or(0);
+    part_stem(FOLDER, uid_validity, uid, part_id)
+}
+
+fn part_path(dir: &Path, stem: &str) -> PathBuf {
+    dir.join(stem)
+}
+
+fn thumbnail_path(dir: &Path, stem: &str) -> PathBuf {
+    dir.join(format!("{}.thumb.png", stem))
+}
+
+/// Marks a cached file as recently used, which is what eviction goes by.
+fn touch(path: &Path) {
+    if let Ok(file) = fs::File::options().write(true).open(path) {
+        let _ = file.set_modified(SystemTime::now());
+    }
+}
+
+/// Deletes the least recently used files until the account's cache fits its budget.
+fn evict(dir: &Path) {
+    evict_to(dir, MAX_ACCOUNT_CACHE_BYTES);
+}
+
+fn evict_to(dir: &Path, budget: u64) {
+    let Ok(entries) = fs::read_dir(dir) else { return };
+    let mut files: Vec<(SystemTime, u64, PathBuf)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let meta = entry.metadata().ok().filter(|m| m.is_file())?;
+            Some((meta.modified().unwrap_or(SystemTime::UNIX_EPOCH), meta.len(), entry.path()))
+        })
+        .collect();
+
+    let mut total: u64 = files.iter().map(|(_, len, _)| len).sum();
+    if total <= budget {
+        return;
+    }
+    files.sort_by_key(|(modified, _, _)| *modified);
+    for (_, len, path) in files {
+        if total <= budget {
+            break;
+        }
+        if fs::remove_file(&path).is_ok() {
+            total -= len;
+        }
+    }
+    log::debug!("Attachment cache trimmed to {} bytes", total);
+}
+
+fn write_cached(dir: &Path, path: &Path, bytes: &[u8]) {
+    if let Err(e) = fs::create_dir_all(dir).and_then(|_| fs::write(path, bytes)) {
+        log::warn!("Attachment cache: could not write {}: {}", path.display(), e);
+    }
+}
+
+/// Drops every cached attachment of `email`.
+pub fn purge_account(app_handle: &AppHandle, email: &str) {
+    if let Some(dir) = account_dir(app_handle, email) {
+        let _ = fs::remove_dir_all(dir);
+    }
+}
+
+/// Name and declared type of a part, from the attachment list cached with the body.
+fn attachment_info(app_handle: &AppHandle, uid: u32, part_id: &str) -> Option<MessageAttachment> {
+    let (_, attachments_json) = database::get_message_body_cache(app_handle, FOLDER, uid).ok()??;
+    let attachments: Vec<MessageAttachment> = serde_json::from_str(&attachments_json?).ok()?;
+    attachments.into_iter().find(|a| a.part_id == part_id)
+}
+
+pub fn is_pdf(bytes: &[u8]) -> bool {
+    // Readers accept the header anywhere in the first kilobyte
+    bytes[..bytes.len().min(1024)].windows(5).any(|w| w == b"%PDF-")
+}
+
+/// Text formats are recognized by declared type or extension; neither can be sniffed reliably.
+pub fn is_text(mime: &str, name: &str) -> bool {
+    let mime = mime.split(';').next().unwrap_or_default().trim();
+    let extension = Path::new(name).extension().and_then(|e| e.to_str()).unwrap_or_default();
+    TEXT_MIMES.iter().any(|m| mime.eq_ignore_ascii_case(m))
+        || TEXT_EXTENSIONS.iter().any(|e| extension.eq_ignore_ascii_case(e))
+}
+
+fn classify(bytes: &[u8], mime: &str, name: &str) -> PreviewKind {
+    if message_protocol::sniff_image(bytes).is_some() {
+        PreviewKind::Image
+    } else if is_pdf(bytes) {
+        PreviewKind::Pdf
+    } else if is_text(mime, name) {
+        PreviewKind::Text
+    } else {
+        PreviewKind::Other
+    }
+}
+
+fn decode_image(bytes: &[u8]) -> Option<DynamicImage> {
+    let mut reader = ImageReader::new(Cursor::new(bytes)).with_guessed_format().ok()?;
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(16_384);
+    limits.max_image_height = Some(16_384);
+    limits.max_alloc = Some(MAX_DECODED_IMAGE_BYTES);
+    reader.limits(limits);
+    reader.decode().ok()
+}
+
+fn encode_thumbnail(image: &DynamicImage) -> Option<Vec<u8>> {
+    let mut out = Cursor::new(Vec::new());
+    image.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE).write_to(&mut out, ImageFormat::Png).ok()?;
+    Some(out.into_inner())
+}
+
+/// Truncates to `MAX_TEXT_CHARS`, reporting whether anything was cut.
+fn truncate_text(mut text: String) -> (String, bool) {
+    match text.char_indices().nth(MAX_TEXT_CHARS) {
+        Some((end, _)) => {
+            text.truncate(end);
+            (text, true)
+        }
+        None => (text, false),
+    }
+}
+
+/// Looks up a page attribute, following `/Parent` for the ones pages inherit from the tree.
+fn inherited<'a>(doc: &'a Document, page_id: ObjectId, key: &[u8]) -> Option<&'a Object> {
+    let mut node = doc.get_dictionary(page_id).ok()?;
+    // Bounded, since a malformed tree may loop
+    for _ in 0..32 {
+        if let Ok(value) = node.get_deref(key, doc) {
+            return Some(value);
+        }
+        node = node.get_deref(b"Parent", doc).ok()?.as_dict().ok()?;
+    }
+    None
+}
+
+/// Image XObjects a page can draw, with their pixel size.
+fn page_images(doc: &Document, page_id: ObjectId) -> Vec<(&Stream, i64, i64)> {
+    let Some(xobjects) = inherited(doc, page_id, b"Resources")
+        .and_then(|r| r.as_dict().ok())
+        .and_then(|r| r.get_deref(b"XObject", doc).ok())
+        .and_then(|x| x.as_dict().ok())
+    else {
+        return Vec::new();
+    };
+
+    xobjects
+        .iter()
+        .filter_map(|(_, value)| {
+            let stream = doc.dereference(value).ok()?.1.as_stream().ok()?;
+            let dict = &stream.dict;
+            if dict.get_deref(b"Subtype", doc).and_then(Object::as_name).ok()? != b"Image" {
+                return None;
+            }
+            let width = dict.get_deref(b"Width", doc).and_then(Object::as_i64).ok()?;
+            let height = dict.get_deref(b"Height", doc).and_then(Object::as_i64).ok()?;
+            Some((stream, width, height))
+        })
+        .collect()
+}
+
+fn names(doc: &Document, object: Option<&Object>) -> Vec<Vec<u8>> {
+    match object.and_then(|o| doc.dereference(o).ok()).map(|(_, o)| o) {
+        Some(Object::Name(name)) => vec![name.clone()],
+        Some(Object::Array(items)) => items.iter().filter_map(|i| i.as_name().ok().map(<[u8]>::to_vec)).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Decodes an image XObject to pixels. Covers JPEG and 8-bit RGB or grayscale samples,
+/// which is what scanners and most PDF producers emit for page images.
+fn decode_pdf_image(doc: &Document, stream: &Stream, width: i64, height: i64) -> Option<DynamicImage> {
+    let filters = names(doc, stream.dict.get(b"Filter").ok());
+    if filters.last().map(Vec::as_slice) == Some(b"DCTDecode") {
+        let jpeg = if filters.len() > 1 { stream.decompressed_content().ok()? } else { stream.content.clone() };
+        return decode_image(&jpeg);
+    }
+    if filters.iter().any(|f| f != b"FlateDecode") {
+        return None;
+    }
+    if stream.dict.get_deref(b"BitsPerComponent", doc).and_then(Object::as_i64).ok() != Some(8) {
+        return None;
+    }
+
+    let width = u32::try_from(width).ok()?;
+    let height = u32::try_from(height).ok()?;
+    if u64::from(width) * u64::from(height) * 3 > MAX_DECODED_IMAGE_BYTES {
+        return None;
+    }
+    let samples = if filters.is_empty() { stream.content.clone() } else { stream.decompressed_content().ok()? };
+    let color_space = names(doc, stream.dict.get(b"ColorSpace").ok());
+    match color_space.first().map(Vec::as_slice) {
+        Some(b"DeviceRGB") => RgbImage::from_raw(width, height, samples).map(DynamicImage::ImageRgb8),
+        Some(b"DeviceGray") => GrayImage::from_raw(width, height, samples).map(DynamicImage::ImageLuma8),
+        _ => None,
+    }
+}
+
+fn media_box_aspect(doc: &Document, page_id: ObjectId) -> Option<f32> {
+    let media_box = inherited(doc, page_id, b"MediaBox")?.as_array().ok()?;
+    let coords: Vec<f32> = media_box.iter().filter_map(|o| o.as_float().ok()).collect();
+    let [x0, y0, x1, y1] = coords[..] else { return None };
+    let (width, height) = ((x1 - x0).abs(), (y1 - y0).abs());
+    (height > 0.0).then(|| width / height)
+}
+
+/// A thumbnail of the first page, without a rasterizer: it's only possible when the page
+/// is one big image, as with scanned documents.
+fn pdf_thumbnail(doc: &Document, page_id: ObjectId) -> Option<Vec<u8>> {
+    let images = page_images(doc, page_id);
+    let &(stream, width, height) = images
+        .iter()
+        .filter(|(_, w, h)| *w >= MIN_PAGE_IMAGE_PIXELS && *h >= MIN_PAGE_IMAGE_PIXELS)
+        .max_by_key(|(_, w, h)| w.saturating_mul(*h))?;
+
+    // An image with the page's proportions is most likely the page itself
+    if let Some(page_aspect) = media_box_aspect(doc, page_id) {
+        let image_aspect = width as f32 / height as f32;
+        if (image_aspect - page_aspect).abs() / page_aspect > 0.15 {
+            return None;
+        }
+    }
+    encode_thumbnail(&decode_pdf_image(doc, stream, width, height)?)
+}
+
+struct Rendered {
+    kind: PreviewKind,
+    thumbnail: Option<Vec<u8>>,
+    text: Option<String>,
+    truncated: bool,
+    page_count: Option<u32>,
+}
+
+fn render(bytes: &[u8], mime: &str, name: &str, want_thumbnail: bool) -> Rendered {
+    let kind = classify(bytes, mime, name);
+    let mut rendered = Rendered { kind, thumbnail: None, text: None, truncated: false, page_count: None };
+
+    match kind {
+        PreviewKind::Image => {
+            if want_thumbnail {
+                rendered.thumbnail = decode_image(bytes).as_ref().and_then(encode_thumbnail);
+            }
+        }
+        PreviewKind::Pdf => match Document::load_mem(bytes) {
+            Ok(doc) => {
+                let pages = doc.get_pages();
+                rendered.page_count = Some(pages.len() as u32);
+                if let Some((&number, &page_id)) = pages.iter().next() {
+                    if want_thumbnail {
+                        rendered.thumbnail = pdf_thumbnail(&doc, page_id);
+                    }
+                    let text = doc.extract_text(&[number]).unwrap_or_default();
+                    let text = text.trim();
+                    if !text.is_empty() {
+                        let (text, truncated) = truncate_text(text.to_string());
+                        rendered.text = Some(text);
+                        rendered.truncated = truncated;
+                    }
+                }
+            }
+            Err(e) => log::debug!("Attachment preview: unreadable PDF: {}", e),
+        },
+        PreviewKind::Text => {
+            // The cached attachment list keeps no charset, so it is detected
+            let (text, truncated) = truncate_text(mime_decode::decode_text(bytes, None, false));
+            rendered.text = Some(text);
+            rendered.truncated = truncated;
+        }
+        PreviewKind::Other => {}
+    }
+    rendered
+}
+
+/// Fetches attachment `part_id` of `uid` into the account's cache and describes how to
+/// show it: a protocol URL for images and PDFs, a thumbnail where one can be made in
+/// process, and the decoded text of text files.
+pub async fn preview_attachment(app_handle: &AppHandle, account: &Account, uid: u32, part_id: &str) -> Result<AttachmentPreview, String> {
+    // Previews are cached on disk, which decrypted content must never reach
+    if openpgp::is_decrypted_part(part_id) {
+        return Err("Attachments of encrypted messages can't be previewed; download them instead".to_string());
+    }
+    if !valid_part_id(part_id) {
+        return Err(format!("Invalid part id: {}", part_id));
+    }
+    let dir = account_dir(app_handle, &account.email).ok_or("No cache directory")?;
+    let stem = synced_stem(app_handle, uid, part_id);
+    let path = part_path(&dir, &stem);
+
+    let bytes = match fs::read(&path) {
+        Ok(bytes) => {
+            touch(&path);
+            bytes
+        }
+        Err(_) => {
+            // BODYSTRUCTURE gives the encoded size, so a large part is refused before any
+            // of it is downloaded. Base64 decodes to three quarters of its length.
+            let (octets, encoding) = attachment_download::part_outline(account, uid, part_id).await?;
+            let size = if attachment_download::is_base64(&encoding) { octets / 4 * 3 } else { octets };
+            if size > MAX_PREVIEW_BYTES as u64 {
+                return Err("Attachment is too large to preview; download it instead".to_string());
+            }
+            let bytes = message_body::fetch_attachment_part(account, uid, part_id).await?;
+            if bytes.len() > MAX_PREVIEW_BYTES {
+                return Err("Attachment is too large to preview; download it instead".to_string());
+            }
+            bytes
+        }
+    };
+
+    let app = app_handle.clone();
+    let part = part_id.to_string();
+    tokio::task::spawn_blocking(move || {
+        let info = attachment_info(&app, uid, &part);
+        let name = info.as_ref().map(|a| a.name.clone()).unwrap_or_else(|| format!("part-{}", part));
+        let mime = info.map(|a| a.type_mime).unwrap_or_else(|| "application/octet-stream".to_string());
+
+        if !path.exists() {
+            write_cached(&dir, &path, &bytes);
+        }
+        let thumb_path = thumbnail_path(&dir, &stem);
+        let cached_thumbnail = thumb_path.exists();
+        let rendered = render(&bytes, &mime, &name, !cached_thumbnail);
+        if let Some(thumbnail) = &rendered.thumbnail {
+            write_cached(&dir, &thumb_path, thumbnail);
+        } else if cached_thumbnail {
+            touch(&thumb_path);
+        }
+        evict(&dir);
+
+        let base = format!("/{}/attachment/{}", uid, part);
+        let url = match rendered.kind {
+            PreviewKind::Other => None,
+            _ => Some(message_protocol::url(&base)),
+        };
+        let thumbnail_url = thumb_path.exists().then(|| message_protocol::url(&format!("{}/thumbnail", base)));
+
+        Ok(AttachmentPreview {
+            kind: rendered.kind,
+            name,
+            mime,
+            size: bytes.len() as u64,
+            url,
+            thumbnail_url,
+            text: rendered.text,
+            truncated: rendered.truncated,
+            page_count: rendered.page_count,
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// A previously previewed attachment or its thumbnail, as served by the message protocol.
+/// Only the cache is consulted; previewing is what puts files there.
+pub fn cached_file(app_handle: &AppHandle, account: &Account, uid: u32, part_id: &str, thumbnail: bool) -> Option<(Vec<u8>, PreviewKind)> {
+    if !valid_part_id(part_id) {
+        return None;
+    }
+    let dir = account_dir(app_handle, &account.email)?;
+    let stem = synced_stem(app_handle, uid, part_id);
+    if thumbnail {
+        let bytes = fs::read(thumbnail_path(&dir, &stem)).ok()?;
+        return Some((bytes, PreviewKind::Image));
+    }
+
+    let path = part_path(&dir, &stem);
+    let bytes = fs::read(&path).ok()?;
+    touch(&path);
+    let info = attachment_info(app_handle, uid, part_id);
+    let (mime, name) = info.map(|a| (a.type_mime, a.name)).unwrap_or_default();
+    let kind = classify(&bytes, &mime, &name);
+    let bytes = match kind {
+        PreviewKind::Text => mime_decode::decode_text(&bytes, None, false).into_bytes(),
+        _ => bytes,
+    };
+    Some((bytes, kind))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn part_ids_are_section_numbers() {
+        for part_id in ["1", "2.1", "1.2.10"] {
+            assert!(valid_part_id(part_id), "{}", part_id);
+        }
+        for part_id in ["", ".", "1.", ".1", "1..2", "..", "../1", "1/2", "1\\2", "a", "1.a", "-1", "+1", " 1", "１"] {
+            assert!(!valid_part_id(part_id), "{:?}", part_id);
+        }
+    }
+
+    #[test]
+    fn cached_names_depend_on_folder_and_uid_validity() {
+        let stem = part_stem("INBOX", 7, 42, "1.2");
+        assert!(stem.ends_with("_7_42_1.2"), "{}", stem);
+        assert_ne!(stem, part_stem("Archive", 7, 42, "1.2"));
+        assert_ne!(stem, part_stem("INBOX", 8, 42, "1.2"));
+        // Folder names never reach the path as written
+        assert!(!part_stem("../Sent", 7, 42, "1").contains('/'));
+    }
+
+    #[test]
+    fn content_decides_the_kind_before_the_declared_type() {
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+        assert_eq!(classify(png, "application/octet-stream", "scan"), PreviewKind::Image);
+        assert_eq!(classify(b"%PDF-1.7\n", "application/octet-stream", "file.bin"), PreviewKind::Pdf);
+        assert_eq!(classify(b"%PDF-1.4\n", "text/plain", "notes.txt"), PreviewKind::Pdf);
+        let prefixed = [&[b' '; 512][..], b"%PDF-1.4"].concat();
+        assert_eq!(classify(&prefixed, "", ""), PreviewKind::Pdf);
+
+        // SVG and HTML may carry script, so they are never shown inline
+        assert_eq!(classify(b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>", "image/svg+xml", "logo.svg"), PreviewKind::Other);
+        assert_eq!(classify(b"<html><body>hi</body></html>", "text/html", "page.html"), PreviewKind::Other);
+
+        assert_eq!(classify(b"a,b\n1,2\n", "text/csv; charset=utf-8", "data"), PreviewKind::Text);
+        assert_eq!(classify(b"hello", "application/octet-stream", "README.MD"), PreviewKind::Text);
+        assert_eq!(classify(b"PK\x03\x04", "application/zip", "archive.zip"), PreviewKind::Other);
+    }
+
+    #[test]
+    fn text_is_cut_at_a_character_boundary() {
+        assert_eq!(truncate_text("short".to_string()), ("short".to_string(), false));
+
+        let exact = "a".repeat(MAX_TEXT_CHARS);
+        assert_eq!(truncate_text(exact.clone()), (exact, false));
+
+        let (text, truncated) = truncate_text("é".repeat(MAX_TEXT_CHARS + 1));
+        assert!(truncated);
+        assert_eq!(text.chars().count(), MAX_TEXT_CHARS);
+        assert!(text.chars().all(|c| c == 'é'));
+    }
+
+    #[test]
+    fn eviction_drops_least_recently_used_files_first() {
+        let dir = std::env::temp_dir().join(format!("orbitmail-evict-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("subdir")).unwrap();
+
+        let now = SystemTime::now();
+        for (name, age) in [("old", 30), ("middle", 20), ("new", 10)] {
+            let path = dir.join(name);
+            fs::write(&path, [0u8; 10]).unwrap();
+            fs::File::options().write(true).open(&path).unwrap().set_modified(now - Duration::from_secs(age)).unwrap();
+        }
+
+        evict_to(&dir, 30);
+        assert!(dir.join("old").exists(), "within budget nothing is removed");
+
+        touch(&dir.join("old"));
+        evict_to(&dir, 20);
+        assert!(!dir.join("middle").exists());
+        assert!(dir.join("old").exists() && dir.join("new").exists(), "touched files count as recently used");
+
+        evict_to(&dir, 0);
+        assert!(!dir.join("old").exists() && !dir.join("new").exists());
+        assert!(dir.join("subdir").is_dir(), "only files are evicted");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,175 @@
+use crate::mail::db_pool::{get_conn, retry_busy};
+use crate::mail::message_list::parse_address_header;
+use crate::mail::pgp_keyring::{self, KeyEntry};
+use base64::Engine;
+use pgp::ser::Serialize as _;
+use tauri::AppHandle;
+
+/// Base64 line length for keydata in outgoing headers, folded as Autocrypt suggests.
+const KEYDATA_LINE_LENGTH: usize = 76;
+
+/// A parsed `Autocrypt:` header (Autocrypt Level 1 §2.1).
+struct AutocryptHeader {
+    addr: String,
+    prefer_encrypt: bool,
+    keydata: Vec<u8>,
+}
+
+/// Parses an `Autocrypt:` header value. Headers with an unknown attribute that isn't
+/// marked optional with a leading underscore must be ignored, as must key types other than 1.
+fn parse(value: &str) -> Option<AutocryptHeader> {
+    let mut addr = None;
+    let mut prefer_encrypt = false;
+    let mut keydata = None;
+
+    // A trailing `;` leaves an empty attribute, which says nothing
+    for attribute in value.split(';').filter(|a| !a.trim().is_empty()) {
+        let (name, value) = attribute.split_once('=')?;
+        let name = name.trim().to_ascii_lowercase();
+        match name.as_str() {
+            "addr" => addr = Some(value.trim().to_lowercase()),
+            "type" if value.trim() == "1" => {}
+            "prefer-encrypt" => prefer_encrypt = value.trim().eq_ignore_ascii_case("mutual"),
+            "keydata" => {
+                let compact: String = value.chars().filter(|c| !c.is_whitespace()).collect();
+                keydata = base64::engine::general_purpose::STANDARD.decode(compact).ok();
+            }
+            _ if name.starts_with('_') => {}
+            _ => return None,
+        }
+    }
+
+    Some(AutocryptHeader { addr: addr?, prefer_encrypt, keydata: keydata? })
+}
+
+/// The header a message announces, if it names the message's sender.
+fn announced(value: &str, sender: &str) -> Option<AutocryptHeader> {
+    parse(value).filter(|header| header.addr.eq_ignore_ascii_case(sender.trim()))
+}
+
+/// Learns keys from the `Autocrypt:` headers of freshly synced messages. A header only
+/// counts when it names the From address, and only a newer message replaces what an
+/// address announced before.
+pub fn record_fetches(app_handle: &AppHandle, fetches: &[imap::types::Fetch]) {
+    let now = chrono::Utc::now().timestamp();
+
+    for msg in fetches {
+        let Some(parsed) = msg.header().and_then(|h| mailparse::parse_mail(h).ok()) else { continue };
+
+        let mut headers = Vec::new();
+        let mut from = Vec::new();
+        let mut date = None;
+        for header in parsed.get_headers() {
+            match header.get_key().to_lowercase().as_str() {
+                "autocrypt" => headers.push(header.get_value()),
+                "from" => from = parse_address_header(header),
+                "date" => date = mailparse::dateparse(&header.get_value()).ok(),
+                _ => {}
+            }
+        }
+
+        // More than one header, or more than one sender, and the message says nothing
+        let ([value], [sender]) = (headers.as_slice(), from.as_slice()) else { continue };
+        let Some(header) = announced(value, &sender.email) else { continue };
+        // A date in the future can't hold back later updates
+        let timestamp = date.unwrap_or(now).min(now);
+
+        if let Err(e) = record(app_handle, &header, timestamp) {
+            log::warn!("Autocrypt header from {} ignored: {}", header.addr, e);
+        }
+    }
+}
+
+fn record(app_handle: &AppHandle, header: &AutocryptHeader, timestamp: i64) -> Result<(), String> {
+    let conn = get_conn(app_handle)?;
+    let known: Option<i64> = conn
+        .query_row(
+            "SELECT autocrypt_timestamp FROM autocrypt_peers WHERE address = ?1",
+            [&header.addr],
+            |row| row.get(0),
+        )
+        .ok();
+    if known.is_some_and(|known| known >= timestamp) {
+        return Ok(());
+    }
+    drop(conn);
+
+    let fingerprint = pgp_keyring::import_autocrypt(app_handle, &header.keydata)?;
+
+    let conn = get_conn(app_handle)?;
+    retry_busy(|| conn.execute(
+        "INSERT INTO autocrypt_peers (address, fingerprint, prefer_encrypt, last_seen, autocrypt_timestamp)
+         VALUES (?1, ?2, ?3, ?4, ?4)
+         ON CONFLICT(address) DO UPDATE SET
+            fingerprint = excluded.fingerprint,
+            prefer_encrypt = excluded.prefer_encrypt,
+            last_seen = excluded.last_seen,
+            autocrypt_timestamp = excluded.autocrypt_timestamp",
+        rusqlite::params![header.addr, fingerprint, header.prefer_encrypt, timestamp],
+    )).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// `Autocrypt:` header value announcing the key mail from `from` is signed with.
+pub fn outgoing_header(entries: &[KeyEntry], from: &str) -> Option<String> {
+    let entry = pgp_keyring::signing_key(entries, from)?;
+    let keydata = base64::engine::general_purpose::STANDARD.encode(entry.public.to_bytes().ok()?);
+    let lines: Vec<&str> = keydata
+        .as_bytes()
+        .chunks(KEYDATA_LINE_LENGTH)
+        .filter_map(|chunk| std::str::from_utf8(chunk).ok())
+        .collect();
+    Some(format!("addr={}; keydata=\r\n {}", from.trim(), lines.join("\r\n ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEYDATA: &str = "mDMEZk5hABYJKwYBBAHaRw8BAQdA";
+
+    fn keydata() -> Vec<u8> {
+        base64::engine::general_purpose::STANDARD.decode(KEYDATA).unwrap()
+    }
+
+    #[test]
+    fn folded_keydata_is_unfolded() {
+        let value = format!("addr=Ada@Example.com; prefer-encrypt=mutual; keydata=\r\n {}\r\n {}", &KEYDATA[..12], &KEYDATA[12..]);
+        let header = parse(&value).unwrap();
+        assert_eq!(header.addr, "ada@example.com");
+        assert!(header.prefer_encrypt);
+        assert_eq!(header.keydata, keydata());
+
+        assert!(parse("addr=ada@example.com; keydata=not base64!").is_none());
+    }
+
+    #[test]
+    fn only_key_type_1_is_accepted() {
+        let header = parse(&format!("addr=ada@example.com; type=1; keydata={}", KEYDATA)).unwrap();
+        assert_eq!(header.keydata, keydata());
+        assert!(!header.prefer_encrypt);
+        assert!(parse(&format!("addr=ada@example.com; type=2; keydata={}", KEYDATA)).is_none());
+    }
+
+    #[test]
+    fn empty_attributes_are_skipped() {
+        assert!(parse(&format!("addr=ada@example.com; keydata={};", KEYDATA)).is_some());
+        assert!(parse(&format!("addr=ada@example.com;; keydata={}; ", KEYDATA)).is_some());
+    }
+
+    #[test]
+    fn unknown_attributes_are_critical_unless_optional() {
+        assert!(parse(&format!("addr=ada@example.com; _comment=hello; keydata={}", KEYDATA)).is_some());
+        assert!(parse(&format!("addr=ada@example.com; comment=hello; keydata={}", KEYDATA)).is_none());
+        assert!(parse(&format!("addr=ada@example.com; garbage; keydata={}", KEYDATA)).is_none());
+        assert!(parse(&format!("keydata={}", KEYDATA)).is_none());
+        assert!(parse("addr=ada@example.com").is_none());
+    }
+
+    #[test]
+    fn a_header_only_counts_for_its_sender() {
+        let value = format!("addr=ada@example.com; keydata={}", KEYDATA);
+        assert!(announced(&value, " ADA@example.com ").is_some());
+        assert!(announced(&value, "mallory@example.com").is_none());
+    }
+}
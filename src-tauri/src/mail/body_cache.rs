@@ -43,3 +43,7 @@ pub fn insert_cached_body(uid: u32, html: String) {
         },
     );
 }
+
+pub fn clear_cached_bodies() {
+    CACHE.clear();
+}
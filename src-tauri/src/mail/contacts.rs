@@ -0,0 +1,216 @@
+use crate::mail::db_pool::{get_conn, write_transaction};
+use crate::mail::message_list::{EmailAddress, MessageHeader};
+use std::collections::HashMap;
+use tauri::AppHandle;
+
+/// Recency score halves roughly every three weeks.
+const RECENCY_DECAY_DAYS: f64 = 30.0;
+
+/// Candidate rows considered per autocomplete query before ranking.
+const MAX_CANDIDATES: u32 = 500;
+
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContactSuggestion {
+    pub email: String,
+    /// Most recently seen display name.
+    pub name: Option<String>,
+    /// Every display name seen for this address.
+    pub names: Vec<String>,
+    pub frequency: u32,
+    pub last_seen: i64,
+    /// Whether the user has ever sent mail to this address.
+    pub written_to: bool,
+    pub frequency_score: f64,
+    pub recency_score: f64,
+    pub score: f64,
+}
+
+#[derive(Default)]
+struct Harvested {
+    name: Option<String>,
+    name_date: i64,
+    names: Vec<String>,
+    frequency: u32,
+    last_seen: i64,
+    sent_count: u32,
+}
+
+/// Automated senders make poor autocomplete suggestions.
+fn is_unreplyable(email: &str) -> bool {
+    let local = email.split('@').next().unwrap_or_default();
+    ["noreply", "no-reply", "no_reply", "donotreply", "do-not-reply", "mailer-daemon"]
+        .iter()
+        .any(|marker| local.contains(marker))
+}
+
+/// Updates the contacts store from freshly synced headers.
+///
+/// Mail in `sent` counts its To/Cc/Bcc recipients as people the user wrote to; everything
+/// else contributes its From/Reply-To/To/Cc addresses. The user's own address is skipped.
+pub fn record_messages(app_handle: &AppHandle, user_email: &str, messages: &[MessageHeader], sent: bool) -> Result<(), String> {
+    let user_email = user_email.to_lowercase();
+    let mut harvested: HashMap<String, Harvested> = HashMap::new();
+
+    for msg in messages {
+        let a = &msg.addresses;
+        let addresses: Vec<&EmailAddress> = if sent {
+            a.to.iter().chain(&a.cc).chain(&a.bcc).collect()
+        } else {
+            a.from.iter().chain(&a.reply_to).chain(&a.to).chain(&a.cc).collect()
+        };
+
+        for addr in addresses {
+            let email = addr.email.trim().to_lowercase();
+            if email == user_email || is_unreplyable(&email) {
+                continue;
+            }
+
+            let entry = harvested.entry(email).or_default();
+            entry.frequency += 1;
+            entry.last_seen = entry.last_seen.max(msg.date);
+            if sent {
+                entry.sent_count += 1;
+            }
+            if let Some(name) = &addr.name {
+                if msg.date >= entry.name_date {
+                    entry.name = Some(name.clone());
+                    entry.name_date = msg.date;
+                }
+                if !entry.names.contains(name) {
+                    entry.names.push(name.clone());
+                }
+            }
+        }
+    }
+
+    if harvested.is_empty() {
+        return Ok(());
+    }
+
+    let mut conn = get_conn(app_handle)?;
+    write_transaction(&mut conn, |tx| {
+        let mut existing_names = tx.prepare_cached("SELECT display_names FROM contacts WHERE email = ?1")?;
+        let mut upsert = tx.prepare_cached(
+            "INSERT INTO contacts (email, display_name, display_names, frequency, last_seen, sent_count)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT(email) DO UPDATE SET
+                display_name = CASE
+                    WHEN excluded.display_name IS NOT NULL AND excluded.last_seen >= last_seen THEN excluded.display_name
+                    ELSE COALESCE(display_name, excluded.display_name)
+                END,
+                display_names = excluded.display_names,
+                frequency = frequency + excluded.frequency,
+                last_seen = MAX(last_seen, excluded.last_seen),
+                sent_count = sent_count + excluded.sent_count"
+        )?;
+
+        for (email, contact) in &harvested {
+            let stored: Option<String> = existing_names.query_row([email], |row| row.get(0)).ok();
+            let mut names: Vec<String> = stored
+                .and_then(|json| serde_json::from_str(&json).ok())
+                .unwrap_or_default();
+            for name in &contact.names {
+                if !names.contains(name) {
+                    names.push(name.clone());
+                }
+            }
+            let names_json = serde_json::to_string(&names).unwrap_or_else(|_| "[]".to_string());
+
+            upsert.execute(rusqlite::params![
+                email,
+                contact.name,
+                names_json,
+                contact.frequency,
+                contact.last_seen,
+                contact.sent_count,
+            ])?;
+        }
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+/// How well `query` matches a contact: 0 for a prefix of the address or a name,
+/// 1 for the start of a later word, 2 for the domain, 3 for anything else.
+fn match_tier(query: &str, email: &str, names: &[String]) -> u8 {
+    let local = email.split('@').next().unwrap_or_default();
+    let domain = email.split('@').nth(1).unwrap_or_default();
+    let names_lower: Vec<String> = names.iter().map(|n| n.to_lowercase()).collect();
+
+    if email.starts_with(query) || names_lower.iter().any(|n| n.starts_with(query)) {
+        0
+    } else if names_lower.iter().any(|n| n.split_whitespace().any(|w| w.starts_with(query)))
+        || local.split(['.', '_', '-', '+']).any(|part| part.starts_with(query))
+    {
+        1
+    } else if domain.starts_with(query) {
+        2
+    } else {
+        3
+    }
+}
+
+fn score(frequency: u32, sent_count: u32, last_seen: i64, now: i64) -> (f64, f64, f64) {
+    // Writing to someone is a much stronger signal than receiving list mail from them
+    let frequency_score = ((1 + frequency + 2 * sent_count) as f64).ln();
+    let age_days = ((now - last_seen).max(0) as f64) / 86_400.0;
+    let recency_score = (-age_days / RECENCY_DECAY_DAYS).exp();
+    let written_bonus = if sent_count > 0 { 1.0 } else { 0.0 };
+
+    (frequency_score, recency_score, frequency_score + 2.0 * recency_score + written_bonus)
+}
+
+/// Ranks stored contacts against what the user has typed so far.
+/// An empty query returns the highest scoring contacts.
+pub fn autocomplete(app_handle: &AppHandle, query: &str, limit: u32) -> Result<Vec<ContactSuggestion>, String> {
+    let query = query.trim().to_lowercase();
+    let pattern = format!(
+        "%{}%",
+        query.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+    );
+
+    let conn = get_conn(app_handle)?;
+    let mut stmt = conn.prepare_cached(
+        "SELECT email, display_name, display_names, frequency, last_seen, sent_count
+         FROM contacts
+         WHERE email LIKE ?1 ESCAPE '\\' OR display_names LIKE ?1 ESCAPE '\\'
+         ORDER BY sent_count > 0 DESC, last_seen DESC
+         LIMIT ?2"
+    ).map_err(|e| e.to_string())?;
+
+    let now = chrono::Utc::now().timestamp();
+    let rows = stmt.query_map(rusqlite::params![pattern, MAX_CANDIDATES], |row| {
+        let names_json: String = row.get(2)?;
+        let frequency: u32 = row.get(3)?;
+        let last_seen: i64 = row.get(4)?;
+        let sent_count: u32 = row.get(5)?;
+        let (frequency_score, recency_score, score) = score(frequency, sent_count, last_seen, now);
+
+        Ok(ContactSuggestion {
+            email: row.get(0)?,
+            name: row.get(1)?,
+            names: serde_json::from_str(&names_json).unwrap_or_default(),
+            frequency,
+            last_seen,
+            written_to: sent_count > 0,
+            frequency_score,
+            recency_score,
+            score,
+        })
+    }).map_err(|e| e.to_string())?;
+
+    let mut ranked = Vec::new();
+    for r in rows {
+        let suggestion = r.map_err(|e| e.to_string())?;
+        let tier = if query.is_empty() { 0 } else { match_tier(&query, &suggestion.email, &suggestion.names) };
+        ranked.push((tier, suggestion));
+    }
+
+    ranked.sort_by(|(tier_a, a), (tier_b, b)| {
+        tier_a.cmp(tier_b).then(b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal))
+    });
+
+    Ok(ranked.into_iter().take(limit as usize).map(|(_, s)| s).collect())
+}
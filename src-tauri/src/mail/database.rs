@@ -1,8 +1,11 @@
-use rusqlite::{Connection, Result};
+use crate::mail::db_pool::{self, get_conn, retry_busy, write_transaction};
+use crate::mail::migrations;
+use rusqlite::{OptionalExtension, Result};
 use std::path::PathBuf;
 use tauri::AppHandle;
 use tauri::Manager;
-use crate::mail::message_list::MessageHeader;
+use crate::mail::message_list::{parse_address_list, AddressHeaders, EmailAddress, MessageHeader, ThreadingHeaders};
+use crate::mail::threading::ThreadInput;
 
 pub fn get_db_path(app_handle: &AppHandle) -> Result<PathBuf, String> {
     let app_dir = app_handle
@@ -18,145 +21,44 @@ pub fn get_db_path(app_handle: &AppHandle) -> Result<PathBuf, String> {
 
 pub fn init_db(app_handle: &AppHandle) -> Result<(), String> {
     let db_path = get_db_path(app_handle)?;
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS messages (
-            folder TEXT NOT NULL,
-            uid INTEGER NOT NULL,
-            uid_validity INTEGER, -- Left for generic IMAP parity
-            subject TEXT,
-            sender TEXT,
-            date INTEGER NOT NULL,
-            snippet TEXT,
-            body TEXT,
-            seen INTEGER DEFAULT 0,
-            flagged INTEGER DEFAULT 0,
-            has_attachments INTEGER DEFAULT 0,
-            thread_id TEXT,
-            body_fetched INTEGER DEFAULT 0,
-            processed_html TEXT,
-            PRIMARY KEY (folder, uid)
-        )",
-        (),
-    ).map_err(|e| e.to_string())?;
-
-    // Safe Schema Migration for existing databases
-    let mut stmt = conn.prepare("PRAGMA table_info(messages)").unwrap();
-    let mut has_processed_html = false;
-    let rows = stmt.query_map([], |row| {
-        let name: String = row.get(1)?;
-        Ok(name)
-    }).unwrap();
-
-    for name in rows {
-        if let Ok(col_name) = name {
-            if col_name == "processed_html" {
-                has_processed_html = true;
-                break;
-            }
-        }
-    }
+    db_pool::install(app_handle, db_path.clone());
+    let mut conn = get_conn(app_handle)?;
 
-    if !has_processed_html {
-        conn.execute("ALTER TABLE messages ADD COLUMN processed_html TEXT", ()).map_err(|e| e.to_string())?;
-    }
-
-    let mut stmt = conn.prepare("PRAGMA table_info(messages)").unwrap();
-    let mut has_attachments_json = false;
-    let rows = stmt.query_map([], |row| {
-        let name: String = row.get(1)?;
-        Ok(name)
-    }).unwrap();
-
-    for name in rows {
-        if let Ok(col_name) = name {
-            if col_name == "attachments_json" {
-                has_attachments_json = true;
-                break;
-            }
-        }
-    }
-
-    if !has_attachments_json {
-        conn.execute("ALTER TABLE messages ADD COLUMN attachments_json TEXT", ()).map_err(|e| e.to_string())?;
-    }
-
-
-    // Performance Indexes
-    conn.execute("CREATE INDEX IF NOT EXISTS idx_messages_folder_uid_desc ON messages(folder, uid DESC)", ()).map_err(|e| e.to_string())?;
-    conn.execute("CREATE INDEX IF NOT EXISTS idx_messages_folder_date ON messages(folder, date DESC)", ()).map_err(|e| e.to_string())?;
-    conn.execute("CREATE INDEX IF NOT EXISTS idx_messages_thread ON messages(thread_id)", ()).map_err(|e| e.to_string())?;
-    conn.execute("CREATE INDEX IF NOT EXISTS idx_messages_seen ON messages(seen)", ()).map_err(|e| e.to_string())?;
-    conn.execute("CREATE INDEX IF NOT EXISTS idx_messages_flagged ON messages(flagged)", ()).map_err(|e| e.to_string())?;
-
-    // FTS5 Setup
-    conn.execute(
-        "CREATE VIRTUAL TABLE IF NOT EXISTS messages_fts USING fts5(
-            subject,
-            sender,
-            snippet,
-            content='messages',
-            content_rowid='rowid'
-        )",
-        (),
-    ).map_err(|e| e.to_string())?;
-
-    conn.execute(
-        "CREATE TRIGGER IF NOT EXISTS messages_ai AFTER INSERT ON messages BEGIN
-            INSERT INTO messages_fts(rowid, subject, sender, snippet)
-            VALUES (new.rowid, new.subject, new.sender, new.snippet);
-        END",
-        (),
-    ).map_err(|e| e.to_string())?;
-
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS mailbox_state (
-            mailbox TEXT PRIMARY KEY,
-            uid_validity INTEGER
-        )",
-        (),
-    ).map_err(|e| e.to_string())?;
-
-    Ok(())
+    migrations::run(&mut conn, &db_path)
 }
 
 pub fn get_mailbox_validity(app_handle: &AppHandle, mailbox: &str) -> Result<Option<u32>, String> {
-    let db_path = get_db_path(app_handle)?;
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let conn = get_conn(app_handle)?;
 
-    let mut stmt = conn.prepare("SELECT uid_validity FROM mailbox_state WHERE mailbox = ?1").unwrap();
+    let mut stmt = conn.prepare_cached("SELECT uid_validity FROM mailbox_state WHERE mailbox = ?1").unwrap();
     let validity = stmt.query_row([mailbox], |row| row.get(0)).ok();
 
     Ok(validity)
 }
 
 pub fn update_mailbox_validity(app_handle: &AppHandle, mailbox: &str, validity: u32) -> Result<(), String> {
-    let db_path = get_db_path(app_handle)?;
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let conn = get_conn(app_handle)?;
 
-    conn.execute(
+    retry_busy(|| conn.execute(
         "INSERT OR REPLACE INTO mailbox_state (mailbox, uid_validity) VALUES (?1, ?2)",
         rusqlite::params![mailbox, validity],
-    ).map_err(|e| e.to_string())?;
+    )).map_err(|e| e.to_string())?;
 
     Ok(())
 }
 
 pub fn clear_messages(app_handle: &AppHandle, folder: &str) -> Result<(), String> {
-    let db_path = get_db_path(app_handle)?;
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let conn = get_conn(app_handle)?;
 
-    conn.execute("DELETE FROM messages WHERE folder = ?1", rusqlite::params![folder]).map_err(|e| e.to_string())?;
+    retry_busy(|| conn.execute("DELETE FROM messages WHERE folder = ?1", rusqlite::params![folder])).map_err(|e| e.to_string())?;
 
     Ok(())
 }
 
 pub fn get_highest_uid(app_handle: &AppHandle, folder: &str) -> Result<u32, String> {
-    let db_path = get_db_path(app_handle)?;
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let conn = get_conn(app_handle)?;
 
-    let mut stmt = conn.prepare("SELECT MAX(uid) FROM messages WHERE folder = ?1").unwrap();
+    let mut stmt = conn.prepare_cached("SELECT MAX(uid) FROM messages WHERE folder = ?1").unwrap();
     let max_uid: Option<u32> = stmt.query_row(rusqlite::params![folder], |row| row.get(0)).unwrap_or(None);
 
     Ok(max_uid.unwrap_or(0))
@@ -167,38 +69,74 @@ pub fn insert_or_update_messages(app_handle: &AppHandle, messages: &[MessageHead
         return Ok(());
     }
 
-    let db_path = get_db_path(app_handle)?;
-    let mut conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let mut conn = get_conn(app_handle)?;
 
-    let tx = conn.transaction().map_err(|e| e.to_string())?;
-    {
-        let mut stmt = tx.prepare(
-            "INSERT INTO messages (folder, uid, uid_validity, subject, sender, date, seen, flagged, snippet)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+    write_transaction(&mut conn, |tx| {
+        let mut stmt = tx.prepare_cached(
+            "INSERT INTO messages (folder, uid, uid_validity, subject, sender, date, seen, flagged, snippet,
+                                   message_id, in_reply_to, references_ids, gm_thread_id,
+                                   from_addrs, to_addrs, cc_addrs, bcc_addrs, reply_to_addrs,
+                                   list_id, list_unsubscribe, size, internal_date, has_attachments)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23)
              ON CONFLICT(folder, uid) DO UPDATE SET
                 subject = excluded.subject,
                 sender = excluded.sender,
                 date = excluded.date,
                 seen = excluded.seen,
                 flagged = excluded.flagged,
-                snippet = excluded.snippet"
-        ).map_err(|e| e.to_string())?;
+                snippet = CASE WHEN body_fetched = 1 THEN snippet ELSE COALESCE(NULLIF(excluded.snippet, ''), snippet) END,
+                has_attachments = excluded.has_attachments,
+                message_id = COALESCE(excluded.message_id, message_id),
+                in_reply_to = COALESCE(excluded.in_reply_to, in_reply_to),
+                references_ids = COALESCE(excluded.references_ids, references_ids),
+                gm_thread_id = COALESCE(excluded.gm_thread_id, gm_thread_id),
+                from_addrs = excluded.from_addrs,
+                to_addrs = excluded.to_addrs,
+                cc_addrs = excluded.cc_addrs,
+                bcc_addrs = excluded.bcc_addrs,
+                reply_to_addrs = excluded.reply_to_addrs,
+                list_id = excluded.list_id,
+                list_unsubscribe = excluded.list_unsubscribe,
+                size = COALESCE(excluded.size, size),
+                internal_date = COALESCE(excluded.internal_date, internal_date)"
+        )?;
 
         for msg in messages {
+            let references = if msg.threading.references.is_empty() {
+                None
+            } else {
+                Some(msg.threading.references.join(" "))
+            };
+            let a = &msg.addresses;
+
             stmt.execute(rusqlite::params![
                 msg.folder,
                 msg.uid,
                 msg.uid_validity,
                 msg.subject,
-                msg.from,
+                msg.sender(),
                 msg.date,
                 if msg.seen { 1 } else { 0 },
                 if msg.flagged { 1 } else { 0 },
                 msg.snippet.as_deref().unwrap_or(""),
-            ]).map_err(|e| e.to_string())?;
+                msg.threading.message_id,
+                msg.threading.in_reply_to,
+                references,
+                msg.threading.gm_thread_id,
+                addresses_to_json(&a.from),
+                addresses_to_json(&a.to),
+                addresses_to_json(&a.cc),
+                addresses_to_json(&a.bcc),
+                addresses_to_json(&a.reply_to),
+                msg.list_id,
+                msg.list_unsubscribe,
+                msg.size,
+                msg.internal_date,
+                if msg.has_attachments { 1 } else { 0 },
+            ])?;
         }
-    }
-    tx.commit().map_err(|e| e.to_string())?;
+        Ok(())
+    })?;
 
     Ok(())
 }
@@ -207,51 +145,94 @@ pub fn load_cached_messages(app_handle: &AppHandle, limit: usize) -> Result<Vec<
     load_messages_page(app_handle, "INBOX", None, limit as u32)
 }
 
+/// Column list matching `parse_header_row`, qualified so it can be used in joins.
+pub const HEADER_COLUMNS: &str = "messages.uid, messages.uid_validity, messages.subject, messages.sender, messages.date, messages.seen, messages.flagged, messages.snippet, messages.folder, messages.has_attachments, messages.thread_id, \
+    messages.from_addrs, messages.to_addrs, messages.cc_addrs, messages.bcc_addrs, messages.reply_to_addrs, \
+    messages.message_id, messages.in_reply_to, messages.references_ids, messages.list_id, messages.list_unsubscribe, messages.size, messages.internal_date";
+
+/// Number of columns in `HEADER_COLUMNS`; extra selected columns start at this index.
+pub const HEADER_COLUMN_COUNT: usize = 23;
+
+fn addresses_from_json(json: Option<String>) -> Vec<EmailAddress> {
+    json.and_then(|j| serde_json::from_str(&j).ok()).unwrap_or_default()
+}
+
+fn addresses_to_json(addresses: &[EmailAddress]) -> String {
+    serde_json::to_string(addresses).unwrap_or_else(|_| "[]".to_string())
+}
+
+/// Maps a row selected with `HEADER_COLUMNS` into a `MessageHeader`.
+pub fn parse_header_row(row: &rusqlite::Row) -> rusqlite::Result<MessageHeader> {
+    let mut from = addresses_from_json(row.get(11)?);
+    // Rows cached before envelopes were stored only have the raw sender string
+    if from.is_empty() {
+        from = parse_address_list(&row.get::<_, Option<String>>(3)?.unwrap_or_default());
+    }
+    let references: Option<String> = row.get(18)?;
+
+    Ok(MessageHeader {
+        uid: row.get(0)?,
+        uid_validity: row.get(1)?,
+        subject: row.get(2)?,
+        addresses: AddressHeaders {
+            from,
+            to: addresses_from_json(row.get(12)?),
+            cc: addresses_from_json(row.get(13)?),
+            bcc: addresses_from_json(row.get(14)?),
+            reply_to: addresses_from_json(row.get(15)?),
+        },
+        date: row.get(4)?,
+        seen: row.get::<_, i32>(5)? != 0,
+        flagged: row.get::<_, i32>(6)? != 0,
+        snippet: row.get(7).unwrap_or(None),
+        folder: row.get(8).unwrap_or_else(|_| "INBOX".to_string()),
+        has_attachments: row.get::<_, i32>(9).unwrap_or(0) != 0,
+        thread_id: row.get(10).unwrap_or(None),
+        threading: ThreadingHeaders {
+            message_id: row.get::<_, Option<String>>(16)?.filter(|id| !id.is_empty()),
+            in_reply_to: row.get(17)?,
+            references: references
+                .map(|r| r.split_whitespace().map(str::to_string).collect())
+                .unwrap_or_default(),
+            gm_thread_id: None,
+        },
+        list_id: row.get(19)?,
+        list_unsubscribe: row.get(20)?,
+        size: row.get(21)?,
+        internal_date: row.get(22)?,
+    })
+}
+
 pub fn load_messages_page(app_handle: &AppHandle, folder: &str, before_uid: Option<u32>, limit: u32) -> Result<Vec<MessageHeader>, String> {
-    let db_path = get_db_path(app_handle)?;
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    let parse_row = |row: &rusqlite::Row| -> rusqlite::Result<MessageHeader> {
-        Ok(MessageHeader {
-            uid: row.get(0)?,
-            uid_validity: row.get(1)?,
-            subject: row.get(2)?,
-            from: row.get(3)?,
-            date: row.get(4)?,
-            seen: row.get::<_, i32>(5)? != 0,
-            flagged: row.get::<_, i32>(6)? != 0,
-            snippet: row.get(7).unwrap_or(None),
-            folder: row.get(8).unwrap_or_else(|_| "INBOX".to_string()),
-            has_attachments: row.get::<_, i32>(9).unwrap_or(0) != 0,
-            thread_id: row.get(10).unwrap_or(None),
-        })
-    };
+    let conn = get_conn(app_handle)?;
 
     let mut messages = Vec::new();
 
     if let Some(uid) = before_uid {
-        let mut stmt = conn.prepare(
-            "SELECT uid, uid_validity, subject, sender, date, seen, flagged, snippet, folder, has_attachments, thread_id
+        let mut stmt = conn.prepare_cached(&format!(
+            "SELECT {}
              FROM messages 
              WHERE folder = ?1 AND uid < ?2
              ORDER BY uid DESC 
-             LIMIT ?3"
-        ).map_err(|e| e.to_string())?;
+             LIMIT ?3",
+            HEADER_COLUMNS
+        )).map_err(|e| e.to_string())?;
 
-        let msg_iter = stmt.query_map(rusqlite::params![folder, uid, limit], parse_row).map_err(|e| e.to_string())?;
+        let msg_iter = stmt.query_map(rusqlite::params![folder, uid, limit], parse_header_row).map_err(|e| e.to_string())?;
         for msg in msg_iter {
             messages.push(msg.map_err(|e| e.to_string())?);
         }
     } else {
-        let mut stmt = conn.prepare(
-            "SELECT uid, uid_validity, subject, sender, date, seen, flagged, snippet, folder, has_attachments, thread_id
+        let mut stmt = conn.prepare_cached(&format!(
+            "SELECT {}
              FROM messages 
              WHERE folder = ?1
              ORDER BY uid DESC 
-             LIMIT ?2"
-        ).map_err(|e| e.to_string())?;
+             LIMIT ?2",
+            HEADER_COLUMNS
+        )).map_err(|e| e.to_string())?;
 
-        let msg_iter = stmt.query_map(rusqlite::params![folder, limit], parse_row).map_err(|e| e.to_string())?;
+        let msg_iter = stmt.query_map(rusqlite::params![folder, limit], parse_header_row).map_err(|e| e.to_string())?;
         for msg in msg_iter {
             messages.push(msg.map_err(|e| e.to_string())?);
         }
@@ -260,12 +241,39 @@ pub fn load_messages_page(app_handle: &AppHandle, folder: &str, before_uid: Opti
     Ok(messages)
 }
 
+/// Loads cached headers for the given UIDs. UIDs that are not cached are simply absent from the result.
+pub fn load_messages_by_uids(app_handle: &AppHandle, folder: &str, uids: &[u32]) -> Result<Vec<MessageHeader>, String> {
+    if uids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let conn = get_conn(app_handle)?;
+
+    let mut messages = Vec::new();
+    // Stay well below SQLITE_MAX_VARIABLE_NUMBER
+    for chunk in uids.chunks(500) {
+        let placeholders = vec!["?"; chunk.len()].join(",");
+        let mut stmt = conn.prepare_cached(&format!(
+            "SELECT {} FROM messages WHERE folder = ? AND uid IN ({})",
+            HEADER_COLUMNS, placeholders
+        )).map_err(|e| e.to_string())?;
+
+        let mut params: Vec<&dyn rusqlite::ToSql> = vec![&folder];
+        params.extend(chunk.iter().map(|u| u as &dyn rusqlite::ToSql));
+
+        let msg_iter = stmt.query_map(params.as_slice(), parse_header_row).map_err(|e| e.to_string())?;
+        for msg in msg_iter {
+            messages.push(msg.map_err(|e| e.to_string())?);
+        }
+    }
+
+    Ok(messages)
+}
 
 pub fn get_message_body_cache(app_handle: &AppHandle, folder: &str, uid: u32) -> Result<Option<(String, Option<String>)>, String> {
-    let db_path = get_db_path(app_handle)?;
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let conn = get_conn(app_handle)?;
 
-    let mut stmt = conn.prepare("SELECT processed_html, attachments_json FROM messages WHERE folder = ?1 AND uid = ?2 AND body_fetched = 1 AND processed_html IS NOT NULL").unwrap();
+    let mut stmt = conn.prepare_cached("SELECT processed_html, attachments_json FROM messages WHERE folder = ?1 AND uid = ?2 AND body_fetched = 1 AND processed_html IS NOT NULL").unwrap();
     let result = stmt.query_row(rusqlite::params![folder, uid], |row| {
         Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?))
     }).ok();
@@ -273,23 +281,222 @@ pub fn get_message_body_cache(app_handle: &AppHandle, folder: &str, uid: u32) ->
     Ok(result)
 }
 
-pub fn update_message_body(app_handle: &AppHandle, folder: &str, uid: u32, body: &str, snippet: &str, attachments_json: Option<String>) -> Result<(), String> {
-    let db_path = get_db_path(app_handle)?;
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+pub fn update_message_body(
+    app_handle: &AppHandle,
+    folder: &str,
+    uid: u32,
+    body: &str,
+    snippet: &str,
+    attachments_json: Option<String>,
+    body_text: &str,
+    attachment_names: &str,
+) -> Result<(), String> {
+    let conn = get_conn(app_handle)?;
+
+    retry_busy(|| conn.execute(
+        "UPDATE messages SET processed_html = ?1, snippet = ?2, attachments_json = ?3, body_text = ?4, attachment_names = ?5, body_fetched = 1 WHERE folder = ?6 AND uid = ?7",
+        rusqlite::params![body, snippet, attachments_json, body_text, attachment_names, folder, uid],
+    )).map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+/// Signature status of a cached signed body, as JSON; see `openpgp::PgpStatus`.
+pub fn set_message_pgp_status(app_handle: &AppHandle, folder: &str, uid: u32, status_json: Option<String>) -> Result<(), String> {
+    let conn = get_conn(app_handle)?;
+
+    retry_busy(|| conn.execute(
+        "UPDATE messages SET pgp_status = ?1 WHERE folder = ?2 AND uid = ?3",
+        rusqlite::params![status_json, folder, uid],
+    )).map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+pub fn get_message_pgp_status(app_handle: &AppHandle, folder: &str, uid: u32) -> Result<Option<String>, String> {
+    let conn = get_conn(app_handle)?;
+
+    let mut stmt = conn.prepare_cached("SELECT pgp_status FROM messages WHERE folder = ?1 AND uid = ?2").map_err(|e| e.to_string())?;
+    let status = stmt.query_row(rusqlite::params![folder, uid], |row| row.get(0)).unwrap_or(None);
+
+    Ok(status)
+}
+
+/// Drops cached signed bodies so their signatures are checked again against a changed keyring.
+pub fn forget_signed_bodies(app_handle: &AppHandle) -> Result<(), String> {
+    let conn = get_conn(app_handle)?;
+
+    retry_busy(|| conn.execute(
+        "UPDATE messages SET processed_html = NULL, body_fetched = 0, pgp_status = NULL WHERE pgp_status IS NOT NULL",
+        (),
+    )).map_err(|e| e.to_string())?;
 
-    conn.execute(
-        "UPDATE messages SET processed_html = ?1, snippet = ?2, attachments_json = ?3, body_fetched = 1 WHERE folder = ?4 AND uid = ?5",
-        rusqlite::params![body, snippet, attachments_json, folder, uid],
+    Ok(())
+}
+
+/// Returns up to `limit` cached bodies that predate body indexing, as `(rowid, processed_html, attachments_json)`.
+pub fn get_unindexed_bodies(app_handle: &AppHandle, limit: u32) -> Result<Vec<(i64, String, Option<String>)>, String> {
+    let conn = get_conn(app_handle)?;
+
+    let mut stmt = conn.prepare_cached(
+        "SELECT rowid, processed_html, attachments_json FROM messages
+         WHERE body_fetched = 1 AND processed_html IS NOT NULL AND body_text IS NULL
+         LIMIT ?1"
     ).map_err(|e| e.to_string())?;
 
+    let rows = stmt.query_map(rusqlite::params![limit], |row| {
+        Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+    }).map_err(|e| e.to_string())?;
+
+    let mut bodies = Vec::new();
+    for r in rows {
+        bodies.push(r.map_err(|e| e.to_string())?);
+    }
+    Ok(bodies)
+}
+
+/// Stores extracted search text for a batch of rows in one transaction.
+pub fn set_body_index_text(app_handle: &AppHandle, entries: &[(i64, String, String)]) -> Result<(), String> {
+    let mut conn = get_conn(app_handle)?;
+
+    write_transaction(&mut conn, |tx| {
+        let mut stmt = tx.prepare_cached("UPDATE messages SET body_text = ?1, attachment_names = ?2 WHERE rowid = ?3")?;
+        for (rowid, body_text, attachment_names) in entries {
+            stmt.execute(rusqlite::params![body_text, attachment_names, rowid])?;
+        }
+        Ok(())
+    })?;
+
     Ok(())
 }
 
+/// UIDs cached before envelope headers were stored, newest first.
+pub fn get_uids_missing_envelope(app_handle: &AppHandle, folder: &str, limit: u32) -> Result<Vec<u32>, String> {
+    let conn = get_conn(app_handle)?;
+
+    let mut stmt = conn.prepare_cached(
+        "SELECT uid FROM messages WHERE folder = ?1 AND (message_id IS NULL OR from_addrs IS NULL) ORDER BY uid DESC LIMIT ?2"
+    ).map_err(|e| e.to_string())?;
+
+    let rows = stmt.query_map(rusqlite::params![folder, limit], |row| row.get(0)).map_err(|e| e.to_string())?;
+
+    let mut uids = Vec::new();
+    for u in rows {
+        uids.push(u.map_err(|e| e.to_string())?);
+    }
+    Ok(uids)
+}
+
+/// Stores envelope headers for already-cached messages without touching subject, flags or snippet.
+/// `gone` lists UIDs the server no longer returned; they are marked so they aren't requested again.
+pub fn set_envelope_headers(app_handle: &AppHandle, folder: &str, headers: &[MessageHeader], gone: &[u32]) -> Result<(), String> {
+    let mut conn = get_conn(app_handle)?;
+
+    write_transaction(&mut conn, |tx| {
+        let mut stmt = tx.prepare_cached(
+            "UPDATE messages SET message_id = ?1, in_reply_to = ?2, references_ids = ?3,
+                                 gm_thread_id = COALESCE(?4, gm_thread_id),
+                                 sender = ?5, from_addrs = ?6, to_addrs = ?7, cc_addrs = ?8, bcc_addrs = ?9, reply_to_addrs = ?10,
+                                 list_id = ?11, list_unsubscribe = ?12, size = ?13, internal_date = ?14, has_attachments = ?15
+             WHERE folder = ?16 AND uid = ?17"
+        )?;
+
+        for msg in headers {
+            let a = &msg.addresses;
+            stmt.execute(rusqlite::params![
+                msg.threading.message_id.as_deref().unwrap_or(""),
+                msg.threading.in_reply_to,
+                msg.threading.references.join(" "),
+                msg.threading.gm_thread_id,
+                msg.sender(),
+                addresses_to_json(&a.from),
+                addresses_to_json(&a.to),
+                addresses_to_json(&a.cc),
+                addresses_to_json(&a.bcc),
+                addresses_to_json(&a.reply_to),
+                msg.list_id,
+                msg.list_unsubscribe,
+                msg.size,
+                msg.internal_date,
+                if msg.has_attachments { 1 } else { 0 },
+                folder,
+                msg.uid,
+            ])?;
+        }
+
+        let mut gone_stmt = tx.prepare_cached(
+            "UPDATE messages SET message_id = COALESCE(message_id, ''), from_addrs = COALESCE(from_addrs, '[]')
+             WHERE folder = ?1 AND uid = ?2"
+        )?;
+        for uid in gone {
+            gone_stmt.execute(rusqlite::params![folder, uid])?;
+        }
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+/// Loads every cached message's threading inputs for a full thread recomputation.
+pub fn load_thread_inputs(app_handle: &AppHandle) -> Result<Vec<ThreadInput>, String> {
+    let conn = get_conn(app_handle)?;
+
+    let mut stmt = conn.prepare_cached(
+        "SELECT rowid, message_id, in_reply_to, references_ids, gm_thread_id, subject, thread_id
+         FROM messages ORDER BY date, rowid"
+    ).map_err(|e| e.to_string())?;
+
+    let rows = stmt.query_map([], |row| {
+        let references: Option<String> = row.get(3)?;
+        Ok(ThreadInput {
+            rowid: row.get(0)?,
+            message_id: row.get::<_, Option<String>>(1)?.filter(|id| !id.is_empty()),
+            in_reply_to: row.get(2)?,
+            references: references
+                .map(|r| r.split_whitespace().map(str::to_string).collect())
+                .unwrap_or_default(),
+            gm_thread_id: row.get(4)?,
+            subject: row.get::<_, Option<String>>(5)?.unwrap_or_default(),
+            thread_id: row.get(6)?,
+        })
+    }).map_err(|e| e.to_string())?;
+
+    let mut inputs = Vec::new();
+    for r in rows {
+        inputs.push(r.map_err(|e| e.to_string())?);
+    }
+    Ok(inputs)
+}
+
+pub fn set_thread_ids(app_handle: &AppHandle, updates: &[(i64, String)]) -> Result<(), String> {
+    if updates.is_empty() {
+        return Ok(());
+    }
+
+    let mut conn = get_conn(app_handle)?;
+
+    write_transaction(&mut conn, |tx| {
+        let mut stmt = tx.prepare_cached("UPDATE messages SET thread_id = ?1 WHERE rowid = ?2")?;
+        for (rowid, thread_id) in updates {
+            stmt.execute(rusqlite::params![thread_id, rowid])?;
+        }
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+pub fn has_unthreaded_messages(app_handle: &AppHandle) -> Result<bool, String> {
+    let conn = get_conn(app_handle)?;
+
+    conn.query_row("SELECT EXISTS(SELECT 1 FROM messages WHERE thread_id IS NULL)", (), |row| row.get(0))
+        .map_err(|e| e.to_string())
+}
+
 pub fn get_unfetched_recent_uids(app_handle: &AppHandle, folder: &str, limit: u32) -> Result<Vec<u32>, String> {
-    let db_path = get_db_path(app_handle)?;
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let conn = get_conn(app_handle)?;
 
-    let mut stmt = conn.prepare(
+    let mut stmt = conn.prepare_cached(
         "SELECT uid FROM messages 
          WHERE folder = ?1 AND body_fetched = 0 AND uid > (SELECT MAX(uid) - 200 FROM messages WHERE folder = ?1) 
          ORDER BY uid DESC LIMIT ?2"
@@ -308,47 +515,93 @@ pub fn get_unfetched_recent_uids(app_handle: &AppHandle, folder: &str, limit: u3
 }
 
 pub fn is_message_seen(app_handle: &AppHandle, folder: &str, uid: u32) -> Result<bool, String> {
-    let db_path = get_db_path(app_handle)?;
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let conn = get_conn(app_handle)?;
 
-    let mut stmt = conn.prepare("SELECT seen FROM messages WHERE folder = ?1 AND uid = ?2").unwrap();
+    let mut stmt = conn.prepare_cached("SELECT seen FROM messages WHERE folder = ?1 AND uid = ?2").unwrap();
     let seen: Option<i32> = stmt.query_row(rusqlite::params![folder, uid], |row| row.get(0)).ok();
 
     Ok(seen.unwrap_or(0) != 0)
 }
 
 pub fn set_message_seen(app_handle: &AppHandle, folder: &str, uid: u32, seen: bool) -> Result<(), String> {
-    let db_path = get_db_path(app_handle)?;
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let conn = get_conn(app_handle)?;
 
-    conn.execute(
+    retry_busy(|| conn.execute(
         "UPDATE messages SET seen = ?1 WHERE folder = ?2 AND uid = ?3",
         rusqlite::params![if seen { 1 } else { 0 }, folder, uid],
-    ).map_err(|e| e.to_string())?;
+    )).map_err(|e| e.to_string())?;
 
     Ok(())
 }
 
 pub fn set_message_flagged(app_handle: &AppHandle, folder: &str, uid: u32, flagged: bool) -> Result<(), String> {
-    let db_path = get_db_path(app_handle)?;
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let conn = get_conn(app_handle)?;
 
-    conn.execute(
+    retry_busy(|| conn.execute(
         "UPDATE messages SET flagged = ?1 WHERE folder = ?2 AND uid = ?3",
         rusqlite::params![if flagged { 1 } else { 0 }, folder, uid],
-    ).map_err(|e| e.to_string())?;
+    )).map_err(|e| e.to_string())?;
 
     Ok(())
 }
 
 pub fn delete_message_local(app_handle: &AppHandle, folder: &str, uid: u32) -> Result<(), String> {
-    let db_path = get_db_path(app_handle)?;
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let conn = get_conn(app_handle)?;
 
-    conn.execute(
+    retry_busy(|| conn.execute(
         "DELETE FROM messages WHERE folder = ?1 AND uid = ?2",
         rusqlite::params![folder, uid],
-    ).map_err(|e| e.to_string())?;
+    )).map_err(|e| e.to_string())?;
+
+    Ok(())
+}
 
+/// Deletes every cached message, search index entry, mailbox state row, harvested contact and
+/// Autocrypt key. Keys imported by hand stay in the keyring.
+fn clear_mail_cache(tx: &rusqlite::Transaction) -> rusqlite::Result<()> {
+    tx.execute("DELETE FROM messages", ())?;
+    tx.execute("INSERT INTO messages_fts(messages_fts) VALUES('delete-all')", ())?;
+    tx.execute("DELETE FROM mailbox_state", ())?;
+    tx.execute("DELETE FROM contacts", ())?;
+    tx.execute("DELETE FROM autocrypt_peers", ())?;
+    tx.execute("DELETE FROM pgp_keys WHERE source = 'autocrypt' AND secret_key IS NULL", ())?;
     Ok(())
 }
+
+fn cache_owner(tx: &rusqlite::Transaction) -> rusqlite::Result<Option<String>> {
+    tx.query_row("SELECT account FROM cache_owner WHERE id = 1", (), |row| row.get(0))
+        .optional()
+}
+
+/// Hands the mail cache to `email`, the account becoming active. Another account's mail is
+/// deleted first; returns whether it was, so in-memory and on-disk body caches go with it.
+/// A cache with no owner yet predates owner tracking and is kept.
+pub fn claim_mail_cache(app_handle: &AppHandle, email: &str) -> Result<bool, String> {
+    let mut conn = get_conn(app_handle)?;
+
+    write_transaction(&mut conn, |tx| {
+        let cleared = match cache_owner(tx)? {
+            Some(owner) if !owner.eq_ignore_ascii_case(email) => {
+                clear_mail_cache(tx)?;
+                true
+            }
+            _ => false,
+        };
+        tx.execute("INSERT OR REPLACE INTO cache_owner (id, account) VALUES (1, ?1)", [email])?;
+        Ok(cleared)
+    })
+}
+
+/// Deletes the cached mail of a removed account, if the cache holds it. Returns whether it did.
+pub fn purge_account_data(app_handle: &AppHandle, email: &str) -> Result<bool, String> {
+    let mut conn = get_conn(app_handle)?;
+
+    write_transaction(&mut conn, |tx| {
+        if !cache_owner(tx)?.is_some_and(|owner| owner.eq_ignore_ascii_case(email)) {
+            return Ok(false);
+        }
+        clear_mail_cache(tx)?;
+        tx.execute("DELETE FROM cache_owner", ())?;
+        Ok(true)
+    })
+}
@@ -0,0 +1,292 @@
+use rusqlite::{Connection, ErrorCode, Transaction, TransactionBehavior};
+use std::ops::{Deref, DerefMut};
+use std::path::PathBuf;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, ThreadId};
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+/// Connections kept open at once. WAL allows these to read concurrently while one writes.
+const MAX_CONNECTIONS: usize = 4;
+
+/// How long SQLite itself waits on a lock before reporting SQLITE_BUSY.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+const STATEMENT_CACHE_CAPACITY: usize = 64;
+
+struct PoolInner {
+    path: PathBuf,
+    idle: Mutex<Vec<Connection>>,
+    /// Number of connections currently checked out.
+    in_use: Mutex<usize>,
+    available: Condvar,
+    /// Threads holding a connection, one entry per checkout.
+    holders: Mutex<Vec<ThreadId>>,
+}
+
+/// Shared pool of SQLite connections to `orbitmail.db`, stored in Tauri state.
+///
+/// Connections are opened lazily, configured once (WAL, busy timeout, statement cache)
+/// and reused, so IDLE sync, prefetch writes and UI paging don't each pay for a fresh open.
+#[derive(Clone)]
+pub struct DbPool {
+    inner: Arc<PoolInner>,
+}
+
+/// A connection checked out of the pool; returned automatically on drop.
+pub struct PooledConnection {
+    conn: Option<Connection>,
+    pool: Arc<PoolInner>,
+    holder: ThreadId,
+}
+
+impl DbPool {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            inner: Arc::new(PoolInner {
+                path,
+                idle: Mutex::new(Vec::new()),
+                in_use: Mutex::new(0),
+                available: Condvar::new(),
+                holders: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    /// Checks out a connection, opening a new one if the pool isn't full yet
+    /// and otherwise waiting for one to be returned.
+    ///
+    /// A thread must not check out a second connection while it holds one: with every
+    /// connection held that way, all of them wait on each other forever. Such a nested
+    /// checkout fails instead of waiting.
+    pub fn get(&self) -> Result<PooledConnection, String> {
+        let holder = thread::current().id();
+        if self.inner.holders.lock().unwrap().contains(&holder) {
+            log::error!("Nested database checkout refused");
+            return Err("Nested get_conn: this thread already holds a database connection".to_string());
+        }
+
+        let mut in_use = self.inner.in_use.lock().unwrap();
+
+        loop {
+            if let Some(conn) = self.inner.idle.lock().unwrap().pop() {
+                *in_use += 1;
+                return Ok(self.checked_out(conn, holder));
+            }
+
+            if *in_use < MAX_CONNECTIONS {
+                *in_use += 1;
+                drop(in_use);
+
+                return match open_connection(&self.inner.path) {
+                    Ok(conn) => Ok(self.checked_out(conn, holder)),
+                    Err(e) => {
+                        *self.inner.in_use.lock().unwrap() -= 1;
+                        self.inner.available.notify_one();
+                        Err(e)
+                    }
+                };
+            }
+
+            in_use = self.inner.available.wait(in_use).unwrap();
+        }
+    }
+
+    fn checked_out(&self, conn: Connection, holder: ThreadId) -> PooledConnection {
+        self.inner.holders.lock().unwrap().push(holder);
+        PooledConnection { conn: Some(conn), pool: self.inner.clone(), holder }
+    }
+}
+
+impl Deref for PooledConnection {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.conn.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledConnection {
+    fn deref_mut(&mut self) -> &mut Connection {
+        self.conn.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            // A connection left inside a transaction (e.g. after a panic) must not be reused
+            if conn.is_autocommit() {
+                self.pool.idle.lock().unwrap().push(conn);
+            }
+        }
+        // Matched by the thread that checked it out, wherever the connection is dropped
+        let mut holders = self.pool.holders.lock().unwrap();
+        if let Some(index) = holders.iter().position(|h| *h == self.holder) {
+            holders.swap_remove(index);
+        }
+        drop(holders);
+
+        *self.pool.in_use.lock().unwrap() -= 1;
+        self.pool.available.notify_one();
+    }
+}
+
+fn open_connection(path: &PathBuf) -> Result<Connection, String> {
+    let conn = Connection::open(path).map_err(|e| e.to_string())?;
+
+    conn.busy_timeout(BUSY_TIMEOUT).map_err(|e| e.to_string())?;
+    conn.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+    conn.execute_batch(
+        "PRAGMA journal_mode = WAL;
+         PRAGMA synchronous = NORMAL;
+         PRAGMA foreign_keys = ON;"
+    ).map_err(|e| e.to_string())?;
+
+    Ok(conn)
+}
+
+/// Registers the pool in Tauri state. Called once from `init_db`.
+pub fn install(app_handle: &AppHandle, path: PathBuf) {
+    app_handle.manage(DbPool::new(path));
+}
+
+/// Checks out a pooled connection for the app database. Drop it before calling anything
+/// that checks out its own; see `DbPool::get`.
+pub fn get_conn(app_handle: &AppHandle) -> Result<PooledConnection, String> {
+    let pool = app_handle
+        .try_state::<DbPool>()
+        .ok_or_else(|| "Database not initialized".to_string())?;
+    pool.get()
+}
+
+fn is_busy(e: &rusqlite::Error) -> bool {
+    matches!(
+        e,
+        rusqlite::Error::SqliteFailure(err, _)
+            if err.code == ErrorCode::DatabaseBusy || err.code == ErrorCode::DatabaseLocked
+    )
+}
+
+/// Retries `f` with backoff while SQLite reports the database as busy or locked.
+///
+/// The busy timeout already covers ordinary lock waits; this catches the cases SQLite
+/// refuses to wait on (e.g. checkpoint contention) so they never reach the UI.
+pub fn retry_busy<T, F>(mut f: F) -> rusqlite::Result<T>
+where
+    F: FnMut() -> rusqlite::Result<T>,
+{
+    let mut attempt = 0;
+    loop {
+        match f() {
+            Err(e) if is_busy(&e) && attempt < 5 => {
+                attempt += 1;
+                log::debug!("SQLite busy, retrying (attempt {})", attempt);
+                std::thread::sleep(Duration::from_millis(50 * attempt));
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Runs `f` in an IMMEDIATE transaction and commits it, retrying the whole transaction
+/// while the database is busy (see `retry_busy`). `f` may therefore run more than once
+/// and must only write through `tx`.
+pub fn write_transaction<T, F>(conn: &mut Connection, mut f: F) -> Result<T, String>
+where
+    F: FnMut(&Transaction) -> rusqlite::Result<T>,
+{
+    retry_busy(|| {
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let value = f(&tx)?;
+        tx.commit()?;
+        Ok(value)
+    })
+    .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_db(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("db-pool-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("test.db")
+    }
+
+    #[test]
+    fn connections_are_reused_after_drop() {
+        let pool = DbPool::new(temp_db("reuse"));
+        pool.get().unwrap().execute_batch("CREATE TABLE t (x INTEGER)").unwrap();
+        pool.get().unwrap().execute("INSERT INTO t VALUES (1)", ()).unwrap();
+
+        assert_eq!(pool.inner.idle.lock().unwrap().len(), 1);
+        assert!(pool.inner.holders.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn nested_checkout_on_one_thread_fails() {
+        let pool = DbPool::new(temp_db("nested"));
+        let outer = pool.get().unwrap();
+        assert!(matches!(pool.get(), Err(e) if e.contains("Nested get_conn")));
+
+        // The refused checkout took nothing from the pool
+        assert_eq!(*pool.inner.in_use.lock().unwrap(), 1);
+        drop(outer);
+        let _again = pool.get().unwrap();
+    }
+
+    #[test]
+    fn connections_dropped_on_another_thread_release_their_holder() {
+        let pool = DbPool::new(temp_db("moved"));
+        let conn = pool.get().unwrap();
+        thread::spawn(move || drop(conn)).join().unwrap();
+
+        // The first checkout is no longer held, so this isn't a nested one
+        let _again = pool.get().unwrap();
+    }
+
+    #[test]
+    fn write_transaction_retries_while_another_writer_holds_the_lock() {
+        let path = temp_db("busy");
+        let pool = DbPool::new(path.clone());
+        pool.get().unwrap().execute_batch("CREATE TABLE t (x INTEGER)").unwrap();
+
+        let blocker = Connection::open(&path).unwrap();
+        blocker.execute_batch("BEGIN IMMEDIATE; INSERT INTO t VALUES (1);").unwrap();
+        let release = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(150));
+            blocker.execute_batch("COMMIT").unwrap();
+        });
+
+        let mut conn = pool.get().unwrap();
+        // No busy handler, so only the retry loop can get past the held lock
+        conn.busy_timeout(Duration::ZERO).unwrap();
+        let total: i64 = write_transaction(&mut conn, |tx| {
+            tx.execute("INSERT INTO t VALUES (2)", ())?;
+            tx.query_row("SELECT SUM(x) FROM t", (), |row| row.get(0))
+        })
+        .unwrap();
+        release.join().unwrap();
+
+        assert_eq!(total, 3);
+        assert!(conn.is_autocommit());
+    }
+
+    #[test]
+    fn write_transaction_rolls_back_on_error() {
+        let pool = DbPool::new(temp_db("rollback"));
+        let mut conn = pool.get().unwrap();
+        conn.execute_batch("CREATE TABLE t (x INTEGER NOT NULL)").unwrap();
+
+        let result = write_transaction(&mut conn, |tx| {
+            tx.execute("INSERT INTO t VALUES (1)", ())?;
+            tx.execute("INSERT INTO t VALUES (NULL)", ())
+        });
+
+        assert!(result.is_err());
+        assert_eq!(conn.query_row("SELECT COUNT(*) FROM t", (), |row| row.get::<_, i64>(0)).unwrap(), 0);
+    }
+}
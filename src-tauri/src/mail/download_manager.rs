@@ -0,0 +1,510 @@
+use crate::auth::account::Account;
+use crate::auth::session;
+use crate::mail::attachment_download::{self, DownloadProgress, DownloadStatus, DOWNLOAD_CANCELLED};
+use crate::mail::attachment_export;
+use crate::mail::db_pool::{get_conn, retry_busy};
+use crate::mail::message_body::FETCH_PERMITS;
+use crate::settings_store::JsonSettings;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter, Manager};
+
+/// Emitted with a `DownloadRecord` whenever a download is queued, makes progress or stops.
+pub const DOWNLOAD_UPDATED_EVENT: &str = "download:updated";
+
+/// Downloads transferring at once. Each takes a fetch permit per chunk, so staying one
+/// below `FETCH_PERMITS` leaves a permit that only body fetches compete for: opening a
+/// message waits for at most one chunk, however many downloads are queued.
+const MAX_CONCURRENT_DOWNLOADS: usize = FETCH_PERMITS - 1;
+
+/// Completed and cancelled downloads kept in the history; the oldest are forgotten first.
+/// Failed ones stay until cleared, since they may still have a partial file on disk.
+const MAX_HISTORY: i64 = 200;
+
+/// File types that run code when opened. `open` refuses them: a single click on a
+/// download shouldn't launch a program a stranger sent.
+const EXECUTABLE_EXTENSIONS: &[&str] = &[
+    // Windows
+    "exe", "com", "scr", "pif", "bat", "cmd", "msi", "msp", "msc", "cpl", "hta", "jar", "js", "jse",
+    "vb", "vbs", "vbe", "wsf", "wsh", "ws", "ps1", "psm1", "reg", "lnk", "url", "inf", "scf",
+    "application", "appref-ms", "gadget", "settingcontent-ms",
+    // macOS
+    "app", "command", "tool", "pkg", "mpkg", "terminal", "workflow", "scpt",
+    // Linux
+    "desktop", "sh", "run", "bin", "appimage",
+];
+
+const RECORD_COLUMNS: &str = "id, account, uid, part_id, file_name, path, status, received, total, error, created_at, finished_at";
+
+/// Where attachments go when the user doesn't pick a location for each one.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct DownloadSettings {
+    /// Folder attachments are saved to; `None` means the system Downloads folder.
+    pub directory: Option<String>,
+    /// Show a save dialog for every attachment instead of using `directory`.
+    pub ask_where_to_save: bool,
+}
+
+/// One entry of the download history, queued, running or finished.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DownloadRecord {
+    pub id: String,
+    pub account: String,
+    pub uid: u32,
+    pub part_id: String,
+    pub file_name: String,
+    /// Final location of the file; while downloading, it is written next to it as `.part`.
+    pub path: String,
+    pub status: DownloadStatus,
+    /// Encoded bytes fetched; with `total`, this gives the fraction done.
+    pub received: u64,
+    pub total: u64,
+    pub error: Option<String>,
+    pub created_at: i64,
+    pub finished_at: Option<i64>,
+}
+
+static SETTINGS: JsonSettings<DownloadSettings> = JsonSettings::new("download_settings.json");
+
+/// Ids waiting for a slot, in the order they were queued.
+static QUEUE: Lazy<Mutex<VecDeque<String>>> = Lazy::new(|| Mutex::new(VecDeque::new()));
+
+/// Ids of the downloads holding a slot.
+static RUNNING: Lazy<Mutex<HashSet<String>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// Loads download settings and settles downloads the previous run left unfinished.
+/// Called once during app setup, after the database is ready.
+pub fn init(app_handle: &AppHandle) {
+    SETTINGS.load(app_handle);
+
+    // Nothing survives a restart running; those downloads wait for the user to resume them
+    let interrupted = get_conn(app_handle).and_then(|conn| {
+        retry_busy(|| conn.execute(
+            "UPDATE downloads SET status = 'paused' WHERE status IN ('queued', 'downloading')",
+            (),
+        )).map_err(|e| e.to_string())
+    });
+    if let Err(e) = interrupted {
+        log::warn!("Failed to restore download history: {}", e);
+    }
+}
+
+pub fn current_settings() -> DownloadSettings {
+    SETTINGS.get()
+}
+
+pub fn save_settings(app_handle: &AppHandle, mut settings: DownloadSettings) -> Result<(), String> {
+    settings.directory = settings.directory.map(|d| d.trim().to_string()).filter(|d| !d.is_empty());
+    if let Some(directory) = &settings.directory {
+        if !Path::new(directory).is_dir() {
+            return Err(format!("Download folder not found: {}", directory));
+        }
+    }
+    SETTINGS.save(app_handle, settings)
+}
+
+/// The folder attachments are saved to when no path is given.
+pub fn download_directory(app_handle: &AppHandle) -> Result<PathBuf, String> {
+    match current_settings().directory {
+        Some(directory) => Ok(PathBuf::from(directory)),
+        None => app_handle
+            .path()
+            .download_dir()
+            .map_err(|e| format!("Failed to resolve Downloads folder: {}", e)),
+    }
+}
+
+fn status_name(status: DownloadStatus) -> &'static str {
+    match status {
+        DownloadStatus::Queued => "queued",
+        DownloadStatus::Downloading => "downloading",
+        DownloadStatus::Paused => "paused",
+        DownloadStatus::Completed => "completed",
+        DownloadStatus::Failed => "failed",
+        DownloadStatus::Cancelled => "cancelled",
+    }
+}
+
+fn parse_status(name: &str) -> DownloadStatus {
+    match name {
+        "queued" => DownloadStatus::Queued,
+        "downloading" => DownloadStatus::Downloading,
+        "paused" => DownloadStatus::Paused,
+        "completed" => DownloadStatus::Completed,
+        "cancelled" => DownloadStatus::Cancelled,
+        _ => DownloadStatus::Failed,
+    }
+}
+
+fn is_finished(status: DownloadStatus) -> bool {
+    matches!(status, DownloadStatus::Completed | DownloadStatus::Failed | DownloadStatus::Cancelled)
+}
+
+fn row_to_record(row: &rusqlite::Row) -> rusqlite::Result<DownloadRecord> {
+    Ok(DownloadRecord {
+        id: row.get(0)?,
+        account: row.get(1)?,
+        uid: row.get(2)?,
+        part_id: row.get(3)?,
+        file_name: row.get(4)?,
+        path: row.get(5)?,
+        status: parse_status(&row.get::<_, String>(6)?),
+        received: row.get::<_, i64>(7)?.max(0) as u64,
+        total: row.get::<_, i64>(8)?.max(0) as u64,
+        error: row.get(9)?,
+        created_at: row.get(10)?,
+        finished_at: row.get(11)?,
+    })
+}
+
+fn load_record(app_handle: &AppHandle, id: &str) -> Result<DownloadRecord, String> {
+    let conn = get_conn(app_handle)?;
+    let mut stmt = conn
+        .prepare_cached(&format!("SELECT {} FROM downloads WHERE id = ?1", RECORD_COLUMNS))
+        .map_err(|e| e.to_string())?;
+    stmt.query_row([id], row_to_record).map_err(|e| match e {
+        rusqlite::Error::QueryReturnedNoRows => format!("Download {} not found", id),
+        e => e.to_string(),
+    })
+}
+
+/// The whole history, newest first.
+pub fn list(app_handle: &AppHandle) -> Result<Vec<DownloadRecord>, String> {
+    let conn = get_conn(app_handle)?;
+    let mut stmt = conn
+        .prepare_cached(&format!("SELECT {} FROM downloads ORDER BY created_at DESC, rowid DESC", RECORD_COLUMNS))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt.query_map([], row_to_record).map_err(|e| e.to_string())?;
+
+    let mut records = Vec::new();
+    for r in rows {
+        records.push(r.map_err(|e| e.to_string())?);
+    }
+    Ok(records)
+}
+
+fn emit_record(app_handle: &AppHandle, id: &str) {
+    let Ok(record) = load_record(app_handle, id) else { return };
+    if let Err(e) = app_handle.emit(DOWNLOAD_UPDATED_EVENT, &record) {
+        log::warn!("Failed to emit {}: {}", DOWNLOAD_UPDATED_EVENT, e);
+    }
+}
+
+fn set_status(app_handle: &AppHandle, id: &str, status: DownloadStatus, error: Option<&str>) -> Result<(), String> {
+    let finished_at = is_finished(status).then(|| chrono::Utc::now().timestamp());
+    let conn = get_conn(app_handle)?;
+    retry_busy(|| conn.execute(
+        "UPDATE downloads SET status = ?1, error = ?2, finished_at = ?3 WHERE id = ?4",
+        rusqlite::params![status_name(status), error, finished_at, id],
+    )).map_err(|e| e.to_string())?;
+    drop(conn);
+    emit_record(app_handle, id);
+    Ok(())
+}
+
+/// Mirrors a download's progress into its history entry. Called by `attachment_download`
+/// for every event it emits.
+pub fn record_progress(app_handle: &AppHandle, progress: &DownloadProgress) {
+    let finished_at = is_finished(progress.status).then(|| chrono::Utc::now().timestamp());
+    // Pausing and cancelling end the transfer with an error too, but aren't failures
+    let error = progress.error.as_deref().filter(|_| progress.status == DownloadStatus::Failed);
+
+    let updated = get_conn(app_handle).and_then(|conn| {
+        retry_busy(|| conn.execute(
+            "UPDATE downloads SET status = ?1, received = ?2, total = ?3, error = ?4, finished_at = ?5 WHERE id = ?6",
+            rusqlite::params![
+                status_name(progress.status),
+                progress.received as i64,
+                progress.total as i64,
+                error,
+                finished_at,
+                progress.id,
+            ],
+        )).map_err(|e| e.to_string())
+    });
+    match updated {
+        Ok(0) => {}
+        Ok(_) => emit_record(app_handle, &progress.id),
+        Err(e) => log::warn!("Failed to record download progress: {}", e),
+    }
+}
+
+/// Where a new download of `file_name` goes: `save_path` as given, since the save dialog
+/// already asked about overwriting, or else the download folder under a name that collides
+/// with neither a file there, a partial file, nor another unfinished download.
+fn resolve_destination(app_handle: &AppHandle, file_name: &str, save_path: Option<String>) -> Result<String, String> {
+    if let Some(path) = save_path.filter(|p| !p.trim().is_empty()) {
+        return Ok(path);
+    }
+
+    let dir = download_directory(app_handle)?;
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+
+    let mut taken: HashSet<String> = list(app_handle)?
+        .into_iter()
+        .filter(|r| !is_finished(r.status))
+        .filter_map(|r| {
+            let path = PathBuf::from(r.path);
+            (path.parent() == Some(dir.as_path()))
+                .then(|| path.file_name().map(|n| n.to_string_lossy().to_lowercase()))
+                .flatten()
+        })
+        .collect();
+
+    let name = attachment_export::unique_name(&attachment_export::safe_file_name(file_name), &mut taken, |candidate| {
+        let path = dir.join(candidate);
+        path.exists() || attachment_download::partial_path(&path.to_string_lossy()).exists()
+    });
+    Ok(dir.join(name).to_string_lossy().into_owned())
+}
+
+/// Forgets the oldest completed and cancelled downloads beyond `MAX_HISTORY`.
+fn prune_history(conn: &rusqlite::Connection) -> rusqlite::Result<usize> {
+    conn.execute(
+        "DELETE FROM downloads WHERE status IN ('completed', 'cancelled') AND id NOT IN
+            (SELECT id FROM downloads ORDER BY created_at DESC, rowid DESC LIMIT ?1)",
+        [MAX_HISTORY],
+    )
+}
+
+/// Queues attachment `part_id` of `uid` and returns its history entry straight away;
+/// progress follows as `DOWNLOAD_UPDATED_EVENT`s.
+pub fn enqueue(
+    app_handle: &AppHandle,
+    account: &Account,
+    uid: u32,
+    part_id: String,
+    file_name: String,
+    save_path: Option<String>,
+) -> Result<DownloadRecord, String> {
+    let now = chrono::Utc::now();
+    let id = format!("{}-{}-{}", uid, part_id.replace('.', "_"), now.timestamp_millis());
+
+    {
+        // Held while picking the name, so two downloads can't settle on the same one
+        let mut queue = QUEUE.lock().unwrap();
+        let path = resolve_destination(app_handle, &file_name, save_path)?;
+
+        let conn = get_conn(app_handle)?;
+        retry_busy(|| conn.execute(
+            "INSERT INTO downloads (id, account, uid, part_id, file_name, path, status, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, 'queued', ?7)",
+            rusqlite::params![id, account.email, uid, part_id, file_name, path, now.timestamp()],
+        )).map_err(|e| e.to_string())?;
+        if let Err(e) = retry_busy(|| prune_history(&conn)) {
+            log::warn!("Failed to prune download history: {}", e);
+        }
+        queue.push_back(id.clone());
+    }
+
+    emit_record(app_handle, &id);
+    pump(app_handle);
+    load_record(app_handle, &id)
+}
+
+/// Starts queued downloads while slots are free.
+fn pump(app_handle: &AppHandle) {
+    loop {
+        let id = {
+            let mut running = RUNNING.lock().unwrap();
+            if running.len() >= MAX_CONCURRENT_DOWNLOADS {
+                return;
+            }
+            let Some(id) = QUEUE.lock().unwrap().pop_front() else { return };
+            running.insert(id.clone());
+            id
+        };
+
+        let app_handle = app_handle.clone();
+        tauri::async_runtime::spawn(async move {
+            run_download(&app_handle, &id).await;
+            RUNNING.lock().unwrap().remove(&id);
+            pump(&app_handle);
+        });
+    }
+}
+
+/// Continues a download from its saved state if it has one, and starts it otherwise.
+async fn run_download(app_handle: &AppHandle, id: &str) {
+    let result = async {
+        let record = load_record(app_handle, id)?;
+        let account = session::load_accounts(app_handle)
+            .into_iter()
+            .find(|a| a.email.eq_ignore_ascii_case(&record.account))
+            .ok_or("The account this download belongs to was removed")?;
+
+        set_status(app_handle, id, DownloadStatus::Downloading, None)?;
+        if attachment_download::has_state(app_handle, id) {
+            attachment_download::resume(app_handle, &account, id).await
+        } else {
+            attachment_download::start(app_handle, &account, id.to_string(), record.uid, record.part_id, record.path).await
+        }
+    }
+    .await;
+
+    // Errors from before the transfer began never reached `record_progress`
+    if let Err(e) = result {
+        let still_downloading = load_record(app_handle, id).is_ok_and(|r| r.status == DownloadStatus::Downloading);
+        if still_downloading && e != DOWNLOAD_CANCELLED {
+            if let Err(e) = set_status(app_handle, id, DownloadStatus::Failed, Some(e.as_str())) {
+                log::warn!("Failed to record download failure: {}", e);
+            }
+        }
+    }
+}
+
+fn remove_queued(id: &str) -> bool {
+    let mut queue = QUEUE.lock().unwrap();
+    let before = queue.len();
+    queue.retain(|queued| queued != id);
+    queue.len() != before
+}
+
+/// Pauses a running download, keeping its partial file for `retry`. A queued one just
+/// leaves the queue.
+pub fn pause(app_handle: &AppHandle, id: &str) -> Result<(), String> {
+    if remove_queued(id) {
+        return set_status(app_handle, id, DownloadStatus::Paused, None);
+    }
+    attachment_download::cancel(app_handle, id, false)
+}
+
+/// Stops a download and deletes what it fetched. The entry stays in the history as cancelled.
+pub fn cancel(app_handle: &AppHandle, id: &str) -> Result<(), String> {
+    let record = load_record(app_handle, id)?;
+    if record.status == DownloadStatus::Completed {
+        return Err("This download has already finished".to_string());
+    }
+
+    let running = RUNNING.lock().unwrap().contains(id);
+    remove_queued(id);
+    attachment_download::cancel(app_handle, id, true)?;
+    // A running download reports its own cancellation once the current chunk is in
+    if !running && record.status != DownloadStatus::Cancelled {
+        set_status(app_handle, id, DownloadStatus::Cancelled, None)?;
+    }
+    Ok(())
+}
+
+/// Queues a paused, failed or cancelled download again. It continues from the last chunk
+/// when its partial file is still there, and starts over otherwise.
+pub fn retry(app_handle: &AppHandle, id: &str) -> Result<DownloadRecord, String> {
+    let record = load_record(app_handle, id)?;
+    if !matches!(record.status, DownloadStatus::Paused | DownloadStatus::Failed | DownloadStatus::Cancelled) {
+        return Err("Only stopped downloads can be retried".to_string());
+    }
+
+    let running = RUNNING.lock().unwrap().contains(id);
+    {
+        let mut queue = QUEUE.lock().unwrap();
+        if running || queue.iter().any(|queued| queued == id) {
+            return Err("Download already running".to_string());
+        }
+        set_status(app_handle, id, DownloadStatus::Queued, None)?;
+        queue.push_back(id.to_string());
+    }
+
+    pump(app_handle);
+    load_record(app_handle, id)
+}
+
+/// Whether opening `path` would run it rather than show it. Trailing dots and spaces are
+/// ignored, as Windows does.
+fn is_executable(path: &str) -> bool {
+    Path::new(path.trim_end_matches(['.', ' ']))
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|extension| EXECUTABLE_EXTENSIONS.iter().any(|e| extension.eq_ignore_ascii_case(e)))
+}
+
+/// Opens a completed download with the system's default application. Programs and
+/// scripts are refused; the user can still run them from their folder.
+pub fn open(app_handle: &AppHandle, id: &str) -> Result<(), String> {
+    let record = load_record(app_handle, id)?;
+    if record.status != DownloadStatus::Completed {
+        return Err("This download hasn't finished".to_string());
+    }
+    if !Path::new(&record.path).exists() {
+        return Err(format!("{} was moved or deleted", record.path));
+    }
+    if is_executable(&record.path) {
+        return Err(format!("{} is a program or script, so it isn't opened from here. Open it from its folder if you trust the sender.", record.file_name));
+    }
+    open::that(&record.path).map_err(|e| format!("Failed to open {}: {}", record.path, e))
+}
+
+/// Forgets finished downloads. Failed ones also lose their partial file.
+pub fn clear_history(app_handle: &AppHandle) -> Result<(), String> {
+    for record in list(app_handle)? {
+        if record.status == DownloadStatus::Failed && !RUNNING.lock().unwrap().contains(&record.id) {
+            attachment_download::cancel(app_handle, &record.id, true)?;
+        }
+    }
+
+    let conn = get_conn(app_handle)?;
+    retry_busy(|| conn.execute(
+        "DELETE FROM downloads WHERE status IN ('completed', 'failed', 'cancelled')",
+        (),
+    )).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Stops and forgets every download of `email`: queued ones leave the queue, running ones
+/// stop and delete their partial file, and the history entries and saved states go.
+/// Completed files stay where they were saved. Called on logout.
+pub fn purge_account(app_handle: &AppHandle, email: &str) -> Result<(), String> {
+    for record in list(app_handle)?.into_iter().filter(|r| r.account.eq_ignore_ascii_case(email)) {
+        remove_queued(&record.id);
+        if record.status != DownloadStatus::Completed {
+            attachment_download::cancel(app_handle, &record.id, true)?;
+        }
+    }
+    attachment_download::purge_account(app_handle, email);
+
+    let conn = get_conn(app_handle)?;
+    retry_busy(|| conn.execute("DELETE FROM downloads WHERE account = ?1 COLLATE NOCASE", [email]))
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn programs_and_scripts_are_executable() {
+        for path in [
+            "/home/u/Downloads/setup.exe",
+            "C:\\Users\\u\\Downloads\\invoice.pdf.EXE",
+            "C:\\Users\\u\\Downloads\\run.bat. . ",
+            "/tmp/shortcut.lnk",
+            "/Users/u/Downloads/install.command",
+            "/home/u/Downloads/app.desktop",
+            "/home/u/Downloads/tool.AppImage",
+            "/home/u/Downloads/script.ps1",
+        ] {
+            assert!(is_executable(path), "{}", path);
+        }
+    }
+
+    #[test]
+    fn documents_are_not_executable() {
+        for path in ["/home/u/Downloads/report.pdf", "/home/u/Downloads/exe", "/home/u/Downloads/photo.jpg", "/home/u/Downloads/notes", "/home/u/.bashrc"] {
+            assert!(!is_executable(path), "{}", path);
+        }
+    }
+
+    #[test]
+    fn statuses_round_trip() {
+        use DownloadStatus::*;
+        for status in [Queued, Downloading, Paused, Completed, Failed, Cancelled] {
+            assert_eq!(parse_status(status_name(status)), status);
+        }
+    }
+}
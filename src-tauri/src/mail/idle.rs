@@ -10,8 +10,6 @@ use once_cell::sync::Lazy;
 use tokio::task::JoinHandle;
 use tokio::sync::mpsc;
 
-use native_tls::TlsConnector;
-
 static IDLE_TASK: Lazy<Mutex<Option<JoinHandle<()>>>> =
     Lazy::new(|| Mutex::new(None));
 
@@ -110,18 +108,14 @@ async fn run_idle_loop(
 
     let email = current_account.email;
     let access_token = current_account.access_token;
+    let server_config = current_account.imap;
 
     tokio::task::spawn_blocking(move || {
 
         // ===============================
         // Connect
         // ===============================
-        let tls = TlsConnector::builder()
-            .build()
-            .map_err(|e| format!("TLS Error: {}", e))?;
-
-        let client = imap::connect(("imap.gmail.com", 993), "imap.gmail.com", &tls)
-            .map_err(|e| format!("Connect Error: {}", e))?;
+        let client = crate::mail::tls::connect_imap(&server_config)?;
 
         let auth_raw = format!("user={}\x01auth=Bearer {}\x01\x01", email, access_token);
 
@@ -1,6 +1,5 @@
 use crate::auth::account::Account;
 use std::time::Duration;
-use native_tls::TlsConnector;
 
 #[derive(Debug, serde::Serialize)]
 pub struct Mailbox {
@@ -13,21 +12,14 @@ pub struct Mailbox {
 pub async fn get_mailboxes(account: Account) -> Result<Vec<Mailbox>, String> {
     let email = account.email.clone();
     let access_token = account.access_token.clone();
+    let server_config = account.imap.clone();
 
     // IMAP crate is blocking → run in blocking thread
     let handle = tokio::task::spawn_blocking(move || {
-        let domain = "imap.gmail.com";
-        let port = 993;
-
         // --------------------------------------------------
         // 1. TLS CONNECTION
         // --------------------------------------------------
-        let tls = TlsConnector::builder()
-            .build()
-            .map_err(|e| format!("TLS Error: {}", e))?;
-
-        let client = imap::connect((domain, port), domain, &tls)
-            .map_err(|e| format!("IMAP Connection Error: {}", e))?;
+        let client = crate::mail::tls::connect_imap(&server_config)?;
 
         // --------------------------------------------------
         // 2. BUILD XOAUTH2 STRING (IMPORTANT)
@@ -5,7 +5,6 @@ use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::Mutex as AsyncMutex;
 use std::time::Instant;
-use native_tls::TlsConnector;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum SessionKind {
@@ -43,16 +42,7 @@ pub fn create_session(account: &Account) -> Result<ImapSession, String> {
 fn connect_and_authenticate(
     account: &Account,
 ) -> Result<imap::Session<native_tls::TlsStream<std::net::TcpStream>>, String> {
-    let domain = "imap.gmail.com";
-    let port = 993;
-
-    let tls = TlsConnector::builder()
-        .danger_accept_invalid_certs(true)
-        .build()
-        .map_err(|e| format!("TLS Builder Error: {}", e))?;
-
-    let client = imap::connect((domain, port), domain, &tls)
-        .map_err(|e| format!("IMAP Connection Error: {}", e))?;
+    let client = crate::mail::tls::connect_imap(&account.imap)?;
 
     let auth_raw = format!(
         "user={}\x01auth=Bearer {}\x01\x01",
@@ -159,3 +149,28 @@ where
         // owned_guard drops here, releasing the tokio async mutex naturally!
     }).await.map_err(|e| format!("Spawn blocking error: {}", e))?
 }
+
+/// Removes every pooled session belonging to `email` and logs them out.
+/// Waits for any in-flight operation on a session to finish before closing it.
+pub async fn close_sessions_for(email: &str) {
+    let removed: Vec<Arc<ManagedSession>> = {
+        let mut pools = SESSION_MANAGER.lock().unwrap();
+        let keys: Vec<(String, SessionKind)> = pools
+            .keys()
+            .filter(|(owner, _)| owner == email)
+            .cloned()
+            .collect();
+        keys.into_iter().filter_map(|key| pools.remove(&key)).collect()
+    };
+
+    for managed in removed {
+        let mut guard = managed.session.clone().lock_owned().await;
+        if let Some(mut s) = guard.take() {
+            let _ = tokio::task::spawn_blocking(move || {
+                let _ = s.session.logout();
+            }).await;
+        }
+    }
+
+    log::info!("Closed pooled IMAP sessions for removed account.");
+}
@@ -0,0 +1,54 @@
+use crate::mail::database;
+use crate::mail::message_body::{attachment_index_names, body_index_text, MessageAttachment};
+use std::time::Duration;
+use tauri::AppHandle;
+
+const BATCH_SIZE: u32 = 50;
+
+/// Indexes message bodies that were cached before body search existed.
+/// Works in small batches with pauses so it never competes with foreground queries.
+pub fn spawn_body_index_backfill(app_handle: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut total = 0usize;
+
+        loop {
+            let app = app_handle.clone();
+            let indexed = tokio::task::spawn_blocking(move || {
+                let rows = database::get_unindexed_bodies(&app, BATCH_SIZE)?;
+
+                let entries: Vec<(i64, String, String)> = rows
+                    .into_iter()
+                    .map(|(rowid, html, attachments_json)| {
+                        let attachments: Vec<MessageAttachment> = attachments_json
+                            .and_then(|json| serde_json::from_str(&json).ok())
+                            .unwrap_or_default();
+                        (rowid, body_index_text(&html), attachment_index_names(&attachments))
+                    })
+                    .collect();
+
+                database::set_body_index_text(&app, &entries)?;
+                Ok::<usize, String>(entries.len())
+            })
+            .await;
+
+            match indexed {
+                Ok(Ok(0)) => break,
+                Ok(Ok(n)) => total += n,
+                Ok(Err(e)) => {
+                    log::error!("Body index backfill failed: {}", e);
+                    break;
+                }
+                Err(e) => {
+                    log::error!("Body index backfill task failed: {}", e);
+                    break;
+                }
+            }
+
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+
+        if total > 0 {
+            log::info!("Body index backfill complete: {} messages indexed.", total);
+        }
+    });
+}
@@ -1,6 +1,10 @@
 use crate::auth::account::Account;
 use crate::mail::database;
 use crate::mail::imap_session;
+use crate::mail::mime_decode;
+use crate::mail::openpgp::{self, PgpPayload, PgpStatus};
+use crate::mail::remote_content::{self, RemoteContentReport};
+use crate::mail::sanitize;
 use imap_proto::types::BodyStructure;
 
 use tauri::{AppHandle, Manager};
@@ -8,23 +12,17 @@ use mailparse::{parse_mail, ParsedMail};
 use tokio::sync::Semaphore;
 use std::sync::Arc;
 use once_cell::sync::Lazy;
-pub static CONCURRENT_FETCH_LIMIT: Lazy<Arc<Semaphore>> = Lazy::new(|| Arc::new(Semaphore::new(3)));
+/// IMAP fetches allowed in flight at once, shared by body fetches, prefetch and downloads.
+pub const FETCH_PERMITS: usize = 3;
+pub static CONCURRENT_FETCH_LIMIT: Lazy<Arc<Semaphore>> = Lazy::new(|| Arc::new(Semaphore::new(FETCH_PERMITS)));
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use regex::Regex;
 
-use std::time::{SystemTime, UNIX_EPOCH};
-use std::sync::OnceLock;
-
-static SESSION_DIR_NAME: OnceLock<String> = OnceLock::new();
-
-fn get_session_dir_name() -> &'static str {
-    SESSION_DIR_NAME.get_or_init(|| {
-        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
-        format!("session_{}_{}", now, std::process::id())
-    })
-}
+use std::path::PathBuf;
+use base64::Engine;
+use sha2::{Digest, Sha256};
 
 use serde::{Serialize, Deserialize};
 
@@ -38,17 +36,22 @@ pub struct MessageAttachment {
     pub type_mime: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct MessageDetail {
     pub body: String,
     pub attachments: Vec<MessageAttachment>,
+    /// Filled in when the body is served for display; the cached body keeps all references.
+    #[serde(default)]
+    pub remote_content: RemoteContentReport,
+    /// Set for PGP/MIME messages: whether they were decrypted and whose signature they carry.
+    #[serde(default)]
+    pub pgp: Option<PgpStatus>,
 }
 
 struct CidCandidate {
     cid: String,
     part_index: usize,
-    mime: String,
 }
 
 struct MimeParts {
@@ -59,6 +62,14 @@ struct MimeParts {
     all_parts: Vec<Vec<u8>>, // store raw body bytes instead of lifetimes
 }
 
+/// Text of a part, with the charset label checked against the content rather than trusted.
+/// mailparse reports `us-ascii` when there is no label, so the raw parameter is used.
+fn decode_text_part(part: &ParsedMail, is_html: bool) -> Option<String> {
+    let raw = part.get_body_raw().ok()?;
+    let label = part.ctype.params.get("charset").map(String::as_str);
+    Some(mime_decode::decode_text(&raw, label, is_html))
+}
+
 impl MimeParts {
     fn new() -> Self {
         Self {
@@ -92,20 +103,19 @@ impl MimeParts {
                 self.cid_candidates.push(CidCandidate {
                     cid: cid_val,
                     part_index: current_index,
-                    mime: ctype.clone(),
                 });
             }
         }
 
         if part.subparts.is_empty() {
             if ctype == "text/html" {
-                if let Ok(body) = part.get_body() {
+                if let Some(body) = decode_text_part(part, true) {
                     if body.trim().len() >= 20 {
                         self.best_html = Some(body);
                     }
                 }
             } else if ctype == "text/plain" {
-                if let Ok(body) = part.get_body() {
+                if let Some(body) = decode_text_part(part, false) {
                     if !body.trim().is_empty() {
                         self.best_text = Some(body);
                     }
@@ -119,69 +129,66 @@ impl MimeParts {
     }
 }
 
+/// `src="cid:..."` and CSS `url(cid:...)` references in sanitized HTML.
+static CID_REFERENCE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?i)(src="|url\((?:&quot;|')?)cid:([^"'\s)&<>]+)"#).unwrap()
+});
+
+/// Largest inline part kept in the on-disk cache.
+const MAX_INLINE_PART_BYTES: usize = 5 * 1024 * 1024;
+
+/// Path on the message protocol that serves the inline part with this Content-ID.
+pub fn inline_part_url(uid: u32, cid: &str) -> String {
+    format!("/{}/cid/{}", uid, base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(cid))
+}
+
+/// Cache file for an inline part. The Content-ID is hashed so arbitrary IDs make safe names.
+pub fn inline_part_path(app_handle: &AppHandle, uid: u32, cid: &str) -> Option<PathBuf> {
+    let cache_dir = app_handle.path().app_cache_dir().ok()?;
+    let name: String = Sha256::digest(cid.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect();
+    Some(cache_dir.join("orbitmail_inline").join(uid.to_string()).join(name))
+}
+
+/// Points `cid:` references at the message protocol, which serves the part by Content-ID.
+/// Returns the referenced parts that came with the fetched payload, by Content-ID; the
+/// rest are fetched from the server the first time they are requested.
 fn rewrite_cid_images(
-    app_handle: &AppHandle, 
     uid: u32, 
-    mut html: String, 
+    html: String, 
     parts: &MimeParts
-) -> String {
-    if parts.cid_candidates.is_empty() {
-        return html;
-    }
-
-    let Ok(re) = Regex::new(r#"(?i)src\s*=\s*["']?\s*cid:([^"'\s>]+)"#) else {
-        return html;
-    };
-
+) -> (String, HashMap<String, Vec<u8>>) {
     let mut referenced_cids = HashSet::new();
-    for cap in re.captures_iter(&html) {
-        if let Some(m) = cap.get(1) {
-            referenced_cids.insert(m.as_str().to_string());
-        }
-    }
-
-    if referenced_cids.is_empty() {
-        return html;
-    }
+    let rewritten = CID_REFERENCE.replace_all(&html, |cap: &regex::Captures| {
+        referenced_cids.insert(cap[2].to_string());
+        format!("{}{}", &cap[1], inline_part_url(uid, &cap[2]))
+    }).into_owned();
+
+    let inline = parts.cid_candidates
+        .iter()
+        .filter(|candidate| referenced_cids.contains(&candidate.cid))
+        .map(|candidate| (candidate.cid.clone(), parts.all_parts[candidate.part_index].clone()))
+        .filter(|(_, bytes)| bytes.len() <= MAX_INLINE_PART_BYTES)
+        .collect();
+
+    (rewritten, inline)
+}
 
-    let Ok(cache_dir) = app_handle.path().app_cache_dir() else { return html };
-    let inline_dir = cache_dir.join("orbitmail_inline").join(get_session_dir_name());
-    let _ = fs::create_dir_all(&inline_dir);
-    
-    for candidate in &parts.cid_candidates {
-        if referenced_cids.contains(&candidate.cid) {
-            let safe_cid = candidate.cid.replace(|c: char| !c.is_ascii_alphanumeric(), "_");
-            let ext = match candidate.mime.as_str() {
-                "image/png" => "png",
-                "image/jpeg" | "image/jpg" => "jpg",
-                "image/gif" => "gif",
-                "image/webp" => "webp",
-                _ => "bin",
-            };
-            let file_name = format!("uid_{}_cid_{}.{}", uid, safe_cid, ext);
-            let filepath = inline_dir.join(&file_name);
-
-            // Write if missing and under 5MB
-            if !filepath.exists() {
-                let raw_bytes = &parts.all_parts[candidate.part_index];
-                if raw_bytes.len() <= 5 * 1024 * 1024 {
-                    let _ = fs::write(&filepath, raw_bytes);
-                }
+/// Writes inline parts to the on-disk cache the message protocol serves them from.
+/// Never called for decrypted messages, whose parts stay in memory.
+fn cache_inline_parts(app_handle: &AppHandle, uid: u32, inline: HashMap<String, Vec<u8>>) {
+    for (cid, bytes) in inline {
+        let Some(filepath) = inline_part_path(app_handle, uid, &cid) else { continue };
+        if !filepath.exists() {
+            if let Some(dir) = filepath.parent() {
+                let _ = fs::create_dir_all(dir);
             }
-
-            // Tauri asset replacement string
-            let asset_url = format!("asset://localhost/{}", filepath.to_string_lossy().replace('\\', "/"));
-            
-            // String Rewrite
-            let cid_pattern = format!("cid:{}", candidate.cid);
-            html = html.replace(&cid_pattern, &asset_url);
+            let _ = fs::write(&filepath, bytes);
         }
     }
-
-    html
 }
 
-fn extract_displayable_body(app_handle: &AppHandle, uid: u32, raw_email: &[u8]) -> Result<String, String> {
+/// The body to display, sanitized, and the inline parts it references by Content-ID.
+fn extract_displayable_body(uid: u32, raw_email: &[u8]) -> Result<(String, HashMap<String, Vec<u8>>), String> {
     // If it's a full email or section with MIME prepended, parse_mail works.
     let parsed_res = parse_mail(raw_email);
     
@@ -195,18 +202,36 @@ fn extract_displayable_body(app_handle: &AppHandle, uid: u32, raw_email: &[u8])
             let escaped = text.replace("&", "&amp;").replace("<", "&lt;").replace(">", "&gt;");
             format!("<pre style=\"white-space:pre-wrap;font-family:system-ui\">{}</pre>", escaped)
         } else {
-            let fallback = parsed.get_body().unwrap_or_else(|_| String::from_utf8_lossy(raw_email).to_string());
+            let fallback = decode_text_part(&parsed, false).unwrap_or_else(|| mime_decode::decode_text(raw_email, None, false));
             let escaped = fallback.replace("&", "&amp;").replace("<", "&lt;").replace(">", "&gt;");
             format!("<pre style=\"white-space:pre-wrap;font-family:system-ui\">{}</pre>", escaped)
         };
-        rewrite_cid_images(app_handle, uid, html_content, &parts)
+        rewrite_cid_images(uid, sanitize::sanitize_html(&html_content), &parts)
     } else {
-        String::from_utf8_lossy(raw_email).to_string()
+        (sanitize::sanitize_html(&mime_decode::decode_text(raw_email, None, true)), HashMap::new())
     };
 
     Ok(base_html)
 }
 
+/// The plain-text body of a message without an HTML one, where inline signatures live.
+fn plain_text_body(raw_email: &[u8]) -> Option<String> {
+    let parsed = parse_mail(raw_email).ok()?;
+    let mut parts = MimeParts::new();
+    parts.traverse(&parsed);
+    if parts.best_html.is_some() {
+        return None;
+    }
+    parts.best_text
+}
+
+/// Removes every cached inline part, for every message.
+pub fn purge_inline_cache(app_handle: &AppHandle) {
+    if let Ok(cache_dir) = app_handle.path().app_cache_dir() {
+        let _ = fs::remove_dir_all(cache_dir.join("orbitmail_inline"));
+    }
+}
+
 fn format_size(bytes: u32) -> String {
     if bytes < 1024 {
         format!("{} B", bytes)
@@ -254,6 +279,26 @@ fn collect_parts_from_bs<'a>(
     }
 }
 
+/// Section of the image part whose Content-ID is `cid`, in the same dotted form as `part_id`.
+fn find_cid_section(bs: &BodyStructure, prefix: &str, cid: &str) -> Option<String> {
+    match bs {
+        BodyStructure::Basic { common, other, .. } if common.ty.ty.eq_ignore_ascii_case("image") => {
+            let id = other.id?.trim().trim_start_matches('<').trim_end_matches('>');
+            id.eq_ignore_ascii_case(cid)
+                .then(|| if prefix.is_empty() { "1".to_string() } else { prefix.to_string() })
+        }
+        BodyStructure::Multipart { bodies, .. } => bodies.iter().enumerate().find_map(|(i, part)| {
+            let part_id = if prefix.is_empty() {
+                format!("{}", i + 1)
+            } else {
+                format!("{}.{}", prefix, i + 1)
+            };
+            find_cid_section(part, &part_id, cid)
+        }),
+        _ => None,
+    }
+}
+
 fn check_is_attachment(common: &imap_proto::types::BodyContentCommon, octets: u32, part_id: &str, attachments: &mut Vec<MessageAttachment>) {
     let mut is_attachment = false;
     let mut filename = String::new();
@@ -262,19 +307,15 @@ fn check_is_attachment(common: &imap_proto::types::BodyContentCommon, octets: u3
         if disp.ty.to_lowercase() == "attachment" {
             is_attachment = true;
         }
-        if let Some(params) = &disp.params {
-            if let Some(f) = params.iter().find(|(k, _)| k.to_lowercase() == "filename").map(|(_, v)| v.to_string()) {
-                filename = f;
-            }
+        if let Some(f) = mime_decode::body_param(&disp.params, "filename") {
+            filename = f;
         }
     }
 
     // Fallback to name parameter in Content-Type
-    if filename.is_empty() {
-        if let Some(params) = &common.ty.params {
-            if let Some(n) = params.iter().find(|(k, _)| k.to_lowercase() == "name").map(|(_, v)| v.to_string()) {
-                filename = n;
-            }
+    if filename.trim().is_empty() {
+        if let Some(n) = mime_decode::body_param(&common.ty.params, "name") {
+            filename = n;
         }
     }
 
@@ -297,12 +338,15 @@ fn _find_best_part<'a>(bs: &'a BodyStructure<'a>, prefix: &str) -> Option<String
 }
 
 
-fn generate_preview(html: &str) -> String {
+/// Upper bound on body text stored for full-text search, to keep the index compact.
+const MAX_INDEXED_BODY_CHARS: usize = 64 * 1024;
+
+/// Strips markup from rendered message HTML, leaving collapsed plain text.
+pub fn html_to_text(html: &str) -> String {
     let re_style = Regex::new(r"(?si)<style[^>]*>.*?</style>").unwrap();
     let re_script = Regex::new(r"(?si)<script[^>]*>.*?</script>").unwrap();
     let re_hidden = Regex::new(r"(?si)<[^>]*display\s*:\s*none[^>]*>.*?</[^>]+>").unwrap();
     let re_tags = Regex::new(r"(?si)<[^>]+>").unwrap();
-    let re_boiler = Regex::new(r"(?i)\b(unsubscribe|subscribe|view in browser|click here)\b").unwrap();
     let re_space = Regex::new(r"\s+").unwrap();
 
     let mut stripped = re_style.replace_all(html, " ").to_string();
@@ -318,8 +362,25 @@ fn generate_preview(html: &str) -> String {
     stripped = stripped.replace("&quot;", "\"");
     stripped = stripped.replace("&#39;", "'");
 
+    re_space.replace_all(&stripped, " ").trim().to_string()
+}
+
+/// Text indexed for body search: the stripped body, capped in length.
+pub fn body_index_text(html: &str) -> String {
+    html_to_text(html).chars().take(MAX_INDEXED_BODY_CHARS).collect()
+}
+
+/// Space-separated attachment filenames, indexed so searches can match them.
+pub fn attachment_index_names(attachments: &[MessageAttachment]) -> String {
+    attachments.iter().map(|a| a.name.as_str()).collect::<Vec<_>>().join(" ")
+}
+
+fn generate_preview(html: &str) -> String {
+    let re_boiler = Regex::new(r"(?i)\b(unsubscribe|subscribe|view in browser|click here)\b").unwrap();
+    let re_space = Regex::new(r"\s+").unwrap();
+
     // Boilerplate words
-    stripped = re_boiler.replace_all(&stripped, "").to_string();
+    let mut stripped = re_boiler.replace_all(&html_to_text(html), "").to_string();
 
     // Clean whitespace
     stripped = re_space.replace_all(&stripped, " ").to_string();
@@ -332,7 +393,120 @@ fn generate_preview(html: &str) -> String {
     }
 }
 
+/// Attachments inside a decrypted entity, numbered under `openpgp::DECRYPTED_PART_PREFIX`,
+/// with their decoded bytes by part id.
+fn decrypted_attachments(entity: &[u8]) -> (Vec<MessageAttachment>, HashMap<String, Vec<u8>>) {
+    fn collect(part: &ParsedMail, attachments: &mut Vec<MessageAttachment>, parts: &mut HashMap<String, Vec<u8>>) {
+        if !part.subparts.is_empty() {
+            for subpart in &part.subparts {
+                collect(subpart, attachments, parts);
+            }
+            return;
+        }
+
+        let disposition = part.get_content_disposition();
+        let filename = disposition.params.get("filename")
+            .or_else(|| part.ctype.params.get("name"))
+            .filter(|f| !f.trim().is_empty())
+            .cloned();
+        if disposition.disposition != mailparse::DispositionType::Attachment && filename.is_none() {
+            return;
+        }
+        let Ok(bytes) = part.get_body_raw() else { return };
+
+        let part_id = format!("{}{}", openpgp::DECRYPTED_PART_PREFIX, attachments.len() + 1);
+        attachments.push(MessageAttachment {
+            part_id: part_id.clone(),
+            name: filename.unwrap_or_else(|| "unnamed_attachment".to_string()),
+            size: format_size(bytes.len() as u32),
+            type_mime: part.ctype.mimetype.to_lowercase(),
+        });
+        parts.insert(part_id, bytes);
+    }
+
+    let mut attachments = Vec::new();
+    let mut parts = HashMap::new();
+    if let Ok(parsed) = parse_mail(entity) {
+        collect(&parsed, &mut attachments, &mut parts);
+    }
+    (attachments, parts)
+}
+
+/// From address of a cached message.
+fn message_sender(app_handle: &AppHandle, uid: u32) -> Option<String> {
+    database::load_messages_by_uids(app_handle, "INBOX", &[uid])
+        .ok()
+        .and_then(|headers| headers.into_iter().next())
+        .and_then(|header| header.addresses.from.into_iter().next())
+        .map(|from| from.email)
+}
+
+/// Decrypts or verifies a PGP/MIME message, then renders it like any other. Signed bodies
+/// are cached with their signature status; decrypted ones are only kept in memory.
+async fn open_pgp_message(
+    app_handle: &AppHandle,
+    account: &Account,
+    uid: u32,
+    payload: PgpPayload,
+    mut attachments: Vec<MessageAttachment>,
+) -> Result<MessageDetail, String> {
+    let app = app_handle.clone();
+    let email = account.email.clone();
+    tokio::task::spawn_blocking(move || {
+        let sender = message_sender(&app, uid);
+        let opened = openpgp::open(&app, payload, sender.as_deref());
+        let status = opened.status;
+
+        // Not cached either way, so unlocking or importing a key and reopening retries
+        let Some(entity) = opened.entity else {
+            let notice = if status.encrypted { "This message is encrypted." } else { "This message could not be read." };
+            return Ok(MessageDetail {
+                body: format!("<p style=\"font-family:system-ui;color:#888\">{}</p>", notice),
+                attachments,
+                remote_content: RemoteContentReport::default(),
+                pgp: Some(status),
+            });
+        };
+
+        let (body, inline) = extract_displayable_body(uid, &entity)?;
+
+        if status.encrypted {
+            let (decrypted, mut parts) = decrypted_attachments(&entity);
+            attachments.extend(decrypted);
+            // Decrypted images never touch the disk cache
+            parts.extend(inline.into_iter().map(|(cid, bytes)| (openpgp::inline_part_id(&cid), bytes)));
+            let detail = MessageDetail {
+                body,
+                attachments,
+                remote_content: RemoteContentReport::default(),
+                pgp: Some(status),
+            };
+            openpgp::remember(&email, uid, &detail, parts);
+            return Ok(detail);
+        }
+
+        cache_inline_parts(&app, uid, inline);
+        let preview = generate_preview(&body);
+        let attachments_json = serde_json::to_string(&attachments).ok();
+        let body_text = body_index_text(&body);
+        let attachment_names = attachment_index_names(&attachments);
+        let _ = database::update_message_body(&app, "INBOX", uid, &body, &preview, attachments_json, &body_text, &attachment_names);
+        let _ = database::set_message_pgp_status(&app, "INBOX", uid, serde_json::to_string(&status).ok());
+
+        Ok(MessageDetail {
+            body,
+            attachments,
+            remote_content: RemoteContentReport::default(),
+            pgp: Some(status),
+        })
+    }).await.map_err(|e| e.to_string())?
+}
+
 pub async fn fetch_and_cache_body_internal(app_handle: &AppHandle, account: &Account, uid: u32) -> Result<MessageDetail, String> {
+    if let Some(detail) = openpgp::cached_detail(&account.email, uid) {
+        return Ok(detail);
+    }
+
     let app_handle_cache = app_handle.clone();
     
     // 1. Check caches in a blocking task
@@ -347,7 +521,10 @@ pub async fn fetch_and_cache_body_internal(app_handle: &AppHandle, account: &Acc
             } else {
                 Vec::new()
             };
-            return Ok((Some(MessageDetail { body: cached_body, attachments }), stored_validity));
+            let pgp = database::get_message_pgp_status(&app_handle_cache, "INBOX", uid)
+                .unwrap_or_default()
+                .and_then(|json| serde_json::from_str(&json).ok());
+            return Ok((Some(MessageDetail { body: cached_body, attachments, remote_content: RemoteContentReport::default(), pgp }), stored_validity));
         }
 
         Ok::<_, String>((None, stored_validity))
@@ -367,7 +544,7 @@ pub async fn fetch_and_cache_body_internal(app_handle: &AppHandle, account: &Acc
 
     // -- SEMAPHORE ACQUIRE (NETWORK BOUNDARY) --
     let _permit = CONCURRENT_FETCH_LIMIT.clone().acquire_owned().await.map_err(|e| e.to_string())?;
-    log::debug!("IMAP fetch start: uid={}, active_permits={}", uid, 3 - CONCURRENT_FETCH_LIMIT.available_permits());
+    log::debug!("IMAP fetch start: uid={}, active_permits={}", uid, FETCH_PERMITS - CONCURRENT_FETCH_LIMIT.available_permits());
     
     let imap_result = imap_session::execute_with_session(&account, imap_session::SessionKind::Prefetch, move |session| {
         let mut target_part = String::new();
@@ -383,6 +560,17 @@ pub async fn fetch_and_cache_body_internal(app_handle: &AppHandle, account: &Acc
                 let mut plain = None;
                 collect_parts_from_bs(bs, "", &mut html, &mut plain, &mut attachments);
                 target_part = html.or(plain).unwrap_or_default();
+
+                // PGP/MIME needs the encrypted or signed parts whole, not just the text part
+                if let Some(layout) = openpgp::find_layout(bs, "") {
+                    attachments.retain(|a| !layout.is_hidden_part(&a.part_id));
+                    log::debug!("Fetching PGP/MIME parts: UID {}, {}", uid, layout.fetch_query());
+                    let fetch_results = session.uid_fetch(uid.to_string(), layout.fetch_query())
+                        .map_err(|e| format!("IMAP fetch payload error: {}", e))?;
+                    let msg = fetch_results.iter().next().ok_or_else(|| "Could not retrieve message body.".to_string())?;
+                    let payload = layout.payload(msg)?;
+                    return Ok((String::new(), Vec::new(), attachments, Some(payload)));
+                }
             }
         }
 
@@ -416,10 +604,10 @@ pub async fn fetch_and_cache_body_internal(app_handle: &AppHandle, account: &Acc
                 }
             }
         }
-        Ok::<_, String>((target_part, full_payload, attachments))
+        Ok::<_, String>((target_part, full_payload, attachments, None))
     }).await;
     
-    let (fetched_target_part, fetched_full_payload, fetched_attachments) = match imap_result {
+    let (fetched_target_part, fetched_full_payload, fetched_attachments, fetched_pgp) = match imap_result {
         Ok(data) => data,
         Err(e) => return Err(format!("IMAP Execution Error: {:?}", e)),
     };
@@ -428,12 +616,19 @@ pub async fn fetch_and_cache_body_internal(app_handle: &AppHandle, account: &Acc
     drop(_permit);
     log::debug!("IMAP fetch complete: uid={}", uid);
 
+    if let Some(payload) = fetched_pgp {
+        return open_pgp_message(app_handle, account, uid, payload, fetched_attachments).await;
+    }
+
     // -- CPU BOUNDARY (HTML PARSING & DB STORAGE) --
     let parsed_body = if !fetched_full_payload.is_empty() {
-        match extract_displayable_body(app_handle, uid, &fetched_full_payload) {
-            Ok(parsed) => parsed,
+        match extract_displayable_body(uid, &fetched_full_payload) {
+            Ok((parsed, inline)) => {
+                cache_inline_parts(app_handle, uid, inline);
+                parsed
+            }
             Err(_) => {
-                let fallback = String::from_utf8_lossy(&fetched_full_payload).to_string();
+                let fallback = mime_decode::decode_text(&fetched_full_payload, None, false);
                 let escaped = fallback.replace("&", "&amp;").replace("<", "&lt;").replace(">", "&gt;");
                 format!("<pre style=\"white-space:pre-wrap;font-family:system-ui\">{}</pre>", escaped)
             }
@@ -444,53 +639,121 @@ pub async fn fetch_and_cache_body_internal(app_handle: &AppHandle, account: &Acc
 
     let preview = generate_preview(&parsed_body);
     let attachments_json = serde_json::to_string(&fetched_attachments).ok();
+    let body_text = body_index_text(&parsed_body);
+    let attachment_names = attachment_index_names(&fetched_attachments);
     
-    let _ = database::update_message_body(app_handle, "INBOX", uid, &parsed_body, &preview, attachments_json);
+    let _ = database::update_message_body(app_handle, "INBOX", uid, &parsed_body, &preview, attachments_json, &body_text, &attachment_names);
+
+    // Inline signatures are cached with their status like PGP/MIME ones, so importing a key checks them again
+    let pgp = plain_text_body(&fetched_full_payload)
+        .and_then(|text| openpgp::verify_cleartext(app_handle, &text, message_sender(app_handle, uid).as_deref()));
+    if let Some(status) = &pgp {
+        let _ = database::set_message_pgp_status(app_handle, "INBOX", uid, serde_json::to_string(status).ok());
+    }
     
     Ok(MessageDetail {
         body: parsed_body,
         attachments: fetched_attachments,
+        remote_content: RemoteContentReport::default(),
+        pgp,
     })
 }
 
-pub async fn get_message_body(app_handle: &AppHandle, account: Account, uid: u32) -> Result<MessageDetail, String> {
-    fetch_and_cache_body_internal(app_handle, &account, uid).await
+/// Body for display, with remote content blocked unless `load_remote` is set or the sender
+/// is on the allowlist.
+pub async fn get_message_body(app_handle: &AppHandle, account: Account, uid: u32, load_remote: bool) -> Result<MessageDetail, String> {
+    let mut detail = fetch_and_cache_body_internal(app_handle, &account, uid).await?;
+
+    let app = app_handle.clone();
+    let sender = tokio::task::spawn_blocking(move || message_sender(&app, uid))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let (body, report) = remote_content::apply_policy(&detail.body, sender.as_deref(), load_remote);
+    detail.body = body;
+    detail.remote_content = report;
+    Ok(detail)
+}
+
+/// Decoded bytes of an inline part: from memory for a decrypted message, from the cache,
+/// or else located by Content-ID in the BODYSTRUCTURE and fetched.
+pub async fn fetch_inline_part(app_handle: &AppHandle, account: &Account, uid: u32, cid: &str) -> Result<Vec<u8>, String> {
+    if let Ok(bytes) = openpgp::decrypted_part(&account.email, uid, &openpgp::inline_part_id(cid)) {
+        return Ok(bytes);
+    }
+
+    let cache_path = inline_part_path(app_handle, uid, cid);
+    if let Some(bytes) = cache_path.as_ref().and_then(|p| fs::read(p).ok()) {
+        return Ok(bytes);
+    }
+
+    let cid_owned = cid.to_string();
+    let section = imap_session::execute_with_session(account, imap_session::SessionKind::Primary, move |session| {
+        let fetch_bs = session.uid_fetch(uid.to_string(), "(BODYSTRUCTURE)")
+            .map_err(|e| format!("IMAP fetch_bs error: {}", e))?;
+        Ok::<_, String>(fetch_bs.iter().next()
+            .and_then(|msg| msg.bodystructure())
+            .and_then(|bs| find_cid_section(bs, "", &cid_owned)))
+    }).await?.ok_or_else(|| format!("No inline part with Content-ID {}", cid))?;
+
+    let bytes = fetch_attachment_part(account, uid, &section).await?;
+    if let Some(path) = cache_path.filter(|_| bytes.len() <= MAX_INLINE_PART_BYTES) {
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let _ = fs::write(path, &bytes);
+    }
+    Ok(bytes)
 }
 
 pub async fn fetch_attachment_part(account: &Account, uid: u32, part_id: &str) -> Result<Vec<u8>, String> {
-    let part_id_clone = part_id.to_string();
+    let mut parts = fetch_attachment_parts(account, uid, &[part_id.to_string()]).await?;
+    parts.pop().ok_or_else(|| "Could not retrieve attachment part.".to_string())
+}
+
+/// Fetches several parts of one message in a single round trip, transfer-decoded and in
+/// the order of `part_ids`.
+pub async fn fetch_attachment_parts(account: &Account, uid: u32, part_ids: &[String]) -> Result<Vec<Vec<u8>>, String> {
+    // Attachments of encrypted messages only exist decrypted, in memory
+    if part_ids.iter().any(|part_id| openpgp::is_decrypted_part(part_id)) {
+        return part_ids.iter().map(|part_id| openpgp::decrypted_part(&account.email, uid, part_id)).collect();
+    }
+
+    let part_ids = part_ids.to_vec();
     
     // -- SEMAPHORE ACQUIRE --
     let _permit = CONCURRENT_FETCH_LIMIT.clone().acquire_owned().await.map_err(|e| e.to_string())?;
     
     let imap_result = imap_session::execute_with_session(account, imap_session::SessionKind::Primary, move |session| {
-        let mime_query = format!("BODY.PEEK[{}.MIME]", part_id_clone);
-        let body_query = format!("BODY.PEEK[{}]", part_id_clone);
-        let fetch_query = format!("({})", [mime_query, body_query].join(" "));
+        let queries: Vec<String> = part_ids
+            .iter()
+            .flat_map(|part_id| [format!("BODY.PEEK[{}.MIME]", part_id), format!("BODY.PEEK[{}]", part_id)])
+            .collect();
+        let fetch_query = format!("({})", queries.join(" "));
 
-        log::debug!("Fetching attachment: UID {}, Part {}", uid, part_id_clone);
+        log::debug!("Fetching attachments: UID {}, Parts {:?}", uid, part_ids);
         let fetch_results = session.uid_fetch(uid.to_string(), &fetch_query)
             .map_err(|e| format!("IMAP fetch attachment error: {}", e))?;
+        let msg = fetch_results.iter().next().ok_or("Could not retrieve attachment part.")?;
 
-        if let Some(msg) = fetch_results.iter().next() {
+        part_ids.iter().map(|part_id| {
             let mut full_part = Vec::new();
             
-            let parts: Vec<u32> = part_id_clone.split('.').filter_map(|s| s.parse().ok()).collect();
+            let parts: Vec<u32> = part_id.split('.').filter_map(|s| s.parse().ok()).collect();
             let section_path = imap_proto::types::SectionPath::Part(parts.clone(), None);
             let mime_path = imap_proto::types::SectionPath::Part(parts, Some(imap_proto::types::MessageSection::Mime));
             
             if let Some(m) = msg.section(&mime_path) { full_part.extend_from_slice(m); }
             // Add a newline between MIME and body if not present, though mailparse usually handles it
             full_part.extend_from_slice(b"\r\n");
-            if let Some(b) = msg.section(&section_path) { full_part.extend_from_slice(b); }
-
-            if !full_part.is_empty() {
-                if let Ok(parsed) = mailparse::parse_mail(&full_part) {
-                    return parsed.get_body_raw().map_err(|e| format!("Decoding error: {}", e));
-                }
-            }
-        }
-        Err("Could not retrieve attachment part.".to_string())
+            let body = msg.section(&section_path)
+                .ok_or_else(|| format!("Could not retrieve attachment part {}.", part_id))?;
+            full_part.extend_from_slice(body);
+
+            let parsed = mailparse::parse_mail(&full_part)
+                .map_err(|_| format!("Could not retrieve attachment part {}.", part_id))?;
+            parsed.get_body_raw().map_err(|e| format!("Decoding error: {}", e))
+        }).collect()
     }).await;
 
     imap_result
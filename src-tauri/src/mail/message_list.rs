@@ -1,7 +1,6 @@
 use crate::auth::account::Account;
 use std::time::Duration;
-use native_tls::TlsConnector;
-use mailparse::parse_mail;
+use crate::mail::sync::{parse_header_to_message, HEADER_FETCH_QUERY};
 use tauri::AppHandle;
 
 #[derive(Debug, serde::Serialize)]
@@ -10,30 +9,111 @@ pub struct MessageHeader {
     pub uid: u32,
     pub uid_validity: u32,
     pub subject: String,
-    pub from: String,
+    /// From, To, Cc, Bcc and Reply-To as parsed address lists.
+    #[serde(flatten)]
+    pub addresses: AddressHeaders,
     pub date: i64,
     pub seen: bool,
     pub flagged: bool,
     pub has_attachments: bool,
     pub thread_id: Option<String>,
     pub snippet: Option<String>,
+    /// Message-ID, In-Reply-To and References.
+    #[serde(flatten)]
+    pub threading: ThreadingHeaders,
+    pub list_id: Option<String>,
+    pub list_unsubscribe: Option<String>,
+    /// RFC822.SIZE in bytes.
+    pub size: Option<u32>,
+    /// Server INTERNALDATE (arrival time), as a Unix timestamp.
+    pub internal_date: Option<i64>,
+}
+
+impl MessageHeader {
+    /// The first From address as `Name <addr>`, for display and the `sender` column.
+    pub fn sender(&self) -> String {
+        self.addresses.from.first().map(EmailAddress::formatted).unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct EmailAddress {
+    pub name: Option<String>,
+    pub email: String,
+}
+
+impl EmailAddress {
+    pub fn display_name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.email)
+    }
+
+    pub fn formatted(&self) -> String {
+        match &self.name {
+            Some(name) => format!("{} <{}>", name, self.email),
+            None => self.email.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, serde::Serialize)]
+pub struct AddressHeaders {
+    pub from: Vec<EmailAddress>,
+    pub to: Vec<EmailAddress>,
+    pub cc: Vec<EmailAddress>,
+    pub bcc: Vec<EmailAddress>,
+    pub reply_to: Vec<EmailAddress>,
+}
+
+/// Parses a raw address list such as a legacy `sender` column value.
+pub fn parse_address_list(value: &str) -> Vec<EmailAddress> {
+    match mailparse::addrparse(value) {
+        Ok(list) => flatten_addresses(&list),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Parses an address header (From, To, Cc, ...), flattening groups into their members.
+/// Malformed headers yield an empty list.
+pub fn parse_address_header(header: &mailparse::MailHeader) -> Vec<EmailAddress> {
+    match mailparse::addrparse_header(header) {
+        Ok(list) => flatten_addresses(&list),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn flatten_addresses(list: &mailparse::MailAddrList) -> Vec<EmailAddress> {
+    let single = |info: &mailparse::SingleInfo| EmailAddress {
+        name: info.display_name.clone().filter(|n| !n.trim().is_empty()),
+        email: info.addr.clone(),
+    };
+
+    list.iter()
+        .flat_map(|addr| match addr {
+            mailparse::MailAddr::Single(info) => vec![single(info)],
+            mailparse::MailAddr::Group(group) => group.addrs.iter().map(single).collect(),
+        })
+        .filter(|a| a.email.contains('@'))
+        .collect()
+}
+
+#[derive(Debug, Default, Clone, serde::Serialize)]
+pub struct ThreadingHeaders {
+    pub message_id: Option<String>,
+    pub in_reply_to: Option<String>,
+    pub references: Vec<String>,
+    /// Gmail's X-GM-THRID, when the server provides one.
+    #[serde(skip)]
+    pub gm_thread_id: Option<String>,
 }
 
 pub async fn get_inbox_messages(app_handle: &AppHandle, account: Account) -> Result<Vec<MessageHeader>, String> {
     let email = account.email.clone();
     let access_token = account.access_token.clone();
+    let server_config = account.imap.clone();
     let app_handle_clone = app_handle.clone();
 
     let handle = tokio::task::spawn_blocking(move || {
-        let domain = "imap.gmail.com";
-        let port = 993;
-
-        let tls = TlsConnector::builder()
-            .build()
-            .map_err(|e| format!("TLS Error: {}", e))?;
-
-        let client = imap::connect((domain, port), domain, &tls)
-            .map_err(|e| format!("IMAP Connection Error: {}", e))?;
+        let client = crate::mail::tls::connect_imap(&server_config)?;
 
         let auth_raw = format!(
             "user={}\x01auth=Bearer {}\x01\x01",
@@ -70,78 +150,16 @@ pub async fn get_inbox_messages(app_handle: &AppHandle, account: Account) -> Res
             }
 
             let uids_str = recent_uids.iter().map(|u| u.to_string()).collect::<Vec<_>>().join(",");
-            let fetches = session.uid_fetch(uids_str, "(UID FLAGS BODY.PEEK[HEADER.FIELDS (SUBJECT FROM DATE)])")
+            let fetches = session.uid_fetch(uids_str, HEADER_FETCH_QUERY)
                 .map_err(|e| format!("IMAP UID Fetch Error: {}", e))?;
 
-            let mut messages = Vec::new();
-
-            for fetch in fetches.iter() {
-                let content = fetch.header().or_else(|| fetch.body()).or_else(|| fetch.text());
-                
-                let actual_uid = fetch.uid.unwrap_or(0);
-                
-                if let Some(body) = content {
-                    let mut subject = String::new();
-                    let mut from = String::new();
-                    let mut date = String::new();
-                    let mut seen = false;
-                    let mut flagged = false;
-
-                    for flag in fetch.flags() {
-                        match flag {
-                            imap::types::Flag::Seen => seen = true,
-                            imap::types::Flag::Flagged => flagged = true,
-                            _ => {}
-                        }
-                    }
-
-                    let mut plain_body = String::new();
-
-                    if let Ok(parsed) = parse_mail(body) {
-                        for header in parsed.get_headers() {
-                            let key = header.get_key().to_lowercase();
-                            let value = header.get_value();
-                            match key.as_str() {
-                                "subject" => subject = value,
-                                "from" => from = value,
-                                "date" => date = value,
-                                _ => {}
-                            }
-                        }
-                        // Attempt to extract raw text content for snippet extraction locally
-                        plain_body = parsed.get_body().unwrap_or_default();
-                    }
-
-                    let timestamp = chrono::DateTime::parse_from_rfc2822(&date)
-                        .map(|dt| dt.timestamp())
-                        .unwrap_or(0);
-
-                    let snippet = if !plain_body.is_empty() {
-                        let clean: String = plain_body
-                            .replace('\n', " ")
-                            .replace('\r', "")
-                            .chars()
-                            .take(180)
-                            .collect();
-                        Some(clean)
-                    } else {
-                        None
-                    };
-
-                    messages.push(MessageHeader {
-                        folder: "INBOX".to_string(),
-                        uid: actual_uid,
-                        uid_validity,
-                        subject,
-                        from,
-                        date: timestamp,
-                        seen,
-                        flagged,
-                        has_attachments: false, // For future IMAP multi-part traversal implementation
-                        thread_id: None,        // For future IMAP THREAD correlation
-                        snippet,
-                    });
-                }
+            let mut messages: Vec<MessageHeader> = fetches
+                .iter()
+                .filter_map(|fetch| parse_header_to_message(fetch, "INBOX", uid_validity))
+                .collect();
+
+            if let Err(e) = crate::mail::snippet::fill_snippets(&mut session, &fetches, &mut messages) {
+                log::warn!("Snippet fetch failed: {}", e);
             }
 
             if messages.is_empty() && !recent_uids.is_empty() {
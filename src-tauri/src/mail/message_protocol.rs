@@ -0,0 +1,385 @@
+use crate::auth::session::get_active_account;
+use crate::mail::attachment_preview::{self, PreviewKind};
+use crate::mail::message_body;
+use base64::Engine;
+use once_cell::sync::Lazy;
+use sha2::{Digest, Sha256};
+use tauri::http::{header, Method, Request, Response, StatusCode, Uri};
+use tauri::AppHandle;
+
+/// URI scheme the reading pane loads message bodies from, e.g. `orbitmail-msg://localhost/42`.
+/// Windows and Android expose it as `http://orbitmail-msg.localhost/42` instead.
+pub const SCHEME: &str = "orbitmail-msg";
+
+/// Sources that resolve to this protocol on every platform.
+const SELF_SOURCES: &str = "orbitmail-msg: http://orbitmail-msg.localhost";
+
+const SHELL_STYLE: &str = r#"
+html { opacity: 0; }
+html.ready { opacity: 1; }
+html, body { font-family: system-ui, -apple-system, sans-serif; margin: 0; padding: 0; overflow: hidden; width: 100%; }
+img { max-width: 100%; height: auto; }
+img[data-remote-blocked] { background: repeating-linear-gradient(45deg, #f3f4f6, #f3f4f6 6px, #e5e7eb 6px, #e5e7eb 12px); }
+a { color: #2563eb; }
+#email-content-wrapper { display: block; overflow: hidden; }
+
+/* Smart invert for dark mode */
+html.dark-mode {
+    filter: invert(1) hue-rotate(180deg) brightness(1.05);
+    background-color: #EEEEEE; /* Inverts to ~#111111, matching the outer pane */
+}
+html.dark-mode img,
+html.dark-mode video,
+html.dark-mode picture,
+html.dark-mode svg,
+html.dark-mode [style*="background-image"] {
+    filter: invert(1) hue-rotate(180deg);
+}
+html.dark-mode a { color: #3b82f6; }
+"#;
+
+/// The only script allowed to run in a message document: dark-mode detection and reporting
+/// the content height to the parent frame. It is allowed by hash, so it must stay constant.
+const SHELL_SCRIPT: &str = r#"
+(() => {
+    const root = document.documentElement;
+    const wrapper = document.getElementById('email-content-wrapper');
+    let lastHeight = 0;
+
+    if (root.dataset.theme === 'dark') {
+        const brightness = (color) => {
+            const m = color.match(/rgba?\((\d+),\s*(\d+),\s*(\d+)/);
+            return m ? (m[1] * 299 + m[2] * 587 + m[3] * 114) / 1000 : 255;
+        };
+        const transparent = (bg) => bg === 'rgba(0, 0, 0, 0)' || bg === 'transparent';
+        const bodyBg = getComputedStyle(document.body).backgroundColor;
+        const wrapperBg = getComputedStyle(wrapper).backgroundColor;
+        const effective = !transparent(wrapperBg) ? wrapperBg : !transparent(bodyBg) ? bodyBg : 'rgb(255, 255, 255)';
+        // Light mail gets inverted; mail that is already dark is left alone
+        if (brightness(effective) > 128) root.classList.add('dark-mode');
+    }
+    root.classList.add('ready');
+
+    const sendHeight = () => {
+        const height = wrapper.scrollHeight;
+        if (height !== lastHeight) {
+            lastHeight = height;
+            window.parent.postMessage({ type: 'resize', height, id: root.dataset.uid }, '*');
+        }
+    };
+    window.addEventListener('load', sendHeight);
+    new ResizeObserver(sendHeight).observe(wrapper);
+    new MutationObserver(sendHeight).observe(wrapper, { childList: true, subtree: true, attributes: true });
+    let polls = 0;
+    const poll = setInterval(() => { sendHeight(); if (++polls > 50) clearInterval(poll); }, 100);
+})();
+"#;
+
+static SHELL_SCRIPT_HASH: Lazy<String> = Lazy::new(|| {
+    base64::engine::general_purpose::STANDARD.encode(Sha256::digest(SHELL_SCRIPT.as_bytes()))
+});
+
+/// Absolute URL of `path` on this protocol, in the form the webview on this platform uses.
+pub fn url(path: &str) -> String {
+    if cfg!(any(windows, target_os = "android")) {
+        format!("http://{}.localhost{}", SCHEME, path)
+    } else {
+        format!("{}://localhost{}", SCHEME, path)
+    }
+}
+
+/// Raster formats inline parts may be served as, identified by their magic bytes.
+/// Anything else, SVG in particular, is refused.
+pub fn sniff_image(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if bytes.len() >= 12 && bytes.starts_with(b"RIFF") && &bytes[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else if bytes.starts_with(b"BM") {
+        Some("image/bmp")
+    } else {
+        None
+    }
+}
+
+/// Policy for a message document. Nothing may be fetched, framed, submitted or connected to
+/// except this protocol's own inline parts, plus remote images once the user allowed them.
+fn document_csp(remote_loaded: bool) -> String {
+    let remote = if remote_loaded { " https: http:" } else { "" };
+    format!(
+        "default-src 'none'; img-src {self_src} data:{remote}; style-src 'unsafe-inline'; \
+         script-src 'sha256-{hash}'; base-uri 'none'; form-action 'none'; frame-src 'none'",
+        self_src = SELF_SOURCES,
+        remote = remote,
+        hash = *SHELL_SCRIPT_HASH,
+    )
+}
+
+fn respond(status: StatusCode, content_type: &str, csp: &str, body: Vec<u8>) -> Response<Vec<u8>> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::CONTENT_SECURITY_POLICY, csp)
+        .header(header::X_CONTENT_TYPE_OPTIONS, "nosniff")
+        .header(header::REFERRER_POLICY, "no-referrer")
+        .header(header::CACHE_CONTROL, "no-store")
+        .body(body)
+        .unwrap_or_default()
+}
+
+fn error(status: StatusCode, message: &str) -> Response<Vec<u8>> {
+    respond(status, "text/plain; charset=utf-8", "default-src 'none'", message.as_bytes().to_vec())
+}
+
+async fn serve_document(app_handle: &AppHandle, uid: u32, load_remote: bool, dark: bool) -> Response<Vec<u8>> {
+    let Some(account) = get_active_account(app_handle) else {
+        return error(StatusCode::UNAUTHORIZED, "No active account");
+    };
+
+    let detail = match message_body::get_message_body(app_handle, account, uid, load_remote).await {
+        Ok(detail) => detail,
+        Err(e) => {
+            log::warn!("Message protocol: body for uid {} failed: {}", uid, e);
+            return error(StatusCode::BAD_GATEWAY, "Could not load message");
+        }
+    };
+
+    let document = format!(
+        "<!DOCTYPE html>\n<html data-theme=\"{theme}\" data-uid=\"{uid}\"><head>\
+         <meta charset=\"utf-8\"><meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\
+         <style>{style}</style></head><body><div id=\"email-content-wrapper\">{body}</div>\
+         <script>{script}</script></body></html>",
+        theme = if dark { "dark" } else { "light" },
+        uid = uid,
+        style = SHELL_STYLE,
+        body = detail.body,
+        script = SHELL_SCRIPT,
+    );
+
+    respond(
+        StatusCode::OK,
+        "text/html; charset=utf-8",
+        &document_csp(detail.remote_content.loaded),
+        document.into_bytes(),
+    )
+}
+
+async fn serve_inline_part(app_handle: &AppHandle, uid: u32, cid: &str) -> Response<Vec<u8>> {
+    let Some(account) = get_active_account(app_handle) else {
+        return error(StatusCode::UNAUTHORIZED, "No active account");
+    };
+
+    match message_body::fetch_inline_part(app_handle, &account, uid, cid).await {
+        Ok(bytes) => match sniff_image(&bytes) {
+            Some(mime) => respond(StatusCode::OK, mime, "default-src 'none'", bytes),
+            None => error(StatusCode::UNSUPPORTED_MEDIA_TYPE, "Inline part is not a supported image"),
+        },
+        Err(e) => {
+            log::warn!("Message protocol: inline part {} of uid {} failed: {}", cid, uid, e);
+            error(StatusCode::NOT_FOUND, "Inline part not found")
+        }
+    }
+}
+
+fn serve_attachment(app_handle: &AppHandle, uid: u32, part_id: &str, thumbnail: bool) -> Response<Vec<u8>> {
+    let Some(account) = get_active_account(app_handle) else {
+        return error(StatusCode::UNAUTHORIZED, "No active account");
+    };
+
+    match attachment_preview::cached_file(app_handle, &account, uid, part_id, thumbnail) {
+        Some((bytes, PreviewKind::Image)) => match sniff_image(&bytes) {
+            Some(mime) => respond(StatusCode::OK, mime, "default-src 'none'", bytes),
+            None => error(StatusCode::UNSUPPORTED_MEDIA_TYPE, "Attachment is not a supported image"),
+        },
+        Some((bytes, PreviewKind::Pdf)) => respond(StatusCode::OK, "application/pdf", "default-src 'none'", bytes),
+        Some((bytes, PreviewKind::Text)) => respond(StatusCode::OK, "text/plain; charset=utf-8", "default-src 'none'", bytes),
+        Some((_, PreviewKind::Other)) => error(StatusCode::UNSUPPORTED_MEDIA_TYPE, "Attachment can't be previewed"),
+        None => error(StatusCode::NOT_FOUND, "Attachment not previewed"),
+    }
+}
+
+/// What a request on the message protocol asks for.
+#[derive(Debug, PartialEq)]
+enum Route {
+    Document { uid: u32, load_remote: bool, dark: bool },
+    InlinePart { uid: u32, cid: String },
+    Attachment { uid: u32, part_id: String, thumbnail: bool },
+}
+
+/// Parses a request into a `Route`, or the status and message to refuse it with.
+fn route(method: &Method, uri: &Uri) -> Result<Route, (StatusCode, &'static str)> {
+    if method != Method::GET {
+        return Err((StatusCode::METHOD_NOT_ALLOWED, "Method not allowed"));
+    }
+
+    let query: Vec<(String, String)> = url::form_urlencoded::parse(uri.query().unwrap_or_default().as_bytes())
+        .into_owned()
+        .collect();
+    let flag = |name: &str, value: &str| query.iter().any(|(k, v)| k == name && v == value);
+
+    let segments: Vec<&str> = uri.path().split('/').filter(|s| !s.is_empty()).collect();
+    let Some(uid) = segments.first().and_then(|s| s.parse::<u32>().ok()) else {
+        return Err((StatusCode::NOT_FOUND, "Not found"));
+    };
+
+    let attachment = |part_id: &str, thumbnail: bool| {
+        if !attachment_preview::valid_part_id(part_id) {
+            return Err((StatusCode::BAD_REQUEST, "Invalid part"));
+        }
+        Ok(Route::Attachment { uid, part_id: part_id.to_string(), thumbnail })
+    };
+
+    match segments[1..] {
+        [] => Ok(Route::Document { uid, load_remote: flag("remote", "1"), dark: flag("theme", "dark") }),
+        ["cid", encoded_cid] => base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(encoded_cid)
+            .ok()
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+            .map(|cid| Route::InlinePart { uid, cid })
+            .ok_or((StatusCode::BAD_REQUEST, "Invalid Content-ID")),
+        ["attachment", part_id] => attachment(part_id, false),
+        ["attachment", part_id, "thumbnail"] => attachment(part_id, true),
+        _ => Err((StatusCode::NOT_FOUND, "Not found")),
+    }
+}
+
+/// Handles a request on the message protocol:
+///
+/// - `/<uid>` — the sanitized body as a standalone document. `?remote=1` loads remote
+///   content for this view; `?theme=dark` enables the dark-mode inversion.
+/// - `/<uid>/cid/<base64url Content-ID>` — an inline image referenced by that body.
+/// - `/<uid>/attachment/<part>[/thumbnail]` — an attachment `preview_attachment` cached,
+///   or its thumbnail. Only images, PDFs and text are served.
+///
+/// Every response carries its own CSP, so message content can't run script, submit forms,
+/// or load anything beyond what the user allowed, whatever the embedding page permits.
+pub async fn handle(app_handle: AppHandle, request: Request<Vec<u8>>) -> Response<Vec<u8>> {
+    match route(request.method(), request.uri()) {
+        Ok(Route::Document { uid, load_remote, dark }) => serve_document(&app_handle, uid, load_remote, dark).await,
+        Ok(Route::InlinePart { uid, cid }) => serve_inline_part(&app_handle, uid, &cid).await,
+        Ok(Route::Attachment { uid, part_id, thumbnail }) => serve_attachment(&app_handle, uid, &part_id, thumbnail),
+        Err((status, message)) => error(status, message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sources of `directive` in `csp`, or `None` when the policy doesn't set it.
+    fn sources<'a>(csp: &'a str, directive: &str) -> Option<Vec<&'a str>> {
+        csp.split(';').map(str::trim).find_map(|d| {
+            let mut tokens = d.split_whitespace();
+            (tokens.next() == Some(directive)).then(|| tokens.collect())
+        })
+    }
+
+    fn routed(method: Method, uri: &str) -> Result<Route, StatusCode> {
+        route(&method, &uri.parse().unwrap()).map_err(|(status, _)| status)
+    }
+
+    fn cid_path(cid: &[u8]) -> String {
+        format!("orbitmail-msg://localhost/42/cid/{}", base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(cid))
+    }
+
+    #[test]
+    fn documents_load_nothing_remote_until_allowed() {
+        let blocked = document_csp(false);
+        assert_eq!(sources(&blocked, "default-src"), Some(vec!["'none'"]));
+        let images = sources(&blocked, "img-src").unwrap();
+        assert!(!images.contains(&"https:") && !images.contains(&"http:"), "{}", blocked);
+
+        let loaded = document_csp(true);
+        let images = sources(&loaded, "img-src").unwrap();
+        assert!(images.contains(&"https:") && images.contains(&"http:"), "{}", loaded);
+
+        // Allowing remote images opens nothing else
+        for csp in [&blocked, &loaded] {
+            for directive in ["default-src", "base-uri", "form-action", "frame-src"] {
+                assert_eq!(sources(csp, directive), Some(vec!["'none'"]), "{}", directive);
+            }
+            assert_eq!(sources(csp, "connect-src"), None, "falls back to default-src");
+        }
+    }
+
+    #[test]
+    fn only_the_shell_script_may_run() {
+        let csp = document_csp(true);
+        let hash = base64::engine::general_purpose::STANDARD.encode(Sha256::digest(SHELL_SCRIPT.as_bytes()));
+        assert_eq!(sources(&csp, "script-src"), Some(vec![format!("'sha256-{}'", hash).as_str()]));
+        assert!(!csp.contains("unsafe-eval"));
+    }
+
+    #[test]
+    fn only_raster_images_are_sniffed() {
+        assert_eq!(sniff_image(b"\x89PNG\r\n\x1a\n\0\0"), Some("image/png"));
+        assert_eq!(sniff_image(&[0xFF, 0xD8, 0xFF, 0xE0]), Some("image/jpeg"));
+        assert_eq!(sniff_image(b"GIF89a\x01\x00"), Some("image/gif"));
+        assert_eq!(sniff_image(b"RIFF\x10\0\0\0WEBPVP8 "), Some("image/webp"));
+        assert_eq!(sniff_image(b"BM\x36\0"), Some("image/bmp"));
+
+        assert_eq!(sniff_image(b"<svg xmlns=\"http://www.w3.org/2000/svg\"><script>alert(1)</script></svg>"), None);
+        assert_eq!(sniff_image(b"<?xml version=\"1.0\"?><svg/>"), None);
+        assert_eq!(sniff_image(b"<html><body><img src=x onerror=alert(1)></body></html>"), None);
+        assert_eq!(sniff_image(b"RIFF\x10\0\0\0WAVEfmt "), None);
+        assert_eq!(sniff_image(b"RIFF"), None);
+        assert_eq!(sniff_image(b""), None);
+    }
+
+    #[test]
+    fn routes_only_get_requests_for_a_uid() {
+        assert_eq!(routed(Method::POST, "orbitmail-msg://localhost/42"), Err(StatusCode::METHOD_NOT_ALLOWED));
+        assert_eq!(routed(Method::PUT, "orbitmail-msg://localhost/42"), Err(StatusCode::METHOD_NOT_ALLOWED));
+
+        for uri in ["orbitmail-msg://localhost/", "orbitmail-msg://localhost/abc", "orbitmail-msg://localhost/-1", "orbitmail-msg://localhost/4294967296"] {
+            assert_eq!(routed(Method::GET, uri), Err(StatusCode::NOT_FOUND), "{}", uri);
+        }
+        assert_eq!(routed(Method::GET, "orbitmail-msg://localhost/42/other"), Err(StatusCode::NOT_FOUND));
+
+        assert_eq!(
+            routed(Method::GET, "http://orbitmail-msg.localhost/42"),
+            Ok(Route::Document { uid: 42, load_remote: false, dark: false })
+        );
+        assert_eq!(
+            routed(Method::GET, "orbitmail-msg://localhost/42?remote=1&theme=dark"),
+            Ok(Route::Document { uid: 42, load_remote: true, dark: true })
+        );
+        assert_eq!(
+            routed(Method::GET, "orbitmail-msg://localhost/42?remote=true"),
+            Ok(Route::Document { uid: 42, load_remote: false, dark: false })
+        );
+    }
+
+    #[test]
+    fn inline_parts_need_a_valid_content_id_encoding() {
+        assert_eq!(
+            routed(Method::GET, &cid_path(b"logo@example.com")),
+            Ok(Route::InlinePart { uid: 42, cid: "logo@example.com".to_string() })
+        );
+        assert_eq!(routed(Method::GET, "orbitmail-msg://localhost/42/cid/!!!"), Err(StatusCode::BAD_REQUEST));
+        assert_eq!(routed(Method::GET, "orbitmail-msg://localhost/42/cid/bG9nbw=="), Err(StatusCode::BAD_REQUEST));
+        assert_eq!(routed(Method::GET, &cid_path(&[0xFF, 0xFE])), Err(StatusCode::BAD_REQUEST));
+    }
+
+    #[test]
+    fn attachment_part_ids_cannot_leave_the_cache() {
+        assert_eq!(
+            routed(Method::GET, "orbitmail-msg://localhost/42/attachment/1.2"),
+            Ok(Route::Attachment { uid: 42, part_id: "1.2".to_string(), thumbnail: false })
+        );
+        assert_eq!(
+            routed(Method::GET, "orbitmail-msg://localhost/42/attachment/2/thumbnail"),
+            Ok(Route::Attachment { uid: 42, part_id: "2".to_string(), thumbnail: true })
+        );
+
+        for part in ["..", "1..2", "..%2F..%2Fsecret", "%2e%2e", "1.2.", "x"] {
+            let uri = format!("orbitmail-msg://localhost/42/attachment/{}", part);
+            assert_eq!(routed(Method::GET, &uri), Err(StatusCode::BAD_REQUEST), "{}", part);
+        }
+        assert_eq!(routed(Method::GET, "orbitmail-msg://localhost/42/attachment/../../etc"), Err(StatusCode::NOT_FOUND));
+    }
+}
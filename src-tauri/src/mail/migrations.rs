@@ -0,0 +1,685 @@
+use crate::mail::message_body::{self, MessageAttachment};
+use crate::mail::mime_decode;
+use crate::mail::sanitize;
+use rusqlite::{Connection, Transaction, TransactionBehavior};
+use std::path::Path;
+
+/// One numbered step of the `orbitmail.db` schema. The version reached is stored in
+/// `PRAGMA user_version`, so each step runs exactly once per database.
+struct Migration {
+    version: u32,
+    description: &'static str,
+    /// Drops or rewrites existing data; the database file is backed up before running it.
+    destructive: bool,
+    up: fn(&Transaction) -> rusqlite::Result<()>,
+}
+
+// Versions 1-5 reproduce the schema history from before `user_version` was tracked.
+// Databases from that era report version 0 and may already have some of these changes,
+// which is why those steps check before altering anything. New steps don't need to.
+const MIGRATIONS: &[Migration] = &[
+    Migration { version: 1, description: "base messages and mailbox_state tables", destructive: false, up: base_schema },
+    Migration { version: 2, description: "processed_html column", destructive: false, up: add_processed_html },
+    Migration { version: 3, description: "attachments_json column", destructive: false, up: add_attachments_json },
+    Migration { version: 4, description: "body search columns", destructive: false, up: add_body_search_columns },
+    Migration { version: 5, description: "full-text index over bodies and attachment names", destructive: true, up: rebuild_fts_with_bodies },
+    Migration { version: 6, description: "threading headers", destructive: false, up: add_threading_headers },
+    Migration { version: 7, description: "contacts", destructive: false, up: add_contacts },
+    Migration { version: 8, description: "envelope columns", destructive: false, up: add_envelope_columns },
+    Migration { version: 9, description: "saved searches", destructive: false, up: add_saved_searches },
+    Migration { version: 10, description: "message list sort indexes", destructive: false, up: add_sort_indexes },
+    Migration { version: 11, description: "re-sanitize cached message bodies", destructive: true, up: resanitize_bodies },
+    Migration { version: 12, description: "drop bodies with asset:// inline images", destructive: true, up: drop_asset_url_bodies },
+    Migration { version: 13, description: "decode encoded-word attachment names", destructive: true, up: decode_attachment_names },
+    Migration { version: 14, description: "download history", destructive: false, up: add_downloads },
+    Migration { version: 15, description: "OpenPGP keyring and signature status", destructive: false, up: add_openpgp },
+    Migration { version: 16, description: "re-check cached signatures for key source and inline signatures", destructive: true, up: forget_signature_statuses },
+    Migration { version: 17, description: "mail cache owner", destructive: false, up: add_cache_owner },
+];
+
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+/// Brings the database at `db_path` up to the latest schema version.
+///
+/// Refuses databases written by a newer build rather than guessing at their layout.
+pub fn run(conn: &mut Connection, db_path: &Path) -> Result<(), String> {
+    let current: u32 = conn
+        .query_row("PRAGMA user_version", (), |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    let latest = latest_version();
+
+    if current > latest {
+        return Err(format!(
+            "Database schema version {} is newer than this version of OrbitMail supports ({}). Please update the app.",
+            current, latest
+        ));
+    }
+
+    let pending: Vec<&Migration> = MIGRATIONS.iter().filter(|m| m.version > current).collect();
+    if pending.is_empty() {
+        return Ok(());
+    }
+
+    if pending.iter().any(|m| m.destructive) && has_user_data(conn)? {
+        backup(conn, db_path, current)?;
+    }
+
+    for migration in pending {
+        log::info!("Migrating database to v{}: {}", migration.version, migration.description);
+
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(|e| e.to_string())?;
+        (migration.up)(&tx)
+            .map_err(|e| format!("Migration to v{} failed: {}", migration.version, e))?;
+        tx.pragma_update(None, "user_version", migration.version)
+            .map_err(|e| e.to_string())?;
+        tx.commit().map_err(|e| e.to_string())?;
+    }
+
+    Ok(())
+}
+
+fn has_user_data(conn: &Connection) -> Result<bool, String> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'messages')",
+        (),
+        |row| row.get(0),
+    ).map_err(|e| e.to_string())
+}
+
+/// Writes a consistent copy next to the database, e.g. `orbitmail.db.v4.bak`.
+fn backup(conn: &Connection, db_path: &Path, version: u32) -> Result<(), String> {
+    let mut file_name = db_path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(format!(".v{}.bak", version));
+    let backup_path = db_path.with_file_name(file_name);
+
+    // VACUUM INTO refuses to overwrite an existing file
+    if backup_path.exists() {
+        std::fs::remove_file(&backup_path)
+            .map_err(|e| format!("Failed to replace old database backup: {}", e))?;
+    }
+
+    log::info!("Backing up database to {}", backup_path.display());
+    conn.execute("VACUUM INTO ?1", [backup_path.to_string_lossy()])
+        .map_err(|e| format!("Database backup failed: {}", e))?;
+
+    Ok(())
+}
+
+fn column_exists(tx: &Transaction, table: &str, column: &str) -> rusqlite::Result<bool> {
+    let mut stmt = tx.prepare(&format!("PRAGMA table_info({})", table))?;
+    let names = stmt.query_map([], |row| row.get::<_, String>(1))?;
+    for name in names {
+        if name? == column {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+fn base_schema(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS messages (
+            folder TEXT NOT NULL,
+            uid INTEGER NOT NULL,
+            uid_validity INTEGER, -- Left for generic IMAP parity
+            subject TEXT,
+            sender TEXT,
+            date INTEGER NOT NULL,
+            snippet TEXT,
+            body TEXT,
+            seen INTEGER DEFAULT 0,
+            flagged INTEGER DEFAULT 0,
+            has_attachments INTEGER DEFAULT 0,
+            thread_id TEXT,
+            body_fetched INTEGER DEFAULT 0,
+            PRIMARY KEY (folder, uid)
+        );
+
+        CREATE TABLE IF NOT EXISTS mailbox_state (
+            mailbox TEXT PRIMARY KEY,
+            uid_validity INTEGER
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_messages_folder_uid_desc ON messages(folder, uid DESC);
+        CREATE INDEX IF NOT EXISTS idx_messages_folder_date ON messages(folder, date DESC);
+        CREATE INDEX IF NOT EXISTS idx_messages_thread ON messages(thread_id);
+        CREATE INDEX IF NOT EXISTS idx_messages_seen ON messages(seen);
+        CREATE INDEX IF NOT EXISTS idx_messages_flagged ON messages(flagged);"
+    )
+}
+
+fn add_processed_html(tx: &Transaction) -> rusqlite::Result<()> {
+    if !column_exists(tx, "messages", "processed_html")? {
+        tx.execute("ALTER TABLE messages ADD COLUMN processed_html TEXT", ())?;
+    }
+    Ok(())
+}
+
+fn add_attachments_json(tx: &Transaction) -> rusqlite::Result<()> {
+    if !column_exists(tx, "messages", "attachments_json")? {
+        tx.execute("ALTER TABLE messages ADD COLUMN attachments_json TEXT", ())?;
+    }
+    Ok(())
+}
+
+fn add_body_search_columns(tx: &Transaction) -> rusqlite::Result<()> {
+    if !column_exists(tx, "messages", "body_text")? {
+        tx.execute("ALTER TABLE messages ADD COLUMN body_text TEXT", ())?;
+    }
+    if !column_exists(tx, "messages", "attachment_names")? {
+        tx.execute("ALTER TABLE messages ADD COLUMN attachment_names TEXT", ())?;
+    }
+    Ok(())
+}
+
+/// Replaces any earlier subject-only index (and its triggers, if it had them) and rebuilds it from `messages`.
+fn rebuild_fts_with_bodies(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "DROP TRIGGER IF EXISTS messages_ai;
+         DROP TRIGGER IF EXISTS messages_ad;
+         DROP TRIGGER IF EXISTS messages_au;
+         DROP TABLE IF EXISTS messages_fts;
+
+         CREATE VIRTUAL TABLE messages_fts USING fts5(
+            subject,
+            sender,
+            snippet,
+            body_text,
+            attachment_names,
+            content='messages',
+            content_rowid='rowid'
+         );
+
+         CREATE TRIGGER messages_ai AFTER INSERT ON messages BEGIN
+            INSERT INTO messages_fts(rowid, subject, sender, snippet, body_text, attachment_names)
+            VALUES (new.rowid, new.subject, new.sender, new.snippet, new.body_text, new.attachment_names);
+         END;
+
+         CREATE TRIGGER messages_ad AFTER DELETE ON messages BEGIN
+            INSERT INTO messages_fts(messages_fts, rowid, subject, sender, snippet, body_text, attachment_names)
+            VALUES ('delete', old.rowid, old.subject, old.sender, old.snippet, old.body_text, old.attachment_names);
+         END;
+
+         CREATE TRIGGER messages_au AFTER UPDATE OF subject, sender, snippet, body_text, attachment_names ON messages BEGIN
+            INSERT INTO messages_fts(messages_fts, rowid, subject, sender, snippet, body_text, attachment_names)
+            VALUES ('delete', old.rowid, old.subject, old.sender, old.snippet, old.body_text, old.attachment_names);
+            INSERT INTO messages_fts(rowid, subject, sender, snippet, body_text, attachment_names)
+            VALUES (new.rowid, new.subject, new.sender, new.snippet, new.body_text, new.attachment_names);
+         END;
+
+         INSERT INTO messages_fts(messages_fts) VALUES('rebuild');"
+    )
+}
+
+fn add_threading_headers(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE messages ADD COLUMN message_id TEXT;
+         ALTER TABLE messages ADD COLUMN in_reply_to TEXT;
+         ALTER TABLE messages ADD COLUMN references_ids TEXT;
+         ALTER TABLE messages ADD COLUMN gm_thread_id TEXT;
+         CREATE INDEX IF NOT EXISTS idx_messages_message_id ON messages(message_id);"
+    )
+}
+
+fn add_contacts(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE contacts (
+            email TEXT PRIMARY KEY, -- lowercased
+            display_name TEXT,      -- most recently seen name
+            display_names TEXT NOT NULL DEFAULT '[]',
+            frequency INTEGER NOT NULL DEFAULT 0,
+            last_seen INTEGER NOT NULL DEFAULT 0,
+            sent_count INTEGER NOT NULL DEFAULT 0
+        );
+        CREATE INDEX idx_contacts_last_seen ON contacts(last_seen DESC);"
+    )
+}
+
+/// Address lists are stored as JSON arrays of `{name, email}`.
+fn add_envelope_columns(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE messages ADD COLUMN from_addrs TEXT;
+         ALTER TABLE messages ADD COLUMN to_addrs TEXT;
+         ALTER TABLE messages ADD COLUMN cc_addrs TEXT;
+         ALTER TABLE messages ADD COLUMN bcc_addrs TEXT;
+         ALTER TABLE messages ADD COLUMN reply_to_addrs TEXT;
+         ALTER TABLE messages ADD COLUMN list_id TEXT;
+         ALTER TABLE messages ADD COLUMN list_unsubscribe TEXT;
+         ALTER TABLE messages ADD COLUMN size INTEGER;
+         ALTER TABLE messages ADD COLUMN internal_date INTEGER;"
+    )
+}
+
+fn add_saved_searches(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE saved_searches (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            icon TEXT,
+            query TEXT NOT NULL,  -- search language, see query.rs
+            position INTEGER NOT NULL DEFAULT 0,
+            created_at INTEGER NOT NULL
+        );"
+    )
+}
+
+/// Keyset paging in paging.rs: one index per sort key, each ending in uid as the tie-breaker,
+/// plus partial indexes for the unread and flagged filters on the default date order.
+fn add_sort_indexes(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE INDEX idx_messages_folder_date_uid ON messages(folder, date, uid);
+         CREATE INDEX idx_messages_folder_sender ON messages(folder, IFNULL(sender, '') COLLATE NOCASE, uid);
+         CREATE INDEX idx_messages_folder_subject ON messages(folder, IFNULL(subject, '') COLLATE NOCASE, uid);
+         CREATE INDEX idx_messages_folder_size ON messages(folder, IFNULL(size, 0), uid);
+         CREATE INDEX idx_messages_folder_unread ON messages(folder, date, uid) WHERE seen = 0;
+         CREATE INDEX idx_messages_folder_flagged ON messages(folder, date, uid) WHERE flagged = 1;"
+    )
+}
+
+/// Bodies cached before sanitizing was added hold raw sender HTML. Rows are rewritten in
+/// rowid batches so the whole cache is never held in memory at once. Bodies with asset://
+/// images are left for `drop_asset_url_bodies`; sanitizing would strip the links it looks for.
+fn resanitize_bodies(tx: &Transaction) -> rusqlite::Result<()> {
+    const BATCH: i64 = 200;
+    let mut select = tx.prepare(
+        "SELECT rowid, processed_html FROM messages
+         WHERE processed_html IS NOT NULL AND processed_html NOT LIKE '%asset://localhost/%' AND rowid > ?1
+         ORDER BY rowid LIMIT ?2"
+    )?;
+    let mut update = tx.prepare("UPDATE messages SET processed_html = ?1 WHERE rowid = ?2")?;
+
+    let mut last_rowid = 0;
+    loop {
+        let batch: Vec<(i64, String)> = select
+            .query_map(rusqlite::params![last_rowid, BATCH], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+        let Some(&(last, _)) = batch.last() else { break };
+
+        for (rowid, html) in &batch {
+            update.execute(rusqlite::params![sanitize::sanitize_html(html), rowid])?;
+        }
+        last_rowid = last;
+    }
+    Ok(())
+}
+
+/// Inline images used to be linked as `asset://localhost/<cache path>`, which the message
+/// protocol doesn't serve. Those bodies are refetched on next open with `cid` links instead.
+fn drop_asset_url_bodies(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute(
+        "UPDATE messages SET processed_html = NULL, body_fetched = 0
+         WHERE processed_html LIKE '%asset://localhost/%'",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Attachment names used to be stored exactly as BODYSTRUCTURE gave them, so RFC 2047
+/// encoded words show up verbatim. RFC 2231 names were lost entirely and only come back
+/// when the body is refetched.
+fn decode_attachment_names(tx: &Transaction) -> rusqlite::Result<()> {
+    let rows: Vec<(i64, String)> = tx
+        .prepare("SELECT rowid, attachments_json FROM messages WHERE attachments_json LIKE '%=?%?=%'")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+
+    let mut update = tx.prepare("UPDATE messages SET attachments_json = ?1, attachment_names = ?2 WHERE rowid = ?3")?;
+    for (rowid, json) in rows {
+        let Ok(mut attachments) = serde_json::from_str::<Vec<MessageAttachment>>(&json) else { continue };
+        for attachment in &mut attachments {
+            attachment.name = mime_decode::decode_encoded_words(attachment.name.as_bytes());
+        }
+        let json = serde_json::to_string(&attachments).unwrap_or(json);
+        update.execute(rusqlite::params![json, message_body::attachment_index_names(&attachments), rowid])?;
+    }
+    Ok(())
+}
+
+/// Download manager queue and history, see download_manager.rs. Progress of a running
+/// download is also kept here so the list survives a restart.
+fn add_downloads(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE downloads (
+            id TEXT PRIMARY KEY,
+            account TEXT NOT NULL,
+            uid INTEGER NOT NULL,
+            part_id TEXT NOT NULL,
+            file_name TEXT NOT NULL,
+            path TEXT NOT NULL,
+            status TEXT NOT NULL,  -- queued, downloading, paused, completed, failed, cancelled
+            received INTEGER NOT NULL DEFAULT 0,
+            total INTEGER NOT NULL DEFAULT 0,
+            error TEXT,
+            created_at INTEGER NOT NULL,
+            finished_at INTEGER
+        );
+        CREATE INDEX idx_downloads_created ON downloads(created_at);"
+    )
+}
+
+/// Keyring and Autocrypt peer state for openpgp.rs, plus the signature status of cached
+/// signed bodies. Decrypted bodies are never cached, so they need no column.
+fn add_openpgp(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE pgp_keys (
+            fingerprint TEXT PRIMARY KEY,  -- lower-case hex
+            user_ids TEXT NOT NULL,        -- newline-separated
+            public_key BLOB NOT NULL,      -- binary transferable public key
+            secret_key BLOB,               -- binary transferable secret key, as imported
+            source TEXT NOT NULL,          -- imported, autocrypt
+            added_at INTEGER NOT NULL
+        );
+        CREATE TABLE autocrypt_peers (
+            address TEXT PRIMARY KEY,      -- lower-cased
+            fingerprint TEXT NOT NULL,
+            prefer_encrypt INTEGER NOT NULL DEFAULT 0,
+            last_seen INTEGER NOT NULL,
+            autocrypt_timestamp INTEGER NOT NULL
+        );
+        ALTER TABLE messages ADD COLUMN pgp_status TEXT;"
+    )
+}
+
+/// Signature statuses now say where the verifying key came from, and a key learned from an
+/// Autocrypt header no longer counts as verified. Statuses cached without that are checked again,
+/// along with bodies holding an inline signature, which were cached before those were checked.
+fn forget_signature_statuses(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute(
+        "UPDATE messages SET processed_html = NULL, body_fetched = 0, pgp_status = NULL
+         WHERE pgp_status IS NOT NULL OR processed_html LIKE '%-----BEGIN PGP SIGNED MESSAGE-----%'",
+        [],
+    )?;
+    Ok(())
+}
+
+/// The account whose mail is in `messages`, `mailbox_state` and the search index. None of
+/// those are keyed by account, so the cache holds one account at a time.
+fn add_cache_owner(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE cache_owner (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            account TEXT NOT NULL
+        );"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn temp_db(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("migrations-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("orbitmail.db")
+    }
+
+    fn user_version(conn: &Connection) -> u32 {
+        conn.query_row("PRAGMA user_version", (), |row| row.get(0)).unwrap()
+    }
+
+    /// Applies the steps up to `version`, as an older build would have left the database.
+    fn migrate_to(conn: &mut Connection, version: u32) {
+        for migration in MIGRATIONS.iter().filter(|m| m.version <= version) {
+            let tx = conn.transaction().unwrap();
+            (migration.up)(&tx).unwrap();
+            tx.pragma_update(None, "user_version", migration.version).unwrap();
+            tx.commit().unwrap();
+        }
+    }
+
+    /// A message row using only the columns that exist at `version`.
+    fn insert_message(conn: &Connection, version: u32, uid: u32, subject: &str) {
+        let mut columns = vec!["folder", "uid", "subject", "sender", "date", "snippet"];
+        let mut values: Vec<&dyn rusqlite::ToSql> = vec![&"INBOX", &uid, &subject, &"Ada <ada@example.com>", &1_700_000_000i64, &"Snippet"];
+        if version >= 2 {
+            columns.push("processed_html");
+            values.push(&"<p>Body</p>");
+        }
+        if version >= 4 {
+            columns.push("body_text");
+            values.push(&"Body");
+        }
+        let placeholders = vec!["?"; columns.len()].join(", ");
+        conn.execute(
+            &format!("INSERT INTO messages ({}) VALUES ({})", columns.join(", "), placeholders),
+            values.as_slice(),
+        ).unwrap();
+    }
+
+    /// Everything that makes up the schema: objects by type and name, plus each table's columns.
+    fn schema(conn: &Connection) -> Vec<String> {
+        let objects: Vec<(String, String)> = conn
+            .prepare("SELECT type, name FROM sqlite_master WHERE name NOT LIKE 'sqlite_%' ORDER BY type, name")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+
+        let mut schema = Vec::new();
+        for (kind, name) in objects {
+            if kind == "table" {
+                let columns: Vec<String> = conn
+                    .prepare(&format!("PRAGMA table_info({})", name))
+                    .unwrap()
+                    .query_map([], |row| row.get(1))
+                    .unwrap()
+                    .collect::<rusqlite::Result<_>>()
+                    .unwrap();
+                schema.push(format!("table {} ({})", name, columns.join(", ")));
+            } else {
+                schema.push(format!("{} {}", kind, name));
+            }
+        }
+        schema
+    }
+
+    fn fresh_schema() -> Vec<String> {
+        let path = temp_db("fresh");
+        let mut conn = Connection::open(&path).unwrap();
+        run(&mut conn, &path).unwrap();
+        schema(&conn)
+    }
+
+    fn fts_matches(conn: &Connection, term: &str) -> u32 {
+        conn.query_row(
+            "SELECT COUNT(*) FROM messages_fts WHERE messages_fts MATCH ?1",
+            [term],
+            |row| row.get(0),
+        ).unwrap()
+    }
+
+    #[test]
+    fn every_older_version_migrates_to_the_fresh_schema() {
+        let expected = fresh_schema();
+
+        for version in 1..latest_version() {
+            let path = temp_db(&format!("from-v{}", version));
+            let mut conn = Connection::open(&path).unwrap();
+            migrate_to(&mut conn, version);
+            insert_message(&conn, version, 1, "Quarterly report");
+
+            run(&mut conn, &path).unwrap();
+
+            assert_eq!(user_version(&conn), latest_version(), "from v{}", version);
+            assert_eq!(schema(&conn), expected, "from v{}", version);
+            assert_eq!(fts_matches(&conn, "quarterly"), 1, "from v{}", version);
+
+            // Rows written after the upgrade are indexed by the triggers
+            insert_message(&conn, latest_version(), 2, "Holiday plans");
+            assert_eq!(fts_matches(&conn, "holiday"), 1, "from v{}", version);
+        }
+    }
+
+    #[test]
+    fn legacy_databases_without_a_version_migrate_cleanly() {
+        let path = temp_db("legacy");
+        let mut conn = Connection::open(&path).unwrap();
+        // What a pre-`user_version` build left behind: processed_html, and a subject-only
+        // full-text index with its own triggers
+        conn.execute_batch(
+            "CREATE TABLE messages (
+                folder TEXT NOT NULL, uid INTEGER NOT NULL, uid_validity INTEGER,
+                subject TEXT, sender TEXT, date INTEGER NOT NULL, snippet TEXT, body TEXT,
+                seen INTEGER DEFAULT 0, flagged INTEGER DEFAULT 0, has_attachments INTEGER DEFAULT 0,
+                thread_id TEXT, body_fetched INTEGER DEFAULT 0, processed_html TEXT,
+                PRIMARY KEY (folder, uid)
+            );
+            CREATE TABLE mailbox_state (mailbox TEXT PRIMARY KEY, uid_validity INTEGER);
+            CREATE VIRTUAL TABLE messages_fts USING fts5(subject, content='messages', content_rowid='rowid');
+            CREATE TRIGGER messages_ai AFTER INSERT ON messages BEGIN
+                INSERT INTO messages_fts(rowid, subject) VALUES (new.rowid, new.subject);
+            END;"
+        ).unwrap();
+        insert_message(&conn, 2, 1, "Quarterly report");
+        assert_eq!(user_version(&conn), 0);
+
+        run(&mut conn, &path).unwrap();
+
+        assert_eq!(user_version(&conn), latest_version());
+        assert_eq!(schema(&conn), fresh_schema());
+        assert_eq!(fts_matches(&conn, "quarterly"), 1);
+        assert_eq!(fts_matches(&conn, "snippet"), 1);
+    }
+
+    #[test]
+    fn destructive_steps_back_up_existing_data_first() {
+        let path = temp_db("backup");
+        let mut conn = Connection::open(&path).unwrap();
+        migrate_to(&mut conn, 10);
+        insert_message(&conn, 10, 1, "Quarterly report");
+
+        run(&mut conn, &path).unwrap();
+
+        let backup_path = path.with_file_name("orbitmail.db.v10.bak");
+        let backup = Connection::open(&backup_path).unwrap();
+        assert_eq!(user_version(&backup), 10);
+        let subject: String = backup.query_row("SELECT subject FROM messages", (), |row| row.get(0)).unwrap();
+        assert_eq!(subject, "Quarterly report");
+    }
+
+    #[test]
+    fn forgetting_signature_statuses_backs_up_first() {
+        let path = temp_db("pgp-backup");
+        let mut conn = Connection::open(&path).unwrap();
+        migrate_to(&mut conn, 15);
+        insert_message(&conn, 15, 1, "Signed report");
+
+        run(&mut conn, &path).unwrap();
+
+        assert!(path.with_file_name("orbitmail.db.v15.bak").exists());
+    }
+
+    #[test]
+    fn additive_steps_skip_the_backup() {
+        let path = temp_db("no-backup");
+        let mut conn = Connection::open(&path).unwrap();
+        migrate_to(&mut conn, 16);
+        insert_message(&conn, 16, 1, "Quarterly report");
+
+        run(&mut conn, &path).unwrap();
+
+        assert_eq!(user_version(&conn), latest_version());
+        assert!(!path.with_file_name("orbitmail.db.v16.bak").exists());
+    }
+
+    #[test]
+    fn cached_bodies_are_rewritten() {
+        let path = temp_db("rewrite");
+        let mut conn = Connection::open(&path).unwrap();
+        migrate_to(&mut conn, 10);
+        conn.execute_batch(
+            r#"INSERT INTO messages (folder, uid, date, processed_html, body_fetched)
+               VALUES ('INBOX', 1, 0, '<p>Hello</p><script>alert(1)</script>', 1);
+               INSERT INTO messages (folder, uid, date, processed_html, body_fetched)
+               VALUES ('INBOX', 2, 0, '<img src="asset://localhost/cache/logo.png">', 1);
+               INSERT INTO messages (folder, uid, date, attachments_json, attachment_names)
+               VALUES ('INBOX', 3, 0, '[{"partId":"2","name":"=?UTF-8?Q?R=C3=A9sum=C3=A9.pdf?=","size":"1 KB","type":"application/pdf"}]', '');"#
+        ).unwrap();
+
+        run(&mut conn, &path).unwrap();
+
+        let sanitized: String = conn.query_row("SELECT processed_html FROM messages WHERE uid = 1", (), |row| row.get(0)).unwrap();
+        assert!(sanitized.contains("Hello"));
+        assert!(!sanitized.contains("script"));
+
+        let (asset_html, fetched): (Option<String>, i64) = conn
+            .query_row("SELECT processed_html, body_fetched FROM messages WHERE uid = 2", (), |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!((asset_html, fetched), (None, 0));
+
+        let (json, names): (String, String) = conn
+            .query_row("SELECT attachments_json, attachment_names FROM messages WHERE uid = 3", (), |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        let attachments: Vec<MessageAttachment> = serde_json::from_str(&json).unwrap();
+        assert_eq!(attachments[0].name, "Résumé.pdf");
+        assert_eq!(attachments[0].part_id, "2");
+        assert_eq!(names, "Résumé.pdf");
+    }
+
+    #[test]
+    fn cached_signature_statuses_are_checked_again() {
+        let path = temp_db("signatures");
+        let mut conn = Connection::open(&path).unwrap();
+        migrate_to(&mut conn, 15);
+        conn.execute_batch(
+            r#"INSERT INTO messages (folder, uid, date, processed_html, body_fetched, pgp_status)
+               VALUES ('INBOX', 1, 0, '<p>Signed</p>', 1, '{"encrypted":false,"decrypted":false,"signature":{"state":"valid"}}');
+               INSERT INTO messages (folder, uid, date, processed_html, body_fetched)
+               VALUES ('INBOX', 2, 0, '<p>Plain</p>', 1);
+               INSERT INTO messages (folder, uid, date, processed_html, body_fetched)
+               VALUES ('INBOX', 3, 0, '<pre>-----BEGIN PGP SIGNED MESSAGE-----</pre>', 1);"#
+        ).unwrap();
+
+        run(&mut conn, &path).unwrap();
+
+        let rows: Vec<(Option<String>, i64, Option<String>)> = conn
+            .prepare("SELECT processed_html, body_fetched, pgp_status FROM messages ORDER BY uid")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(rows, [(None, 0, None), (Some("<p>Plain</p>".to_string()), 1, None), (None, 0, None)]);
+    }
+
+    #[test]
+    fn the_cache_has_one_owner() {
+        let path = temp_db("owner");
+        let mut conn = Connection::open(&path).unwrap();
+        run(&mut conn, &path).unwrap();
+
+        conn.execute("INSERT OR REPLACE INTO cache_owner (id, account) VALUES (1, 'ada@example.com')", ()).unwrap();
+        conn.execute("INSERT OR REPLACE INTO cache_owner (id, account) VALUES (1, 'bob@example.com')", ()).unwrap();
+        assert!(conn.execute("INSERT INTO cache_owner (id, account) VALUES (2, 'eve@example.com')", ()).is_err());
+
+        let owner: String = conn.query_row("SELECT account FROM cache_owner", (), |row| row.get(0)).unwrap();
+        assert_eq!(owner, "bob@example.com");
+    }
+
+    #[test]
+    fn running_twice_is_a_no_op() {
+        let path = temp_db("twice");
+        let mut conn = Connection::open(&path).unwrap();
+        run(&mut conn, &path).unwrap();
+        run(&mut conn, &path).unwrap();
+        assert_eq!(user_version(&conn), latest_version());
+    }
+
+    #[test]
+    fn databases_from_a_newer_build_are_refused() {
+        let path = temp_db("newer");
+        let mut conn = Connection::open(&path).unwrap();
+        conn.pragma_update(None, "user_version", latest_version() + 1).unwrap();
+
+        let err = run(&mut conn, &path).unwrap_err();
+        assert!(err.contains("newer"), "{}", err);
+        assert_eq!(user_version(&conn), latest_version() + 1);
+    }
+}
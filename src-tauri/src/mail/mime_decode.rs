@@ -0,0 +1,296 @@
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
+use imap_proto::types::BodyParams;
+use once_cell::sync::Lazy;
+use regex::bytes::Regex;
+
+/// Bytes of an HTML part scanned for a `<meta>` charset declaration.
+const META_PRESCAN_BYTES: usize = 2048;
+
+/// Labels mail clients attach without looking at the content.
+const PLACEHOLDER_LABELS: &[&str] = &["us-ascii", "ascii", "unknown", "unknown-8bit", "x-unknown", "default"];
+
+static META_CHARSET: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?i)<meta[^>]*?charset\s*=\s*["']?\s*([a-z0-9_:.-]+)"#).unwrap()
+});
+
+/// Decodes RFC 2047 encoded words (`=?UTF-8?B?...?=`) in a header or parameter value.
+pub fn decode_encoded_words(raw: &[u8]) -> String {
+    let mut line = b"X: ".to_vec();
+    line.extend_from_slice(raw);
+    match mailparse::parse_header(&line) {
+        Ok((header, _)) => header.get_value(),
+        Err(_) => String::from_utf8_lossy(raw).into_owned(),
+    }
+}
+
+fn percent_decode(value: &str) -> Vec<u8> {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok());
+        match (bytes[i], hex.and_then(|h| u8::from_str_radix(h, 16).ok())) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (b, _) => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+/// Splits an RFC 2231 extended value into its charset and the still-encoded text.
+/// `UTF-8'en'%E2%82%AC` gives `(Some("UTF-8"), "%E2%82%AC")`.
+fn split_extended(value: &str) -> (Option<&str>, &str) {
+    let mut pieces = value.splitn(3, '\'');
+    match (pieces.next(), pieces.next(), pieces.next()) {
+        (Some(charset), Some(_language), Some(text)) => (Some(charset).filter(|c| !c.is_empty()), text),
+        _ => (None, value),
+    }
+}
+
+fn decode_with_label(bytes: &[u8], label: Option<&str>) -> String {
+    let encoding = label
+        .and_then(|l| Encoding::for_label(l.trim().as_bytes()))
+        .unwrap_or(UTF_8);
+    match encoding.decode_without_bom_handling_and_without_replacement(bytes) {
+        Some(text) => text.into_owned(),
+        None => decode_text(bytes, None, false),
+    }
+}
+
+/// Looks up parameter `name` and decodes it.
+///
+/// Handles RFC 2231 extended values (`name*=charset'lang'%XX`), numbered continuations
+/// (`name*0*=`, `name*1=` ...), and RFC 2047 encoded words in a plain `name=`, which many
+/// clients still send. Names are compared case-insensitively.
+pub fn decode_param<'a>(params: impl IntoIterator<Item = (&'a str, &'a str)>, name: &str) -> Option<String> {
+    let name = name.to_ascii_lowercase();
+    let mut plain = None;
+    let mut extended = None;
+    let mut sections: Vec<(u32, bool, &str)> = Vec::new();
+
+    for (key, value) in params {
+        let key = key.trim().to_ascii_lowercase();
+        let Some(rest) = key.strip_prefix(name.as_str()) else { continue };
+        match rest {
+            "" => plain = Some(value),
+            "*" => extended = Some(value),
+            _ => {
+                let Some(section) = rest.strip_prefix('*') else { continue };
+                let (number, encoded) = match section.strip_suffix('*') {
+                    Some(number) => (number, true),
+                    None => (section, false),
+                };
+                if let Ok(index) = number.parse() {
+                    sections.push((index, encoded, value));
+                }
+            }
+        }
+    }
+
+    if !sections.is_empty() {
+        sections.sort_by_key(|(index, _, _)| *index);
+        let mut charset = None;
+        let mut bytes = Vec::new();
+        for (index, encoded, value) in sections {
+            if !encoded {
+                bytes.extend_from_slice(value.as_bytes());
+                continue;
+            }
+            // Only the first section carries the charset and language
+            let text = if index == 0 {
+                let (label, text) = split_extended(value);
+                charset = label;
+                text
+            } else {
+                value
+            };
+            bytes.extend(percent_decode(text));
+        }
+        return Some(decode_with_label(&bytes, charset));
+    }
+
+    if let Some(value) = extended {
+        let (charset, text) = split_extended(value);
+        return Some(decode_with_label(&percent_decode(text), charset));
+    }
+
+    plain.map(|value| decode_encoded_words(value.as_bytes()))
+}
+
+/// `decode_param` over a BODYSTRUCTURE parameter list.
+pub fn body_param(params: &BodyParams, name: &str) -> Option<String> {
+    decode_param(params.as_ref()?.iter().copied(), name)
+}
+
+fn meta_charset(bytes: &[u8]) -> Option<&'static Encoding> {
+    let head = &bytes[..bytes.len().min(META_PRESCAN_BYTES)];
+    let label = META_CHARSET.captures(head)?.get(1)?.as_bytes();
+    // A page can't sensibly declare a UTF-16 charset in ASCII-compatible markup
+    Encoding::for_label(label).map(|e| e.output_encoding())
+}
+
+fn detect(bytes: &[u8], last: bool) -> &'static Encoding {
+    let mut detector = EncodingDetector::new();
+    detector.feed(bytes, last);
+    detector.guess(None, true)
+}
+
+/// Decodes with `encoding`, or `None` if the bytes are malformed for it. With `last` unset,
+/// an incomplete sequence at the very end is not an error.
+fn decode_strict(encoding: &'static Encoding, bytes: &[u8], last: bool) -> Option<String> {
+    let mut decoder = encoding.new_decoder_without_bom_handling();
+    let mut out = String::with_capacity(decoder.max_utf8_buffer_length_without_replacement(bytes.len())?);
+    let (result, _) = decoder.decode_to_string_without_replacement(bytes, &mut out, last);
+    match result {
+        encoding_rs::DecoderResult::InputEmpty => Some(out),
+        _ => None,
+    }
+}
+
+fn decode_impl(bytes: &[u8], label: Option<&str>, is_html: bool, last: bool) -> String {
+    let label = label.map(str::trim).filter(|l| !PLACEHOLDER_LABELS.iter().any(|p| l.eq_ignore_ascii_case(p)));
+    let declared = label
+        .and_then(|l| Encoding::for_label(l.as_bytes()))
+        .or_else(|| if is_html && label.is_none() { meta_charset(bytes) } else { None });
+
+    // Plain ASCII reads the same in every charset except the escape-based ones like ISO-2022-JP
+    if bytes.is_ascii() && !bytes.contains(&0x1B) && declared.map_or(true, |e| e.is_ascii_compatible()) {
+        return String::from_utf8_lossy(bytes).into_owned();
+    }
+
+    if let Some(encoding) = declared {
+        // Legacy Latin labels are often slapped on UTF-8 text; valid multi-byte UTF-8
+        // is very unlikely to occur by chance, so it wins
+        if encoding == WINDOWS_1252 {
+            if let Some(text) = decode_strict(UTF_8, bytes, last) {
+                return text;
+            }
+        }
+        if let Some(text) = decode_strict(encoding, bytes, last) {
+            return text;
+        }
+        log::debug!("Text labeled {} is malformed for it; detecting charset", encoding.name());
+    }
+
+    let guessed = detect(bytes, last);
+    decode_strict(guessed, bytes, last).unwrap_or_else(|| guessed.decode_without_bom_handling(bytes).0.into_owned())
+}
+
+/// Decodes a text part to a string.
+///
+/// `label` is the part's `charset` parameter. It is trusted unless it's missing, a
+/// placeholder like `us-ascii` on 8-bit text, or doesn't fit the bytes; then HTML parts
+/// fall back to a `<meta>` declaration and everything else to statistical detection.
+pub fn decode_text(bytes: &[u8], label: Option<&str>, is_html: bool) -> String {
+    decode_impl(bytes, label, is_html, true)
+}
+
+/// Same as `decode_text` for the first bytes of a part, where the cut may split a character.
+pub fn decode_text_prefix(bytes: &[u8], label: Option<&str>, is_html: bool) -> String {
+    decode_impl(bytes, label, is_html, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::{GB18030, ISO_2022_JP, SHIFT_JIS, WINDOWS_1251};
+
+    fn param(params: &[(&str, &str)], name: &str) -> Option<String> {
+        decode_param(params.iter().copied(), name)
+    }
+
+    const JAPANESE: &str = "こんにちは、世界。今日はいい天気ですね。日本語のメールです。";
+    const RUSSIAN: &str = "Привет, как дела? Это письмо на русском языке, в старой кодировке.";
+    const CHINESE: &str = "你好，这是一封用简体中文写的电子邮件，编码是国标。";
+
+    #[test]
+    fn extended_params_are_percent_decoded_in_their_charset() {
+        assert_eq!(param(&[("filename*", "UTF-8''%E6%97%A5%E6%9C%AC%E8%AA%9E.pdf")], "filename").as_deref(), Some("日本語.pdf"));
+        assert_eq!(param(&[("filename*", "windows-1251''%CF%F0%E8%E2%E5%F2.txt")], "filename").as_deref(), Some("Привет.txt"));
+        assert_eq!(param(&[("name*", "''plain%20name.txt")], "name").as_deref(), Some("plain name.txt"));
+    }
+
+    #[test]
+    fn continuations_are_joined_in_order() {
+        let params = [("filename*2*", "%E9.doc"), ("FILENAME*0*", "ISO-8859-1'fr'R%E9sum"), ("filename*1", "e_final")];
+        assert_eq!(param(&params, "filename").as_deref(), Some("Résume_finalé.doc"));
+
+        let plain = [("name*0", "long_"), ("name*1", "file"), ("name*2", "name.txt")];
+        assert_eq!(param(&plain, "name").as_deref(), Some("long_filename.txt"));
+    }
+
+    #[test]
+    fn extended_values_win_over_plain_ones() {
+        let params = [("name", "plain.txt"), ("name*", "utf-8''%C3%BCber.txt")];
+        assert_eq!(param(&params, "name").as_deref(), Some("über.txt"));
+    }
+
+    #[test]
+    fn plain_values_decode_encoded_words() {
+        assert_eq!(param(&[("Name", "=?UTF-8?Q?caf=C3=A9.txt?=")], "name").as_deref(), Some("café.txt"));
+        assert_eq!(param(&[("name", "report.pdf")], "NAME").as_deref(), Some("report.pdf"));
+    }
+
+    #[test]
+    fn other_params_do_not_match() {
+        assert_eq!(param(&[("filenamex", "no"), ("filename*x", "no")], "filename"), None);
+    }
+
+    #[test]
+    fn undeclared_charsets_are_detected() {
+        assert_eq!(decode_text(&SHIFT_JIS.encode(JAPANESE).0, None, false), JAPANESE);
+        assert_eq!(decode_text(&WINDOWS_1251.encode(RUSSIAN).0, None, false), RUSSIAN);
+        assert_eq!(decode_text(&GB18030.encode(CHINESE).0, None, false), CHINESE);
+    }
+
+    #[test]
+    fn placeholder_and_wrong_labels_are_not_trusted() {
+        let sjis = SHIFT_JIS.encode(JAPANESE).0;
+        assert_eq!(decode_text(&sjis, Some("us-ascii"), false), JAPANESE);
+        assert_eq!(decode_text(&sjis, Some(" unknown-8bit "), false), JAPANESE);
+        assert_eq!(decode_text(&GB18030.encode(CHINESE).0, Some("utf-8"), false), CHINESE);
+    }
+
+    #[test]
+    fn latin_labels_on_utf8_text_read_as_utf8() {
+        assert_eq!(decode_text("Café déjà vu".as_bytes(), Some("iso-8859-1"), false), "Café déjà vu");
+        assert_eq!(decode_text(b"Caf\xe9 d\xe9j\xe0 vu", Some("iso-8859-1"), false), "Café déjà vu");
+    }
+
+    #[test]
+    fn escape_based_charsets_are_decoded() {
+        let jis = ISO_2022_JP.encode("日本語のテキスト").0;
+        assert!(jis.is_ascii());
+        assert_eq!(decode_text(&jis, Some("ISO-2022-JP"), false), "日本語のテキスト");
+        assert_eq!(decode_text(b"plain ascii", Some("ISO-2022-JP"), false), "plain ascii");
+    }
+
+    #[test]
+    fn html_meta_charset_is_used_without_a_label() {
+        let mut html = b"<html><head><meta http-equiv=\"Content-Type\" content=\"text/html; charset=windows-1251\"></head><body>".to_vec();
+        html.extend_from_slice(&WINDOWS_1251.encode(RUSSIAN).0);
+        assert!(decode_text(&html, None, true).ends_with(RUSSIAN));
+
+        let mut html5 = b"<meta charset='koi8-r'>".to_vec();
+        html5.extend_from_slice(&encoding_rs::KOI8_R.encode("Привет").0);
+        assert_eq!(decode_text(&html5, None, true), "<meta charset='koi8-r'>Привет");
+    }
+
+    #[test]
+    fn prefixes_may_end_inside_a_character() {
+        let text = "Grüße aus München – €".as_bytes();
+        let cut = &text[..text.len() - 2];
+        assert_eq!(decode_text_prefix(cut, Some("utf-8"), false), "Grüße aus München – ");
+
+        let sjis = SHIFT_JIS.encode(JAPANESE).0;
+        let prefix = decode_text_prefix(&sjis[..sjis.len() - 1], Some("shift_jis"), false);
+        assert!(JAPANESE.starts_with(&prefix) && prefix.len() > JAPANESE.len() / 2, "{}", prefix);
+    }
+}
@@ -4,8 +4,30 @@ pub mod database;
 pub mod sync;
 pub mod message_body;
 pub mod imap_session;
+pub mod db_pool;
+pub mod migrations;
 pub mod body_cache;
 pub mod idle;
 pub mod poll;
 pub mod prefetch;
 pub mod notifications;
+pub mod tls;
+pub mod search;
+pub mod query;
+pub mod indexer;
+pub mod threading;
+pub mod contacts;
+pub mod snippet;
+pub mod saved_searches;
+pub mod paging;
+pub mod sanitize;
+pub mod remote_content;
+pub mod message_protocol;
+pub mod mime_decode;
+pub mod attachment_preview;
+pub mod attachment_export;
+pub mod attachment_download;
+pub mod download_manager;
+pub mod pgp_keyring;
+pub mod openpgp;
+pub mod autocrypt;
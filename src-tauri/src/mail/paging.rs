@@ -0,0 +1,360 @@
+use crate::mail::database::{self, HEADER_COLUMNS, HEADER_COLUMN_COUNT};
+use crate::mail::db_pool::get_conn;
+use crate::mail::message_list::MessageHeader;
+use crate::mail::query::{Query, SqlParam, Term};
+use base64::Engine;
+use rusqlite::types::Value;
+use rusqlite::Connection;
+use tauri::AppHandle;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SortKey {
+    #[default]
+    Date,
+    Sender,
+    Subject,
+    Size,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SortDirection {
+    Asc,
+    #[default]
+    Desc,
+}
+
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct MessageFilter {
+    pub unread_only: bool,
+    pub flagged_only: bool,
+    pub has_attachments: bool,
+    /// Inclusive lower bound, Unix seconds.
+    pub after: Option<i64>,
+    /// Exclusive upper bound, Unix seconds.
+    pub before: Option<i64>,
+    /// Substring of the sender's name or address.
+    pub sender: Option<String>,
+}
+
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessagePage {
+    pub messages: Vec<MessageHeader>,
+    /// Pass back as `cursor` to get the next page; `None` on the last page.
+    pub next_cursor: Option<String>,
+}
+
+/// Position after the last row of a page: its sort value plus the UID as tie-breaker.
+/// Rows arriving mid-scroll sort before or after it but never shift it.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct Cursor {
+    sort: SortKey,
+    direction: SortDirection,
+    value: CursorValue,
+    uid: u32,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+enum CursorValue {
+    Int(i64),
+    Text(String),
+}
+
+impl Cursor {
+    fn encode(&self) -> String {
+        let json = serde_json::to_vec(self).unwrap_or_default();
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(json)
+    }
+
+    fn decode(cursor: &str) -> Result<Self, String> {
+        let json = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(cursor)
+            .map_err(|_| "Invalid page cursor".to_string())?;
+        serde_json::from_slice(&json).map_err(|_| "Invalid page cursor".to_string())
+    }
+}
+
+impl SortKey {
+    /// Sort expression; each has a matching `(folder, <expr>, uid)` index.
+    fn sql(self) -> &'static str {
+        match self {
+            SortKey::Date => "messages.date",
+            SortKey::Sender => "IFNULL(messages.sender, '') COLLATE NOCASE",
+            SortKey::Subject => "IFNULL(messages.subject, '') COLLATE NOCASE",
+            SortKey::Size => "IFNULL(messages.size, 0)",
+        }
+    }
+}
+
+impl MessageFilter {
+    fn to_sql(&self) -> (String, Vec<SqlParam>) {
+        let mut terms = Vec::new();
+        if self.unread_only {
+            terms.push(Query::Term(Term::IsUnread));
+        }
+        if self.flagged_only {
+            terms.push(Query::Term(Term::IsStarred));
+        }
+        if self.has_attachments {
+            terms.push(Query::Term(Term::HasAttachment));
+        }
+        if let Some(sender) = self.sender.as_deref().map(str::trim).filter(|s| !s.is_empty()) {
+            terms.push(Query::Term(Term::From(sender.to_string())));
+        }
+
+        let (mut sql, mut params) = Query::And(terms).to_sql();
+        if let Some(after) = self.after {
+            sql.push_str(" AND messages.date >= ?");
+            params.push(SqlParam::Int(after));
+        }
+        if let Some(before) = self.before {
+            sql.push_str(" AND messages.date < ?");
+            params.push(SqlParam::Int(before));
+        }
+        (sql, params)
+    }
+}
+
+/// One page of `folder`, sorted and filtered, continuing after `cursor` if given.
+pub fn load_page(
+    app_handle: &AppHandle,
+    folder: &str,
+    sort: SortKey,
+    direction: SortDirection,
+    filter: &MessageFilter,
+    cursor: Option<&str>,
+    limit: u32,
+) -> Result<MessagePage, String> {
+    let conn = get_conn(app_handle)?;
+    page_from(&conn, folder, sort, direction, filter, cursor, limit)
+}
+
+fn page_from(
+    conn: &Connection,
+    folder: &str,
+    sort: SortKey,
+    direction: SortDirection,
+    filter: &MessageFilter,
+    cursor: Option<&str>,
+    limit: u32,
+) -> Result<MessagePage, String> {
+    let sort_sql = sort.sql();
+    let (order, comparison) = match direction {
+        SortDirection::Asc => ("ASC", ">"),
+        SortDirection::Desc => ("DESC", "<"),
+    };
+
+    let (filter_sql, filter_params) = filter.to_sql();
+    let mut params = vec![SqlParam::Text(folder.to_string())];
+    params.extend(filter_params);
+
+    let cursor_sql = match cursor.map(Cursor::decode).transpose()? {
+        Some(c) if c.sort != sort || c.direction != direction => {
+            return Err("Page cursor belongs to a different sort order".to_string());
+        }
+        Some(c) => {
+            params.push(match c.value {
+                CursorValue::Int(i) => SqlParam::Int(i),
+                CursorValue::Text(s) => SqlParam::Text(s),
+            });
+            params.push(SqlParam::Int(c.uid as i64));
+            format!("AND ({}, messages.uid) {} (?, ?)", sort_sql, comparison)
+        }
+        None => String::new(),
+    };
+
+    // One extra row tells us whether another page exists
+    params.push(SqlParam::Int(limit as i64 + 1));
+
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT {cols}, {sort} AS sort_value
+         FROM messages
+         WHERE messages.folder = ? AND {filter} {cursor}
+         ORDER BY {sort} {order}, messages.uid {order}
+         LIMIT ?",
+        cols = HEADER_COLUMNS,
+        sort = sort_sql,
+        filter = filter_sql,
+        cursor = cursor_sql,
+        order = order,
+    )).map_err(|e| e.to_string())?;
+
+    let rows = stmt.query_map(rusqlite::params_from_iter(params.iter()), |row| {
+        Ok((database::parse_header_row(row)?, row.get::<_, Value>(HEADER_COLUMN_COUNT)?))
+    }).map_err(|e| e.to_string())?;
+
+    let mut page = Vec::new();
+    for r in rows {
+        page.push(r.map_err(|e| e.to_string())?);
+    }
+
+    let has_more = page.len() > limit as usize;
+    page.truncate(limit as usize);
+
+    let next_cursor = match page.last() {
+        Some((header, value)) if has_more => {
+            let value = match value {
+                Value::Integer(i) => CursorValue::Int(*i),
+                Value::Text(s) => CursorValue::Text(s.clone()),
+                _ => CursorValue::Text(String::new()),
+            };
+            Some(Cursor { sort, direction, value, uid: header.uid }.encode())
+        }
+        _ => None,
+    };
+
+    Ok(MessagePage {
+        messages: page.into_iter().map(|(header, _)| header).collect(),
+        next_cursor,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sort values are distinct per key except for the date tie between uids 2 and 3.
+    fn paging_db(name: &str) -> (Connection, std::path::PathBuf) {
+        let path = std::env::temp_dir().join(format!("paging-{}-{}.db", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut conn = Connection::open(&path).unwrap();
+        crate::mail::migrations::run(&mut conn, &path).unwrap();
+        conn.execute_batch(
+            "INSERT INTO messages (folder, uid, uid_validity, date, sender, subject, size, seen, flagged, has_attachments) VALUES
+                ('INBOX', 1, 1, 100, 'Carol <c@x>', 'banana', 300, 1, 0, 0),
+                ('INBOX', 2, 1, 200, 'alice <a@x>', 'Cherry', 100, 0, 1, 0),
+                ('INBOX', 3, 1, 200, 'Bob <b@x>', 'apple', 200, 0, 0, 1),
+                ('INBOX', 4, 1, 400, 'dave <d@x>', 'Date', NULL, 1, 1, 1),
+                ('INBOX', 5, 1, 300, 'Eve <e@x>', 'elder', 500, 1, 0, 0),
+                ('Sent', 6, 1, 1000, 'Ada <ada@x>', 'all', 50, 0, 1, 1);"
+        ).unwrap();
+        (conn, path)
+    }
+
+    fn uids(page: &MessagePage) -> Vec<u32> {
+        page.messages.iter().map(|m| m.uid).collect()
+    }
+
+    fn first_page(conn: &Connection, sort: SortKey, direction: SortDirection, filter: &MessageFilter) -> Vec<u32> {
+        uids(&page_from(conn, "INBOX", sort, direction, filter, None, 10).unwrap())
+    }
+
+    /// Every page of a scroll, two rows at a time, with `between` run after each page.
+    fn scroll(conn: &Connection, sort: SortKey, direction: SortDirection, mut between: impl FnMut(&Connection)) -> Vec<u32> {
+        let mut seen = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = page_from(conn, "INBOX", sort, direction, &MessageFilter::default(), cursor.as_deref(), 2).unwrap();
+            seen.extend(uids(&page));
+            between(conn);
+            match page.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => return seen,
+            }
+        }
+    }
+
+    #[test]
+    fn every_sort_key_orders_both_ways() {
+        let (conn, path) = paging_db("sorts");
+        let filter = MessageFilter::default();
+
+        for (sort, ascending) in [
+            (SortKey::Date, vec![1, 2, 3, 5, 4]),
+            (SortKey::Sender, vec![2, 3, 1, 4, 5]),
+            (SortKey::Subject, vec![3, 1, 2, 4, 5]),
+            // A missing size sorts as zero
+            (SortKey::Size, vec![4, 2, 3, 1, 5]),
+        ] {
+            assert_eq!(first_page(&conn, sort, SortDirection::Asc, &filter), ascending, "{:?}", sort);
+            let descending: Vec<u32> = ascending.iter().rev().copied().collect();
+            assert_eq!(first_page(&conn, sort, SortDirection::Desc, &filter), descending, "{:?}", sort);
+        }
+
+        drop(conn);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn every_filter_narrows_the_folder() {
+        let (conn, path) = paging_db("filters");
+        let list = |filter: MessageFilter| first_page(&conn, SortKey::Date, SortDirection::Asc, &filter);
+
+        assert_eq!(list(MessageFilter { unread_only: true, ..Default::default() }), [2, 3]);
+        assert_eq!(list(MessageFilter { flagged_only: true, ..Default::default() }), [2, 4]);
+        assert_eq!(list(MessageFilter { has_attachments: true, ..Default::default() }), [3, 4]);
+        assert_eq!(list(MessageFilter { after: Some(200), ..Default::default() }), [2, 3, 5, 4]);
+        assert_eq!(list(MessageFilter { before: Some(300), ..Default::default() }), [1, 2, 3]);
+        assert_eq!(list(MessageFilter { sender: Some("ALI".to_string()), ..Default::default() }), [2]);
+        assert_eq!(list(MessageFilter { sender: Some("  ".to_string()), ..Default::default() }), [1, 2, 3, 5, 4]);
+        assert_eq!(
+            list(MessageFilter { unread_only: true, has_attachments: true, ..Default::default() }),
+            [3]
+        );
+
+        drop(conn);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn ties_break_on_uid_across_pages() {
+        let (conn, path) = paging_db("ties");
+
+        // Uids 2 and 3 share a date; a page boundary falls between them
+        let first = page_from(&conn, "INBOX", SortKey::Date, SortDirection::Asc, &MessageFilter::default(), None, 2).unwrap();
+        assert_eq!(uids(&first), [1, 2]);
+        let next = page_from(&conn, "INBOX", SortKey::Date, SortDirection::Asc, &MessageFilter::default(), first.next_cursor.as_deref(), 2).unwrap();
+        assert_eq!(uids(&next), [3, 5]);
+
+        assert_eq!(scroll(&conn, SortKey::Date, SortDirection::Desc, |_| {}), [4, 5, 3, 2, 1]);
+
+        drop(conn);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn rows_arriving_mid_scroll_do_not_shift_the_cursor() {
+        let (conn, path) = paging_db("arrivals");
+
+        let mut next_uid = 100;
+        let seen = scroll(&conn, SortKey::Date, SortDirection::Desc, |conn| {
+            conn.execute(
+                "INSERT INTO messages (folder, uid, uid_validity, date, sender, subject) VALUES ('INBOX', ?1, 1, 5000, 'New <n@x>', 'new')",
+                [next_uid],
+            ).unwrap();
+            next_uid += 1;
+        });
+
+        // Newer mail lands above the first page: nothing repeats and nothing is skipped
+        assert_eq!(seen, [4, 5, 3, 2, 1]);
+
+        drop(conn);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn cursors_only_continue_the_sort_they_came_from() {
+        let (conn, path) = paging_db("cursors");
+        let filter = MessageFilter::default();
+
+        let first = page_from(&conn, "INBOX", SortKey::Date, SortDirection::Desc, &filter, None, 2).unwrap();
+        let cursor = first.next_cursor.unwrap();
+
+        for (sort, direction) in [(SortKey::Sender, SortDirection::Desc), (SortKey::Date, SortDirection::Asc)] {
+            let err = page_from(&conn, "INBOX", sort, direction, &filter, Some(&cursor), 2).unwrap_err();
+            assert!(err.contains("different sort"), "{}", err);
+        }
+        assert!(page_from(&conn, "INBOX", SortKey::Date, SortDirection::Desc, &filter, Some("not a cursor"), 2).is_err());
+
+        // The last page has no cursor
+        let all = page_from(&conn, "INBOX", SortKey::Date, SortDirection::Desc, &filter, None, 5).unwrap();
+        assert_eq!(all.next_cursor, None);
+
+        drop(conn);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,321 @@
+use crate::mail::database;
+use crate::mail::db_pool::{get_conn, retry_busy};
+use once_cell::sync::Lazy;
+use pgp::composed::signed_key::{from_armor_many, from_bytes_many, PublicOrSecret};
+use pgp::composed::{Deserializable, SignedPublicKey, SignedPublicSubKey, SignedSecretKey};
+use pgp::ser::Serialize as _;
+use pgp::types::{KeyTrait, SecretKeyTrait};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::Mutex;
+use tauri::AppHandle;
+
+/// Passphrases of secret keys unlocked this session, by fingerprint. Never persisted.
+static PASSPHRASES: Lazy<Mutex<HashMap<String, String>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// A key as listed in the keyring settings.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeySummary {
+    pub fingerprint: String,
+    pub user_ids: Vec<String>,
+    pub has_secret: bool,
+    /// Needs `unlock` before it can decrypt or sign.
+    pub locked: bool,
+    pub can_encrypt: bool,
+    /// `imported` or `autocrypt`.
+    pub source: String,
+    pub added_at: i64,
+}
+
+/// A keyring entry, parsed.
+pub struct KeyEntry {
+    pub fingerprint: String,
+    pub user_ids: Vec<String>,
+    pub public: SignedPublicKey,
+    pub secret: Option<SignedSecretKey>,
+    pub source: String,
+    pub added_at: i64,
+}
+
+impl KeyEntry {
+    /// Whether a user id of this key is `address`, bare or in angle brackets.
+    pub fn has_address(&self, address: &str) -> bool {
+        self.user_ids.iter().any(|uid| uid_address(uid).eq_ignore_ascii_case(address.trim()))
+    }
+
+    fn summary(&self) -> KeySummary {
+        KeySummary {
+            fingerprint: self.fingerprint.clone(),
+            user_ids: self.user_ids.clone(),
+            has_secret: self.secret.is_some(),
+            locked: self.secret.as_ref().is_some_and(|s| is_locked(&self.fingerprint, s)),
+            can_encrypt: encryption_subkey(&self.public).is_some(),
+            source: self.source.clone(),
+            added_at: self.added_at,
+        }
+    }
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// The address part of a user id such as `Jane Doe <jane@example.com>`.
+pub fn uid_address(uid: &str) -> &str {
+    match (uid.rfind('<'), uid.rfind('>')) {
+        (Some(start), Some(end)) if start < end => uid[start + 1..end].trim(),
+        _ => uid.trim(),
+    }
+}
+
+/// The subkey messages to this key are encrypted to.
+pub fn encryption_subkey(key: &SignedPublicKey) -> Option<&SignedPublicSubKey> {
+    key.public_subkeys.iter().find(|sub| sub.is_encryption_key())
+}
+
+/// The public half of a secret key, with the same self-signatures.
+pub(crate) fn public_of(secret: &SignedSecretKey) -> SignedPublicKey {
+    let subkeys = secret
+        .public_subkeys
+        .iter()
+        .cloned()
+        .chain(secret.secret_subkeys.iter().map(|sub| SignedPublicSubKey::new(sub.key.public_key(), sub.signatures.clone())))
+        .collect();
+    SignedPublicKey::new(secret.primary_key.public_key(), secret.details.clone(), subkeys)
+}
+
+fn user_ids(key: &SignedPublicKey) -> Vec<String> {
+    key.details.users.iter().map(|user| user.id.id().to_string()).collect()
+}
+
+/// Protected secret keys are locked until their passphrase is given this session.
+pub fn is_locked(fingerprint: &str, secret: &SignedSecretKey) -> bool {
+    secret.primary_key.secret_params().is_encrypted() && !PASSPHRASES.lock().unwrap().contains_key(fingerprint)
+}
+
+/// Passphrase to use for a secret key; unprotected keys take an empty one.
+pub fn passphrase(fingerprint: &str) -> String {
+    PASSPHRASES.lock().unwrap().get(fingerprint).cloned().unwrap_or_default()
+}
+
+/// Parses every key in armored or binary input.
+fn parse_keys(data: &[u8]) -> Result<Vec<PublicOrSecret>, String> {
+    let is_armored = String::from_utf8_lossy(&data[..data.len().min(1024)]).contains("-----BEGIN PGP");
+    let keys: Vec<PublicOrSecret> = if is_armored {
+        let (keys, _) = from_armor_many(Cursor::new(data)).map_err(|e| format!("Invalid OpenPGP key: {}", e))?;
+        keys.collect::<Result<_, _>>()
+    } else {
+        from_bytes_many(Cursor::new(data)).collect::<Result<_, _>>()
+    }
+    .map_err(|e| format!("Invalid OpenPGP key: {}", e))?;
+
+    if keys.is_empty() {
+        return Err("No OpenPGP keys found".to_string());
+    }
+    Ok(keys)
+}
+
+fn store(
+    conn: &rusqlite::Connection,
+    public: &SignedPublicKey,
+    secret: Option<&SignedSecretKey>,
+    source: &str,
+) -> Result<String, String> {
+    public.verify().map_err(|e| format!("Key has invalid self-signatures: {}", e))?;
+
+    let fingerprint = hex(&public.fingerprint());
+    let public_bytes = public.to_bytes().map_err(|e| e.to_string())?;
+    let secret_bytes = secret.map(|s| s.to_bytes()).transpose().map_err(|e| e.to_string())?;
+
+    // Imported keys aren't downgraded by an Autocrypt header carrying the same key,
+    // and a secret half, once known, isn't dropped by a later public-only import
+    retry_busy(|| conn.execute(
+        "INSERT INTO pgp_keys (fingerprint, user_ids, public_key, secret_key, source, added_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+         ON CONFLICT(fingerprint) DO UPDATE SET
+            user_ids = excluded.user_ids,
+            public_key = excluded.public_key,
+            secret_key = COALESCE(excluded.secret_key, secret_key),
+            source = CASE WHEN excluded.source = 'imported' THEN 'imported' ELSE source END",
+        rusqlite::params![
+            fingerprint,
+            user_ids(public).join("\n"),
+            public_bytes,
+            secret_bytes,
+            source,
+            chrono::Utc::now().timestamp(),
+        ],
+    )).map_err(|e| e.to_string())?;
+    Ok(fingerprint)
+}
+
+/// Adds the keys in `data`, armored or binary, public or secret. `source` records where
+/// they came from. Returns the fingerprints stored.
+pub fn import(app_handle: &AppHandle, data: &[u8], source: &str) -> Result<Vec<String>, String> {
+    let keys = parse_keys(data)?;
+
+    let conn = get_conn(app_handle)?;
+    let mut fingerprints = Vec::new();
+    for key in keys {
+        let fingerprint = match key {
+            PublicOrSecret::Public(public) => store(&conn, &public, None, source)?,
+            PublicOrSecret::Secret(secret) => {
+                secret.verify().map_err(|e| format!("Key has invalid self-signatures: {}", e))?;
+                store(&conn, &public_of(&secret), Some(&secret), source)?
+            }
+        };
+        fingerprints.push(fingerprint);
+    }
+    drop(conn);
+
+    // Signatures that couldn't be checked before may verify now
+    database::forget_signed_bodies(app_handle)?;
+    Ok(fingerprints)
+}
+
+/// Stores a key announced in an Autocrypt header. Returns its fingerprint.
+pub fn import_autocrypt(app_handle: &AppHandle, keydata: &[u8]) -> Result<String, String> {
+    let public = SignedPublicKey::from_bytes(Cursor::new(keydata)).map_err(|e| format!("Invalid OpenPGP key: {}", e))?;
+    if encryption_subkey(&public).is_none() {
+        return Err("The key has no encryption subkey".to_string());
+    }
+
+    let conn = get_conn(app_handle)?;
+    let known: bool = conn
+        .query_row("SELECT EXISTS(SELECT 1 FROM pgp_keys WHERE fingerprint = ?1)", [hex(&public.fingerprint())], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    let fingerprint = store(&conn, &public, None, "autocrypt")?;
+    drop(conn);
+
+    // Only a key seen for the first time can change signature results
+    if !known {
+        database::forget_signed_bodies(app_handle)?;
+    }
+    Ok(fingerprint)
+}
+
+fn load(app_handle: &AppHandle, fingerprint: Option<&str>) -> Result<Vec<KeyEntry>, String> {
+    let conn = get_conn(app_handle)?;
+    let mut stmt = conn.prepare_cached(
+        "SELECT fingerprint, public_key, secret_key, source, added_at FROM pgp_keys
+         WHERE ?1 IS NULL OR fingerprint = ?1 ORDER BY added_at"
+    ).map_err(|e| e.to_string())?;
+
+    let rows = stmt.query_map([fingerprint], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, Vec<u8>>(1)?,
+            row.get::<_, Option<Vec<u8>>>(2)?,
+            row.get::<_, String>(3)?,
+            row.get::<_, i64>(4)?,
+        ))
+    }).map_err(|e| e.to_string())?;
+
+    let mut entries = Vec::new();
+    for row in rows {
+        let (fingerprint, public_bytes, secret_bytes, source, added_at) = row.map_err(|e| e.to_string())?;
+        // Keys this build can't parse are skipped rather than breaking the whole keyring
+        let Ok(public) = SignedPublicKey::from_bytes(Cursor::new(public_bytes)) else {
+            log::warn!("Skipping unreadable OpenPGP key {}", fingerprint);
+            continue;
+        };
+        let secret = secret_bytes.and_then(|bytes| SignedSecretKey::from_bytes(Cursor::new(bytes)).ok());
+        entries.push(KeyEntry {
+            fingerprint,
+            user_ids: user_ids(&public),
+            public,
+            secret,
+            source,
+            added_at,
+        });
+    }
+    Ok(entries)
+}
+
+pub fn all(app_handle: &AppHandle) -> Result<Vec<KeyEntry>, String> {
+    load(app_handle, None)
+}
+
+pub fn get(app_handle: &AppHandle, fingerprint: &str) -> Result<KeyEntry, String> {
+    load(app_handle, Some(&fingerprint.to_ascii_lowercase()))?
+        .into_iter()
+        .next()
+        .ok_or_else(|| format!("No key with fingerprint {}", fingerprint))
+}
+
+pub fn list(app_handle: &AppHandle) -> Result<Vec<KeySummary>, String> {
+    Ok(all(app_handle)?.iter().map(KeyEntry::summary).collect())
+}
+
+/// Armored public key, or with `include_secret` the secret key as it was imported.
+pub fn export(app_handle: &AppHandle, fingerprint: &str, include_secret: bool) -> Result<String, String> {
+    let entry = get(app_handle, fingerprint)?;
+    let armored = if include_secret {
+        entry
+            .secret
+            .ok_or("There is no secret key for this fingerprint")?
+            .to_armored_string(None.into())
+    } else {
+        entry.public.to_armored_string(None.into())
+    };
+    armored.map_err(|e| e.to_string())
+}
+
+pub fn delete(app_handle: &AppHandle, fingerprint: &str) -> Result<(), String> {
+    let fingerprint = fingerprint.to_ascii_lowercase();
+    let conn = get_conn(app_handle)?;
+    retry_busy(|| conn.execute("DELETE FROM pgp_keys WHERE fingerprint = ?1", [&fingerprint])).map_err(|e| e.to_string())?;
+    retry_busy(|| conn.execute("DELETE FROM autocrypt_peers WHERE fingerprint = ?1", [&fingerprint])).map_err(|e| e.to_string())?;
+    drop(conn);
+
+    PASSPHRASES.lock().unwrap().remove(&fingerprint);
+    database::forget_signed_bodies(app_handle)
+}
+
+/// Checks `passphrase` against a secret key and keeps it in memory for this session.
+pub fn unlock(app_handle: &AppHandle, fingerprint: &str, passphrase: String) -> Result<(), String> {
+    let entry = get(app_handle, fingerprint)?;
+    let secret = entry.secret.ok_or("There is no secret key for this fingerprint")?;
+    secret
+        .unlock(|| passphrase.clone(), |_| Ok(()))
+        .map_err(|_| "Wrong passphrase".to_string())?;
+
+    PASSPHRASES.lock().unwrap().insert(entry.fingerprint, passphrase);
+    Ok(())
+}
+
+/// Forgets every passphrase given this session.
+pub fn lock_all() {
+    PASSPHRASES.lock().unwrap().clear();
+}
+
+/// Key to encrypt to for `address`: one with that address in a user id, imported keys
+/// first, or else the key its mail last announced over Autocrypt.
+pub fn key_for_address(app_handle: &AppHandle, entries: &[KeyEntry], address: &str) -> Result<Option<usize>, String> {
+    let usable = |entry: &KeyEntry| encryption_subkey(&entry.public).is_some();
+
+    if let Some(index) = entries
+        .iter()
+        .enumerate()
+        .filter(|(_, e)| usable(e) && e.has_address(address))
+        .min_by_key(|(_, e)| e.source != "imported")
+        .map(|(index, _)| index)
+    {
+        return Ok(Some(index));
+    }
+
+    let conn = get_conn(app_handle)?;
+    let mut stmt = conn
+        .prepare_cached("SELECT fingerprint FROM autocrypt_peers WHERE address = ?1")
+        .map_err(|e| e.to_string())?;
+    let fingerprint: Option<String> = stmt.query_row([address.trim().to_lowercase()], |row| row.get(0)).ok();
+
+    Ok(fingerprint.and_then(|fp| entries.iter().position(|e| e.fingerprint == fp && usable(e))))
+}
+
+/// Secret key to sign mail from `address` with.
+pub fn signing_key<'a>(entries: &'a [KeyEntry], address: &str) -> Option<&'a KeyEntry> {
+    entries.iter().find(|e| e.secret.is_some() && e.has_address(address))
+}
@@ -0,0 +1,846 @@
+use chrono::{Local, NaiveDate, TimeZone};
+use serde::Serialize;
+use std::fmt;
+
+/// Parsed Gmail-style search query.
+///
+/// Produced by `parse` from input such as
+/// `from:alice has:attachment is:unread after:2026/01/01 label:clients "quarterly report"`
+/// or `is:starred newer_than:7d filename:pdf`
+/// and compiled to SQL over the local cache (`to_sql`), standard IMAP SEARCH
+/// (`to_imap_search`) or Gmail's X-GM-RAW (`to_gmail_raw`).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "camelCase")]
+pub enum Query {
+    And(Vec<Query>),
+    Or(Vec<Query>),
+    Not(Box<Query>),
+    Term(Term),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", content = "value", rename_all = "camelCase")]
+pub enum Term {
+    /// Bare word, optionally ending in `*` for a prefix match.
+    Text(String),
+    /// Quoted phrase.
+    Phrase(String),
+    From(String),
+    To(String),
+    Cc(String),
+    Subject(String),
+    /// `label:` and `in:` both map onto mailboxes.
+    Label(String),
+    HasAttachment,
+    IsUnread,
+    IsRead,
+    IsStarred,
+    /// Inclusive lower bound.
+    After(NaiveDate),
+    /// Exclusive upper bound.
+    Before(NaiveDate),
+    /// Attachment filename substring.
+    Filename(String),
+    /// Age in days relative to when the query is compiled, so saved queries keep sliding.
+    NewerThan(u32),
+    OlderThan(u32),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryError {
+    pub message: String,
+    /// Character offset of the offending token in the input.
+    pub position: usize,
+    /// Length of the offending token in characters.
+    pub length: usize,
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl From<QueryError> for String {
+    fn from(e: QueryError) -> Self {
+        e.to_string()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    LParen,
+    RParen,
+    Minus,
+    Or,
+    Word(String),
+    Phrase(String),
+    Field { key: String, value: String, quoted: bool },
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    position: usize,
+    length: usize,
+}
+
+const FIELD_KEYS: &[&str] = &[
+    "from", "to", "cc", "subject", "label", "in", "has", "is", "after", "before", "filename", "newer_than", "older_than",
+];
+
+fn error(message: impl Into<String>, position: usize, length: usize) -> QueryError {
+    QueryError { message: message.into(), position, length: length.max(1) }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, QueryError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    // Reads a quoted string starting at the opening quote; returns its content and the index after the closing quote
+    let read_quoted = |start: usize| -> Result<(String, usize), QueryError> {
+        let mut j = start + 1;
+        let mut content = String::new();
+        while j < chars.len() && chars[j] != '"' {
+            content.push(chars[j]);
+            j += 1;
+        }
+        if j >= chars.len() {
+            return Err(error("Unterminated quote", start, chars.len() - start));
+        }
+        Ok((content, j + 1))
+    };
+
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        match c {
+            '(' => {
+                tokens.push(Token { kind: TokenKind::LParen, position: start, length: 1 });
+                i += 1;
+            }
+            ')' => {
+                tokens.push(Token { kind: TokenKind::RParen, position: start, length: 1 });
+                i += 1;
+            }
+            '-' if i + 1 < chars.len() && !chars[i + 1].is_whitespace() => {
+                tokens.push(Token { kind: TokenKind::Minus, position: start, length: 1 });
+                i += 1;
+            }
+            '"' => {
+                let (content, next) = read_quoted(start)?;
+                tokens.push(Token { kind: TokenKind::Phrase(content), position: start, length: next - start });
+                i = next;
+            }
+            _ => {
+                let mut word = String::new();
+                while i < chars.len() && !chars[i].is_whitespace() && !matches!(chars[i], '(' | ')' | '"') {
+                    word.push(chars[i]);
+                    i += 1;
+                }
+
+                let field = word.split_once(':').and_then(|(key, value)| {
+                    let key = key.to_ascii_lowercase();
+                    FIELD_KEYS.contains(&key.as_str()).then(|| (key, value.to_string()))
+                });
+
+                let kind = match field {
+                    Some((key, value)) if value.is_empty() && i < chars.len() && chars[i] == '"' => {
+                        let (content, next) = read_quoted(i)?;
+                        i = next;
+                        TokenKind::Field { key, value: content, quoted: true }
+                    }
+                    Some((key, value)) => TokenKind::Field { key, value, quoted: false },
+                    None if word == "OR" || word == "|" => TokenKind::Or,
+                    None => TokenKind::Word(word),
+                };
+                tokens.push(Token { kind, position: start, length: i - start });
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    input_len: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// sequence := or_expr* (implicit AND)
+    fn parse_sequence(&mut self, nested: bool) -> Result<Query, QueryError> {
+        let mut items = Vec::new();
+
+        while let Some(token) = self.peek() {
+            if token.kind == TokenKind::RParen {
+                if nested {
+                    break;
+                }
+                return Err(error("Unmatched ')'", token.position, 1));
+            }
+            items.push(self.parse_or()?);
+        }
+
+        Ok(if items.len() == 1 { items.remove(0) } else { Query::And(items) })
+    }
+
+    /// or_expr := unary ('OR' unary)*
+    fn parse_or(&mut self) -> Result<Query, QueryError> {
+        let mut alternatives = vec![self.parse_unary()?];
+
+        while matches!(self.peek(), Some(t) if t.kind == TokenKind::Or) {
+            let or_token = self.next().unwrap();
+            if matches!(self.peek(), None | Some(Token { kind: TokenKind::RParen, .. })) {
+                return Err(error("Expected a term after OR", or_token.position, or_token.length));
+            }
+            alternatives.push(self.parse_unary()?);
+        }
+
+        Ok(if alternatives.len() == 1 { alternatives.remove(0) } else { Query::Or(alternatives) })
+    }
+
+    /// unary := '-' unary | '(' sequence ')' | term
+    fn parse_unary(&mut self) -> Result<Query, QueryError> {
+        let Some(token) = self.next() else {
+            return Err(error("Unexpected end of query", self.input_len, 1));
+        };
+
+        match token.kind {
+            TokenKind::Minus => {
+                if self.peek().is_none() {
+                    return Err(error("Expected a term after '-'", token.position, 1));
+                }
+                Ok(Query::Not(Box::new(self.parse_unary()?)))
+            }
+            TokenKind::LParen => {
+                let inner = self.parse_sequence(true)?;
+                match self.next() {
+                    Some(Token { kind: TokenKind::RParen, .. }) => {}
+                    _ => return Err(error("Unclosed '('", token.position, 1)),
+                }
+                if inner == Query::And(Vec::new()) {
+                    return Err(error("Empty group", token.position, 2));
+                }
+                Ok(inner)
+            }
+            TokenKind::RParen => Err(error("Unmatched ')'", token.position, 1)),
+            TokenKind::Or => Err(error("OR needs a term on both sides", token.position, token.length)),
+            TokenKind::Word(w) => Ok(Query::Term(Term::Text(w))),
+            TokenKind::Phrase(p) => Ok(Query::Term(Term::Phrase(p))),
+            TokenKind::Field { ref key, ref value, quoted } => {
+                parse_field(key, value, quoted, &token).map(Query::Term)
+            }
+        }
+    }
+}
+
+fn parse_field(key: &str, value: &str, quoted: bool, token: &Token) -> Result<Term, QueryError> {
+    // Point errors at the value part of `key:value`
+    let value_pos = token.position + key.chars().count() + 1;
+    let value_len = token.length.saturating_sub(key.chars().count() + 1);
+
+    if value.is_empty() && !quoted {
+        return Err(error(format!("Missing value for '{}:'", key), token.position, token.length));
+    }
+
+    let lower = value.to_lowercase();
+    let term = match key {
+        "from" => Term::From(value.to_string()),
+        "to" => Term::To(value.to_string()),
+        "cc" => Term::Cc(value.to_string()),
+        "subject" => Term::Subject(value.to_string()),
+        "label" | "in" => Term::Label(value.to_string()),
+        "has" => match lower.as_str() {
+            "attachment" | "attachments" => Term::HasAttachment,
+            _ => return Err(error(format!("Unknown value '{}' for has:", value), value_pos, value_len)),
+        },
+        "is" => match lower.as_str() {
+            "unread" => Term::IsUnread,
+            "read" => Term::IsRead,
+            "starred" | "flagged" => Term::IsStarred,
+            _ => return Err(error(format!("Unknown value '{}' for is:", value), value_pos, value_len)),
+        },
+        "after" | "before" => {
+            let date = parse_date(value)
+                .ok_or_else(|| error(format!("Invalid date '{}', expected YYYY/MM/DD", value), value_pos, value_len))?;
+            if key == "after" { Term::After(date) } else { Term::Before(date) }
+        }
+        "filename" => Term::Filename(value.to_string()),
+        "newer_than" | "older_than" => {
+            let days = parse_age(&lower)
+                .ok_or_else(|| error(format!("Invalid age '{}', expected e.g. 7d, 2w, 3m or 1y", value), value_pos, value_len))?;
+            if key == "newer_than" { Term::NewerThan(days) } else { Term::OlderThan(days) }
+        }
+        _ => return Err(error(format!("Unknown operator '{}:'", key), token.position, token.length)),
+    };
+
+    Ok(term)
+}
+
+fn parse_date(value: &str) -> Option<NaiveDate> {
+    ["%Y/%m/%d", "%Y-%m-%d"]
+        .iter()
+        .find_map(|fmt| NaiveDate::parse_from_str(value, fmt).ok())
+}
+
+/// Parses `<n>d`, `<n>w`, `<n>m` or `<n>y` into a number of days.
+fn parse_age(value: &str) -> Option<u32> {
+    let unit = value.chars().last()?;
+    let count: u32 = value[..value.len() - unit.len_utf8()].parse().ok()?;
+    let days_per_unit = match unit {
+        'd' => 1,
+        'w' => 7,
+        'm' => 30,
+        'y' => 365,
+        _ => return None,
+    };
+    count.checked_mul(days_per_unit)
+}
+
+/// Parses a Gmail-style query into an AST.
+pub fn parse(input: &str) -> Result<Query, QueryError> {
+    let tokens = tokenize(input)?;
+    let mut parser = Parser { tokens, pos: 0, input_len: input.chars().count() };
+    parser.parse_sequence(false)
+}
+
+/// Bound parameter for a compiled SQL fragment.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SqlParam {
+    Text(String),
+    Int(i64),
+}
+
+impl rusqlite::ToSql for SqlParam {
+    fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput<'_>> {
+        match self {
+            SqlParam::Text(s) => s.to_sql(),
+            SqlParam::Int(i) => i.to_sql(),
+        }
+    }
+}
+
+fn like_pattern(value: &str) -> String {
+    let escaped = value.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+fn like_sql(params: &mut Vec<SqlParam>, column: &str, value: &str) -> String {
+    params.push(SqlParam::Text(like_pattern(value)));
+    format!("messages.{} LIKE ? ESCAPE '\\'", column)
+}
+
+/// Matches the names and addresses in a JSON address list column (see `add_envelope_columns`),
+/// so the JSON keys and punctuation around them never match.
+fn address_sql(params: &mut Vec<SqlParam>, column: &str, value: &str) -> String {
+    let pattern = like_pattern(value);
+    params.push(SqlParam::Text(pattern.clone()));
+    params.push(SqlParam::Text(pattern));
+    format!(
+        "EXISTS (SELECT 1 FROM json_each(CASE WHEN json_valid(messages.{0}) THEN messages.{0} ELSE '[]' END) AS addr \
+         WHERE json_extract(addr.value, '$.name') LIKE ? ESCAPE '\\' OR json_extract(addr.value, '$.email') LIKE ? ESCAPE '\\')",
+        column
+    )
+}
+
+fn days_ago(days: u32) -> i64 {
+    chrono::Utc::now().timestamp() - i64::from(days) * 86_400
+}
+
+fn local_midnight(date: NaiveDate) -> i64 {
+    let naive = date.and_hms_opt(0, 0, 0).unwrap();
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .map(|dt| dt.timestamp())
+        .unwrap_or_else(|| naive.and_utc().timestamp())
+}
+
+impl Query {
+    /// Compiles to a SQL boolean expression over the `messages` table with positional `?` parameters.
+    pub fn to_sql(&self) -> (String, Vec<SqlParam>) {
+        let mut params = Vec::new();
+        let sql = self.write_sql(&mut params);
+        (sql, params)
+    }
+
+    fn write_sql(&self, params: &mut Vec<SqlParam>) -> String {
+        match self {
+            Query::And(items) if items.is_empty() => "1".to_string(),
+            Query::And(items) => format!("({})", items.iter().map(|q| q.write_sql(params)).collect::<Vec<_>>().join(" AND ")),
+            Query::Or(items) => format!("({})", items.iter().map(|q| q.write_sql(params)).collect::<Vec<_>>().join(" OR ")),
+            Query::Not(inner) => format!("NOT {}", inner.write_sql(params)),
+            Query::Term(term) => term.write_sql(params),
+        }
+    }
+
+    /// Compiles to standard IMAP SEARCH criteria (RFC 3501) for a search run in `mailbox`.
+    /// Non-ASCII values come out as literals; see `imap_quote`.
+    pub fn to_imap_search(&self, mailbox: &str) -> Result<String, String> {
+        Ok(match self {
+            Query::And(items) if items.is_empty() => "ALL".to_string(),
+            Query::And(items) if items.len() == 1 => items[0].to_imap_search(mailbox)?,
+            Query::And(items) => format!(
+                "({})",
+                items.iter().map(|q| q.to_imap_search(mailbox)).collect::<Result<Vec<_>, _>>()?.join(" ")
+            ),
+            // IMAP OR is binary, so fold n-ary alternatives from the right
+            Query::Or(items) => items
+                .iter()
+                .rev()
+                .map(|q| q.to_imap_search(mailbox))
+                .collect::<Result<Vec<_>, _>>()?
+                .into_iter()
+                .reduce(|acc, q| format!("OR {} {}", q, acc))
+                .unwrap_or_else(|| "ALL".to_string()),
+            Query::Not(inner) => format!("NOT {}", inner.to_imap_search(mailbox)?),
+            Query::Term(term) => term.to_imap_search(mailbox)?,
+        })
+    }
+
+    /// Serializes back into Gmail search syntax for `X-GM-RAW`.
+    pub fn to_gmail_raw(&self) -> String {
+        match self {
+            Query::And(items) => items.iter().map(|q| q.to_gmail_raw_grouped()).collect::<Vec<_>>().join(" "),
+            Query::Or(items) => items.iter().map(|q| q.to_gmail_raw_grouped()).collect::<Vec<_>>().join(" OR "),
+            Query::Not(inner) => format!("-{}", inner.to_gmail_raw_grouped()),
+            Query::Term(term) => term.to_gmail_raw(),
+        }
+    }
+
+    fn to_gmail_raw_grouped(&self) -> String {
+        match self {
+            Query::And(items) | Query::Or(items) if items.len() > 1 => format!("({})", self.to_gmail_raw()),
+            _ => self.to_gmail_raw(),
+        }
+    }
+
+    /// FTS5 expression made of the free-text terms that every result must match,
+    /// used to rank hits with bm25. Negated and OR-ed terms don't contribute.
+    pub fn ranking_fts_query(&self) -> Option<String> {
+        let mut parts = Vec::new();
+        self.collect_required_text(&mut parts);
+        if parts.is_empty() { None } else { Some(parts.join(" ")) }
+    }
+
+    fn collect_required_text(&self, parts: &mut Vec<String>) {
+        match self {
+            Query::And(items) => items.iter().for_each(|q| q.collect_required_text(parts)),
+            Query::Term(term) => {
+                if let Some(fts) = term.fts_expression() {
+                    parts.push(fts);
+                }
+            }
+            Query::Or(_) | Query::Not(_) => {}
+        }
+    }
+}
+
+impl Term {
+    fn fts_expression(&self) -> Option<String> {
+        match self {
+            Term::Text(word) => crate::mail::search::build_fts_query(word),
+            Term::Phrase(phrase) => crate::mail::search::build_fts_query(&format!("\"{}\"", phrase)),
+            _ => None,
+        }
+    }
+
+    fn write_sql(&self, params: &mut Vec<SqlParam>) -> String {
+        match self {
+            Term::Text(_) | Term::Phrase(_) => match self.fts_expression() {
+                Some(fts) => {
+                    params.push(SqlParam::Text(fts));
+                    "messages.rowid IN (SELECT rowid FROM messages_fts WHERE messages_fts MATCH ?)".to_string()
+                }
+                None => "1".to_string(),
+            },
+            Term::From(v) => like_sql(params, "sender", v),
+            Term::Subject(v) => like_sql(params, "subject", v),
+            Term::To(v) => address_sql(params, "to_addrs", v),
+            Term::Cc(v) => address_sql(params, "cc_addrs", v),
+            Term::Label(v) => {
+                params.push(SqlParam::Text(v.clone()));
+                "messages.folder = ? COLLATE NOCASE".to_string()
+            }
+            Term::HasAttachment => "messages.has_attachments = 1".to_string(),
+            Term::IsUnread => "messages.seen = 0".to_string(),
+            Term::IsRead => "messages.seen = 1".to_string(),
+            Term::IsStarred => "messages.flagged = 1".to_string(),
+            Term::After(date) => {
+                params.push(SqlParam::Int(local_midnight(*date)));
+                "messages.date >= ?".to_string()
+            }
+            Term::Before(date) => {
+                params.push(SqlParam::Int(local_midnight(*date)));
+                "messages.date < ?".to_string()
+            }
+            Term::Filename(v) => like_sql(params, "attachment_names", v),
+            Term::NewerThan(days) => {
+                params.push(SqlParam::Int(days_ago(*days)));
+                "messages.date >= ?".to_string()
+            }
+            Term::OlderThan(days) => {
+                params.push(SqlParam::Int(days_ago(*days)));
+                "messages.date < ?".to_string()
+            }
+        }
+    }
+
+    fn to_imap_search(&self, mailbox: &str) -> Result<String, String> {
+        let imap_date = |d: &NaiveDate| d.format("%-d-%b-%Y").to_string();
+        let days_ago_date = |days: &u32| Local::now().date_naive() - chrono::Duration::days(i64::from(*days));
+
+        Ok(match self {
+            Term::Text(w) => format!("TEXT {}", imap_quote(w.trim_end_matches('*'))?),
+            Term::Phrase(p) => format!("TEXT {}", imap_quote(p)?),
+            Term::From(v) => format!("FROM {}", imap_quote(v)?),
+            Term::To(v) => format!("TO {}", imap_quote(v)?),
+            Term::Cc(v) => format!("CC {}", imap_quote(v)?),
+            Term::Subject(v) => format!("SUBJECT {}", imap_quote(v)?),
+            // Labels are mailboxes, and SEARCH only sees the selected one: it matches all
+            // of it or none of it. Gmail labels go through X-GM-RAW instead.
+            Term::Label(v) if v.eq_ignore_ascii_case(mailbox) => "ALL".to_string(),
+            Term::Label(_) => "NOT ALL".to_string(),
+            // No standard key for attachments; mixed multiparts are the closest approximation
+            Term::HasAttachment => "HEADER Content-Type \"multipart/mixed\"".to_string(),
+            Term::IsUnread => "UNSEEN".to_string(),
+            Term::IsRead => "SEEN".to_string(),
+            Term::IsStarred => "FLAGGED".to_string(),
+            Term::After(d) => format!("SINCE {}", imap_date(d)),
+            Term::Before(d) => format!("BEFORE {}", imap_date(d)),
+            // Filenames live in MIME part headers, which BODY searches on most servers
+            Term::Filename(v) => format!("BODY {}", imap_quote(v)?),
+            // IMAP dates have day granularity
+            Term::NewerThan(days) => format!("SINCE {}", imap_date(&days_ago_date(days))),
+            Term::OlderThan(days) => format!("BEFORE {}", imap_date(&days_ago_date(days))),
+        })
+    }
+
+    fn to_gmail_raw(&self) -> String {
+        let value = |v: &str| if v.contains(char::is_whitespace) { format!("\"{}\"", v) } else { v.to_string() };
+
+        match self {
+            Term::Text(w) => w.clone(),
+            Term::Phrase(p) => format!("\"{}\"", p),
+            Term::From(v) => format!("from:{}", value(v)),
+            Term::To(v) => format!("to:{}", value(v)),
+            Term::Cc(v) => format!("cc:{}", value(v)),
+            Term::Subject(v) => format!("subject:{}", value(v)),
+            Term::Label(v) => format!("label:{}", value(v)),
+            Term::HasAttachment => "has:attachment".to_string(),
+            Term::IsUnread => "is:unread".to_string(),
+            Term::IsRead => "is:read".to_string(),
+            Term::IsStarred => "is:starred".to_string(),
+            Term::After(d) => format!("after:{}", d.format("%Y/%m/%d")),
+            Term::Before(d) => format!("before:{}", d.format("%Y/%m/%d")),
+            Term::Filename(v) => format!("filename:{}", value(v)),
+            Term::NewerThan(days) => format!("newer_than:{}d", days),
+            Term::OlderThan(days) => format!("older_than:{}d", days),
+        }
+    }
+}
+
+/// Renders `value` as an IMAP string. 7-bit values are quoted, escaping `\` and `"`;
+/// anything else is sent as a non-synchronizing literal (RFC 7888), since quoted strings
+/// can't carry 8-bit data. Only send those to servers advertising LITERAL+ or LITERAL-.
+/// CR, LF and NUL are refused: no search term needs them, and they would end the command.
+pub fn imap_quote(value: &str) -> Result<String, String> {
+    if value.contains(['\r', '\n', '\0']) {
+        return Err("Search terms can't contain line breaks or NUL characters".to_string());
+    }
+    if value.is_ascii() {
+        Ok(format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"")))
+    } else {
+        Ok(format!("{{{}+}}\r\n{}", value.len(), value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(input: &str) -> Vec<TokenKind> {
+        tokenize(input).unwrap().into_iter().map(|t| t.kind).collect()
+    }
+
+    fn field(key: &str, value: &str, quoted: bool) -> TokenKind {
+        TokenKind::Field { key: key.to_string(), value: value.to_string(), quoted }
+    }
+
+    fn term(t: Term) -> Query {
+        Query::Term(t)
+    }
+
+    fn text(w: &str) -> Query {
+        term(Term::Text(w.to_string()))
+    }
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn tokenize_splits_fields_phrases_and_operators() {
+        assert_eq!(
+            kinds(r#"From:alice -(is:unread OR "a b") subject:"q3 report" | x"#),
+            vec![
+                field("from", "alice", false),
+                TokenKind::Minus,
+                TokenKind::LParen,
+                field("is", "unread", false),
+                TokenKind::Or,
+                TokenKind::Phrase("a b".to_string()),
+                TokenKind::RParen,
+                field("subject", "q3 report", true),
+                TokenKind::Or,
+                TokenKind::Word("x".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenize_leaves_ordinary_words_alone() {
+        // Unknown keys, inner hyphens, a lone '-' and lower-case "or" are plain words
+        assert_eq!(
+            kinds("http://x.org e-mail - or"),
+            vec![
+                TokenKind::Word("http://x.org".to_string()),
+                TokenKind::Word("e-mail".to_string()),
+                TokenKind::Word("-".to_string()),
+                TokenKind::Word("or".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenize_reports_positions_in_characters() {
+        let tokens = tokenize("héllo from:bob").unwrap();
+        assert_eq!((tokens[1].position, tokens[1].length), (6, 8));
+
+        let err = tokenize(r#"ünï "open"#).unwrap_err();
+        assert_eq!((err.message.as_str(), err.position, err.length), ("Unterminated quote", 4, 5));
+    }
+
+    #[test]
+    fn adjacent_terms_bind_looser_than_or() {
+        assert_eq!(
+            parse("a b OR c d").unwrap(),
+            Query::And(vec![text("a"), Query::Or(vec![text("b"), text("c")]), text("d")])
+        );
+        assert_eq!(parse("a OR b OR c").unwrap(), Query::Or(vec![text("a"), text("b"), text("c")]));
+    }
+
+    #[test]
+    fn groups_and_negation_nest() {
+        assert_eq!(
+            parse("-(from:a OR label:x) has:attachment").unwrap(),
+            Query::And(vec![
+                Query::Not(Box::new(Query::Or(vec![
+                    term(Term::From("a".to_string())),
+                    term(Term::Label("x".to_string())),
+                ]))),
+                term(Term::HasAttachment),
+            ])
+        );
+        assert_eq!(parse("--a").unwrap(), Query::Not(Box::new(Query::Not(Box::new(text("a"))))));
+        assert_eq!(parse("").unwrap(), Query::And(Vec::new()));
+    }
+
+    #[test]
+    fn field_values_are_parsed() {
+        assert_eq!(
+            parse("after:2026/01/02 before:2026-02-03 newer_than:2w older_than:1y is:flagged is:read").unwrap(),
+            Query::And(vec![
+                term(Term::After(date(2026, 1, 2))),
+                term(Term::Before(date(2026, 2, 3))),
+                term(Term::NewerThan(14)),
+                term(Term::OlderThan(365)),
+                term(Term::IsStarred),
+                term(Term::IsRead),
+            ])
+        );
+        assert_eq!(parse(r#"subject:"""#).unwrap(), term(Term::Subject(String::new())));
+    }
+
+    #[test]
+    fn syntax_errors_point_at_the_offending_token() {
+        let err = |input: &str| {
+            let e = parse(input).unwrap_err();
+            (e.message, e.position, e.length)
+        };
+
+        assert_eq!(err("a )"), ("Unmatched ')'".to_string(), 2, 1));
+        assert_eq!(err("(a b"), ("Unclosed '('".to_string(), 0, 1));
+        assert_eq!(err("x ()"), ("Empty group".to_string(), 2, 2));
+        assert_eq!(err("a OR"), ("Expected a term after OR".to_string(), 2, 2));
+        assert_eq!(err("OR a"), ("OR needs a term on both sides".to_string(), 0, 2));
+        assert_eq!(err("from:"), ("Missing value for 'from:'".to_string(), 0, 5));
+        assert_eq!(err("is:bogus"), ("Unknown value 'bogus' for is:".to_string(), 3, 5));
+        assert_eq!(err("x after:2026/13/01").1, 8);
+        assert_eq!(err("newer_than:7x").0, "Invalid age '7x', expected e.g. 7d, 2w, 3m or 1y");
+        assert!(parse("older_than:99999999999d").is_err());
+    }
+
+    /// Evaluates a compiled query against a small in-memory `messages` table.
+    fn matching_uids(query: &str) -> Vec<u32> {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE messages (
+                uid INTEGER, folder TEXT, subject TEXT, sender TEXT, to_addrs TEXT, cc_addrs TEXT,
+                date INTEGER, seen INTEGER, flagged INTEGER, has_attachments INTEGER, attachment_names TEXT
+            );
+            CREATE VIRTUAL TABLE messages_fts USING fts5(subject, body);",
+        )
+        .unwrap();
+
+        let jan10 = local_midnight(date(2026, 1, 10));
+        let rows = [
+            (1, "INBOX", "Quarterly report", "Alice <alice@example.com>", jan10, true, false, "q3.pdf", "numbers inside"),
+            (2, "INBOX", "Lunch?", "Bob <bob@example.com>", jan10 - 1, false, true, "", "tacos at noon"),
+            (3, "Sent", "Re: Quarterly report", "Me <me@example.com>", jan10 + 86_400, true, false, "", "looks good"),
+            (4, "INBOX", "100% off_sale", "Shop <deals@shop.test>", jan10, false, false, "", "discounts"),
+        ];
+        for (uid, folder, subject, sender, date, seen, flagged, attachments, body) in rows {
+            conn.execute(
+                "INSERT INTO messages (rowid, uid, folder, subject, sender, to_addrs, cc_addrs, date, seen, flagged, has_attachments, attachment_names)
+                 VALUES (?1, ?1, ?2, ?3, ?4, '', '', ?5, ?6, ?7, ?8, ?9)",
+                rusqlite::params![uid, folder, subject, sender, date, seen, flagged, !attachments.is_empty(), attachments],
+            )
+            .unwrap();
+            conn.execute("INSERT INTO messages_fts (rowid, subject, body) VALUES (?1, ?2, ?3)", rusqlite::params![uid, subject, body])
+                .unwrap();
+        }
+
+        // Address lists as stored by sync; message 4 predates envelope capture
+        conn.execute_batch(
+            r#"UPDATE messages SET to_addrs = '[{"name":"Me","email":"me@example.com"}]' WHERE uid IN (1, 2);
+               UPDATE messages SET to_addrs = '[{"name":"Alice","email":"alice@example.com"}]',
+                                   cc_addrs = '[{"name":null,"email":"carol@example.com"}]' WHERE uid = 3;"#,
+        )
+        .unwrap();
+
+        let (sql, params) = parse(query).unwrap().to_sql();
+        let mut stmt = conn.prepare(&format!("SELECT uid FROM messages WHERE {} ORDER BY uid", sql)).unwrap();
+        let uids = stmt
+            .query_map(rusqlite::params_from_iter(params.iter()), |row| row.get(0))
+            .unwrap()
+            .collect::<Result<Vec<u32>, _>>()
+            .unwrap();
+        uids
+    }
+
+    #[test]
+    fn to_sql_filters_on_fields_and_flags() {
+        assert_eq!(matching_uids("from:ALICE"), vec![1]);
+        assert_eq!(matching_uids("subject:quarterly -in:sent"), vec![1]);
+        assert_eq!(matching_uids("label:sent"), vec![3]);
+        assert_eq!(matching_uids("is:unread"), vec![2, 4]);
+        assert_eq!(matching_uids("is:starred OR has:attachment"), vec![1, 2]);
+        assert_eq!(matching_uids("filename:pdf"), vec![1]);
+        assert_eq!(matching_uids(""), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn to_sql_matches_address_names_and_emails_only() {
+        assert_eq!(matching_uids("to:alice"), vec![3]);
+        assert_eq!(matching_uids("to:ME@EXAMPLE"), vec![1, 2]);
+        assert_eq!(matching_uids("cc:carol"), vec![3]);
+        assert_eq!(matching_uids("-to:me@"), vec![3, 4]);
+        // JSON keys and punctuation are not part of any address
+        assert_eq!(matching_uids("to:email"), Vec::<u32>::new());
+        assert_eq!(matching_uids("cc:name"), Vec::<u32>::new());
+        assert_eq!(matching_uids("to:\":\""), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn to_sql_escapes_like_wildcards() {
+        assert_eq!(matching_uids("subject:100%"), vec![4]);
+        assert_eq!(matching_uids("subject:lunch_"), Vec::<u32>::new());
+        assert_eq!(matching_uids("subject:off_"), vec![4]);
+    }
+
+    #[test]
+    fn to_sql_dates_use_local_midnight_bounds() {
+        assert_eq!(matching_uids("after:2026/01/10"), vec![1, 3, 4]);
+        assert_eq!(matching_uids("before:2026/01/10"), vec![2]);
+        assert_eq!(matching_uids("after:2026/01/10 before:2026/01/11"), vec![1, 4]);
+    }
+
+    #[test]
+    fn to_sql_matches_text_through_the_fts_index() {
+        assert_eq!(matching_uids("report"), vec![1, 3]);
+        assert_eq!(matching_uids("\"at noon\""), vec![2]);
+        assert_eq!(matching_uids("disc*"), vec![4]);
+        assert_eq!(matching_uids("report -looks"), vec![1]);
+        // Nothing searchable left after cleaning, so the term doesn't filter
+        assert_eq!(matching_uids("%%% is:unread"), vec![2, 4]);
+    }
+
+    #[test]
+    fn to_imap_search_compiles_criteria() {
+        let imap = |input: &str| parse(input).unwrap().to_imap_search("INBOX").unwrap();
+
+        assert_eq!(imap(""), "ALL");
+        assert_eq!(imap("from:alice is:unread"), r#"(FROM "alice" UNSEEN)"#);
+        assert_eq!(imap("a OR b OR c"), r#"OR TEXT "a" OR TEXT "b" TEXT "c""#);
+        assert_eq!(imap("-is:starred rep*"), r#"(NOT FLAGGED TEXT "rep")"#);
+        assert_eq!(imap("after:2026/01/02 before:2026/12/25"), "(SINCE 2-Jan-2026 BEFORE 25-Dec-2026)");
+        assert_eq!(imap(r#"subject:"it's on" filename:a\b"#), r#"(SUBJECT "it's on" BODY "a\\b")"#);
+        assert_eq!(imap("has:attachment"), r#"HEADER Content-Type "multipart/mixed""#);
+    }
+
+    #[test]
+    fn to_imap_search_matches_labels_against_the_searched_mailbox() {
+        let imap = |input: &str| parse(input).unwrap().to_imap_search("INBOX").unwrap();
+
+        assert_eq!(imap("in:inbox"), "ALL");
+        assert_eq!(imap("label:work"), "NOT ALL");
+        assert_eq!(imap("-label:work"), "NOT NOT ALL");
+    }
+
+    #[test]
+    fn imap_strings_use_literals_for_8bit_and_refuse_line_breaks() {
+        assert_eq!(imap_quote("plain").unwrap(), r#""plain""#);
+        assert_eq!(imap_quote(r#"say "hi" \o/"#).unwrap(), r#""say \"hi\" \\o/""#);
+        assert_eq!(imap_quote("café").unwrap(), "{5+}\r\ncafé");
+        assert_eq!(
+            parse("from:zoë").unwrap().to_imap_search("INBOX").unwrap(),
+            "FROM {4+}\r\nzoë"
+        );
+
+        assert!(imap_quote("a\r\nb").is_err());
+        assert!(imap_quote("a\0b").is_err());
+        assert!(parse("\"two\nlines\"").unwrap().to_imap_search("INBOX").is_err());
+    }
+
+    #[test]
+    fn gmail_raw_reparses_to_the_same_query() {
+        for input in [
+            r#"from:alice subject:"q3 report" -(label:x OR is:unread) newer_than:2w"#,
+            "a (b OR c) has:attachment after:2026/01/02",
+        ] {
+            let query = parse(input).unwrap();
+            assert_eq!(parse(&query.to_gmail_raw()).unwrap(), query, "{}", query.to_gmail_raw());
+        }
+    }
+}
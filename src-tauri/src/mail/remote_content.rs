@@ -0,0 +1,422 @@
+use crate::settings_store::JsonSettings;
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+/// Stands in for blocked images so their width/height attributes still hold the layout.
+const PLACEHOLDER_SRC: &str = "data:image/gif;base64,R0lGODlhAQABAIAAAAAAAP///yH5BAEAAAAALAAAAAABAAEAAAIBRAA7";
+
+/// URL fragments used by common mailing platforms for open-tracking beacons.
+const KNOWN_TRACKERS: &[&str] = &[
+    "list-manage.com/track/open",
+    "mandrillapp.com/track/open",
+    "sendgrid.net/wf/open",
+    "/wf/open?upn=",
+    "mailtrack.io/trace",
+    "mailchimp.com/track",
+    "hubspotlinks.com/e2t/o",
+    "hubspotemail.net/e2t/o",
+    "track.hubspot.com",
+    "pixel.mailerlite.com",
+    "open.convertkit-mail",
+    "/open.gif",
+    "/open.aspx",
+    "/track/open",
+    "/tracking/open",
+    "google-analytics.com/collect",
+    "doubleclick.net/",
+    "facebook.com/tr",
+    "mixpanel.com/track",
+    "pixel.wp.com",
+    "yesware.com/t/",
+    "getnotify.com",
+    "bananatag.com",
+    "mailstat.us/tr",
+];
+
+// Sanitized HTML is re-serialized by html5ever, so every attribute value is double-quoted.
+static IMG_TAG: Lazy<Regex> = Lazy::new(|| Regex::new(r#"(?i)<img((?:\s+[^\s=/>]+(?:="[^"]*")?)*)\s*/?>"#).unwrap());
+static ATTRIBUTE: Lazy<Regex> = Lazy::new(|| Regex::new(r#"([^\s=/>]+)(?:="([^"]*)")?"#).unwrap());
+static CSS_URL: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)url\(([^)]*)\)").unwrap());
+/// `image-set()` candidates can be plain strings as well as `url()`s, nested one level deep.
+static CSS_IMAGE_SET: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)(?:-webkit-)?image-set\(((?:[^()]|\([^()]*\))*)\)").unwrap());
+static STYLE_ATTRIBUTE: Lazy<Regex> = Lazy::new(|| Regex::new(r#"(?i)(\sstyle=")([^"]*)(")"#).unwrap());
+static STYLE_BLOCK: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?si)(<style\b[^>]*>)(.*?)(</style\s*>)").unwrap());
+
+/// Remote content preferences, persisted next to the other app-wide settings.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RemoteContentSettings {
+    /// Replace remote images and CSS backgrounds with placeholders and strip tracking pixels.
+    pub block_remote_content: bool,
+    /// Lower-cased addresses whose messages always load remote content.
+    pub allowed_senders: Vec<String>,
+}
+
+impl Default for RemoteContentSettings {
+    fn default() -> Self {
+        Self {
+            block_remote_content: true,
+            allowed_senders: Vec::new(),
+        }
+    }
+}
+
+/// What was withheld from a rendered body, so the UI can offer to load it.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteContentReport {
+    /// Remote images, `srcset` candidates and CSS `url()` references replaced by placeholders.
+    pub blocked: u32,
+    /// 1x1, hidden and known-tracker images removed outright.
+    pub trackers_removed: u32,
+    /// Whether remote content was loaded, by request or because the sender is allowed.
+    pub loaded: bool,
+    /// Sender address the "always load" choice would apply to.
+    pub sender: Option<String>,
+}
+
+static SETTINGS: JsonSettings<RemoteContentSettings> = JsonSettings::new("remote_content.json");
+
+/// Loads persisted remote content settings into memory. Called once during app setup.
+pub fn init(app_handle: &AppHandle) {
+    SETTINGS.load(app_handle);
+}
+
+pub fn current_settings() -> RemoteContentSettings {
+    SETTINGS.get()
+}
+
+/// Lower-cases, trims and de-duplicates the sender allowlist.
+fn normalize(mut settings: RemoteContentSettings) -> RemoteContentSettings {
+    settings.allowed_senders = settings
+        .allowed_senders
+        .iter()
+        .map(|s| s.trim().to_lowercase())
+        .filter(|s| !s.is_empty())
+        .collect();
+    settings.allowed_senders.sort();
+    settings.allowed_senders.dedup();
+    settings
+}
+
+pub fn save_settings(app_handle: &AppHandle, settings: RemoteContentSettings) -> Result<(), String> {
+    SETTINGS.save(app_handle, normalize(settings))
+}
+
+/// Adds `sender` to the allowlist so their messages always load remote content.
+pub fn allow_sender(app_handle: &AppHandle, sender: &str) -> Result<RemoteContentSettings, String> {
+    let sender = sender.trim().to_lowercase();
+    if !sender.contains('@') {
+        return Err(format!("Invalid sender address: {}", sender));
+    }
+    let mut settings = current_settings();
+    settings.allowed_senders.push(sender);
+    save_settings(app_handle, settings)?;
+    Ok(current_settings())
+}
+
+pub fn disallow_sender(app_handle: &AppHandle, sender: &str) -> Result<RemoteContentSettings, String> {
+    let sender = sender.trim().to_lowercase();
+    let mut settings = current_settings();
+    settings.allowed_senders.retain(|s| *s != sender);
+    save_settings(app_handle, settings)?;
+    Ok(current_settings())
+}
+
+fn is_remote(url: &str) -> bool {
+    let url = url.trim().to_ascii_lowercase();
+    url.starts_with("http:") || url.starts_with("https:") || url.starts_with("//")
+}
+
+fn is_known_tracker(url: &str) -> bool {
+    let url = url.to_ascii_lowercase();
+    KNOWN_TRACKERS.iter().any(|t| url.contains(t))
+}
+
+/// Declarations of an inline `style` attribute as lower-cased `(property, value)` pairs.
+fn style_declarations(attrs: &[(String, String)]) -> Vec<(String, String)> {
+    let Some((_, style)) = attrs.iter().find(|(k, _)| k == "style") else { return Vec::new() };
+    style
+        .split(';')
+        .filter_map(|decl| decl.split_once(':'))
+        .map(|(prop, value)| {
+            let value = value.trim().trim_end_matches("!important").trim();
+            (prop.trim().to_ascii_lowercase(), value.to_ascii_lowercase())
+        })
+        .collect()
+}
+
+/// Pixel size from a `width`/`height` attribute or the matching inline style declaration.
+fn dimension(attrs: &[(String, String)], name: &str) -> Option<f32> {
+    let parse = |v: &str| v.trim().trim_end_matches("px").trim().parse().ok();
+    attrs
+        .iter()
+        .find(|(k, _)| k == name)
+        .and_then(|(_, v)| parse(v))
+        .or_else(|| style_declarations(attrs).iter().find(|(k, _)| k == name).and_then(|(_, v)| parse(v)))
+}
+
+fn is_hidden(attrs: &[(String, String)]) -> bool {
+    style_declarations(attrs).iter().any(|(prop, value)| match prop.as_str() {
+        "display" => value == "none",
+        "visibility" => value == "hidden",
+        "opacity" => value.parse::<f32>().is_ok_and(|o| o == 0.0),
+        _ => false,
+    })
+}
+
+/// A remote image that exists only to report the open: tiny, hidden, or from a tracking host.
+fn is_tracking_pixel(src: &str, attrs: &[(String, String)]) -> bool {
+    if is_known_tracker(src) {
+        return true;
+    }
+    let tiny = |name| dimension(attrs, name).is_some_and(|px| px <= 1.0);
+    (tiny("width") && tiny("height")) || is_hidden(attrs)
+}
+
+/// The address in a `url(...)` argument or a CSS string, without quotes. Style attributes
+/// come back from html5ever with their quotes escaped as `&quot;`.
+fn css_target(raw: &str) -> String {
+    let raw = raw.replace("&quot;", "\"");
+    raw.trim().trim_matches(|c| c == '"' || c == '\'').trim().to_string()
+}
+
+/// Number of remote images among the comma-separated candidates of an `image-set()`.
+/// A candidate is a `url()` or a string, followed by descriptors like `2x`.
+fn remote_image_set_candidates(candidates: &str) -> u32 {
+    let candidates = candidates.replace("&quot;", "\"");
+    candidates
+        .split(',')
+        .filter(|candidate| {
+            let candidate = candidate.trim();
+            let reference = if candidate.get(..4).is_some_and(|p| p.eq_ignore_ascii_case("url(")) {
+                candidate[4..].split(')').next()
+            } else if let Some(quote) = candidate.chars().next().filter(|&c| c == '"' || c == '\'') {
+                candidate[1..].split(quote).next()
+            } else {
+                candidate.split_whitespace().next()
+            };
+            is_remote(&css_target(reference.unwrap_or_default()))
+        })
+        .count() as u32
+}
+
+/// Replaces remote `url()` targets and `image-set()`s with remote candidates by `none`,
+/// counting each remote image.
+fn block_css_urls(css: &str, blocked: &mut u32) -> String {
+    let css = CSS_IMAGE_SET.replace_all(css, |cap: &Captures| {
+        let remote = remote_image_set_candidates(&cap[1]);
+        *blocked += remote;
+        if remote > 0 {
+            "none".to_string()
+        } else {
+            cap[0].to_string()
+        }
+    });
+
+    CSS_URL
+        .replace_all(&css, |cap: &Captures| {
+            if is_remote(&css_target(&cap[1])) {
+                *blocked += 1;
+                "none".to_string()
+            } else {
+                cap[0].to_string()
+            }
+        })
+        .into_owned()
+}
+
+fn rewrite_images(html: &str, block_remote: bool, report: &mut RemoteContentReport) -> String {
+    IMG_TAG
+        .replace_all(html, |cap: &Captures| {
+            let attrs: Vec<(String, String)> = ATTRIBUTE
+                .captures_iter(&cap[1])
+                .map(|a| (a[1].to_ascii_lowercase(), a.get(2).map_or("", |v| v.as_str()).to_string()))
+                .collect();
+            let src = attrs.iter().find(|(k, _)| k == "src").map(|(_, v)| v.as_str()).unwrap_or_default();
+            let srcset = attrs.iter().find(|(k, _)| k == "srcset").map(|(_, v)| v.as_str()).unwrap_or_default();
+
+            if is_remote(src) && is_tracking_pixel(src, &attrs) {
+                report.trackers_removed += 1;
+                return String::new();
+            }
+            if !block_remote {
+                return cap[0].to_string();
+            }
+
+            let remote_candidates = srcset
+                .split(',')
+                .filter(|c| is_remote(c.split_whitespace().next().unwrap_or_default()))
+                .count() as u32;
+            if !is_remote(src) && remote_candidates == 0 {
+                return cap[0].to_string();
+            }
+            report.blocked += remote_candidates + u32::from(is_remote(src));
+
+            let mut tag = String::from("<img");
+            for (name, value) in &attrs {
+                match name.as_str() {
+                    "src" if is_remote(value) => tag.push_str(&format!(" src=\"{}\"", PLACEHOLDER_SRC)),
+                    "srcset" => {}
+                    _ => tag.push_str(&format!(" {}=\"{}\"", name, value)),
+                }
+            }
+            tag.push_str(" data-remote-blocked=\"true\">");
+            tag
+        })
+        .into_owned()
+}
+
+/// Strips tracking pixels and, when `block_remote` is set, replaces remote `<img>` sources,
+/// `srcset` candidates and CSS `url()` references with placeholders.
+///
+/// Expects HTML that already went through `sanitize::sanitize_html`; local `cid:`, `data:`
+/// and `asset:` references are left alone.
+pub fn block_remote_content(html: &str, block_remote: bool) -> (String, RemoteContentReport) {
+    let mut report = RemoteContentReport::default();
+    let html = rewrite_images(html, block_remote, &mut report);
+    if !block_remote {
+        return (html, report);
+    }
+
+    let mut blocked = 0;
+    let html = STYLE_ATTRIBUTE.replace_all(&html, |cap: &Captures| {
+        format!("{}{}{}", &cap[1], block_css_urls(&cap[2], &mut blocked), &cap[3])
+    });
+    let html = STYLE_BLOCK.replace_all(&html, |cap: &Captures| {
+        format!("{}{}{}", &cap[1], block_css_urls(&cap[2], &mut blocked), &cap[3])
+    });
+    report.blocked += blocked;
+
+    (html.into_owned(), report)
+}
+
+/// Applies the remote content policy to a cached body for display.
+///
+/// `force_load` is the one-off "load remote content" choice for this message; senders on
+/// the allowlist load it every time. Tracking pixels are removed in every case while
+/// blocking is enabled.
+pub fn apply_policy(body: &str, sender: Option<&str>, force_load: bool) -> (String, RemoteContentReport) {
+    let settings = current_settings();
+    let sender = sender.map(str::to_lowercase);
+
+    if !settings.block_remote_content {
+        let report = RemoteContentReport { loaded: true, sender, ..Default::default() };
+        return (body.to_string(), report);
+    }
+
+    let allowed = sender.as_ref().is_some_and(|s| settings.allowed_senders.contains(s));
+    let load = force_load || allowed;
+    let (html, mut report) = block_remote_content(body, !load);
+    report.loaded = load;
+    report.sender = sender;
+    (html, report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracking_pixels_are_removed_even_when_loading() {
+        let html = concat!(
+            "<p>Hi</p>",
+            "<img src=\"https://t.example/o.gif\" width=\"1\" height=\"1\">",
+            "<img src=\"https://t.example/o.gif\" style=\"width: 1px; height: 1px\">",
+            "<img src=\"https://t.example/o.gif\" style=\"display: none\">",
+            "<img src=\"https://t.example/o.gif\" style=\"opacity: 0\">",
+            "<img src=\"https://us1.list-manage.com/track/open.php?u=1\" width=\"600\">",
+        );
+        for block_remote in [true, false] {
+            let (clean, report) = block_remote_content(html, block_remote);
+            assert_eq!(clean, "<p>Hi</p>");
+            assert_eq!(report.trackers_removed, 5);
+            assert_eq!(report.blocked, 0);
+        }
+    }
+
+    #[test]
+    fn local_and_full_size_images_are_not_trackers() {
+        let html = "<img src=\"cid:logo@x\" width=\"1\" height=\"1\"><img src=\"https://cdn.example/hero.png\" width=\"1\" height=\"200\">";
+        let (clean, report) = block_remote_content(html, false);
+        assert_eq!(clean, html);
+        assert_eq!(report.trackers_removed, 0);
+    }
+
+    #[test]
+    fn remote_images_and_srcset_candidates_are_counted_and_replaced() {
+        let html = "<img src=\"https://cdn.example/a.png\" srcset=\"https://cdn.example/a2.png 2x, https://cdn.example/a3.png 3x\" width=\"600\" alt=\"Logo\">";
+        let (clean, report) = block_remote_content(html, true);
+
+        assert_eq!(report.blocked, 3);
+        assert!(!clean.contains("cdn.example"), "{}", clean);
+        assert!(clean.contains(PLACEHOLDER_SRC), "{}", clean);
+        assert!(clean.contains("width=\"600\"") && clean.contains("alt=\"Logo\""), "{}", clean);
+        assert!(clean.contains("data-remote-blocked=\"true\""), "{}", clean);
+
+        let (clean, report) = block_remote_content("<img src=\"cid:a@x\" srcset=\"https://cdn.example/a2.png 2x\">", true);
+        assert_eq!(report.blocked, 1);
+        assert!(clean.contains("src=\"cid:a@x\"") && !clean.contains("srcset"), "{}", clean);
+    }
+
+    #[test]
+    fn css_urls_are_counted_and_replaced() {
+        let html = concat!(
+            "<style>td { background: url('https://cdn.example/bg.png') } p { background: url(cid:bg@x) }</style>",
+            "<td style=\"background-image: url(&quot;https://cdn.example/a.png&quot;); border-image: url(//cdn.example/b.png)\">x</td>",
+        );
+        let (clean, report) = block_remote_content(html, true);
+
+        assert_eq!(report.blocked, 3);
+        assert!(!clean.contains("cdn.example"), "{}", clean);
+        assert!(clean.contains("url(cid:bg@x)"), "{}", clean);
+
+        let (unblocked, report) = block_remote_content(html, false);
+        assert_eq!(unblocked, html);
+        assert_eq!(report.blocked, 0);
+    }
+
+    #[test]
+    fn image_sets_with_remote_candidates_are_blocked() {
+        let html = concat!(
+            "<div style=\"background-image: image-set(&quot;https://t.example/a.png&quot; 1x, url(https://t.example/b.png) 2x)\">x</div>",
+            "<div style=\"background-image: -webkit-image-set('https://t.example/c.png' 1x)\">y</div>",
+            "<style>p { background: IMAGE-SET(\"//t.example/d.png\" type(\"image/png\")) }</style>",
+        );
+        let (clean, report) = block_remote_content(html, true);
+
+        assert_eq!(report.blocked, 4);
+        assert!(!clean.contains("t.example") && !clean.to_lowercase().contains("image-set"), "{}", clean);
+
+        let local = "<div style=\"background-image: image-set(url(cid:a@x) 1x)\">x</div>";
+        assert_eq!(block_remote_content(local, true).0, local);
+    }
+
+    #[test]
+    fn policy_loads_remote_content_only_on_request() {
+        let html = "<img src=\"https://cdn.example/a.png\"><img src=\"https://t.example/o.gif\" width=\"1\" height=\"1\">";
+
+        let (clean, report) = apply_policy(html, Some("Ada@Example.com"), false);
+        assert!(!report.loaded);
+        assert_eq!((report.blocked, report.trackers_removed), (1, 1));
+        assert_eq!(report.sender.as_deref(), Some("ada@example.com"));
+        assert!(!clean.contains("cdn.example"));
+
+        let (clean, report) = apply_policy(html, None, true);
+        assert!(report.loaded);
+        assert_eq!((report.blocked, report.trackers_removed), (0, 1));
+        assert!(clean.contains("cdn.example") && !clean.contains("t.example"));
+    }
+
+    #[test]
+    fn allowlists_are_normalized() {
+        let settings = normalize(RemoteContentSettings {
+            block_remote_content: true,
+            allowed_senders: vec![" Bob@Example.com".into(), "".into(), "ada@example.com".into(), "bob@example.com".into()],
+        });
+        assert_eq!(settings.allowed_senders, ["ada@example.com", "bob@example.com"]);
+    }
+}
@@ -0,0 +1,359 @@
+use ammonia::UrlRelative;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::borrow::Cow;
+
+/// `<style>` blocks are pulled out before ammonia runs, which can't sanitize stylesheets.
+static STYLE_BLOCK: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?si)<style\b[^>]*>(.*?)</style\s*>").unwrap());
+static CSS_COMMENT: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?s)/\*.*?\*/").unwrap());
+
+/// Raster formats only; SVG can carry script.
+const DATA_IMAGE_PREFIXES: &[&str] = &["data:image/png", "data:image/gif", "data:image/jpeg", "data:image/jpg", "data:image/webp"];
+
+/// At-rules whose block holds ordinary rules and is kept after sanitizing them.
+const NESTING_AT_RULES: &[&str] = &["@media", "@supports"];
+
+/// Properties that can load code, bind behaviours or pull content out of the message pane.
+const BLOCKED_PROPERTIES: &[&str] = &["behavior", "-moz-binding", "-webkit-binding", "binding"];
+
+/// Image functions that also take a bare string as the address, which the `url()` check can't see.
+const STRING_IMAGE_FUNCTIONS: &[&str] = &["image-set(", "image(", "cross-fade(", "element("];
+
+static CLEANER: Lazy<ammonia::Builder<'static>> = Lazy::new(|| {
+    let mut builder = ammonia::Builder::default();
+    builder
+        .add_tags(["font", "big", "section", "main", "address", "tfoot"])
+        .add_generic_attributes([
+            "style", "class", "align", "valign", "width", "height", "bgcolor", "border",
+            "cellpadding", "cellspacing", "dir", "color", "face", "size",
+        ])
+        .add_tag_attributes("a", ["name"])
+        .add_tag_attributes("img", ["srcset"])
+        .add_clean_content_tags(["title", "noscript", "template", "iframe", "object", "svg", "math"])
+        .url_schemes(["http", "https", "mailto", "tel", "cid", "data"].into_iter().collect())
+        .url_relative(UrlRelative::Deny)
+        .link_rel(Some("noopener noreferrer"))
+        .set_tag_attribute_value("a", "target", "_blank")
+        .attribute_filter(filter_attribute);
+    builder
+});
+
+fn filter_attribute<'u>(element: &str, attribute: &str, value: &'u str) -> Option<Cow<'u, str>> {
+    let lower = value.trim().to_ascii_lowercase();
+    match (element, attribute) {
+        ("img", "src") => {
+            let allowed = lower.starts_with("http:")
+                || lower.starts_with("https:")
+                || lower.starts_with("cid:")
+                || DATA_IMAGE_PREFIXES.iter().any(|p| lower.starts_with(p));
+            allowed.then_some(Cow::Borrowed(value))
+        }
+        ("img", "srcset") => {
+            // Candidates are `url [descriptor]`; data: URLs may contain commas, so only plain links are kept
+            let allowed = lower.split(',').all(|candidate| {
+                let url = candidate.split_whitespace().next().unwrap_or_default();
+                url.starts_with("http:") || url.starts_with("https:")
+            });
+            allowed.then_some(Cow::Borrowed(value))
+        }
+        (_, "href") => {
+            let allowed = ["http:", "https:", "mailto:", "tel:"].iter().any(|s| lower.starts_with(s));
+            allowed.then_some(Cow::Borrowed(value))
+        }
+        (_, "style") => {
+            let css = sanitize_declarations(value);
+            (!css.is_empty()).then_some(Cow::Owned(css))
+        }
+        _ => Some(Cow::Borrowed(value)),
+    }
+}
+
+/// Whether a `url(...)` target may stay in CSS.
+fn css_url_allowed(url: &str) -> bool {
+    let url = url.trim().trim_matches(|c| c == '"' || c == '\'').trim().to_ascii_lowercase();
+    url.starts_with("http:")
+        || url.starts_with("https:")
+        || url.starts_with("cid:")
+        || DATA_IMAGE_PREFIXES.iter().any(|p| url.starts_with(p))
+}
+
+fn value_allowed(value: &str) -> bool {
+    let lower = value.to_ascii_lowercase();
+    // Escapes and markup are only ever used in mail CSS to smuggle something past a filter
+    if lower.contains('\\') || lower.contains('<') || lower.contains('>') {
+        return false;
+    }
+    let compact: String = lower.chars().filter(|c| !c.is_whitespace()).collect();
+    if ["expression(", "javascript:", "vbscript:", "@import", "-moz-binding"].iter().any(|p| compact.contains(p)) {
+        return false;
+    }
+    if STRING_IMAGE_FUNCTIONS.iter().any(|f| compact.contains(f)) {
+        return false;
+    }
+
+    let mut rest = compact.as_str();
+    while let Some(start) = rest.find("url(") {
+        let after = &rest[start + 4..];
+        let Some(end) = after.find(')') else { return false };
+        if !css_url_allowed(&after[..end]) {
+            return false;
+        }
+        rest = &after[end + 1..];
+    }
+    true
+}
+
+/// Filters a declaration list (a `style` attribute or a rule body), dropping anything that
+/// can run code, load from unexpected schemes, or pin content outside the message flow.
+pub fn sanitize_declarations(css: &str) -> String {
+    let css = CSS_COMMENT.replace_all(css, "");
+    let mut kept = Vec::new();
+
+    for declaration in css.split(';') {
+        let Some((property, value)) = declaration.split_once(':') else { continue };
+        let property = property.trim().to_ascii_lowercase();
+        let value = value.trim();
+
+        let valid_name = !property.is_empty()
+            && property.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+        if !valid_name || value.is_empty() || BLOCKED_PROPERTIES.contains(&property.as_str()) || !value_allowed(value) {
+            continue;
+        }
+        if property == "position" {
+            let position = value.trim_end_matches("!important").trim().to_ascii_lowercase();
+            if position != "static" && position != "relative" {
+                continue;
+            }
+        }
+        kept.push(format!("{}: {}", property, value));
+    }
+
+    kept.join("; ")
+}
+
+/// Index just past the `}` that closes the block opened right before `from`.
+fn matching_brace(css: &str, from: usize) -> Option<usize> {
+    let mut depth = 1;
+    for (i, c) in css[from..].char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(from + i + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn sanitize_rules(css: &str, nested: bool) -> String {
+    let mut out = String::new();
+    let mut rest = css;
+
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            break;
+        }
+        let Some(open) = rest.find('{') else { break };
+
+        if rest.starts_with('@') {
+            // Statement at-rules (@import, @charset, @namespace) end before any block
+            if let Some(semi) = rest.find(';').filter(|&s| s < open) {
+                rest = &rest[semi + 1..];
+                continue;
+            }
+            let Some(end) = matching_brace(rest, open + 1) else { break };
+            let prelude = rest[..open].trim();
+            let name = prelude.split_whitespace().next().unwrap_or_default().to_ascii_lowercase();
+
+            if !nested && NESTING_AT_RULES.contains(&name.as_str()) && value_allowed(prelude) {
+                let inner = sanitize_rules(&rest[open + 1..end - 1], true);
+                if !inner.is_empty() {
+                    out.push_str(&format!("{} {{ {} }}\n", prelude, inner));
+                }
+            }
+            // @font-face, @keyframes and anything unknown are dropped
+            rest = &rest[end..];
+        } else {
+            let Some(close) = rest[open..].find('}').map(|i| open + i) else { break };
+            let selector = rest[..open].trim();
+            let declarations = sanitize_declarations(&rest[open + 1..close]);
+
+            let selector_ok = !selector.is_empty() && !selector.contains(['<', '\\', '@']);
+            if selector_ok && !declarations.is_empty() {
+                out.push_str(&format!("{} {{ {} }}\n", selector, declarations));
+            }
+            rest = &rest[close + 1..];
+        }
+    }
+
+    out
+}
+
+/// Sanitizes a stylesheet rule by rule. Selectors are kept as-is; the message is rendered
+/// in its own document, so they can't reach the app's UI.
+pub fn sanitize_stylesheet(css: &str) -> String {
+    sanitize_rules(&CSS_COMMENT.replace_all(css, ""), false)
+}
+
+/// Allowlist-based cleanup of sender HTML before it is cached or displayed.
+///
+/// Scripts, event handlers, forms, frames, `<meta>`/`<base>`/`<link>`, and non-http(s)
+/// links are removed; inline styles and `<style>` sheets are filtered declaration by declaration.
+pub fn sanitize_html(html: &str) -> String {
+    let sheets: Vec<String> = STYLE_BLOCK
+        .captures_iter(html)
+        .map(|cap| sanitize_stylesheet(&cap[1]))
+        .filter(|sheet| !sheet.is_empty())
+        .collect();
+
+    let body = CLEANER.clean(html).to_string();
+
+    if sheets.is_empty() {
+        body
+    } else {
+        format!("<style>\n{}</style>\n{}", sheets.concat(), body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sanitizes `html` and checks none of `forbidden` survives, ignoring case.
+    fn assert_removed(html: &str, forbidden: &[&str]) -> String {
+        let clean = sanitize_html(html);
+        let lower = clean.to_ascii_lowercase();
+        for needle in forbidden {
+            assert!(!lower.contains(needle), "{:?} survived in {:?}", needle, clean);
+        }
+        clean
+    }
+
+    #[test]
+    fn scripts_and_event_handlers_are_removed() {
+        assert_removed("<p>Hi</p><script>alert(1)</script>", &["<script", "alert"]);
+        assert_removed("<SCRIPT SRC=//evil.test/x.js></SCRIPT>", &["script", "evil"]);
+        assert_removed("<img src=x onerror=alert(1)>", &["onerror", "alert"]);
+        assert_removed("<body onload=alert(1)><div onmouseover=\"alert(2)\">x</div>", &["onload", "onmouseover", "alert"]);
+        assert_removed("<a href=\"https://ok.test\" onclick=\"alert(1)\">x</a>", &["onclick"]);
+    }
+
+    #[test]
+    fn script_links_are_removed() {
+        for html in [
+            "<a href=\"javascript:alert(1)\">x</a>",
+            "<a href=\" JaVaScRiPt:alert(1)\">x</a>",
+            "<a href=\"&#106;&#97;&#118;&#97;&#115;&#99;&#114;&#105;&#112;&#116;&#58;alert(1)\">x</a>",
+            "<a href=\"&#x6A;avascript&colon;alert(1)\">x</a>",
+            "<a href=\"java&#x09;script:alert(1)\">x</a>",
+            "<a href=\"vbscript:msgbox(1)\">x</a>",
+            "<a href=\"data:text/html;base64,PHNjcmlwdD5hbGVydCgxKTwvc2NyaXB0Pg==\">x</a>",
+        ] {
+            assert_removed(html, &["href", "script:", "data:"]);
+        }
+    }
+
+    #[test]
+    fn active_elements_are_removed_with_their_content() {
+        assert_removed("<svg onload=alert(1)><circle r=1 /></svg>", &["svg", "onload", "alert"]);
+        assert_removed("<iframe src=\"https://evil.test\"></iframe>", &["iframe", "evil"]);
+        assert_removed("<object data=\"x.swf\"></object><embed src=\"x.swf\">", &["object", "embed", "swf"]);
+        assert_removed("<math><mi xlink:href=\"javascript:alert(1)\">x</mi></math>", &["math", "javascript"]);
+    }
+
+    #[test]
+    fn forms_and_document_level_tags_are_removed() {
+        let clean = assert_removed(
+            "<form action=\"https://evil.test/steal\"><input name=password><button>Log in</button></form>",
+            &["<form", "action", "<input", "<button"],
+        );
+        assert!(clean.contains("Log in"));
+        assert_removed("<meta http-equiv=\"refresh\" content=\"0;url=https://evil.test\">", &["<meta", "refresh"]);
+        assert_removed("<base href=\"https://evil.test/\"><a href=\"/x\">x</a>", &["<base", "evil", "href=\"/x\""]);
+        assert_removed("<link rel=stylesheet href=\"https://evil.test/x.css\">", &["<link", "evil"]);
+    }
+
+    #[test]
+    fn image_sources_are_limited_to_safe_schemes() {
+        assert_removed("<img src=\"data:text/html;base64,PHNjcmlwdD4=\">", &["data:text"]);
+        assert_removed("<img src=\"data:image/svg+xml;base64,PHN2Zz4=\">", &["svg"]);
+        assert_removed("<img src=\"javascript:alert(1)\">", &["javascript"]);
+        assert_removed("<img srcset=\"https://ok.test/a.png 1x, javascript:alert(1) 2x\">", &["srcset"]);
+
+        let clean = sanitize_html("<img src=\"data:image/png;base64,iVBORw0KGgo=\"><img src=\"cid:logo@x\">");
+        assert!(clean.contains("data:image/png"), "{}", clean);
+        assert!(clean.contains("cid:logo@x"), "{}", clean);
+    }
+
+    #[test]
+    fn dangerous_inline_css_is_dropped() {
+        for style in [
+            "width: expression(alert(1))",
+            "width: EXPRESSION (alert(1))",
+            "behavior: url(x.htc)",
+            "-moz-binding: url(https://evil.test/x.xml#x)",
+            "background: url(javascript:alert(1))",
+            "background: \\75rl(javascript:alert(1))",
+            "background: u\\72l(https://evil.test/x)",
+            "background-image: url(data:image/svg+xml;base64,PHN2Zz4=)",
+            "background-image: image-set('https://tracker.test/x.png' 1x)",
+            "background-image: -webkit-image-set('data:image/svg+xml;base64,PHN2Zz4=' 1x)",
+            "background: cross-fade('https://tracker.test/x.png', red 50%)",
+        ] {
+            let clean = assert_removed(&format!("<p style=\"{}\">x</p>", style), &["style="]);
+            assert!(clean.contains("x</p>"));
+        }
+    }
+
+    #[test]
+    fn overlays_are_dropped_but_ordinary_styles_stay() {
+        let clean = sanitize_html("<div style=\"position: fixed; top: 0; color: red\">x</div>");
+        assert!(!clean.contains("fixed"), "{}", clean);
+        assert!(clean.contains("color: red"), "{}", clean);
+
+        let clean = sanitize_html("<div style=\"position:absolute!important\">x</div><div style=\"position: relative\">y</div>");
+        assert!(!clean.contains("absolute"), "{}", clean);
+        assert!(clean.contains("position: relative"), "{}", clean);
+    }
+
+    #[test]
+    fn stylesheets_are_filtered_rule_by_rule() {
+        let clean = sanitize_html(
+            "<style>@import url(https://evil.test/x.css); p { color: red; behavior: url(x.htc) }
+             .a { background: url(javascript:alert(1)) } @font-face { src: url(https://evil.test/f.woff) }
+             @media (max-width: 600px) { .b { width: expression(alert(1)); margin: 0 } }</style><p>x</p>",
+        );
+        assert!(!clean.contains("import") && !clean.contains("evil") && !clean.contains("behavior"), "{}", clean);
+        assert!(!clean.contains("javascript") && !clean.contains("expression"), "{}", clean);
+        assert!(clean.contains("p { color: red }"), "{}", clean);
+        assert!(clean.contains("@media (max-width: 600px) { .b { margin: 0 }"), "{}", clean);
+    }
+
+    #[test]
+    fn style_blocks_cannot_break_out() {
+        let clean = assert_removed(
+            "<style>p { color: red }</style><script>alert(1)</script><style>x { }</style>",
+            &["<script", "alert"],
+        );
+        assert_eq!(clean.matches("<style>").count(), 1, "{}", clean);
+
+        // Markup smuggled into a selector or value never reaches the emitted sheet
+        assert_removed("<style>p</style><img src=x onerror=alert(1)>{ color: red }</style>", &["onerror"]);
+        assert_removed("<style>a { font-family: '</style><script>alert(1)</script>' }</style>", &["<script", "alert"]);
+        assert_eq!(sanitize_stylesheet("p, </style><svg> { color: red }"), "");
+        assert_eq!(sanitize_stylesheet("p { content: '</style>' }"), "");
+    }
+
+    #[test]
+    fn links_get_safe_targets() {
+        let clean = sanitize_html("<a href=\"https://ok.test\" target=\"_self\">x</a><a href=\"/relative\">y</a>");
+        assert!(clean.contains("target=\"_blank\""), "{}", clean);
+        assert!(clean.contains("rel=\"noopener noreferrer\""), "{}", clean);
+        assert!(!clean.contains("relative"), "{}", clean);
+    }
+}
@@ -0,0 +1,321 @@
+use crate::mail::database::{self, HEADER_COLUMNS};
+use crate::mail::db_pool::{get_conn, retry_busy, write_transaction};
+use crate::mail::message_list::MessageHeader;
+use crate::mail::query::{self, SqlParam};
+use once_cell::sync::Lazy;
+use rusqlite::Connection;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+
+/// Emitted with the smart folders whose membership or unread count changed.
+pub const SMART_FOLDERS_CHANGED_EVENT: &str = "mail:smart-folders-changed";
+
+/// How long `schedule_refresh` waits, so a burst of flag changes is evaluated once.
+const REFRESH_DELAY: Duration = Duration::from_millis(500);
+
+/// Set while a scheduled refresh is waiting to run.
+static REFRESH_PENDING: AtomicBool = AtomicBool::new(false);
+
+/// Last evaluated state per saved search id, used to tell whether membership changed.
+static SNAPSHOTS: Lazy<Mutex<HashMap<i64, Membership>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedSearch {
+    pub id: i64,
+    pub name: String,
+    /// Icon name understood by the sidebar, e.g. `star` or `file-text`.
+    pub icon: Option<String>,
+    /// Query in the search language, e.g. `is:starred newer_than:7d`.
+    pub query: String,
+    pub position: i64,
+}
+
+/// A saved search with its current counts, shown in the sidebar like a mailbox.
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SmartFolder {
+    #[serde(flatten)]
+    pub search: SavedSearch,
+    pub total: u32,
+    pub unread: u32,
+}
+
+/// Cheap fingerprint of a result set. The rowid sum changes when members are swapped
+/// even if the count stays the same.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Membership {
+    total: u32,
+    unread: u32,
+    rowid_sum: i64,
+}
+
+fn compile(query_text: &str) -> Result<(String, Vec<SqlParam>), String> {
+    if query_text.trim().is_empty() {
+        return Err("Saved search query is empty".to_string());
+    }
+    Ok(query::parse(query_text)?.to_sql())
+}
+
+fn load_searches(app_handle: &AppHandle) -> Result<Vec<SavedSearch>, String> {
+    let conn = get_conn(app_handle)?;
+    let mut stmt = conn.prepare_cached(
+        "SELECT id, name, icon, query, position FROM saved_searches ORDER BY position, id"
+    ).map_err(|e| e.to_string())?;
+
+    let rows = stmt.query_map([], |row| {
+        Ok(SavedSearch {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            icon: row.get(2)?,
+            query: row.get(3)?,
+            position: row.get(4)?,
+        })
+    }).map_err(|e| e.to_string())?;
+
+    let mut searches = Vec::new();
+    for r in rows {
+        searches.push(r.map_err(|e| e.to_string())?);
+    }
+    Ok(searches)
+}
+
+fn load_search(app_handle: &AppHandle, id: i64) -> Result<SavedSearch, String> {
+    load_searches(app_handle)?
+        .into_iter()
+        .find(|s| s.id == id)
+        .ok_or_else(|| format!("Saved search {} not found", id))
+}
+
+fn evaluate(app_handle: &AppHandle, search: &SavedSearch) -> Result<Membership, String> {
+    let (where_sql, params) = compile(&search.query)?;
+    let conn = get_conn(app_handle)?;
+
+    // Queries change rarely, so the statement cache keeps these warm across syncs
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT COUNT(*), TOTAL(seen = 0), TOTAL(rowid) FROM messages WHERE {}",
+        where_sql
+    )).map_err(|e| e.to_string())?;
+
+    stmt.query_row(rusqlite::params_from_iter(params.iter()), |row| {
+        Ok(Membership {
+            total: row.get(0)?,
+            unread: row.get::<_, f64>(1)? as u32,
+            rowid_sum: row.get::<_, f64>(2)? as i64,
+        })
+    }).map_err(|e| e.to_string())
+}
+
+fn to_folder(search: SavedSearch, membership: Membership) -> SmartFolder {
+    SmartFolder { search, total: membership.total, unread: membership.unread }
+}
+
+/// All saved searches with live counts. Searches whose stored query no longer parses
+/// are listed with zero counts rather than failing the whole list.
+pub fn list_smart_folders(app_handle: &AppHandle) -> Result<Vec<SmartFolder>, String> {
+    let mut folders = Vec::new();
+    for search in load_searches(app_handle)? {
+        let membership = evaluate(app_handle, &search).unwrap_or_else(|e| {
+            log::warn!("Saved search {} ({}) failed to evaluate: {}", search.id, search.name, e);
+            Membership { total: 0, unread: 0, rowid_sum: 0 }
+        });
+        SNAPSHOTS.lock().unwrap().insert(search.id, membership);
+        folders.push(to_folder(search, membership));
+    }
+    Ok(folders)
+}
+
+pub fn create_saved_search(app_handle: &AppHandle, name: &str, icon: Option<String>, query_text: &str) -> Result<SmartFolder, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Saved search name is empty".to_string());
+    }
+    compile(query_text)?;
+
+    let conn = get_conn(app_handle)?;
+    retry_busy(|| conn.execute(
+        "INSERT INTO saved_searches (name, icon, query, position, created_at)
+         VALUES (?1, ?2, ?3, (SELECT COALESCE(MAX(position), -1) + 1 FROM saved_searches), ?4)",
+        rusqlite::params![name, icon, query_text.trim(), chrono::Utc::now().timestamp()],
+    )).map_err(|e| e.to_string())?;
+    let id = conn.last_insert_rowid();
+    drop(conn);
+
+    let search = load_search(app_handle, id)?;
+    let membership = evaluate(app_handle, &search)?;
+    SNAPSHOTS.lock().unwrap().insert(id, membership);
+    Ok(to_folder(search, membership))
+}
+
+pub fn update_saved_search(app_handle: &AppHandle, id: i64, name: &str, icon: Option<String>, query_text: &str) -> Result<SmartFolder, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Saved search name is empty".to_string());
+    }
+    compile(query_text)?;
+
+    let conn = get_conn(app_handle)?;
+    let updated = retry_busy(|| conn.execute(
+        "UPDATE saved_searches SET name = ?1, icon = ?2, query = ?3 WHERE id = ?4",
+        rusqlite::params![name, icon, query_text.trim(), id],
+    )).map_err(|e| e.to_string())?;
+    drop(conn);
+    if updated == 0 {
+        return Err(format!("Saved search {} not found", id));
+    }
+
+    let search = load_search(app_handle, id)?;
+    let membership = evaluate(app_handle, &search)?;
+    SNAPSHOTS.lock().unwrap().insert(id, membership);
+    Ok(to_folder(search, membership))
+}
+
+pub fn delete_saved_search(app_handle: &AppHandle, id: i64) -> Result<(), String> {
+    let conn = get_conn(app_handle)?;
+    retry_busy(|| conn.execute("DELETE FROM saved_searches WHERE id = ?1", [id])).map_err(|e| e.to_string())?;
+    SNAPSHOTS.lock().unwrap().remove(&id);
+    Ok(())
+}
+
+/// Stores a new sidebar order; `ids` lists every saved search in display order.
+pub fn reorder_saved_searches(app_handle: &AppHandle, ids: &[i64]) -> Result<(), String> {
+    let mut conn = get_conn(app_handle)?;
+    write_transaction(&mut conn, |tx| {
+        let mut stmt = tx.prepare_cached("UPDATE saved_searches SET position = ?1 WHERE id = ?2")?;
+        for (position, id) in ids.iter().enumerate() {
+            stmt.execute(rusqlite::params![position as i64, id])?;
+        }
+        Ok(())
+    })
+}
+
+/// Messages matching a saved search, newest first. Pass the `date`, `folder` and `uid` of
+/// the last message received to get the next page; a search can span folders, so the
+/// UID alone doesn't break ties.
+pub fn load_saved_search_page(app_handle: &AppHandle, id: i64, before: Option<(i64, String, u32)>, limit: u32) -> Result<Vec<MessageHeader>, String> {
+    let search = load_search(app_handle, id)?;
+    let conn = get_conn(app_handle)?;
+    search_page(&conn, &search.query, before, limit)
+}
+
+fn search_page(conn: &Connection, query_text: &str, before: Option<(i64, String, u32)>, limit: u32) -> Result<Vec<MessageHeader>, String> {
+    let (where_sql, mut params) = compile(query_text)?;
+
+    let cursor_sql = match before {
+        Some((date, folder, uid)) => {
+            params.extend([SqlParam::Int(date), SqlParam::Text(folder), SqlParam::Int(uid as i64)]);
+            "AND (messages.date, messages.folder, messages.uid) < (?, ?, ?)"
+        }
+        None => "",
+    };
+    params.push(SqlParam::Int(limit as i64));
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM messages WHERE {} {}
+         ORDER BY messages.date DESC, messages.folder DESC, messages.uid DESC LIMIT ?",
+        HEADER_COLUMNS, where_sql, cursor_sql
+    )).map_err(|e| e.to_string())?;
+
+    let rows = stmt.query_map(rusqlite::params_from_iter(params.iter()), database::parse_header_row)
+        .map_err(|e| e.to_string())?;
+
+    let mut messages = Vec::new();
+    for r in rows {
+        messages.push(r.map_err(|e| e.to_string())?);
+    }
+    Ok(messages)
+}
+
+/// Runs `refresh_and_notify` shortly, off the caller's path. Requests made while one is
+/// waiting are folded into it, so flag changes don't pay for every saved search.
+pub fn schedule_refresh(app_handle: &AppHandle) {
+    if REFRESH_PENDING.swap(true, Ordering::AcqRel) {
+        return;
+    }
+
+    let app_handle = app_handle.clone();
+    tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(REFRESH_DELAY).await;
+        // Cleared first, so a change made while evaluating schedules another pass
+        REFRESH_PENDING.store(false, Ordering::Release);
+        let result = tokio::task::spawn_blocking(move || refresh_and_notify(&app_handle)).await;
+        if let Ok(Err(e)) = result {
+            log::warn!("Smart folder refresh failed: {}", e);
+        }
+    });
+}
+
+/// Re-evaluates every saved search and emits `SMART_FOLDERS_CHANGED_EVENT` with the ones
+/// whose membership or unread count differs from the last evaluation.
+pub fn refresh_and_notify(app_handle: &AppHandle) -> Result<(), String> {
+    let mut changed = Vec::new();
+    for search in load_searches(app_handle)? {
+        let membership = match evaluate(app_handle, &search) {
+            Ok(m) => m,
+            Err(e) => {
+                log::warn!("Saved search {} ({}) failed to evaluate: {}", search.id, search.name, e);
+                continue;
+            }
+        };
+
+        let previous = SNAPSHOTS.lock().unwrap().insert(search.id, membership);
+        if previous != Some(membership) {
+            changed.push(to_folder(search, membership));
+        }
+    }
+
+    if !changed.is_empty() {
+        if let Err(e) = app_handle.emit(SMART_FOLDERS_CHANGED_EVENT, &changed) {
+            log::error!("Failed to emit {} event: {}", SMART_FOLDERS_CHANGED_EVENT, e);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_db(name: &str) -> Connection {
+        let path = std::env::temp_dir().join(format!("saved-searches-{}-{}.db", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut conn = Connection::open(&path).unwrap();
+        crate::mail::migrations::run(&mut conn, &path).unwrap();
+        conn
+    }
+
+    #[test]
+    fn pages_walk_every_match_when_folders_share_dates_and_uids() {
+        let conn = test_db("paging");
+        conn.execute_batch(
+            "INSERT INTO messages (folder, uid, uid_validity, subject, date) VALUES
+                ('INBOX', 7, 1, 'Report', 500),
+                ('[Gmail]/Sent Mail', 7, 1, 'Re: Report', 500),
+                ('INBOX', 6, 1, 'Report draft', 500),
+                ('[Gmail]/Sent Mail', 9, 1, 'Report notes', 400),
+                ('INBOX', 8, 1, 'Lunch', 450);"
+        ).unwrap();
+
+        let mut seen = Vec::new();
+        let mut before = None;
+        loop {
+            let page = search_page(&conn, "subject:report", before.clone(), 1).unwrap();
+            let Some(last) = page.last() else { break };
+            seen.push((last.folder.clone(), last.uid));
+            before = Some((last.date, last.folder.clone(), last.uid));
+        }
+
+        let sent = "[Gmail]/Sent Mail".to_string();
+        let inbox = "INBOX".to_string();
+        assert_eq!(seen, [(sent.clone(), 7), (inbox.clone(), 7), (inbox, 6), (sent, 9)]);
+    }
+
+    #[test]
+    fn saved_searches_need_a_query() {
+        assert!(compile("  ").is_err());
+        assert!(compile("is:unread").is_ok());
+    }
+}
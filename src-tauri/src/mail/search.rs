@@ -0,0 +1,403 @@
+use crate::auth::account::Account;
+use crate::mail::database::{self, HEADER_COLUMNS, HEADER_COLUMN_COUNT};
+use crate::mail::db_pool::get_conn;
+use crate::mail::imap_session::{execute_with_session, SessionKind};
+use crate::mail::message_list::MessageHeader;
+use crate::mail::query::{self, SqlParam};
+use crate::mail::snippet;
+use crate::mail::sync::{parse_header_to_message, HEADER_FETCH_QUERY};
+use std::collections::HashMap;
+use tauri::AppHandle;
+
+/// Upper bound on server hits merged into one result list.
+const MAX_SERVER_HITS: usize = 200;
+
+/// Largest non-synchronizing literal a LITERAL- server accepts (RFC 7888 §4).
+const LITERAL_MINUS_LIMIT: usize = 4096;
+
+/// Rank-fusion damping constant; 60 is the value from the original RRF paper.
+const RRF_K: f64 = 60.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HitSource {
+    Local,
+    Server,
+    Both,
+}
+
+// Private-use markers let FTS5 tag matches without us trusting message text as HTML.
+const MARK_OPEN: &str = "\u{E000}";
+const MARK_CLOSE: &str = "\u{E001}";
+
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchHit {
+    #[serde(flatten)]
+    pub header: MessageHeader,
+    /// bm25 relevance, lower is better.
+    pub score: f64,
+    /// HTML-escaped subject with matches wrapped in `<mark>`.
+    pub subject_highlight: String,
+    /// HTML-escaped excerpt around the best match in any indexed column, with matches wrapped in `<mark>`.
+    pub snippet_highlight: String,
+    /// Whether the hit came from the local index, the server, or both.
+    pub source: HitSource,
+}
+
+/// Turns free-form user input into a safe FTS5 MATCH expression.
+///
+/// `"exact phrase"` stays a phrase, `word*` becomes a prefix query and every other
+/// token is quoted so FTS5 operators and punctuation can't produce syntax errors.
+/// Terms are AND-ed together. Returns `None` when nothing searchable remains.
+pub fn build_fts_query(input: &str) -> Option<String> {
+    let mut terms = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        if c == '"' {
+            chars.next();
+            let phrase: String = chars.by_ref().take_while(|&ch| ch != '"').collect();
+            let words: Vec<String> = phrase.split_whitespace().filter_map(clean_token).collect();
+            if !words.is_empty() {
+                terms.push(format!("\"{}\"", words.join(" ")));
+            }
+            continue;
+        }
+
+        let mut word = String::new();
+        while let Some(&ch) = chars.peek() {
+            if ch.is_whitespace() || ch == '"' {
+                break;
+            }
+            word.push(ch);
+            chars.next();
+        }
+
+        let is_prefix = word.ends_with('*');
+        if let Some(token) = clean_token(&word) {
+            terms.push(if is_prefix { format!("\"{}\"*", token) } else { format!("\"{}\"", token) });
+        }
+    }
+
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
+
+/// Keeps only characters the default unicode61 tokenizer treats as word content.
+fn clean_token(raw: &str) -> Option<String> {
+    let cleaned: String = raw
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    if cleaned.is_empty() { None } else { Some(cleaned) }
+}
+
+fn render_highlight(marked: &str) -> String {
+    marked
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace(MARK_OPEN, "<mark>")
+        .replace(MARK_CLOSE, "</mark>")
+}
+
+/// Searches the local message cache using the Gmail-style query language.
+///
+/// Results are ranked with bm25 when the query has required free-text terms,
+/// otherwise they are ordered newest first.
+pub fn search_messages(app_handle: &AppHandle, query: &str, limit: u32) -> Result<Vec<SearchHit>, String> {
+    if query.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let ast = query::parse(query)?;
+    let (where_sql, where_params) = ast.to_sql();
+
+    let mut params: Vec<SqlParam> = Vec::new();
+    let sql = match ast.ranking_fts_query() {
+        Some(fts_query) => {
+            params.extend([MARK_OPEN, MARK_CLOSE, MARK_OPEN, MARK_CLOSE].map(|m| SqlParam::Text(m.to_string())));
+            params.push(SqlParam::Text(fts_query));
+            format!(
+                "SELECT {cols},
+                        bm25(messages_fts, 5.0, 2.0, 1.0, 1.0, 1.5) AS score,
+                        highlight(messages_fts, 0, ?, ?),
+                        snippet(messages_fts, -1, ?, ?, '…', 16)
+                 FROM messages_fts
+                 JOIN messages ON messages.rowid = messages_fts.rowid
+                 WHERE messages_fts MATCH ? AND {filter}
+                 ORDER BY score
+                 LIMIT ?",
+                cols = HEADER_COLUMNS,
+                filter = where_sql
+            )
+        }
+        None => format!(
+            "SELECT {cols}, 0.0, messages.subject, messages.snippet
+             FROM messages
+             WHERE {filter}
+             ORDER BY messages.date DESC
+             LIMIT ?",
+            cols = HEADER_COLUMNS,
+            filter = where_sql
+        ),
+    };
+    params.extend(where_params);
+    params.push(SqlParam::Int(limit as i64));
+
+    let conn = get_conn(app_handle)?;
+
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let rows = stmt.query_map(rusqlite::params_from_iter(params.iter()), |row| {
+        Ok(SearchHit {
+            header: database::parse_header_row(row)?,
+            score: row.get(HEADER_COLUMN_COUNT)?,
+            subject_highlight: render_highlight(&row.get::<_, Option<String>>(HEADER_COLUMN_COUNT + 1)?.unwrap_or_default()),
+            snippet_highlight: render_highlight(&row.get::<_, Option<String>>(HEADER_COLUMN_COUNT + 2)?.unwrap_or_default()),
+            source: HitSource::Local,
+        })
+    }).map_err(|e| e.to_string())?;
+
+    let mut hits = Vec::new();
+    for hit in rows {
+        hits.push(hit.map_err(|e| e.to_string())?);
+    }
+
+    Ok(hits)
+}
+
+/// Runs the query on the IMAP server and returns matching UIDs, newest first.
+/// Gmail gets the query verbatim through X-GM-RAW so its own operators keep their semantics.
+async fn search_server_uids(account: &Account, ast: &query::Query) -> Result<Vec<u32>, String> {
+    let criteria = if account.imap.host.ends_with("gmail.com") {
+        format!("X-GM-RAW {}", query::imap_quote(&ast.to_gmail_raw())?)
+    } else {
+        ast.to_imap_search("INBOX")?
+    };
+    let criteria = if criteria.is_ascii() { criteria } else { format!("CHARSET UTF-8 {}", criteria) };
+
+    execute_with_session(account, SessionKind::Primary, move |session| {
+        // Non-ASCII values went out as non-synchronizing literals. LITERAL- only allows
+        // them up to 4096 bytes, which the whole command staying under guarantees.
+        if !criteria.is_ascii() {
+            let capabilities = session.capabilities().map_err(|e| format!("IMAP Capability Error: {}", e))?;
+            let accepted = capabilities.has_str("LITERAL+")
+                || (capabilities.has_str("LITERAL-") && criteria.len() <= LITERAL_MINUS_LIMIT);
+            // Not an error: that would tear down a healthy session just to retry
+            if !accepted {
+                log::warn!("Server search skipped: the server takes no literals for non-ASCII text.");
+                return Ok(Vec::new());
+            }
+        }
+
+        // SEARCH runs in the selected mailbox, and the session may have left INBOX
+        session.select("INBOX").map_err(|e| format!("IMAP Select Error: {}", e))?;
+        let mut uids: Vec<u32> = session.uid_search(&criteria)
+            .map_err(|e| format!("IMAP Search Error: {}", e))?
+            .into_iter()
+            .collect();
+        uids.sort_unstable_by_key(|&uid| std::cmp::Reverse(uid));
+        uids.truncate(MAX_SERVER_HITS);
+        Ok(uids)
+    }).await
+}
+
+/// Fetches and caches headers for server hits that haven't been synced yet.
+async fn backfill_headers(app_handle: &AppHandle, account: &Account, uids: Vec<u32>) -> Result<(), String> {
+    if uids.is_empty() {
+        return Ok(());
+    }
+
+    let stored_validity = database::get_mailbox_validity(app_handle, "INBOX")?;
+    let uid_set = uids.iter().map(|u| u.to_string()).collect::<Vec<_>>().join(",");
+
+    let headers = execute_with_session(account, SessionKind::Primary, move |session| {
+        let mailbox = session.select("INBOX").map_err(|e| format!("IMAP Select Error: {}", e))?;
+        let server_validity = mailbox.uid_validity.unwrap_or(0);
+
+        // A validity change means our cached UIDs are meaningless; leave the reset to sync
+        if stored_validity != Some(server_validity) {
+            return Ok(Vec::new());
+        }
+
+        let fetches = session.uid_fetch(&uid_set, HEADER_FETCH_QUERY)
+            .map_err(|e| format!("IMAP Fetch Error: {}", e))?;
+        let mut headers: Vec<MessageHeader> = fetches.iter().filter_map(|f| parse_header_to_message(f, "INBOX", server_validity)).collect();
+        if let Err(e) = snippet::fill_snippets(session, &fetches, &mut headers) {
+            log::warn!("Server search: snippet fetch failed: {}", e);
+        }
+        Ok(headers)
+    }).await?;
+
+    log::info!("Server search: cached {} previously unsynced headers.", headers.len());
+    database::insert_or_update_messages(app_handle, &headers)
+}
+
+/// Searches the local cache and the IMAP server, merging both into one ranked list.
+///
+/// Server hits missing from the cache have their headers fetched and inserted first.
+/// The two rankings are combined with reciprocal rank fusion, since bm25 scores and
+/// server result order are not comparable directly.
+pub async fn search_with_server(app_handle: &AppHandle, account: &Account, query_text: &str, limit: u32) -> Result<Vec<SearchHit>, String> {
+    if query_text.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let ast = query::parse(query_text)?;
+
+    let server_uids = match search_server_uids(account, &ast).await {
+        Ok(uids) => uids,
+        Err(e) => {
+            // Still answer from the cache when offline
+            log::warn!("Server search failed, using local results only: {}", e);
+            Vec::new()
+        }
+    };
+
+    let app = app_handle.clone();
+    let uids = server_uids.clone();
+    let missing = tokio::task::spawn_blocking(move || {
+        let cached = database::load_messages_by_uids(&app, "INBOX", &uids)?;
+        let cached_uids: std::collections::HashSet<u32> = cached.iter().map(|m| m.uid).collect();
+        Ok::<_, String>(uids.into_iter().filter(|u| !cached_uids.contains(u)).collect::<Vec<_>>())
+    }).await.map_err(|e| e.to_string())??;
+
+    if let Err(e) = backfill_headers(app_handle, account, missing).await {
+        log::warn!("Server search: header backfill failed: {}", e);
+    }
+
+    let app = app_handle.clone();
+    let text = query_text.to_string();
+    let (local_hits, server_headers) = tokio::task::spawn_blocking(move || {
+        let local = search_messages(&app, &text, limit)?;
+        let server = database::load_messages_by_uids(&app, "INBOX", &server_uids)?;
+        Ok::<_, String>((local, server))
+    }).await.map_err(|e| e.to_string())??;
+
+    Ok(merge_hits(local_hits, server_headers, limit as usize))
+}
+
+/// UIDs are only unique within a folder, so hits are matched up by folder and UID.
+fn merge_hits(local_hits: Vec<SearchHit>, mut server_headers: Vec<MessageHeader>, limit: usize) -> Vec<SearchHit> {
+    let mut fused: HashMap<(String, u32), (f64, SearchHit)> = HashMap::new();
+
+    for (rank, hit) in local_hits.into_iter().enumerate() {
+        let key = (hit.header.folder.clone(), hit.header.uid);
+        fused.insert(key, (1.0 / (RRF_K + rank as f64 + 1.0), hit));
+    }
+
+    // The server gives no relevance order, so treat newest as best
+    server_headers.sort_by_key(|h| std::cmp::Reverse(h.date));
+    for (rank, header) in server_headers.into_iter().enumerate() {
+        let contribution = 1.0 / (RRF_K + rank as f64 + 1.0);
+        match fused.get_mut(&(header.folder.clone(), header.uid)) {
+            Some((score, hit)) => {
+                *score += contribution;
+                hit.source = HitSource::Both;
+            }
+            None => {
+                let hit = SearchHit {
+                    subject_highlight: render_highlight(&header.subject),
+                    snippet_highlight: render_highlight(header.snippet.as_deref().unwrap_or_default()),
+                    score: 0.0,
+                    source: HitSource::Server,
+                    header,
+                };
+                fused.insert((hit.header.folder.clone(), hit.header.uid), (contribution, hit));
+            }
+        }
+    }
+
+    let mut merged: Vec<(f64, SearchHit)> = fused.into_values().collect();
+    merged.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+    merged.into_iter().take(limit).map(|(_, hit)| hit).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(folder: &str, uid: u32, date: i64) -> MessageHeader {
+        MessageHeader {
+            folder: folder.to_string(),
+            uid,
+            uid_validity: 1,
+            subject: format!("{} {}", folder, uid),
+            addresses: Default::default(),
+            date,
+            seen: false,
+            flagged: false,
+            has_attachments: false,
+            thread_id: None,
+            snippet: None,
+            threading: Default::default(),
+            list_id: None,
+            list_unsubscribe: None,
+            size: None,
+            internal_date: None,
+        }
+    }
+
+    fn local(folder: &str, uid: u32) -> SearchHit {
+        SearchHit {
+            header: header(folder, uid, 0),
+            score: -1.0,
+            subject_highlight: String::new(),
+            snippet_highlight: String::new(),
+            source: HitSource::Local,
+        }
+    }
+
+    fn keys(hits: &[SearchHit]) -> Vec<(&str, u32, HitSource)> {
+        hits.iter().map(|h| (h.header.folder.as_str(), h.header.uid, h.source)).collect()
+    }
+
+    #[test]
+    fn same_uid_in_another_folder_is_a_different_message() {
+        let merged = merge_hits(
+            vec![local("INBOX", 9), local("Sent", 7)],
+            vec![header("INBOX", 7, 100), header("INBOX", 9, 50)],
+            10,
+        );
+
+        // INBOX 9 is found by both, so it outranks each single-source hit
+        assert_eq!(
+            keys(&merged),
+            vec![("INBOX", 9, HitSource::Both), ("INBOX", 7, HitSource::Server), ("Sent", 7, HitSource::Local)]
+        );
+    }
+
+    #[test]
+    fn server_hits_rank_newest_first_and_respect_the_limit() {
+        let merged = merge_hits(Vec::new(), vec![header("INBOX", 1, 10), header("INBOX", 2, 30), header("INBOX", 3, 20)], 2);
+        assert_eq!(keys(&merged), vec![("INBOX", 2, HitSource::Server), ("INBOX", 3, HitSource::Server)]);
+        assert_eq!(merged[0].subject_highlight, "INBOX 2");
+    }
+
+    #[test]
+    fn fts_queries_quote_every_term() {
+        assert_eq!(build_fts_query("hello wor*").as_deref(), Some(r#""hello" "wor"*"#));
+        assert_eq!(build_fts_query(r#""exact  phrase" NEAR(a)"#).as_deref(), Some(r#""exact phrase" "NEAR a""#));
+        assert_eq!(build_fts_query("  -- ** "), None);
+    }
+
+    #[test]
+    fn highlights_escape_message_text() {
+        let marked = format!("<b>{}R&D{}</b>", MARK_OPEN, MARK_CLOSE);
+        assert_eq!(render_highlight(&marked), "&lt;b&gt;<mark>R&amp;D</mark>&lt;/b&gt;");
+    }
+}
@@ -0,0 +1,320 @@
+use crate::mail::message_body::html_to_text;
+use crate::mail::message_list::MessageHeader;
+use crate::mail::mime_decode::{self, body_param};
+use imap::types::Fetch;
+use imap_proto::types::{BodyContentCommon, BodyStructure, ContentEncoding, SectionPath};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+/// Bytes of the preview part fetched per message.
+const SNIPPET_FETCH_BYTES: u32 = 1024;
+
+/// Preview length shown in the message list.
+const SNIPPET_CHARS: usize = 180;
+
+/// UIDs per partial-body fetch command.
+const SNIPPET_FETCH_BATCH: usize = 500;
+
+/// The text part a preview is built from, as located in the BODYSTRUCTURE.
+#[derive(Debug, Clone)]
+pub struct SnippetPart {
+    section: Vec<u32>,
+    charset: Option<String>,
+    encoding: String,
+    is_html: bool,
+}
+
+/// What sync needs to know about a message body without downloading it.
+#[derive(Debug, Default)]
+pub struct BodyOutline {
+    pub part: Option<SnippetPart>,
+    pub has_attachments: bool,
+}
+
+/// Same rule the message view uses: an attachment disposition or any filename.
+fn is_attachment(common: &BodyContentCommon) -> bool {
+    let disposition = common.disposition.as_ref();
+    disposition.is_some_and(|d| d.ty.eq_ignore_ascii_case("attachment"))
+        || disposition.and_then(|d| body_param(&d.params, "filename")).is_some()
+        || body_param(&common.ty.params, "name").is_some()
+}
+
+pub fn encoding_name(encoding: &ContentEncoding) -> String {
+    match encoding {
+        ContentEncoding::SevenBit => "7bit".to_string(),
+        ContentEncoding::EightBit => "8bit".to_string(),
+        ContentEncoding::Binary => "binary".to_string(),
+        ContentEncoding::Base64 => "base64".to_string(),
+        ContentEncoding::QuotedPrintable => "quoted-printable".to_string(),
+        ContentEncoding::Other(other) => other.to_string(),
+    }
+}
+
+fn walk(bs: &BodyStructure, path: &[u32], plain: &mut Option<SnippetPart>, html: &mut Option<SnippetPart>, has_attachments: &mut bool) {
+    match bs {
+        BodyStructure::Text { common, other, .. } => {
+            if is_attachment(common) {
+                *has_attachments = true;
+                return;
+            }
+            let subtype = common.ty.subtype.to_ascii_lowercase();
+            let slot = match subtype.as_str() {
+                "plain" => plain,
+                "html" => html,
+                _ => return,
+            };
+            if slot.is_none() {
+                *slot = Some(SnippetPart {
+                    // A single-part message's body is addressed as section 1
+                    section: if path.is_empty() { vec![1] } else { path.to_vec() },
+                    charset: body_param(&common.ty.params, "charset"),
+                    encoding: encoding_name(&other.transfer_encoding),
+                    is_html: subtype == "html",
+                });
+            }
+        }
+        BodyStructure::Basic { common, .. } => {
+            if is_attachment(common) {
+                *has_attachments = true;
+            }
+        }
+        BodyStructure::Message { .. } => *has_attachments = true,
+        BodyStructure::Multipart { bodies, .. } => {
+            for (i, part) in bodies.iter().enumerate() {
+                let mut child = path.to_vec();
+                child.push(i as u32 + 1);
+                walk(part, &child, plain, html, has_attachments);
+            }
+        }
+    }
+}
+
+/// Picks the preview part (plain text preferred over HTML) and notes whether anything is attached.
+pub fn outline(bs: &BodyStructure) -> BodyOutline {
+    let mut plain = None;
+    let mut html = None;
+    let mut has_attachments = false;
+    walk(bs, &[], &mut plain, &mut html, &mut has_attachments);
+
+    BodyOutline { part: plain.or(html), has_attachments }
+}
+
+/// Decodes the first bytes of a part. Truncation can split a base64 quantum or a multibyte
+/// character, so the tail is trimmed back to something decodable. The charset label is
+/// only a hint; see `mime_decode::decode_text`.
+fn decode_partial(part: &SnippetPart, data: &[u8]) -> String {
+    let mut body: Vec<u8> = data.to_vec();
+    if part.encoding.eq_ignore_ascii_case("base64") {
+        body.retain(|b| !b.is_ascii_whitespace());
+        body.truncate(body.len() - body.len() % 4);
+    } else if part.encoding.eq_ignore_ascii_case("quoted-printable") {
+        // An escape cut after its `=` or first hex digit would be kept literally
+        if let Some(cut) = body.iter().rev().take(2).position(|&b| b == b'=') {
+            body.truncate(body.len() - 1 - cut);
+        }
+    }
+
+    let mut raw = format!("Content-Transfer-Encoding: {}\r\n\r\n", part.encoding).into_bytes();
+    raw.extend_from_slice(&body);
+
+    let bytes = mailparse::parse_mail(&raw)
+        .and_then(|parsed| parsed.get_body_raw())
+        .unwrap_or_else(|_| data.to_vec());
+    let text = mime_decode::decode_text_prefix(&bytes, part.charset.as_deref(), part.is_html);
+    text.trim_end_matches('\u{FFFD}').to_string()
+}
+
+/// Drops a trailing tag or `<style>`/`<script>` block that the byte limit cut open,
+/// which would otherwise leak markup or CSS into the preview.
+fn trim_open_markup(html: &str) -> &str {
+    let mut end = html.len();
+    if let Some(lt) = html.rfind('<') {
+        if !html[lt..].contains('>') {
+            end = lt;
+        }
+    }
+    let lower = html[..end].to_ascii_lowercase();
+    for tag in ["style", "script"] {
+        if let Some(open) = lower.rfind(&format!("<{}", tag)) {
+            if !lower[open..].contains(&format!("</{}", tag)) {
+                end = end.min(open);
+            }
+        }
+    }
+    &html[..end]
+}
+
+/// Collapses decoded text into a one-line preview, skipping quoted replies and signatures.
+fn clean_preview(text: &str) -> Option<String> {
+    let mut kept = Vec::new();
+    for line in text.lines() {
+        if line.trim_end() == "--" {
+            break;
+        }
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('>') {
+            continue;
+        }
+        kept.push(line);
+    }
+
+    let preview: String = kept.join(" ")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .take(SNIPPET_CHARS)
+        .collect();
+    Some(preview).filter(|p| !p.is_empty())
+}
+
+fn preview_from_part(part: &SnippetPart, data: &[u8]) -> Option<String> {
+    let text = decode_partial(part, data);
+    if part.is_html {
+        clean_preview(&html_to_text(trim_open_markup(&text)))
+    } else {
+        clean_preview(&text)
+    }
+}
+
+/// Fills `snippet` for messages fetched with `HEADER_FETCH_QUERY`.
+///
+/// Each message's preview part is fetched with a partial `BODY.PEEK[n]<0.1024>`; messages
+/// sharing a section number are fetched together.
+pub fn fill_snippets<T: Read + Write>(session: &mut imap::Session<T>, fetches: &[Fetch], messages: &mut [MessageHeader]) -> Result<(), String> {
+    let mut parts: HashMap<u32, SnippetPart> = HashMap::new();
+    for fetch in fetches {
+        let (Some(uid), Some(bs)) = (fetch.uid, fetch.bodystructure()) else { continue };
+        if let Some(part) = outline(bs).part {
+            parts.insert(uid, part);
+        }
+    }
+
+    let mut by_section: HashMap<Vec<u32>, Vec<u32>> = HashMap::new();
+    for (uid, part) in &parts {
+        by_section.entry(part.section.clone()).or_default().push(*uid);
+    }
+
+    let mut previews: HashMap<u32, String> = HashMap::new();
+    for (section, uids) in by_section {
+        let section_str = section.iter().map(|n| n.to_string()).collect::<Vec<_>>().join(".");
+        let query = format!("(UID BODY.PEEK[{}]<0.{}>)", section_str, SNIPPET_FETCH_BYTES);
+        let path = SectionPath::Part(section, None);
+
+        for chunk in uids.chunks(SNIPPET_FETCH_BATCH) {
+            let uid_set = chunk.iter().map(|u| u.to_string()).collect::<Vec<_>>().join(",");
+            let results = session.uid_fetch(&uid_set, &query)
+                .map_err(|e| format!("IMAP Snippet Fetch Error: {}", e))?;
+
+            for fetch in results.iter() {
+                let Some(uid) = fetch.uid else { continue };
+                let (Some(part), Some(data)) = (parts.get(&uid), fetch.section(&path)) else { continue };
+                if let Some(preview) = preview_from_part(part, data) {
+                    previews.insert(uid, preview);
+                }
+            }
+        }
+    }
+
+    for msg in messages.iter_mut() {
+        if let Some(preview) = previews.remove(&msg.uid) {
+            msg.snippet = Some(preview);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::Engine;
+
+    fn part(encoding: &str, charset: Option<&str>, is_html: bool) -> SnippetPart {
+        SnippetPart {
+            section: vec![1],
+            charset: charset.map(str::to_string),
+            encoding: encoding.to_string(),
+            is_html,
+        }
+    }
+
+    /// Base64 with the 76-column CRLF line breaks mailers use.
+    fn base64_lines(text: &str) -> Vec<u8> {
+        let encoded = base64::engine::general_purpose::STANDARD.encode(text);
+        encoded.as_bytes().chunks(76).collect::<Vec<_>>().join(&b"\r\n"[..])
+    }
+
+    #[test]
+    fn trim_open_markup_drops_a_cut_tag() {
+        assert_eq!(trim_open_markup("<p>Hello</p><a hre"), "<p>Hello</p>");
+        assert_eq!(trim_open_markup("<p>Hello</p>"), "<p>Hello</p>");
+        assert_eq!(trim_open_markup("<p>Hello</p><"), "<p>Hello</p>");
+    }
+
+    #[test]
+    fn trim_open_markup_drops_unclosed_style_and_script_blocks() {
+        assert_eq!(trim_open_markup("<p>Hi</p><STYLE>p { color: red; }"), "<p>Hi</p>");
+        assert_eq!(trim_open_markup("<p>Hi</p><script>var a = 1;"), "<p>Hi</p>");
+        assert_eq!(trim_open_markup("<style>p {}</style><p>Hi</p><style>.x { mar"), "<style>p {}</style><p>Hi</p>");
+        assert_eq!(
+            trim_open_markup("<style>p {}</style><p>Hi</p>"),
+            "<style>p {}</style><p>Hi</p>"
+        );
+    }
+
+    #[test]
+    fn decode_partial_drops_a_cut_base64_quantum() {
+        let original = "Hello, this preview was cut somewhere in the middle of its second base64 line.";
+        let data = base64_lines(original);
+        // Each whole 4-character quantum is 3 bytes; the CRLF after 76 characters doesn't count
+        for (cut, decoded) in [(10, 6), (37, 27), (76, 57), (77, 57), (78, 57), (90, 66)] {
+            let text = decode_partial(&part("base64", Some("utf-8"), false), &data[..cut]);
+            assert_eq!(text, original[..decoded], "cut {}", cut);
+        }
+    }
+
+    #[test]
+    fn decode_partial_drops_a_cut_multibyte_character() {
+        // "Grüße" with the base64 cut right after the first byte of "ü"
+        let data = base64_lines("Grüße aus Köln");
+        let text = decode_partial(&part("base64", Some("utf-8"), false), &data[..4]);
+        assert_eq!(text, "Gr");
+
+        let text = decode_partial(&part("8bit", Some("utf-8"), false), &"Grüße".as_bytes()[..3]);
+        assert_eq!(text, "Gr");
+    }
+
+    #[test]
+    fn decode_partial_drops_a_cut_quoted_printable_escape() {
+        let data = b"Gr=C3=BC=C3=9Fe aus K=C3=B6ln";
+        for (cut, expected) in [(3, "Gr"), (4, "Gr"), (6, "Gr"), (7, "Gr"), (8, "Grü"), (9, "Grü"), (15, "Grüße")] {
+            let text = decode_partial(&part("quoted-printable", Some("utf-8"), false), &data[..cut]);
+            assert_eq!(text, expected, "cut {}", cut);
+        }
+
+        let soft_break = decode_partial(&part("quoted-printable", Some("utf-8"), false), b"Hello wor=\r\nld, and=");
+        assert_eq!(soft_break, "Hello world, and");
+    }
+
+    #[test]
+    fn decode_partial_falls_back_on_the_charset_label() {
+        let text = decode_partial(&part("quoted-printable", Some("iso-8859-1"), false), b"Caf=E9 cr=E8me");
+        assert_eq!(text, "Café crème");
+    }
+
+    #[test]
+    fn html_previews_skip_cut_markup() {
+        let data = b"<html><body><p>Quarterly <b>numbers</b> attached.</p><style>p { col";
+        let preview = preview_from_part(&part("7bit", None, true), data);
+        assert_eq!(preview.as_deref(), Some("Quarterly numbers attached."));
+    }
+
+    #[test]
+    fn clean_preview_skips_quotes_and_signatures() {
+        let text = "Sounds good.\n\n> On Monday, Ada wrote:\n> Lunch?\n  See   you\tthere.\n-- \nBob\nSent from my phone";
+        assert_eq!(clean_preview(text).as_deref(), Some("Sounds good. See you there."));
+        assert_eq!(clean_preview("> only quoted\n\n"), None);
+        assert_eq!(clean_preview(&"word ".repeat(100)).map(|p| p.chars().count()), Some(SNIPPET_CHARS));
+    }
+}
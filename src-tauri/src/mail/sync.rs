@@ -1,37 +1,50 @@
 use crate::auth::account::Account;
-use crate::mail::message_list::MessageHeader;
+use crate::mail::message_list::{parse_address_header, AddressHeaders, EmailAddress, MessageHeader, ThreadingHeaders};
 use crate::mail::database;
+use crate::mail::threading;
+use crate::mail::contacts;
 use crate::mail::prefetch;
 use crate::mail::notifications;
+use crate::mail::snippet;
+use crate::mail::saved_searches;
+use crate::mail::mime_decode::decode_encoded_words;
 use mailparse::parse_mail;
-use native_tls::TlsConnector;
 use tauri::AppHandle;
 use std::sync::atomic::{AtomicBool, Ordering};
 use tokio::sync::Mutex as AsyncMutex;
 use once_cell::sync::Lazy;
+use imap::types::NameAttribute;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::sync::Mutex;
 
 pub static SYNC_LOCK: Lazy<AsyncMutex<()>> = Lazy::new(|| AsyncMutex::new(()));
 
+/// Fetch items used for header-only syncs and for backfilling server search hits.
+pub const HEADER_FETCH_QUERY: &str = "(UID FLAGS RFC822.SIZE INTERNALDATE ENVELOPE BODYSTRUCTURE BODY.PEEK[HEADER.FIELDS (SUBJECT FROM TO CC BCC REPLY-TO DATE MESSAGE-ID IN-REPLY-TO REFERENCES LIST-ID LIST-UNSUBSCRIBE AUTOCRYPT)])";
+
+/// Cached messages lacking envelope headers are refetched this many at a time, newest first.
+const ENVELOPE_BACKFILL_BATCH: u32 = 500;
+
+/// Sent folder per account email, resolved once per run. `None` means the server has none.
+static SENT_FOLDERS: Lazy<Mutex<HashMap<String, Option<String>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// New headers stored by one folder sync.
+struct FolderSync {
+    messages: Vec<MessageHeader>,
+    is_bootstrap: bool,
+}
+
 pub async fn sync_inbox(app_handle: &AppHandle, account: Account) -> Result<u32, String> {
 
     let email = account.email.clone();
     let access_token = account.access_token.clone();
+    let server_config = account.imap.clone();
+    let is_gmail = server_config.host.ends_with("gmail.com");
     let app_handle_clone = app_handle.clone();
 
     let new_messages_count = tokio::task::spawn_blocking(move || {
-        let mut last_uid = database::get_highest_uid(&app_handle_clone, "INBOX").unwrap_or(0);
-        let stored_validity = database::get_mailbox_validity(&app_handle_clone, "INBOX").unwrap_or(None);
-
-        let domain = "imap.gmail.com";
-        let port = 993;
-
-        let tls = TlsConnector::builder()
-            .danger_accept_invalid_certs(true)
-            .build()
-            .map_err(|e| format!("TLS Builder Error: {}", e))?;
-
-        let client = imap::connect((domain, port), domain, &tls)
-            .map_err(|e| format!("IMAP Connection Error: {}", e))?;
+        let client = crate::mail::tls::connect_imap(&server_config)?;
 
         let auth_raw = format!(
             "user={}\x01auth=Bearer {}\x01\x01",
@@ -51,96 +64,59 @@ pub async fn sync_inbox(app_handle: &AppHandle, account: Account) -> Result<u32,
             .map_err(|(e, _)| format!("IMAP Authentication Failed: {}", e))?;
 
         let result = (|| -> Result<u32, String> {
-            let mailbox = session.select("INBOX").map_err(|e| format!("IMAP Select Error: {}", e))?;
-            let server_validity = mailbox.uid_validity.unwrap_or(0);
-            let uid_next = mailbox.uid_next.unwrap_or(0);
-
-            // 1. UIDVALIDITY Check
-            if stored_validity != Some(server_validity) {
-                log::info!("UIDVALIDITY changed ({} -> {}). Clearing cache.", stored_validity.unwrap_or(0), server_validity);
-                database::clear_messages(&app_handle_clone, "INBOX")?;
-                database::update_mailbox_validity(&app_handle_clone, "INBOX", server_validity)?;
-                last_uid = 0;
-            }
-
-            // 2. Fast Exit Check
-            if uid_next <= last_uid + 1 {
-                log::info!("Inbox already up to date.");
-                return Ok(0);
-            }
-
-            // 3. Exact Sequence Range Fetch
-            let (start_uid, end_uid, is_bootstrap) = if last_uid == 0 {
-                // To support true infinite scrolling across the entire mailbox,
-                // we must fetch all message headers locally instead of just 200.
-                let end = uid_next.saturating_sub(1);
-                (1, end, true)
-            } else {
-                let end = uid_next.saturating_sub(1);
-                (last_uid + 1, end, false)
-            };
-
-            if start_uid > end_uid {
-                log::info!("No new messages (start_uid > end_uid).");
-                return Ok(0);
-            }
-
-            let range = format!("{}:{}", start_uid, end_uid);
-            
-            if is_bootstrap {
-                log::info!("Bootstrap sync interval: {}", range);
-            } else {
-                log::info!("Fetching interval: {}", range);
+            let inbox = sync_folder(&mut session, &app_handle_clone, "INBOX", is_gmail)?;
+            let mut changed = !inbox.messages.is_empty();
+            record_contacts(&app_handle_clone, &email, &inbox.messages, false);
+
+            // Sent mail is only cached so conversations include the user's own replies;
+            // failing to sync it must not fail the inbox sync.
+            let mut folders = vec![("INBOX".to_string(), false)];
+            if let Some(sent) = find_sent_folder(&mut session, &email) {
+                match sync_folder(&mut session, &app_handle_clone, &sent, is_gmail) {
+                    Ok(sent_sync) => {
+                        changed |= !sent_sync.messages.is_empty();
+                        record_contacts(&app_handle_clone, &email, &sent_sync.messages, true);
+                    }
+                    Err(e) => log::warn!("Sent folder sync failed: {}", e),
+                }
+                folders.push((sent, true));
             }
 
-            // --- DEFENSIVE RE-SELECT ---
-            // Explicitly re-selecting INBOX immediately prior to fetch.
-            // Even if the session was recently selected, forcing a re-select right before fetching
-            // refreshes mailbox state and clears any potential IMAP protocol staleness or zombie caching.
-            let _ = session.select("INBOX").map_err(|e| format!("IMAP Re-Select Error: {}", e))?;
-
-            let fetch_results = session.uid_fetch(
-                &range,
-                "(UID FLAGS BODY.PEEK[HEADER.FIELDS (SUBJECT FROM DATE)])"
-            ).map_err(|e| format!("IMAP Fetch Error: {}", e))?;
-
-            let mut messages = Vec::new();
-            for msg in fetch_results.iter() {
-                if let Some(header) = parse_header_to_message(msg, server_validity) {
-                    messages.push(header);
+            for (folder, is_sent) in &folders {
+                match backfill_envelope_headers(&mut session, &app_handle_clone, folder, is_gmail, &email, *is_sent) {
+                    Ok(count) => changed |= count > 0,
+                    Err(e) => log::warn!("Envelope header backfill for {} failed: {}", folder, e),
                 }
             }
 
-            let num_new = messages.len() as u32;
-
-            // --- SUSPICIOUS ZERO-SYNC DETECTION ---
-            // If the server reported a higher UIDNEXT but our exact sequence range fetch returned 0 messages,
-            // we treat this as a stale state indicator. Returning an error forces system recovery/reconnect.
-            if num_new == 0 && (last_uid + 1) < uid_next {
-                log::warn!(
-                    "Suspicious zero-sync detected! Expected messages in range {}, but got 0. Forcing session discard.",
-                    range
-                );
-                return Err("Suspicious zero-sync detected".to_string());
+            if changed || database::has_unthreaded_messages(&app_handle_clone).unwrap_or(false) {
+                match threading::rebuild_threads(&app_handle_clone) {
+                    Ok(updated) => log::info!("Threading: updated {} messages.", updated),
+                    Err(e) => log::warn!("Thread rebuild failed: {}", e),
+                }
             }
 
-            log::info!("Grabbed {} new messages!", num_new);
-            database::insert_or_update_messages(&app_handle_clone, &messages)?;
+            let num_new = inbox.messages.len() as u32;
 
-            if num_new > 0 {
+            if changed {
                 use tauri::Emitter;
                 if let Err(e) = app_handle_clone.emit("mail:updated", ()) {
                     log::error!("Failed to emit mail:updated event: {}", e);
                 }
+            }
 
-                // --- SHOW NOTIFICATIONS ---
-                if !is_bootstrap {
-                    for msg in &messages {
-                        notifications::show_new_email_notification(&app_handle_clone, &msg.from, &msg.subject, msg.uid);
-                    }
+            if changed {
+                if let Err(e) = saved_searches::refresh_and_notify(&app_handle_clone) {
+                    log::warn!("Smart folder refresh failed: {}", e);
                 }
             }
 
+            // --- SHOW NOTIFICATIONS ---
+            if !inbox.is_bootstrap {
+                for msg in &inbox.messages {
+                    notifications::show_new_email_notification(&app_handle_clone, &msg.sender(), &msg.subject, msg.uid);
+                }
+            }
 
             Ok(num_new)
         })();
@@ -165,7 +141,192 @@ pub async fn sync_inbox(app_handle: &AppHandle, account: Account) -> Result<u32,
     new_messages_count
 }
 
-fn parse_header_to_message(msg: &imap::types::Fetch, server_validity: u32) -> Option<MessageHeader> {
+/// Fetches headers newer than the highest cached UID of `folder` and stores them.
+fn sync_folder<T: Read + Write>(session: &mut imap::Session<T>, app_handle: &AppHandle, folder: &str, is_gmail: bool) -> Result<FolderSync, String> {
+    let mut last_uid = database::get_highest_uid(app_handle, folder).unwrap_or(0);
+    let stored_validity = database::get_mailbox_validity(app_handle, folder).unwrap_or(None);
+    let nothing_new = FolderSync { messages: Vec::new(), is_bootstrap: false };
+
+    let mailbox = session.select(folder).map_err(|e| format!("IMAP Select Error: {}", e))?;
+    let server_validity = mailbox.uid_validity.unwrap_or(0);
+    let uid_next = mailbox.uid_next.unwrap_or(0);
+
+    // 1. UIDVALIDITY Check
+    if stored_validity != Some(server_validity) {
+        log::info!("{}: UIDVALIDITY changed ({} -> {}). Clearing cache.", folder, stored_validity.unwrap_or(0), server_validity);
+        database::clear_messages(app_handle, folder)?;
+        // Inline parts, previews and decrypted messages are cached by INBOX UID, which now
+        // names different messages. Other folders' UIDs don't key anything else.
+        if folder == "INBOX" {
+            crate::mail::message_body::purge_inline_cache(app_handle);
+            if let Some(account) = crate::auth::session::get_active_account(app_handle) {
+                crate::mail::attachment_preview::purge_account(app_handle, &account.email);
+                crate::mail::openpgp::forget_account(&account.email);
+            }
+        }
+        database::update_mailbox_validity(app_handle, folder, server_validity)?;
+        last_uid = 0;
+    }
+
+    // 2. Fast Exit Check
+    if uid_next <= last_uid + 1 {
+        log::info!("{} already up to date.", folder);
+        return Ok(nothing_new);
+    }
+
+    // 3. Exact Sequence Range Fetch
+    let (start_uid, end_uid, is_bootstrap) = if last_uid == 0 {
+        // To support true infinite scrolling across the entire mailbox,
+        // we must fetch all message headers locally instead of just 200.
+        let end = uid_next.saturating_sub(1);
+        (1, end, true)
+    } else {
+        let end = uid_next.saturating_sub(1);
+        (last_uid + 1, end, false)
+    };
+
+    if start_uid > end_uid {
+        log::info!("No new messages (start_uid > end_uid).");
+        return Ok(nothing_new);
+    }
+
+    let range = format!("{}:{}", start_uid, end_uid);
+    
+    if is_bootstrap {
+        log::info!("{}: bootstrap sync interval: {}", folder, range);
+    } else {
+        log::info!("{}: fetching interval: {}", folder, range);
+    }
+
+    // --- DEFENSIVE RE-SELECT ---
+    // Explicitly re-selecting the folder immediately prior to fetch.
+    // Even if the session was recently selected, forcing a re-select right before fetching
+    // refreshes mailbox state and clears any potential IMAP protocol staleness or zombie caching.
+    let _ = session.select(folder).map_err(|e| format!("IMAP Re-Select Error: {}", e))?;
+
+    let fetch_results = session.uid_fetch(&range, HEADER_FETCH_QUERY)
+        .map_err(|e| format!("IMAP Fetch Error: {}", e))?;
+
+    let mut messages = Vec::new();
+    for msg in fetch_results.iter() {
+        if let Some(header) = parse_header_to_message(msg, folder, server_validity) {
+            messages.push(header);
+        }
+    }
+
+    let num_new = messages.len() as u32;
+
+    // --- SUSPICIOUS ZERO-SYNC DETECTION ---
+    // If the server reported a higher UIDNEXT but our exact sequence range fetch returned 0 messages,
+    // we treat this as a stale state indicator. Returning an error forces system recovery/reconnect.
+    if num_new == 0 && (last_uid + 1) < uid_next {
+        log::warn!(
+            "Suspicious zero-sync detected! Expected messages in range {}, but got 0. Forcing session discard.",
+            range
+        );
+        return Err("Suspicious zero-sync detected".to_string());
+    }
+
+    if let Err(e) = snippet::fill_snippets(session, &fetch_results, &mut messages) {
+        log::warn!("{}: snippet fetch failed: {}", folder, e);
+    }
+
+    if is_gmail {
+        match threading::fetch_gmail_thread_ids(session, &range) {
+            Ok(ids) => {
+                for msg in &mut messages {
+                    msg.threading.gm_thread_id = ids.get(&msg.uid).cloned();
+                }
+            }
+            Err(e) => log::warn!("X-GM-THRID fetch failed, falling back to header threading: {}", e),
+        }
+    }
+
+    log::info!("{}: grabbed {} new messages!", folder, num_new);
+    database::insert_or_update_messages(app_handle, &messages)?;
+    crate::mail::autocrypt::record_fetches(app_handle, &fetch_results);
+
+    Ok(FolderSync { messages, is_bootstrap })
+}
+
+/// Refetches envelope headers for messages cached before they were stored, harvesting
+/// contacts from them on the way. Returns how many messages were updated.
+fn backfill_envelope_headers<T: Read + Write>(
+    session: &mut imap::Session<T>,
+    app_handle: &AppHandle,
+    folder: &str,
+    is_gmail: bool,
+    user_email: &str,
+    is_sent: bool,
+) -> Result<usize, String> {
+    let uids = database::get_uids_missing_envelope(app_handle, folder, ENVELOPE_BACKFILL_BATCH)?;
+    if uids.is_empty() {
+        return Ok(0);
+    }
+
+    let uid_set = uids.iter().map(|u| u.to_string()).collect::<Vec<_>>().join(",");
+    session.select(folder).map_err(|e| format!("IMAP Select Error: {}", e))?;
+
+    let fetches = session.uid_fetch(&uid_set, HEADER_FETCH_QUERY)
+        .map_err(|e| format!("IMAP Fetch Error: {}", e))?;
+    let mut headers: Vec<MessageHeader> = fetches
+        .iter()
+        .filter_map(|f| parse_header_to_message(f, folder, 0))
+        .collect();
+    record_contacts(app_handle, user_email, &headers, is_sent);
+
+    if is_gmail {
+        if let Ok(ids) = threading::fetch_gmail_thread_ids(session, &uid_set) {
+            for msg in &mut headers {
+                msg.threading.gm_thread_id = ids.get(&msg.uid).cloned();
+            }
+        }
+    }
+
+    // Expunged or unparseable messages are marked as done so they aren't refetched every sync
+    let returned: std::collections::HashSet<u32> = headers.iter().map(|m| m.uid).collect();
+    let gone: Vec<u32> = uids.into_iter().filter(|u| !returned.contains(u)).collect();
+
+    log::info!("{}: backfilled envelope headers for {} messages.", folder, headers.len());
+    database::set_envelope_headers(app_handle, folder, &headers, &gone)?;
+    Ok(headers.len() + gone.len())
+}
+
+fn record_contacts(app_handle: &AppHandle, user_email: &str, messages: &[MessageHeader], sent: bool) {
+    if let Err(e) = contacts::record_messages(app_handle, user_email, messages, sent) {
+        log::warn!("Failed to update contacts: {}", e);
+    }
+}
+
+/// Finds the folder holding sent mail, preferring the RFC 6154 `\Sent` attribute.
+fn find_sent_folder<T: Read + Write>(session: &mut imap::Session<T>, email: &str) -> Option<String> {
+    if let Some(cached) = SENT_FOLDERS.lock().unwrap().get(email) {
+        return cached.clone();
+    }
+
+    let names = match session.list(Some(""), Some("*")) {
+        Ok(names) => names,
+        Err(e) => {
+            log::warn!("IMAP List Error while looking for Sent folder: {}", e);
+            return None;
+        }
+    };
+
+    let by_attribute = names.iter().find(|n| {
+        n.attributes().iter().any(|a| matches!(a, NameAttribute::Custom(c) if c.eq_ignore_ascii_case("\\Sent")))
+    });
+    let by_name = || {
+        names.iter().find(|n| {
+            matches!(n.name(), "[Gmail]/Sent Mail" | "Sent" | "Sent Items" | "Sent Messages" | "INBOX.Sent")
+        })
+    };
+    let sent = by_attribute.or_else(by_name).map(|n| n.name().to_string());
+
+    SENT_FOLDERS.lock().unwrap().insert(email.to_string(), sent.clone());
+    sent
+}
+
+pub fn parse_header_to_message(msg: &imap::types::Fetch, folder: &str, server_validity: u32) -> Option<MessageHeader> {
     let actual_uid = msg.uid?;
     let body = msg.header()?;
 
@@ -175,8 +336,11 @@ fn parse_header_to_message(msg: &imap::types::Fetch, server_validity: u32) -> Op
     let parsed = parse_mail(body).ok()?;
     
     let mut subject = String::new();
-    let mut from = String::new();
     let mut date = String::new();
+    let mut threading = ThreadingHeaders::default();
+    let mut addresses = AddressHeaders::default();
+    let mut list_id = None;
+    let mut list_unsubscribe = None;
 
     for header in parsed.get_headers() {
         let key = header.get_key().to_lowercase();
@@ -184,45 +348,104 @@ fn parse_header_to_message(msg: &imap::types::Fetch, server_validity: u32) -> Op
 
         match key.as_str() {
             "subject" => subject = val,
-            "from" => from = val,
+            "from" => addresses.from = parse_address_header(header),
+            "to" => addresses.to = parse_address_header(header),
+            "cc" => addresses.cc = parse_address_header(header),
+            "bcc" => addresses.bcc = parse_address_header(header),
+            "reply-to" => addresses.reply_to = parse_address_header(header),
             "date" => date = val,
+            "message-id" => threading.message_id = threading::extract_message_ids(&val).into_iter().next(),
+            "in-reply-to" => threading.in_reply_to = threading::extract_message_ids(&val).into_iter().next(),
+            "references" => threading.references = threading::extract_message_ids(&val),
+            "list-id" => list_id = Some(val.trim().to_string()).filter(|v| !v.is_empty()),
+            "list-unsubscribe" => list_unsubscribe = Some(val.trim().to_string()).filter(|v| !v.is_empty()),
             _ => {}
         }
     }
 
+    // The ENVELOPE is the server's own parse; it covers headers mailparse couldn't read
+    if let Some(envelope) = msg.envelope() {
+        fill_from_envelope(envelope, &mut addresses, &mut threading, &mut subject);
+    }
+
+    // Headers were fetched, so an absent Message-ID is recorded as empty rather than unknown
+    threading.message_id.get_or_insert_with(String::new);
+
+    let internal_date = msg.internal_date().map(|dt| dt.timestamp());
     let timestamp = chrono::DateTime::parse_from_rfc2822(&date)
         .map(|dt| dt.timestamp())
+        .ok()
+        .or(internal_date)
         .unwrap_or(0);
 
-    // Minimal text parsing overhead on sync fetch
-    let mut plain_body = String::new();
-    if let Ok(parsed_full) = parse_mail(body) {
-        plain_body = parsed_full.get_body().unwrap_or_default();
-    }
-
-    let snippet = if !plain_body.is_empty() {
-        let clean: String = plain_body
-            .replace('\n', " ")
-            .replace('\r', "")
-            .chars()
-            .take(180)
-            .collect();
-        Some(clean)
-    } else {
-        None
-    };
+    // Snippets need a second fetch of the chosen part; see `snippet::fill_snippets`
+    let has_attachments = msg.bodystructure().is_some_and(|bs| snippet::outline(bs).has_attachments);
 
     Some(MessageHeader {
-        folder: "INBOX".to_string(),
+        folder: folder.to_string(),
         uid: actual_uid,
         uid_validity: server_validity,
         subject,
-        from,
+        addresses,
         date: timestamp,
         seen,
         flagged,
-        has_attachments: false,
+        has_attachments,
         thread_id: None,
-        snippet,
+        snippet: None,
+        threading,
+        list_id,
+        list_unsubscribe,
+        size: msg.size,
+        internal_date,
     })
 }
+
+fn envelope_addresses(list: &Option<Vec<imap_proto::types::Address>>) -> Vec<EmailAddress> {
+    list.iter()
+        .flatten()
+        .filter_map(|a| {
+            // Group syntax shows up as entries without a host
+            let mailbox = String::from_utf8_lossy(a.mailbox?).into_owned();
+            let host = String::from_utf8_lossy(a.host?).into_owned();
+            Some(EmailAddress {
+                name: a.name.map(decode_encoded_words).filter(|n| !n.trim().is_empty()),
+                email: format!("{}@{}", mailbox, host),
+            })
+        })
+        .collect()
+}
+
+fn fill_from_envelope(
+    envelope: &imap_proto::types::Envelope,
+    addresses: &mut AddressHeaders,
+    threading: &mut ThreadingHeaders,
+    subject: &mut String,
+) {
+    let fields = [
+        (&mut addresses.from, &envelope.from),
+        (&mut addresses.to, &envelope.to),
+        (&mut addresses.cc, &envelope.cc),
+        (&mut addresses.bcc, &envelope.bcc),
+        (&mut addresses.reply_to, &envelope.reply_to),
+    ];
+    for (parsed, from_envelope) in fields {
+        if parsed.is_empty() {
+            *parsed = envelope_addresses(from_envelope);
+        }
+    }
+
+    if subject.is_empty() {
+        if let Some(raw) = envelope.subject {
+            *subject = decode_encoded_words(raw);
+        }
+    }
+    if threading.message_id.is_none() {
+        threading.message_id = envelope.message_id
+            .and_then(|raw| threading::extract_message_ids(&String::from_utf8_lossy(raw)).into_iter().next());
+    }
+    if threading.in_reply_to.is_none() {
+        threading.in_reply_to = envelope.in_reply_to
+            .and_then(|raw| threading::extract_message_ids(&String::from_utf8_lossy(raw)).into_iter().next());
+    }
+}
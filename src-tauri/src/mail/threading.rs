@@ -0,0 +1,575 @@
+use crate::mail::database::{self, HEADER_COLUMNS};
+use crate::mail::db_pool::get_conn;
+use crate::mail::message_list::MessageHeader;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use rusqlite::Connection;
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+use tauri::AppHandle;
+
+static MESSAGE_ID_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"<[^<>\s]+>").unwrap());
+static GM_THRID_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"X-GM-THRID (\d+)").unwrap());
+static FETCH_UID_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\bUID (\d+)").unwrap());
+static SUBJECT_PREFIX_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)^\s*((re|fw|fwd|aw|wg|sv|vs|antw)(\[\d+\])?:|\[[^\]]*\])\s*").unwrap());
+
+/// Threading inputs for one cached message, as loaded by `database::load_thread_inputs`.
+pub struct ThreadInput {
+    pub rowid: i64,
+    pub message_id: Option<String>,
+    pub in_reply_to: Option<String>,
+    pub references: Vec<String>,
+    pub gm_thread_id: Option<String>,
+    pub subject: String,
+    pub thread_id: Option<String>,
+}
+
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ThreadSummary {
+    pub thread_id: String,
+    pub subject: String,
+    /// Display names (or addresses) of everyone who wrote in the thread, in order of first message.
+    pub participants: Vec<String>,
+    pub message_count: u32,
+    pub unread_count: u32,
+    pub latest_date: i64,
+    pub latest_snippet: Option<String>,
+    pub has_attachments: bool,
+    pub flagged: bool,
+}
+
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Thread {
+    pub thread_id: String,
+    pub subject: String,
+    /// Messages from every cached folder, oldest first, including the user's replies from
+    /// Sent. Each one's `folder` says where message actions should address it.
+    pub messages: Vec<MessageHeader>,
+}
+
+/// Pulls the `<id@host>` tokens out of a Message-ID, In-Reply-To or References header.
+pub fn extract_message_ids(value: &str) -> Vec<String> {
+    MESSAGE_ID_RE.find_iter(value).map(|m| m.as_str().to_string()).collect()
+}
+
+/// Strips reply/forward markers and list tags so replies group with their original.
+/// Returns the base subject and whether it carried a reply or forward marker.
+fn normalize_subject(subject: &str) -> (String, bool) {
+    let mut rest = subject.trim();
+    let mut is_reply = false;
+    while let Some(caps) = SUBJECT_PREFIX_RE.captures(rest) {
+        is_reply |= caps.get(2).is_some();
+        rest = &rest[caps.get(0).map(|m| m.end()).unwrap_or(0)..];
+    }
+    (rest.trim().to_lowercase(), is_reply)
+}
+
+/// Reads Gmail thread ids for a UID set. The `imap` crate can't parse X-GM-THRID,
+/// so the raw FETCH response is scanned instead.
+pub fn fetch_gmail_thread_ids<T: Read + Write>(session: &mut imap::Session<T>, uid_set: &str) -> Result<HashMap<u32, String>, String> {
+    let raw = session
+        .run_command_and_read_response(format!("UID FETCH {} (UID X-GM-THRID)", uid_set))
+        .map_err(|e| format!("IMAP Fetch Error: {}", e))?;
+
+    let mut ids = HashMap::new();
+    for line in String::from_utf8_lossy(&raw).lines() {
+        let uid = FETCH_UID_RE.captures(line).and_then(|c| c[1].parse::<u32>().ok());
+        let thrid = GM_THRID_RE.captures(line).map(|c| c[1].to_string());
+        if let (Some(uid), Some(thrid)) = (uid, thrid) {
+            ids.insert(uid, thrid);
+        }
+    }
+    Ok(ids)
+}
+
+struct Container {
+    message: Option<usize>,
+    parent: Option<usize>,
+}
+
+/// True if `node` is `ancestor` or sits somewhere below it.
+fn is_descendant(containers: &[Container], mut node: usize, ancestor: usize) -> bool {
+    loop {
+        if node == ancestor {
+            return true;
+        }
+        match containers[node].parent {
+            Some(p) => node = p,
+            None => return false,
+        }
+    }
+}
+
+fn root_of(containers: &[Container], mut node: usize) -> usize {
+    while let Some(p) = containers[node].parent {
+        node = p;
+    }
+    node
+}
+
+/// Assigns a thread id to every input (returned in the same order).
+///
+/// Messages carrying Gmail's X-GM-THRID keep it. Everything else is threaded with the
+/// JWZ algorithm over Message-ID / In-Reply-To / References; roots left without a link
+/// join an earlier thread with the same base subject when their subject marks them as a
+/// reply or forward. A conversation that mixes both adopts the Gmail id.
+pub fn compute_thread_ids(inputs: &[ThreadInput]) -> Vec<String> {
+    let mut containers: Vec<Container> = Vec::new();
+    let mut keys: Vec<Option<String>> = Vec::new();
+    let mut by_id: HashMap<String, usize> = HashMap::new();
+    let mut message_container = vec![0usize; inputs.len()];
+
+    let mut container_for = |id: &str, containers: &mut Vec<Container>, keys: &mut Vec<Option<String>>| -> usize {
+        *by_id.entry(id.to_string()).or_insert_with(|| {
+            containers.push(Container { message: None, parent: None });
+            keys.push(Some(id.to_string()));
+            containers.len() - 1
+        })
+    };
+
+    for (i, input) in inputs.iter().enumerate() {
+        let own = match input.message_id.as_deref() {
+            Some(id) => {
+                let c = container_for(id, &mut containers, &mut keys);
+                if containers[c].message.is_none() {
+                    c
+                } else {
+                    // Duplicate Message-ID (e.g. the same mail in INBOX and Sent): thread it as a sibling copy
+                    containers.push(Container { message: None, parent: None });
+                    keys.push(None);
+                    containers.len() - 1
+                }
+            }
+            None => {
+                containers.push(Container { message: None, parent: None });
+                keys.push(None);
+                containers.len() - 1
+            }
+        };
+        containers[own].message = Some(i);
+        message_container[i] = own;
+
+        let mut chain: Vec<&str> = input.references.iter().map(String::as_str).collect();
+        if let Some(reply_to) = input.in_reply_to.as_deref() {
+            if chain.last() != Some(&reply_to) {
+                chain.push(reply_to);
+            }
+        }
+        // A copy of a message shares its original's place in the tree
+        if keys[own].is_none() {
+            if let Some(id) = input.message_id.as_deref() {
+                chain.push(id);
+            }
+        }
+
+        // Link each reference to the next, unless an earlier message already placed it
+        let mut prev: Option<usize> = None;
+        for id in chain {
+            let c = container_for(id, &mut containers, &mut keys);
+            if let Some(p) = prev {
+                if c != p && containers[c].parent.is_none() && !is_descendant(&containers, p, c) {
+                    containers[c].parent = Some(p);
+                }
+            }
+            prev = Some(c);
+        }
+
+        // The message's own headers are authoritative about its parent
+        containers[own].parent = None;
+        if let Some(p) = prev {
+            if !is_descendant(&containers, p, own) {
+                containers[own].parent = Some(p);
+            }
+        }
+    }
+
+    // Group parentless roots by subject, in date order
+    let mut root_group: HashMap<usize, usize> = HashMap::new();
+    let mut latest_by_subject: HashMap<String, usize> = HashMap::new();
+    for (i, input) in inputs.iter().enumerate() {
+        let root = root_of(&containers, message_container[i]);
+        if root_group.contains_key(&root) {
+            continue;
+        }
+
+        let (base, is_reply) = normalize_subject(&input.subject);
+        let group = if base.is_empty() {
+            root
+        } else if is_reply {
+            *latest_by_subject.entry(base).or_insert(root)
+        } else {
+            latest_by_subject.insert(base, root);
+            root
+        };
+        root_group.insert(root, group);
+    }
+
+    let jwz_ids: Vec<String> = (0..inputs.len())
+        .map(|i| {
+            let group = root_group[&root_of(&containers, message_container[i])];
+            match &keys[group] {
+                Some(id) => format!("mid:{}", id),
+                // Only a message without a Message-ID can head a group without a key
+                None => format!("row:{}", inputs[containers[group].message.unwrap_or(i)].rowid),
+            }
+        })
+        .collect();
+
+    let mut gmail_for_group: HashMap<&str, &str> = HashMap::new();
+    for (input, jwz) in inputs.iter().zip(&jwz_ids) {
+        if let Some(gm) = input.gm_thread_id.as_deref() {
+            gmail_for_group.entry(jwz.as_str()).or_insert(gm);
+        }
+    }
+
+    inputs
+        .iter()
+        .zip(&jwz_ids)
+        .map(|(input, jwz)| match input.gm_thread_id.as_deref().or_else(|| gmail_for_group.get(jwz.as_str()).copied()) {
+            Some(gm) => format!("gm:{}", gm),
+            None => jwz.clone(),
+        })
+        .collect()
+}
+
+/// Recomputes thread ids for the whole cache and writes back the ones that changed.
+pub fn rebuild_threads(app_handle: &AppHandle) -> Result<usize, String> {
+    let inputs = database::load_thread_inputs(app_handle)?;
+    let thread_ids = compute_thread_ids(&inputs);
+
+    let updates: Vec<(i64, String)> = inputs
+        .iter()
+        .zip(thread_ids)
+        .filter(|(input, id)| input.thread_id.as_deref() != Some(id.as_str()))
+        .map(|(input, id)| (input.rowid, id))
+        .collect();
+
+    database::set_thread_ids(app_handle, &updates)?;
+    Ok(updates.len())
+}
+
+/// Display name from an RFC 5322 mailbox, falling back to the bare address.
+fn participant_name(sender: &str) -> String {
+    match sender.split_once('<') {
+        Some((name, addr)) => {
+            let name = name.trim().trim_matches('"').trim();
+            if name.is_empty() {
+                addr.trim_end_matches('>').trim().to_string()
+            } else {
+                name.to_string()
+            }
+        }
+        None => sender.trim().to_string(),
+    }
+}
+
+/// Conversations with at least one INBOX message, most recently active first. Counts,
+/// participants and the latest snippet include the user's own replies from Sent.
+///
+/// `before` is the `(latest_date, thread_id)` of the last summary on the previous page.
+pub fn load_threads_page(app_handle: &AppHandle, before: Option<(i64, String)>, limit: u32) -> Result<Vec<ThreadSummary>, String> {
+    let conn = get_conn(app_handle)?;
+    threads_page_from(&conn, before, limit)
+}
+
+fn threads_page_from(conn: &Connection, before: Option<(i64, String)>, limit: u32) -> Result<Vec<ThreadSummary>, String> {
+    let (before_date, before_id) = match before {
+        Some((date, id)) => (Some(date), id),
+        None => (None, String::new()),
+    };
+
+    let mut stmt = conn.prepare_cached(
+        "SELECT thread_id,
+                MAX(date) AS latest,
+                COUNT(DISTINCT COALESCE(NULLIF(message_id, ''), rowid)),
+                COUNT(DISTINCT CASE WHEN seen = 0 THEN COALESCE(NULLIF(message_id, ''), rowid) END),
+                MAX(flagged),
+                MAX(has_attachments)
+         FROM messages
+         WHERE thread_id IS NOT NULL
+         GROUP BY thread_id
+         HAVING SUM(folder = 'INBOX') > 0 AND (?1 IS NULL OR latest < ?1 OR (latest = ?1 AND thread_id < ?2))
+         ORDER BY latest DESC, thread_id DESC
+         LIMIT ?3"
+    ).map_err(|e| e.to_string())?;
+
+    let rows = stmt.query_map(rusqlite::params![before_date, before_id, limit], |row| {
+        Ok(ThreadSummary {
+            thread_id: row.get(0)?,
+            latest_date: row.get(1)?,
+            message_count: row.get(2)?,
+            unread_count: row.get::<_, Option<u32>>(3)?.unwrap_or(0),
+            flagged: row.get::<_, i32>(4)? != 0,
+            has_attachments: row.get::<_, i32>(5)? != 0,
+            subject: String::new(),
+            participants: Vec::new(),
+            latest_snippet: None,
+        })
+    }).map_err(|e| e.to_string())?;
+
+    let mut summaries = Vec::new();
+    for r in rows {
+        summaries.push(r.map_err(|e| e.to_string())?);
+    }
+
+    let mut member_stmt = conn.prepare_cached(
+        "SELECT sender, subject, snippet FROM messages WHERE thread_id = ?1 ORDER BY date"
+    ).map_err(|e| e.to_string())?;
+
+    for summary in &mut summaries {
+        let members = member_stmt.query_map([&summary.thread_id], |row| {
+            Ok((
+                row.get::<_, Option<String>>(0)?.unwrap_or_default(),
+                row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+                row.get::<_, Option<String>>(2)?,
+            ))
+        }).map_err(|e| e.to_string())?;
+
+        let mut seen = HashSet::new();
+        for member in members {
+            let (sender, subject, snippet) = member.map_err(|e| e.to_string())?;
+            if summary.subject.is_empty() {
+                summary.subject = subject;
+            }
+            let name = participant_name(&sender);
+            if !name.is_empty() && seen.insert(name.to_lowercase()) {
+                summary.participants.push(name);
+            }
+            summary.latest_snippet = snippet.filter(|s| !s.is_empty()).or(summary.latest_snippet.take());
+        }
+    }
+
+    Ok(summaries)
+}
+
+/// The messages of a conversation from every folder, oldest first. Copies with the same
+/// Message-ID (a list post that also arrived directly, a message sent to oneself) are
+/// returned once, as the INBOX copy when there is one.
+pub fn load_thread(app_handle: &AppHandle, thread_id: &str) -> Result<Thread, String> {
+    let conn = get_conn(app_handle)?;
+    thread_from(&conn, thread_id)
+}
+
+fn thread_from(conn: &Connection, thread_id: &str) -> Result<Thread, String> {
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT {}
+         FROM messages
+         WHERE thread_id = ?1
+         ORDER BY date, rowid",
+        HEADER_COLUMNS
+    )).map_err(|e| e.to_string())?;
+
+    let rows = stmt.query_map([thread_id], database::parse_header_row).map_err(|e| e.to_string())?;
+
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    let mut messages: Vec<MessageHeader> = Vec::new();
+    for r in rows {
+        let header = r.map_err(|e| e.to_string())?;
+        if let Some(id) = &header.threading.message_id {
+            if let Some(&index) = positions.get(id) {
+                if messages[index].folder != "INBOX" && header.folder == "INBOX" {
+                    messages[index] = header;
+                }
+                continue;
+            }
+            positions.insert(id.clone(), messages.len());
+        }
+        messages.push(header);
+    }
+
+    if messages.is_empty() {
+        return Err(format!("Thread {} not found", thread_id));
+    }
+
+    Ok(Thread {
+        thread_id: thread_id.to_string(),
+        subject: messages[0].subject.clone(),
+        messages,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(rowid: i64, message_id: Option<&str>, references: &[&str], subject: &str) -> ThreadInput {
+        ThreadInput {
+            rowid,
+            message_id: message_id.map(str::to_string),
+            in_reply_to: references.last().map(|id| id.to_string()),
+            references: references.iter().map(|id| id.to_string()).collect(),
+            gm_thread_id: None,
+            subject: subject.to_string(),
+            thread_id: None,
+        }
+    }
+
+    #[test]
+    fn replies_follow_their_references_chain() {
+        let inputs = [
+            input(1, Some("<a@x>"), &[], "Plans"),
+            input(2, Some("<b@x>"), &["<a@x>"], "Re: Plans"),
+            input(3, Some("<c@x>"), &["<a@x>", "<b@x>"], "Re: Plans"),
+            input(4, Some("<d@x>"), &[], "Something else"),
+        ];
+        let ids = compute_thread_ids(&inputs);
+
+        assert_eq!(ids[..3], ["mid:<a@x>", "mid:<a@x>", "mid:<a@x>"]);
+        assert_eq!(ids[3], "mid:<d@x>");
+    }
+
+    #[test]
+    fn replies_to_an_uncached_message_share_its_thread() {
+        // The original was never synced, and the second reply arrives without In-Reply-To
+        let mut second = input(2, Some("<c@x>"), &["<root@x>", "<b@x>"], "Re: Budget");
+        second.in_reply_to = None;
+        let inputs = [input(1, Some("<b@x>"), &["<root@x>"], "Re: Budget"), second];
+        let ids = compute_thread_ids(&inputs);
+
+        assert_eq!(ids, ["mid:<root@x>", "mid:<root@x>"]);
+    }
+
+    #[test]
+    fn a_reply_synced_before_its_parent_still_joins_it() {
+        let inputs = [
+            input(1, Some("<b@x>"), &["<a@x>"], "Re: Plans"),
+            input(2, Some("<a@x>"), &[], "Plans"),
+        ];
+        let ids = compute_thread_ids(&inputs);
+
+        assert_eq!(ids, ["mid:<a@x>", "mid:<a@x>"]);
+    }
+
+    #[test]
+    fn unlinked_replies_group_by_subject() {
+        let inputs = [
+            input(1, Some("<a@x>"), &[], "Lunch?"),
+            input(2, Some("<b@x>"), &[], "RE: [team] Fwd: lunch?"),
+            input(3, None, &[], "Re: Lunch?"),
+            input(4, Some("<d@x>"), &[], "Lunch?"),
+            input(5, Some("<e@x>"), &[], "Re: Lunch?"),
+        ];
+        let ids = compute_thread_ids(&inputs);
+
+        assert_eq!(ids[..3], ["mid:<a@x>", "mid:<a@x>", "mid:<a@x>"]);
+        // A new message with the same subject starts a new conversation for later replies
+        assert_eq!(ids[3..], ["mid:<d@x>", "mid:<d@x>"]);
+    }
+
+    #[test]
+    fn matching_subjects_without_a_reply_marker_stay_apart() {
+        let inputs = [
+            input(1, None, &[], "Weekly report"),
+            input(2, None, &[], "Weekly report"),
+            input(3, None, &[], ""),
+            input(4, None, &[], "Re:"),
+        ];
+        let ids = compute_thread_ids(&inputs);
+
+        assert_eq!(ids, ["row:1", "row:2", "row:3", "row:4"]);
+    }
+
+    #[test]
+    fn copies_with_the_same_message_id_share_a_thread() {
+        let inputs = [
+            input(1, Some("<a@x>"), &[], "Plans"),
+            input(2, Some("<b@x>"), &["<a@x>"], "Re: Plans"),
+            // The same reply, stored again in Sent
+            input(3, Some("<b@x>"), &["<a@x>"], "Re: Plans"),
+        ];
+        let ids = compute_thread_ids(&inputs);
+
+        assert_eq!(ids, ["mid:<a@x>", "mid:<a@x>", "mid:<a@x>"]);
+    }
+
+    #[test]
+    fn gmail_thread_ids_win_and_spread_to_linked_messages() {
+        let mut original = input(1, Some("<a@x>"), &[], "Plans");
+        original.gm_thread_id = Some("1790".to_string());
+        let mut unrelated = input(3, Some("<c@x>"), &[], "Plans");
+        unrelated.gm_thread_id = Some("1791".to_string());
+        let inputs = [original, input(2, Some("<b@x>"), &["<a@x>"], "Re: Plans"), unrelated];
+        let ids = compute_thread_ids(&inputs);
+
+        assert_eq!(ids, ["gm:1790", "gm:1790", "gm:1791"]);
+    }
+
+    #[test]
+    fn normalize_subject_strips_markers_and_list_tags() {
+        assert_eq!(normalize_subject("Re: Fwd[2]: [list] Hello "), ("hello".to_string(), true));
+        assert_eq!(normalize_subject("[list] Hello"), ("hello".to_string(), false));
+        assert_eq!(normalize_subject("Reply needed"), ("reply needed".to_string(), false));
+    }
+
+    #[test]
+    fn participant_names_prefer_display_names() {
+        assert_eq!(participant_name("\"Ada Lovelace\" <ada@example.com>"), "Ada Lovelace");
+        assert_eq!(participant_name("<ada@example.com>"), "ada@example.com");
+        assert_eq!(participant_name("ada@example.com"), "ada@example.com");
+    }
+
+    fn thread_db(name: &str) -> (Connection, std::path::PathBuf) {
+        let path = std::env::temp_dir().join(format!("threading-{}-{}.db", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut conn = Connection::open(&path).unwrap();
+        crate::mail::migrations::run(&mut conn, &path).unwrap();
+        conn.execute_batch(
+            "INSERT INTO messages (folder, uid, uid_validity, subject, sender, snippet, date, seen, message_id, thread_id) VALUES
+                ('INBOX', 7, 1, 'Re: Plans', 'Bob <bob@x>', 'See you', 300, 0, '<c@x>', 't'),
+                ('INBOX', 5, 1, 'Plans', 'Bob <bob@x>', 'Lunch?', 100, 1, '<a@x>', 't'),
+                ('[Gmail]/Sent Mail', 5, 1, 'Re: Plans', 'Ada <ada@x>', 'Sure', 200, 1, '<b@x>', 't'),
+                ('INBOX', 9, 1, 'Re: Plans', 'Bob <bob@x>', 'See you', 300, 0, '<c@x>', 't'),
+                ('[Gmail]/Sent Mail', 8, 1, 'Note to self', 'Ada <ada@x>', 'Milk', 50, 1, '<e@x>', 'v'),
+                ('INBOX', 8, 1, 'Note to self', 'Ada <ada@x>', 'Milk', 50, 0, '<e@x>', 'v'),
+                ('[Gmail]/Sent Mail', 11, 1, 'Unanswered', 'Ada <ada@x>', 'Hello?', 400, 1, '<f@x>', 'w'),
+                ('INBOX', 6, 1, 'Other', 'Eve <eve@x>', 'Hi', 150, 1, '<d@x>', 'u');"
+        ).unwrap();
+        (conn, path)
+    }
+
+    #[test]
+    fn threads_include_sent_replies_once_oldest_first() {
+        let (conn, path) = thread_db("members");
+
+        let thread = thread_from(&conn, "t").unwrap();
+        let uids: Vec<(&str, u32)> = thread.messages.iter().map(|m| (m.folder.as_str(), m.uid)).collect();
+        assert_eq!(uids, [("INBOX", 5), ("[Gmail]/Sent Mail", 5), ("INBOX", 7)]);
+        assert_eq!(thread.subject, "Plans");
+
+        // The Sent copy comes first by rowid, but the INBOX copy is the one kept
+        let note = thread_from(&conn, "v").unwrap();
+        let uids: Vec<(&str, u32)> = note.messages.iter().map(|m| (m.folder.as_str(), m.uid)).collect();
+        assert_eq!(uids, [("INBOX", 8)]);
+
+        assert!(thread_from(&conn, "missing").is_err());
+
+        drop(conn);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn thread_summaries_count_the_users_replies() {
+        let (conn, path) = thread_db("summaries");
+
+        let page = threads_page_from(&conn, None, 10).unwrap();
+        let ids: Vec<&str> = page.iter().map(|t| t.thread_id.as_str()).collect();
+        // `w` only has a Sent message, so it isn't an inbox conversation
+        assert_eq!(ids, ["t", "u", "v"]);
+
+        let plans = &page[0];
+        assert_eq!(plans.message_count, 3);
+        assert_eq!(plans.unread_count, 1);
+        assert_eq!(plans.participants, ["Bob", "Ada"]);
+        assert_eq!(plans.latest_snippet.as_deref(), Some("See you"));
+        assert_eq!((page[2].message_count, page[2].unread_count), (1, 1));
+
+        let rest = threads_page_from(&conn, Some((plans.latest_date, plans.thread_id.clone())), 10).unwrap();
+        assert_eq!(rest.iter().map(|t| t.thread_id.as_str()).collect::<Vec<_>>(), ["u", "v"]);
+
+        drop(conn);
+        let _ = std::fs::remove_file(&path);
+    }
+}
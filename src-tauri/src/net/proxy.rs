@@ -0,0 +1,522 @@
+use crate::settings_store::JsonSettings;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+use tauri::AppHandle;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProxyMode {
+    /// Always connect directly, ignoring environment variables.
+    None,
+    /// Honour `ALL_PROXY` / `HTTPS_PROXY` / `HTTP_PROXY` / `NO_PROXY`.
+    System,
+    Socks5,
+    Http,
+}
+
+/// App-wide proxy settings applied to IMAP, SMTP and HTTP traffic.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ProxySettings {
+    pub mode: ProxyMode,
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl Default for ProxySettings {
+    fn default() -> Self {
+        Self {
+            mode: ProxyMode::System,
+            host: String::new(),
+            port: 0,
+            username: None,
+            password: None,
+        }
+    }
+}
+
+/// Resolved proxy endpoint for a single connection.
+#[derive(Debug, Clone)]
+struct Endpoint {
+    kind: ProxyMode,
+    host: String,
+    port: u16,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+static SETTINGS: JsonSettings<ProxySettings> = JsonSettings::new("proxy.json");
+
+/// Loads persisted proxy settings into memory. Called once during app setup.
+pub fn init(app_handle: &AppHandle) {
+    SETTINGS.load(app_handle);
+}
+
+pub fn current_settings() -> ProxySettings {
+    SETTINGS.get()
+}
+
+pub fn save_settings(app_handle: &AppHandle, settings: ProxySettings) -> Result<(), String> {
+    if matches!(settings.mode, ProxyMode::Socks5 | ProxyMode::Http) {
+        if settings.host.is_empty() || settings.port == 0 {
+            return Err("Proxy host and port are required".to_string());
+        }
+        // Refuse what `http_client` couldn't use, rather than failing every request later
+        reqwest::Proxy::all(proxy_url(&settings)).map_err(|e| format!("Invalid proxy: {}", e))?;
+    }
+
+    SETTINGS.save(app_handle, settings)
+}
+
+/// Opens a TCP stream to `host:port`, tunnelling through the configured proxy if any.
+/// Used for every non-HTTP mail connection (IMAP, SMTP).
+pub fn connect_tcp(host: &str, port: u16) -> Result<TcpStream, String> {
+    match resolve_endpoint(host)? {
+        None => connect_direct(host, port),
+        Some(proxy) => {
+            let mut stream = connect_direct(&proxy.host, proxy.port)
+                .map_err(|e| format!("Proxy Connection Error: {}", e))?;
+            stream.set_read_timeout(Some(CONNECT_TIMEOUT)).ok();
+
+            match proxy.kind {
+                ProxyMode::Socks5 => socks5_connect(&mut stream, &proxy, host, port)?,
+                _ => http_connect(&mut stream, &proxy, host, port)?,
+            }
+
+            stream.set_read_timeout(None).ok();
+            Ok(stream)
+        }
+    }
+}
+
+fn connect_direct(host: &str, port: u16) -> Result<TcpStream, String> {
+    let addrs = (host, port)
+        .to_socket_addrs()
+        .map_err(|e| format!("Connection Error: {}", e))?;
+
+    let mut last_err = format!("Connection Error: could not resolve {}", host);
+    for addr in addrs {
+        match TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_err = format!("Connection Error: {}", e),
+        }
+    }
+    Err(last_err)
+}
+
+/// The proxy to tunnel through, if any. A proxy variable that can't be parsed is an
+/// error rather than a reason to connect directly, so traffic never leaks around it.
+fn resolve_endpoint(target_host: &str) -> Result<Option<Endpoint>, String> {
+    let settings = current_settings();
+
+    match settings.mode {
+        ProxyMode::None => Ok(None),
+        ProxyMode::Socks5 | ProxyMode::Http => Ok(Some(Endpoint {
+            kind: settings.mode,
+            host: settings.host,
+            port: settings.port,
+            username: settings.username,
+            password: settings.password,
+        })),
+        ProxyMode::System => {
+            if is_excluded_by_env(target_host) {
+                return Ok(None);
+            }
+            let Some((key, value)) = ["ALL_PROXY", "all_proxy", "HTTPS_PROXY", "https_proxy"]
+                .iter()
+                .find_map(|key| std::env::var(key).ok().filter(|v| !v.is_empty()).map(|v| (key, v)))
+            else {
+                return Ok(None);
+            };
+            parse_proxy_url(&value)
+                .map(Some)
+                .ok_or_else(|| format!("Proxy Configuration Error: {} is not a usable proxy URL", key))
+        }
+    }
+}
+
+fn is_excluded_by_env(target_host: &str) -> bool {
+    let Some(list) = std::env::var("NO_PROXY").or_else(|_| std::env::var("no_proxy")).ok() else {
+        return false;
+    };
+
+    let host = target_host.to_ascii_lowercase();
+    list.split(',')
+        .map(|entry| entry.trim().trim_start_matches('.').to_ascii_lowercase())
+        .filter(|entry| !entry.is_empty())
+        .any(|entry| entry == "*" || host == entry || host.ends_with(&format!(".{}", entry)))
+}
+
+fn parse_proxy_url(value: &str) -> Option<Endpoint> {
+    let with_scheme = if value.contains("://") { value.to_string() } else { format!("http://{}", value) };
+    let url = url::Url::parse(&with_scheme).ok()?;
+
+    let kind = match url.scheme() {
+        "socks5" | "socks5h" => ProxyMode::Socks5,
+        "http" | "https" => ProxyMode::Http,
+        _ => return None,
+    };
+    let default_port = if kind == ProxyMode::Socks5 { 1080 } else { 8080 };
+
+    Some(Endpoint {
+        kind,
+        host: url.host_str()?.to_string(),
+        port: url.port().unwrap_or(default_port),
+        username: Some(url.username()).filter(|u| !u.is_empty()).map(str::to_string),
+        password: url.password().map(str::to_string),
+    })
+}
+
+/// RFC 1928 CONNECT with optional RFC 1929 username/password authentication.
+/// The target hostname is sent unresolved so DNS happens on the proxy side.
+fn socks5_connect(stream: &mut TcpStream, proxy: &Endpoint, host: &str, port: u16) -> Result<(), String> {
+    let err = |e: std::io::Error| format!("SOCKS5 Error: {}", e);
+    let has_auth = proxy.username.is_some();
+
+    let greeting: &[u8] = if has_auth { &[0x05, 0x02, 0x00, 0x02] } else { &[0x05, 0x01, 0x00] };
+    stream.write_all(greeting).map_err(err)?;
+
+    let mut choice = [0u8; 2];
+    stream.read_exact(&mut choice).map_err(err)?;
+    if choice[0] != 0x05 {
+        return Err("SOCKS5 Error: not a SOCKS5 proxy".to_string());
+    }
+
+    match choice[1] {
+        0x00 => {}
+        0x02 if has_auth => {
+            let user = proxy.username.as_deref().unwrap_or_default().as_bytes();
+            let pass = proxy.password.as_deref().unwrap_or_default().as_bytes();
+            if user.len() > 255 || pass.len() > 255 {
+                return Err("SOCKS5 Error: credentials too long".to_string());
+            }
+
+            let mut auth = vec![0x01, user.len() as u8];
+            auth.extend_from_slice(user);
+            auth.push(pass.len() as u8);
+            auth.extend_from_slice(pass);
+            stream.write_all(&auth).map_err(err)?;
+
+            let mut status = [0u8; 2];
+            stream.read_exact(&mut status).map_err(err)?;
+            if status[1] != 0x00 {
+                return Err("SOCKS5 Error: proxy authentication failed".to_string());
+            }
+        }
+        _ => return Err("SOCKS5 Error: no acceptable authentication method".to_string()),
+    }
+
+    if host.len() > 255 {
+        return Err("SOCKS5 Error: hostname too long".to_string());
+    }
+    let mut request = vec![0x05, 0x01, 0x00, 0x03, host.len() as u8];
+    request.extend_from_slice(host.as_bytes());
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).map_err(err)?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).map_err(err)?;
+    if reply[1] != 0x00 {
+        return Err(format!("SOCKS5 Error: connect failed (code {})", reply[1]));
+    }
+
+    // Drain the bound address, whose length depends on its type
+    let addr_len = match reply[3] {
+        0x01 => 4,
+        0x04 => 16,
+        0x03 => {
+            let mut len = [0u8; 1];
+            stream.read_exact(&mut len).map_err(err)?;
+            len[0] as usize
+        }
+        _ => return Err("SOCKS5 Error: malformed reply".to_string()),
+    };
+    let mut bound = vec![0u8; addr_len + 2];
+    stream.read_exact(&mut bound).map_err(err)?;
+
+    Ok(())
+}
+
+fn http_connect(stream: &mut TcpStream, proxy: &Endpoint, host: &str, port: u16) -> Result<(), String> {
+    let err = |e: std::io::Error| format!("HTTP Proxy Error: {}", e);
+
+    let mut request = format!("CONNECT {0}:{1} HTTP/1.1\r\nHost: {0}:{1}\r\n", host, port);
+    if let Some(user) = &proxy.username {
+        let credentials = format!("{}:{}", user, proxy.password.as_deref().unwrap_or_default());
+        let encoded = base64::engine::general_purpose::STANDARD.encode(credentials);
+        request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", encoded));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).map_err(err)?;
+
+    // Read byte-by-byte so nothing past the header block is consumed from the tunnel
+    let mut response = Vec::new();
+    let mut byte = [0u8; 1];
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() > 8192 {
+            return Err("HTTP Proxy Error: response header too large".to_string());
+        }
+        stream.read_exact(&mut byte).map_err(err)?;
+        response.push(byte[0]);
+    }
+
+    let head = String::from_utf8_lossy(&response);
+    let status_line = head.lines().next().unwrap_or_default();
+    let status = status_line.split_whitespace().nth(1).unwrap_or_default();
+    if status != "200" {
+        return Err(format!("HTTP Proxy Error: {}", status_line));
+    }
+
+    Ok(())
+}
+
+fn proxy_url(settings: &ProxySettings) -> String {
+    let scheme = if settings.mode == ProxyMode::Socks5 { "socks5h" } else { "http" };
+    format!("{}://{}:{}", scheme, settings.host, settings.port)
+}
+
+/// Client builder routed the way the app proxy settings say. A proxy that can't be
+/// set up is an error: falling back to a direct connection would bypass it silently.
+/// In `System` mode reqwest picks up the proxy environment variables on its own.
+fn client_builder() -> Result<reqwest::ClientBuilder, String> {
+    let settings = current_settings();
+    let builder = reqwest::Client::builder();
+
+    match settings.mode {
+        ProxyMode::None => Ok(builder.no_proxy()),
+        ProxyMode::System => Ok(builder),
+        ProxyMode::Socks5 | ProxyMode::Http => {
+            let mut proxy = reqwest::Proxy::all(proxy_url(&settings))
+                .map_err(|e| format!("Proxy Configuration Error: {}", e))?;
+            if let Some(user) = &settings.username {
+                proxy = proxy.basic_auth(user, settings.password.as_deref().unwrap_or_default());
+            }
+            Ok(builder.proxy(proxy))
+        }
+    }
+}
+
+/// Builds a reqwest client that follows the app proxy settings.
+pub fn http_client() -> Result<reqwest::Client, String> {
+    client_builder()?
+        .build()
+        .map_err(|e| format!("Failed to build HTTP client: {}", e))
+}
+
+/// `oauth2` request adapter that routes token exchanges through the app proxy settings.
+/// Redirects are not followed, so a token endpoint can't bounce the request elsewhere.
+pub async fn oauth_http_client(
+    request: oauth2::HttpRequest,
+) -> Result<oauth2::HttpResponse, oauth2::reqwest::Error<reqwest::Error>> {
+    use oauth2::reqwest::Error;
+
+    let client = client_builder()
+        .map_err(Error::Other)?
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .map_err(Error::Reqwest)?;
+    let method = reqwest::Method::from_bytes(request.method.as_str().as_bytes()).unwrap_or(reqwest::Method::POST);
+
+    let mut builder = client.request(method, request.url.as_str()).body(request.body);
+    for (name, value) in request.headers.iter() {
+        builder = builder.header(name.as_str(), value.as_bytes());
+    }
+
+    let response = builder.send().await.map_err(Error::Reqwest)?;
+
+    let status_code = oauth2::http::StatusCode::from_u16(response.status().as_u16())
+        .unwrap_or(oauth2::http::StatusCode::INTERNAL_SERVER_ERROR);
+    let mut headers = oauth2::http::HeaderMap::new();
+    for (name, value) in response.headers() {
+        if let (Ok(n), Ok(v)) = (
+            oauth2::http::header::HeaderName::from_bytes(name.as_str().as_bytes()),
+            oauth2::http::header::HeaderValue::from_bytes(value.as_bytes()),
+        ) {
+            headers.append(n, v);
+        }
+    }
+    let body = response.bytes().await.map_err(Error::Reqwest)?.to_vec();
+
+    Ok(oauth2::HttpResponse { status_code, headers, body })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread::{self, JoinHandle};
+
+    fn endpoint(kind: ProxyMode, credentials: Option<(&str, &str)>) -> Endpoint {
+        Endpoint {
+            kind,
+            host: "127.0.0.1".to_string(),
+            port: 0,
+            username: credentials.map(|(user, _)| user.to_string()),
+            password: credentials.map(|(_, pass)| pass.to_string()),
+        }
+    }
+
+    /// Runs `server` against the first connection to a local listener and returns the
+    /// client side of that connection along with the server thread.
+    fn mock_proxy<T: Send + 'static>(server: impl FnOnce(TcpStream) -> T + Send + 'static) -> (TcpStream, JoinHandle<T>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = thread::spawn(move || server(listener.accept().unwrap().0));
+        let stream = TcpStream::connect(addr).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        (stream, handle)
+    }
+
+    fn read_n(stream: &mut TcpStream, n: usize) -> Vec<u8> {
+        let mut buf = vec![0u8; n];
+        stream.read_exact(&mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn socks5_connect_sends_the_hostname_unresolved() {
+        let (mut stream, server) = mock_proxy(|mut s| {
+            let greeting = read_n(&mut s, 3);
+            s.write_all(&[0x05, 0x00]).unwrap();
+            let head = read_n(&mut s, 5);
+            let rest = read_n(&mut s, head[4] as usize + 2);
+            // Bound address as a domain, to check the variable-length drain
+            s.write_all(&[0x05, 0x00, 0x00, 0x03, 0x04, b'h', b'o', b's', b't', 0x00, 0x50]).unwrap();
+            s.write_all(b"* OK").unwrap();
+            (greeting, head, rest)
+        });
+
+        socks5_connect(&mut stream, &endpoint(ProxyMode::Socks5, None), "imap.example.com", 993).unwrap();
+        assert_eq!(read_n(&mut stream, 4), b"* OK");
+
+        let (greeting, head, rest) = server.join().unwrap();
+        assert_eq!(greeting, [0x05, 0x01, 0x00]);
+        assert_eq!(head, [0x05, 0x01, 0x00, 0x03, 16]);
+        assert_eq!(&rest[..16], b"imap.example.com");
+        assert_eq!(&rest[16..], 993u16.to_be_bytes());
+    }
+
+    #[test]
+    fn socks5_connect_authenticates_with_username_and_password() {
+        let (mut stream, server) = mock_proxy(|mut s| {
+            let greeting = read_n(&mut s, 4);
+            s.write_all(&[0x05, 0x02]).unwrap();
+            let auth = read_n(&mut s, 2 + 5 + 1 + 6);
+            s.write_all(&[0x01, 0x00]).unwrap();
+            let head = read_n(&mut s, 5);
+            read_n(&mut s, head[4] as usize + 2);
+            s.write_all(&[0x05, 0x00, 0x00, 0x01, 127, 0, 0, 1, 0x00, 0x50]).unwrap();
+            (greeting, auth)
+        });
+
+        socks5_connect(&mut stream, &endpoint(ProxyMode::Socks5, Some(("alice", "secret"))), "example.com", 143).unwrap();
+
+        let (greeting, auth) = server.join().unwrap();
+        assert_eq!(greeting, [0x05, 0x02, 0x00, 0x02]);
+        assert_eq!(auth, b"\x01\x05alice\x06secret");
+    }
+
+    #[test]
+    fn socks5_rejected_credentials_are_reported() {
+        let (mut stream, server) = mock_proxy(|mut s| {
+            read_n(&mut s, 4);
+            s.write_all(&[0x05, 0x02]).unwrap();
+            read_n(&mut s, 2 + 5 + 1 + 6);
+            s.write_all(&[0x01, 0x01]).unwrap();
+        });
+
+        let err = socks5_connect(&mut stream, &endpoint(ProxyMode::Socks5, Some(("alice", "secret"))), "example.com", 143)
+            .unwrap_err();
+        assert!(err.contains("authentication failed"), "{}", err);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn socks5_refused_connection_carries_the_reply_code() {
+        let (mut stream, server) = mock_proxy(|mut s| {
+            read_n(&mut s, 3);
+            s.write_all(&[0x05, 0x00]).unwrap();
+            let head = read_n(&mut s, 5);
+            read_n(&mut s, head[4] as usize + 2);
+            s.write_all(&[0x05, 0x05, 0x00, 0x01, 0, 0, 0, 0, 0, 0]).unwrap();
+        });
+
+        let err = socks5_connect(&mut stream, &endpoint(ProxyMode::Socks5, None), "example.com", 143).unwrap_err();
+        assert!(err.contains("code 5"), "{}", err);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn socks5_requires_a_socks5_server() {
+        let (mut stream, server) = mock_proxy(|mut s| {
+            read_n(&mut s, 3);
+            s.write_all(b"HTTP/1.1 400 Bad Request\r\n\r\n").unwrap();
+        });
+
+        let err = socks5_connect(&mut stream, &endpoint(ProxyMode::Socks5, None), "example.com", 143).unwrap_err();
+        assert!(err.contains("not a SOCKS5 proxy"), "{}", err);
+        server.join().unwrap();
+    }
+
+    /// Reads an HTTP request head, up to and including the blank line.
+    fn read_head(stream: &mut TcpStream) -> String {
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            head.extend(read_n(stream, 1));
+        }
+        String::from_utf8(head).unwrap()
+    }
+
+    #[test]
+    fn http_connect_leaves_tunnelled_bytes_unread() {
+        let (mut stream, server) = mock_proxy(|mut s| {
+            let request = read_head(&mut s);
+            // The server greeting arrives in the same segment as the proxy response
+            s.write_all(b"HTTP/1.1 200 Connection established\r\nProxy-Agent: test\r\n\r\n* OK ready\r\n").unwrap();
+            request
+        });
+
+        http_connect(&mut stream, &endpoint(ProxyMode::Http, Some(("alice", "secret"))), "imap.example.com", 993).unwrap();
+        assert_eq!(read_n(&mut stream, 12), b"* OK ready\r\n");
+
+        let request = server.join().unwrap();
+        assert!(request.starts_with("CONNECT imap.example.com:993 HTTP/1.1\r\nHost: imap.example.com:993\r\n"), "{}", request);
+        // base64("alice:secret")
+        assert!(request.contains("Proxy-Authorization: Basic YWxpY2U6c2VjcmV0\r\n"), "{}", request);
+    }
+
+    #[test]
+    fn http_connect_refusal_is_reported() {
+        let (mut stream, server) = mock_proxy(|mut s| {
+            let request = read_head(&mut s);
+            s.write_all(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n").unwrap();
+            request
+        });
+
+        let err = http_connect(&mut stream, &endpoint(ProxyMode::Http, None), "example.com", 143).unwrap_err();
+        assert!(err.contains("407"), "{}", err);
+        assert!(!server.join().unwrap().contains("Proxy-Authorization"));
+    }
+
+    #[test]
+    fn proxy_urls_are_parsed_with_scheme_defaults() {
+        let socks = parse_proxy_url("socks5h://bob:pw@proxy.local").unwrap();
+        assert_eq!(socks.kind, ProxyMode::Socks5);
+        assert_eq!((socks.host.as_str(), socks.port), ("proxy.local", 1080));
+        assert_eq!((socks.username.as_deref(), socks.password.as_deref()), (Some("bob"), Some("pw")));
+
+        let http = parse_proxy_url("proxy.local:3128").unwrap();
+        assert_eq!(http.kind, ProxyMode::Http);
+        assert_eq!((http.host.as_str(), http.port, http.username), ("proxy.local", 3128, None));
+
+        assert!(parse_proxy_url("ftp://proxy.local").is_none());
+        assert!(parse_proxy_url("http://").is_none());
+    }
+}
@@ -0,0 +1,123 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use tauri::{AppHandle, Manager};
+
+/// App-wide settings kept in memory and persisted as a JSON file in the app data dir.
+/// Until `load` runs, and when the file is missing or unreadable, readers get `T::default()`.
+pub struct JsonSettings<T> {
+    file_name: &'static str,
+    value: RwLock<Option<T>>,
+}
+
+impl<T: Serialize + DeserializeOwned + Default + Clone> JsonSettings<T> {
+    pub const fn new(file_name: &'static str) -> Self {
+        Self { file_name, value: RwLock::new(None) }
+    }
+
+    /// Reads the persisted settings into memory. Called once during app setup.
+    pub fn load(&self, app_handle: &AppHandle) {
+        let loaded = self.path(app_handle).ok().map(|path| read_file(&path)).unwrap_or_default();
+        *self.value.write().unwrap() = Some(loaded);
+    }
+
+    pub fn get(&self) -> T {
+        self.value.read().unwrap().clone().unwrap_or_default()
+    }
+
+    /// Writes `settings` to disk, then makes them current. Validation is up to the caller.
+    pub fn save(&self, app_handle: &AppHandle, settings: T) -> Result<(), String> {
+        write_file(&self.path(app_handle)?, &settings)?;
+        *self.value.write().unwrap() = Some(settings);
+        Ok(())
+    }
+
+    fn path(&self, app_handle: &AppHandle) -> Result<PathBuf, String> {
+        let dir = app_handle
+            .path()
+            .app_data_dir()
+            .map_err(|e| format!("Failed to resolve App Data Dir: {}", e))?;
+        fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        Ok(dir.join(self.file_name))
+    }
+}
+
+fn read_file<T: DeserializeOwned + Default>(path: &Path) -> T {
+    let Ok(content) = fs::read_to_string(path) else { return T::default() };
+    serde_json::from_str(&content).unwrap_or_else(|e| {
+        log::warn!("Ignoring unreadable settings in {}: {}", path.display(), e);
+        T::default()
+    })
+}
+
+/// Writes through a temporary file and a rename, so a crash mid-write can't leave
+/// half a JSON document behind.
+fn write_file<T: Serialize>(path: &Path, settings: &T) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(settings).map_err(|e| e.to_string())?;
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, json).map_err(|e| format!("Failed to write {}: {}", tmp.display(), e))?;
+    fs::rename(&tmp, path).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+    #[serde(default)]
+    struct Example {
+        enabled: bool,
+        name: String,
+    }
+
+    impl Default for Example {
+        fn default() -> Self {
+            Self { enabled: true, name: "default".to_string() }
+        }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("settings-store-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("settings.json")
+    }
+
+    #[test]
+    fn settings_survive_a_round_trip() {
+        let path = temp_path("round-trip");
+        let saved = Example { enabled: false, name: "saved".to_string() };
+        write_file(&path, &saved).unwrap();
+
+        assert_eq!(read_file::<Example>(&path), saved);
+        assert!(!path.with_extension("json.tmp").exists());
+        fs::remove_dir_all(path.parent().unwrap()).ok();
+    }
+
+    #[test]
+    fn missing_or_corrupt_files_read_as_defaults() {
+        let path = temp_path("corrupt");
+        assert_eq!(read_file::<Example>(&path), Example::default());
+
+        fs::write(&path, "{ not json").unwrap();
+        assert_eq!(read_file::<Example>(&path), Example::default());
+        fs::remove_dir_all(path.parent().unwrap()).ok();
+    }
+
+    #[test]
+    fn fields_missing_from_older_files_take_their_defaults() {
+        let path = temp_path("partial");
+        fs::write(&path, r#"{ "name": "old" }"#).unwrap();
+
+        assert_eq!(read_file::<Example>(&path), Example { enabled: true, name: "old".to_string() });
+        fs::remove_dir_all(path.parent().unwrap()).ok();
+    }
+
+    #[test]
+    fn unloaded_settings_read_as_defaults() {
+        static STORE: JsonSettings<Example> = JsonSettings::new("unused.json");
+        assert_eq!(STORE.get(), Example::default());
+    }
+}